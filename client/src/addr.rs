@@ -1,6 +1,7 @@
-use std::net::SocketAddr;
+use network::{ConnectAddr, Network, Participant};
+use std::{net::SocketAddr, sync::Arc};
 use tokio::net::lookup_host;
-use tracing::trace;
+use tracing::{trace, warn};
 
 #[derive(Clone, Debug)]
 pub enum ConnectionArgs {
@@ -14,19 +15,87 @@ pub enum ConnectionArgs {
         hostname: String,
         prefer_ipv6: bool,
     },
+    ///hostname: (hostname|ip):[<port>]
+    /// Servers accept TLS on another port than plain Tcp. Certificates which
+    /// can't be verified are pinned in `pinned_certs` on first use, if given
+    Tls {
+        hostname: String,
+        prefer_ipv6: bool,
+        pinned_certs: Option<crate::tls::PinnedCerts>,
+    },
     Mpsc(u64),
 }
 
 impl ConnectionArgs {
     const DEFAULT_PORT: u16 = 14004;
+    const DEFAULT_TLS_PORT: u16 = 14007;
 }
 
-/// Parse ip address or resolves hostname.
+/// Connects to the server with the given protocol
+pub(crate) async fn connect(
+    network: &Network,
+    addr: &ConnectionArgs,
+) -> Result<Participant, crate::error::Error> {
+    Ok(match addr {
+        ConnectionArgs::Tcp {
+            hostname,
+            prefer_ipv6,
+        } => {
+            try_connect(
+                network,
+                hostname,
+                *prefer_ipv6,
+                ConnectionArgs::DEFAULT_PORT,
+                ConnectAddr::Tcp,
+            )
+            .await?
+        },
+        ConnectionArgs::Quic {
+            hostname,
+            prefer_ipv6,
+        } => {
+            warn!(
+                "QUIC is enabled. This is experimental and you won't be able to connect to TCP \
+                 servers unless deactivated"
+            );
+            let config = quinn::ClientConfig::with_native_roots();
+            try_connect(
+                network,
+                hostname,
+                *prefer_ipv6,
+                ConnectionArgs::DEFAULT_PORT,
+                |a| ConnectAddr::Quic(a, config.clone(), hostname.clone()),
+            )
+            .await?
+        },
+        ConnectionArgs::Tls {
+            hostname,
+            prefer_ipv6,
+            pinned_certs,
+        } => {
+            let config = crate::tls::client_config(hostname, pinned_certs.clone());
+            let name = crate::tls::server_name(hostname);
+            try_connect(
+                network,
+                hostname,
+                *prefer_ipv6,
+                ConnectionArgs::DEFAULT_TLS_PORT,
+                |a| ConnectAddr::Tls(a, Arc::clone(&config), name.clone()),
+            )
+            .await?
+        },
+        ConnectionArgs::Mpsc(id) => network.connect(ConnectAddr::Mpsc(*id)).await?,
+    })
+}
+
+/// Parse ip address or resolves hostname, with `default_port` used if the
+/// address has none.
 /// Note: If you use an ipv6 address, the number after the last
 /// colon will be used as the port unless you use [] around the address.
-pub(crate) async fn resolve(
+pub(crate) async fn resolve_with_port(
     address: &str,
     prefer_ipv6: bool,
+    default_port: u16,
 ) -> Result<Vec<SocketAddr>, std::io::Error> {
     // `lookup_host` will internally try to parse it as a SocketAddr
     // 1. Assume it's a hostname + port
@@ -37,7 +106,7 @@ pub(crate) async fn resolve(
         },
         Err(e) => {
             // 2. Assume its a hostname without port
-            match lookup_host((address, ConnectionArgs::DEFAULT_PORT)).await {
+            match lookup_host((address, default_port)).await {
                 Ok(s) => {
                     trace!("Host lookup without ports succeeded");
                     Ok(sort_ipv6(s, prefer_ipv6))
@@ -52,6 +121,7 @@ pub(crate) async fn try_connect<F>(
     network: &network::Network,
     address: &str,
     prefer_ipv6: bool,
+    default_port: u16,
     f: F,
 ) -> Result<network::Participant, crate::error::Error>
 where
@@ -59,7 +129,7 @@ where
 {
     use crate::error::Error;
     let mut participant = None;
-    for addr in resolve(address, prefer_ipv6)
+    for addr in resolve_with_port(address, prefer_ipv6, default_port)
         .await
         .map_err(Error::HostnameLookupFailed)?
     {
@@ -85,6 +155,10 @@ mod tests {
     use super::*;
     use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
+    async fn resolve(address: &str, prefer_ipv6: bool) -> Result<Vec<SocketAddr>, std::io::Error> {
+        resolve_with_port(address, prefer_ipv6, ConnectionArgs::DEFAULT_PORT).await
+    }
+
     #[tokio::test]
     async fn resolve_localhost() {
         let args = resolve("localhost", false).await.expect("resolve failed");
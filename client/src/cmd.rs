@@ -5,7 +5,7 @@ trait TabComplete {
     fn complete(&self, part: &str, client: &Client) -> Vec<String>;
 }
 
-impl TabComplete for ArgumentSpec {
+impl<S: AsRef<str>> TabComplete for ArgumentSpec<S> {
     fn complete(&self, part: &str, client: &Client) -> Vec<String> {
         match self {
             ArgumentSpec::PlayerName(_) => complete_player(part, client),
@@ -24,9 +24,9 @@ impl TabComplete for ArgumentSpec {
                 }
             },
             ArgumentSpec::Any(_, _) => vec![],
-            ArgumentSpec::Command(_) => complete_command(part),
+            ArgumentSpec::Command(_) => complete_command(part, client),
             ArgumentSpec::Message(_) => complete_player(part, client),
-            ArgumentSpec::SubCommand => complete_command(part),
+            ArgumentSpec::SubCommand => complete_command(part, client),
             ArgumentSpec::Enum(_, strings, _) => strings
                 .iter()
                 .filter(|string| string.starts_with(part))
@@ -51,11 +51,13 @@ fn complete_player(part: &str, client: &Client) -> Vec<String> {
         .collect()
 }
 
-fn complete_command(part: &str) -> Vec<String> {
+fn complete_command(part: &str, client: &Client) -> Vec<String> {
     let part = part.strip_prefix('/').unwrap_or(part);
 
     ChatCommand::iter_with_keywords()
         .map(|(kwd, _)| kwd)
+        // Commands declared by the server, e.g. by its plugins
+        .chain(client.server_commands.iter().map(|cmd| cmd.keyword.as_str()))
         .filter(|kwd| kwd.starts_with(part))
         .map(|kwd| format!("/{}", kwd))
         .collect()
@@ -96,7 +98,7 @@ pub fn complete(line: &str, client: &Client) -> Vec<String> {
         let i = iter.count() + if word.is_empty() { 1 } else { 0 };
         if i == 0 {
             // Completing chat command name
-            complete_command(word)
+            complete_command(word, client)
         } else if let Ok(cmd) = cmd.parse::<ChatCommand>() {
             if let Some(arg) = cmd.data().args.get(i - 1) {
                 // Complete ith argument
@@ -115,6 +117,16 @@ pub fn complete(line: &str, client: &Client) -> Vec<String> {
                     _ => vec![], // End of command. Nothing to complete
                 }
             }
+        } else if let Some(cmd) = client
+            .server_commands
+            .iter()
+            .find(|c| Some(c.keyword.as_str()) == cmd.strip_prefix('/'))
+        {
+            match cmd.args.get(i - 1).or_else(|| cmd.args.last()) {
+                Some(ArgumentSpec::Message(_)) => complete_player(word, client),
+                Some(arg) if i <= cmd.args.len() => arg.complete(word, client),
+                _ => vec![], // End of command. Nothing to complete
+            }
         } else {
             // Completing for unknown chat command
             complete_player(word, client)
@@ -1,4 +1,5 @@
 use authc::AuthClientError;
+use common_net::msg::RegisterError;
 pub use network::{InitProtocolError, NetworkConnectError, NetworkError};
 use network::{ParticipantError, StreamError};
 use specs::error::Error as SpecsError;
@@ -21,6 +22,8 @@ pub enum Error {
     Banned(String),
     /// Persisted character data is invalid or missing
     InvalidCharacter,
+    /// The server no longer keeps the character to resume the session of
+    SessionExpired,
     //TODO: InvalidAlias,
     Other(String),
     SpecsErr(SpecsError),
@@ -45,3 +48,17 @@ impl From<StreamError> for Error {
 impl From<AuthClientError> for Error {
     fn from(err: AuthClientError) -> Self { Self::AuthClientError(err) }
 }
+
+impl From<RegisterError> for Error {
+    fn from(err: RegisterError) -> Self {
+        match err {
+            RegisterError::AuthError(err) => Self::AuthErr(err),
+            RegisterError::InvalidCharacter => Self::InvalidCharacter,
+            RegisterError::NotOnWhitelist => Self::NotOnWhitelist,
+            RegisterError::Kicked(err) => Self::Kicked(err),
+            RegisterError::Banned(reason) => Self::Banned(reason),
+            RegisterError::SessionExpired => Self::SessionExpired,
+            RegisterError::TooManyPlayers => Self::TooManyPlayers,
+        }
+    }
+}
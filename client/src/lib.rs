@@ -5,21 +5,28 @@
 pub mod addr;
 pub mod cmd;
 pub mod error;
+pub mod query;
+mod reconnect;
+pub mod tls;
 
 // Reexports
 pub use crate::error::Error;
 pub use authc::AuthClientError;
-pub use common_net::msg::ServerInfo;
+pub use common_net::msg::{ServerInfo, ServerStatus};
 pub use specs::{
     join::Join,
     saveload::{Marker, MarkerAllocator},
     Builder, DispatcherBuilder, Entity as EcsEntity, ReadStorage, World, WorldExt,
 };
 
-use crate::addr::ConnectionArgs;
+use crate::{
+    addr::ConnectionArgs,
+    reconnect::{Connection, Reconnect},
+};
 use byteorder::{ByteOrder, LittleEndian};
 use common::{
     character::{CharacterId, CharacterItem},
+    cmd::CommandSpec,
     comp::{
         self,
         chat::{KillSource, KillType},
@@ -54,7 +61,7 @@ use common_net::{
         world_msg::{EconomyInfo, PoiInfo, SiteId, SiteInfo},
         ChatMsgValidationError, ClientGeneral, ClientMsg, ClientRegister, ClientType,
         DisconnectReason, InviteAnswer, Notification, PingMsg, PlayerInfo, PlayerListUpdate,
-        PresenceKind, RegisterError, ServerGeneral, ServerInit, ServerRegisterAnswer,
+        PresenceKind, ServerGeneral, ServerInit, ServerRegisterAnswer, SessionToken,
         MAX_BYTES_CHAT_MSG,
     },
     sync::WorldSyncExt,
@@ -64,7 +71,7 @@ use common_systems::add_local_systems;
 use comp::BuffKind;
 use hashbrown::{HashMap, HashSet};
 use image::DynamicImage;
-use network::{ConnectAddr, Network, Participant, Pid, Stream};
+use network::{Network, Participant, Pid, Stream};
 use num::traits::FloatConst;
 use rayon::prelude::*;
 use specs::Component;
@@ -75,7 +82,7 @@ use std::{
     time::{Duration, Instant},
 };
 use tokio::runtime::Runtime;
-use tracing::{debug, error, trace, warn};
+use tracing::{debug, error, info, trace, warn};
 use vek::*;
 
 #[cfg(feature = "tracy")]
@@ -164,6 +171,8 @@ pub struct Client {
     server_info: ServerInfo,
     world_data: WorldData,
     player_list: HashMap<Uid, PlayerInfo>,
+    /// Commands declared by the server in addition to the built-in ones
+    server_commands: Vec<CommandSpec>,
     character_list: CharacterList,
     sites: HashMap<SiteId, SiteInfoRich>,
     pois: Vec<PoiInfo>,
@@ -182,7 +191,8 @@ pub struct Client {
     // The pending trade the client is involved in, and it's id
     pending_trade: Option<(TradeId, PendingTrade, Option<SitePrices>)>,
 
-    network: Option<Network>,
+    addr: ConnectionArgs,
+    network: Option<Arc<Network>>,
     participant: Option<Participant>,
     general_stream: Stream,
     ping_stream: Stream,
@@ -190,6 +200,10 @@ pub struct Client {
     character_screen_stream: Stream,
     in_game_stream: Stream,
     terrain_stream: Stream,
+    /// Token to resume the session with after losing the connection, and how
+    /// long the server keeps the character for
+    session: Option<(SessionToken, Duration)>,
+    reconnect: Option<Reconnect>,
 
     client_timeout: Duration,
     last_server_ping: f64,
@@ -223,29 +237,9 @@ impl Client {
         // TODO: refactor to avoid needing to use this out parameter
         mismatched_server_info: &mut Option<ServerInfo>,
     ) -> Result<Self, Error> {
-        let network = Network::new(Pid::new(), &runtime);
+        let network = Arc::new(Network::new(Pid::new(), &runtime));
 
-        let participant = match addr {
-            ConnectionArgs::Tcp {
-                hostname,
-                prefer_ipv6,
-            } => addr::try_connect(&network, &hostname, prefer_ipv6, ConnectAddr::Tcp).await?,
-            ConnectionArgs::Quic {
-                hostname,
-                prefer_ipv6,
-            } => {
-                warn!(
-                    "QUIC is enabled. This is experimental and you won't be able to connect to \
-                     TCP servers unless deactivated"
-                );
-                let config = quinn::ClientConfig::with_native_roots();
-                addr::try_connect(&network, &hostname, prefer_ipv6, |a| {
-                    ConnectAddr::Quic(a, config.clone(), hostname.clone())
-                })
-                .await?
-            },
-            ConnectionArgs::Mpsc(id) => network.connect(ConnectAddr::Mpsc(id)).await?,
-        };
+        let participant = addr::connect(&network, &addr).await?;
 
         let stream = participant.opened().await?;
         let mut ping_stream = participant.opened().await?;
@@ -608,6 +602,7 @@ impl Client {
                 map: world_map,
             },
             player_list: HashMap::new(),
+            server_commands: Vec::new(),
             character_list: CharacterList::default(),
             sites: sites
                 .iter()
@@ -630,6 +625,7 @@ impl Client {
             pending_invites: HashSet::new(),
             pending_trade: None,
 
+            addr,
             network: Some(network),
             participant: Some(participant),
             general_stream: stream,
@@ -638,6 +634,8 @@ impl Client {
             character_screen_stream,
             in_game_stream,
             terrain_stream,
+            session: None,
+            reconnect: None,
 
             client_timeout,
 
@@ -694,14 +692,13 @@ impl Client {
             None => Ok(username),
         }?;
 
-        self.send_msg_err(ClientRegister { token_or_username })?;
+        self.send_msg_err(ClientRegister {
+            token_or_username,
+            session: None,
+        })?;
 
         match self.register_stream.recv::<ServerRegisterAnswer>().await? {
-            Err(RegisterError::AuthError(err)) => Err(Error::AuthErr(err)),
-            Err(RegisterError::InvalidCharacter) => Err(Error::InvalidCharacter),
-            Err(RegisterError::NotOnWhitelist) => Err(Error::NotOnWhitelist),
-            Err(RegisterError::Kicked(err)) => Err(Error::Kicked(err)),
-            Err(RegisterError::Banned(reason)) => Err(Error::Banned(reason)),
+            Err(e) => Err(e.into()),
             Ok(()) => {
                 self.registered = true;
                 Ok(())
@@ -777,6 +774,7 @@ impl Client {
                     //Always possible
                     ClientGeneral::ChatMsg(_)
                     | ClientGeneral::Command(_, _)
+                    | ClientGeneral::ReportPlayer { .. }
                     | ClientGeneral::Terminate => &mut self.general_stream,
                 };
                 #[cfg(feature = "tracy")]
@@ -1388,6 +1386,11 @@ impl Client {
         self.send_msg(ClientGeneral::Command(name, args));
     }
 
+    /// Report a player, by username, to the moderators of the server.
+    pub fn report_player(&mut self, player: String, reason: String) {
+        self.send_msg(ClientGeneral::ReportPlayer { player, reason });
+    }
+
     /// Remove all cached terrain
     pub fn clear_terrain(&mut self) {
         self.state.clear_terrain();
@@ -1439,11 +1442,120 @@ impl Client {
 
     /// Execute a single client tick, handle input and update the game state by
     /// the given duration.
+    ///
+    /// If the connection to the server is lost while ingame, the client tries
+    /// to resume its session in the background, the game state isn't updated
+    /// until it is resumed. See [`Client::is_reconnecting`].
     pub fn tick(
         &mut self,
         inputs: ControllerInputs,
         dt: Duration,
         add_foreign_systems: impl Fn(&mut DispatcherBuilder),
+    ) -> Result<Vec<Event>, Error> {
+        if let Some(reconnect) = &self.reconnect {
+            match reconnect.poll() {
+                None => return Ok(Vec::new()),
+                Some(result) => {
+                    self.reconnect = None;
+                    self.resume(result?);
+                },
+            }
+        }
+
+        match self.tick_connected(inputs, dt, add_foreign_systems) {
+            Err(e) if self.start_reconnect(&e) => Ok(Vec::new()),
+            result => result,
+        }
+    }
+
+    /// Whether the connection was lost and the client is trying to resume its
+    /// session
+    pub fn is_reconnecting(&self) -> bool { self.reconnect.is_some() }
+
+    /// Starts resuming the session if `e` means the connection was lost
+    fn start_reconnect(&mut self, e: &Error) -> bool {
+        let connection_lost = matches!(
+            e,
+            Error::NetworkErr(_)
+                | Error::ParticipantErr(_)
+                | Error::StreamErr(_)
+                | Error::ServerTimeout
+        );
+        let (token, grace_period, network) = match (self.session, &self.network) {
+            (Some((token, grace_period)), Some(network))
+                if connection_lost && self.presence.is_some() =>
+            {
+                (token, grace_period, Arc::clone(network))
+            },
+            _ => return false,
+        };
+        warn!(?e, "Lost connection to the server");
+
+        if let Some(participant) = self.participant.take() {
+            self.runtime.spawn(async move {
+                if let Err(e) = participant.disconnect().await {
+                    debug!(?e, "Error when disconnecting the lost connection");
+                }
+            });
+        }
+        self.reconnect = Some(Reconnect::start(
+            &self.runtime,
+            network,
+            self.addr.clone(),
+            token,
+            grace_period,
+        ));
+        true
+    }
+
+    /// Continues the session on the resumed connection. The server sends the
+    /// world around the player again, so everything else is dropped.
+    fn resume(&mut self, connection: Connection) {
+        info!("Resumed session");
+        let Connection {
+            participant,
+            general_stream,
+            ping_stream,
+            register_stream,
+            character_screen_stream,
+            in_game_stream,
+            terrain_stream,
+        } = connection;
+        self.participant = Some(participant);
+        self.general_stream = general_stream;
+        self.ping_stream = ping_stream;
+        self.register_stream = register_stream;
+        self.character_screen_stream = character_screen_stream;
+        self.in_game_stream = in_game_stream;
+        self.terrain_stream = terrain_stream;
+
+        let player = self.entity();
+        let others = (
+            &self.state.ecs().entities(),
+            &self.state.ecs().read_storage::<Uid>(),
+        )
+            .join()
+            .filter(|(entity, _)| *entity != player)
+            .map(|(_, uid)| uid.0)
+            .collect::<Vec<_>>();
+        for uid in others {
+            self.state
+                .ecs_mut()
+                .delete_entity_and_clear_from_uid_allocator(uid);
+        }
+
+        // The trade was cancelled when the connection was lost
+        self.pending_trade = None;
+        self.pending_chunks.clear();
+        self.last_server_ping = self.state.get_time();
+        self.last_server_pong = self.state.get_time();
+    }
+
+    fn tick_connected(
+        &mut self,
+        inputs: ControllerInputs,
+        dt: Duration,
+        add_foreign_systems: impl Fn(&mut DispatcherBuilder),
     ) -> Result<Vec<Event>, Error> {
         span!(_guard, "tick", "Client::tick");
         // This tick function is the centre of the Veloren universe. Most client-side
@@ -1820,6 +1932,7 @@ impl Client {
             ServerGeneral::ChatMode(m) => {
                 self.chat_mode = m;
             },
+            ServerGeneral::ServerCommands(commands) => self.server_commands = commands,
             ServerGeneral::SetPlayerEntity(uid) => {
                 if let Some(entity) = self.state.ecs().entity_from_uid(uid.0) {
                     *self.state.ecs_mut().write_resource() = PlayerEntity(Some(entity));
@@ -1970,6 +2083,7 @@ impl Client {
             // Cleanup for when the client goes back to the `presence = None`
             ServerGeneral::ExitInGameSuccess => {
                 self.presence = None;
+                self.session = None;
                 self.clean_state();
             },
             ServerGeneral::InventoryUpdate(inventory, event) => {
@@ -2032,6 +2146,12 @@ impl Client {
                     rich.economy = Some(economy);
                 }
             },
+            ServerGeneral::SessionToken {
+                token,
+                grace_period,
+            } => {
+                self.session = Some((token, grace_period));
+            },
             _ => unreachable!("Not a in_game message"),
         }
         Ok(())
@@ -2585,11 +2705,13 @@ impl Drop for Client {
         }
 
         tokio::task::block_in_place(|| {
-            if let Err(e) = self
-                .runtime
-                .block_on(self.participant.take().unwrap().disconnect())
-            {
-                warn!(?e, "error when disconnecting, couldn't send all data");
+            if let Some(reconnect) = self.reconnect.take() {
+                reconnect.cancel(&self.runtime);
+            }
+            if let Some(participant) = self.participant.take() {
+                if let Err(e) = self.runtime.block_on(participant.disconnect()) {
+                    warn!(?e, "error when disconnecting, couldn't send all data");
+                }
             }
         });
         //explicitly drop the network here while the runtime is still existing
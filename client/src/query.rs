@@ -0,0 +1,94 @@
+//! Queries the status of a server without connecting to it, see
+//! [`common_net::msg::query`].
+
+use crate::error::Error;
+use common_net::msg::query::{
+    decode_query_packet, encode_query_packet, QueryRequest, QueryResponse, ServerStatus,
+    MAX_QUERY_PACKET_SIZE, QUERY_VERSION,
+};
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+use tokio::net::UdpSocket;
+use tracing::debug;
+
+/// Port servers answer status queries on by default
+pub const DEFAULT_QUERY_PORT: u16 = 14006;
+/// Queries are sent up to this many times within the timeout, as UDP packets
+/// may get lost
+const ATTEMPTS: u32 = 3;
+
+/// Queries the status of the server at `hostname` ((hostname|ip):[<port>]),
+/// with the names of the online players if `include_players` is set and the
+/// server lists them.
+pub async fn query_status(
+    hostname: &str,
+    include_players: bool,
+    timeout: Duration,
+) -> Result<ServerStatus, Error> {
+    let addr = crate::addr::resolve_with_port(hostname, false, DEFAULT_QUERY_PORT)
+        .await
+        .map_err(Error::HostnameLookupFailed)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::Other("No Ip Addr provided".to_string()))?;
+    let local: SocketAddr = if addr.is_ipv6() {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(local)
+        .await
+        .map_err(|e| Error::Other(e.to_string()))?;
+    socket
+        .connect(addr)
+        .await
+        .map_err(|e| Error::Other(e.to_string()))?;
+
+    let query = async {
+        let mut buf = vec![0; MAX_QUERY_PACKET_SIZE];
+        // The server first answers with a challenge to send the query again with
+        let mut challenge = None;
+        loop {
+            let request = encode_query_packet(&QueryRequest {
+                version: QUERY_VERSION,
+                include_players,
+                challenge,
+            })
+            .map_err(|e| Error::Other(e.to_string()))?;
+            socket
+                .send(&request)
+                .await
+                .map_err(|e| Error::Other(e.to_string()))?;
+            let response = tokio::time::timeout(timeout / ATTEMPTS, async {
+                loop {
+                    match socket.recv(&mut buf).await {
+                        Ok(len) => {
+                            if let Some(response) = decode_query_packet(&buf[..len]) {
+                                break response;
+                            }
+                        },
+                        // e.g. the port being closed is reported on the next receive
+                        Err(e) => debug!(?e, "Failed to receive status query answer"),
+                    }
+                }
+            })
+            .await;
+            match response {
+                Ok(QueryResponse::Status(status)) => return Ok(*status),
+                Ok(QueryResponse::Challenge(new_challenge)) => challenge = Some(new_challenge),
+                Ok(QueryResponse::UnsupportedVersion(version)) => {
+                    return Err(Error::Other(format!(
+                        "Server speaks version {} of the query protocol, expected {}",
+                        version, QUERY_VERSION
+                    )));
+                },
+                Err(_) => debug!(?addr, "Status query timed out, retrying"),
+            }
+        }
+    };
+    tokio::time::timeout(timeout, query)
+        .await
+        .unwrap_or(Err(Error::ServerTimeout))
+}
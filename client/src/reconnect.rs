@@ -0,0 +1,142 @@
+use crate::{addr::ConnectionArgs, error::Error};
+use common_net::msg::{
+    ClientRegister, ClientType, PingMsg, ServerInfo, ServerInit, ServerRegisterAnswer, SessionToken,
+};
+use network::{Network, Participant, Stream};
+use std::{
+    sync::{mpsc, Arc},
+    time::{Duration, Instant},
+};
+use tokio::{runtime::Runtime, task::JoinHandle};
+use tracing::{debug, info};
+
+/// Time between two attempts to reconnect
+const RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Participant and streams of a connection whose session was resumed
+pub(crate) struct Connection {
+    pub participant: Participant,
+    pub general_stream: Stream,
+    pub ping_stream: Stream,
+    pub register_stream: Stream,
+    pub character_screen_stream: Stream,
+    pub in_game_stream: Stream,
+    pub terrain_stream: Stream,
+}
+
+/// Attempts to resume the session in the background until it succeeds or the
+/// server no longer keeps the character
+pub(crate) struct Reconnect {
+    result: mpsc::Receiver<Result<Connection, Error>>,
+    task: JoinHandle<()>,
+}
+
+impl Reconnect {
+    pub(crate) fn start(
+        runtime: &Runtime,
+        network: Arc<Network>,
+        addr: ConnectionArgs,
+        token: SessionToken,
+        grace_period: Duration,
+    ) -> Self {
+        info!(?grace_period, "Connection lost, reconnecting");
+        let deadline = Instant::now() + grace_period;
+        let (sender, result) = mpsc::channel();
+        let task = runtime.spawn(async move {
+            let _ = sender.send(reconnect(&network, &addr, token, deadline).await);
+        });
+        Self { result, task }
+    }
+
+    /// The resumed connection, or why it couldn't be resumed, once done
+    pub(crate) fn poll(&self) -> Option<Result<Connection, Error>> {
+        match self.result.try_recv() {
+            Ok(result) => Some(result),
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                Some(Err(Error::Other("Reconnecting was aborted".to_owned())))
+            },
+        }
+    }
+
+    /// Stops reconnecting, the task's reference to the `Network` is dropped
+    /// once this returns
+    pub(crate) fn cancel(self, runtime: &Runtime) {
+        self.task.abort();
+        let _ = runtime.block_on(self.task);
+    }
+}
+
+async fn reconnect(
+    network: &Network,
+    addr: &ConnectionArgs,
+    token: SessionToken,
+    deadline: Instant,
+) -> Result<Connection, Error> {
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let err = match tokio::time::timeout(remaining, resume(network, addr, token)).await {
+            Ok(Ok(connection)) => return Ok(connection),
+            // Retrying won't help once the server refused the player
+            Ok(Err(e @ (Error::SessionExpired | Error::Banned(_) | Error::NotOnWhitelist))) => {
+                return Err(e);
+            },
+            Ok(Err(e)) => e,
+            Err(_) => Error::SessionExpired,
+        };
+        if Instant::now() + RETRY_INTERVAL >= deadline {
+            return Err(err);
+        }
+        debug!(?err, "Reconnecting failed, retrying");
+        tokio::time::sleep(RETRY_INTERVAL).await;
+    }
+}
+
+/// Connects like `Client::new` does, and registers with the session token
+/// instead of logging in
+async fn resume(
+    network: &Network,
+    addr: &ConnectionArgs,
+    token: SessionToken,
+) -> Result<Connection, Error> {
+    let participant = crate::addr::connect(network, addr).await?;
+
+    let general_stream = participant.opened().await?;
+    let mut ping_stream = participant.opened().await?;
+    let mut register_stream = participant.opened().await?;
+    let character_screen_stream = participant.opened().await?;
+    let in_game_stream = participant.opened().await?;
+    let terrain_stream = participant.opened().await?;
+
+    register_stream.send(ClientType::Game)?;
+    let _: ServerInfo = register_stream.recv().await?;
+    ping_stream.send(PingMsg::Ping)?;
+
+    // The world is already known, the initial sync is only waited for
+    let mut ping_interval = tokio::time::interval(Duration::from_secs(1));
+    match loop {
+        tokio::select! {
+            res = register_stream.recv() => break res?,
+            _ = ping_interval.tick() => ping_stream.send(PingMsg::Ping)?,
+        }
+    } {
+        ServerInit::GameSync { .. } => {},
+        ServerInit::TooManyPlayers => return Err(Error::TooManyPlayers),
+    }
+
+    register_stream.send(ClientRegister {
+        token_or_username: String::new(),
+        session: Some(token),
+    })?;
+    register_stream.recv::<ServerRegisterAnswer>().await??;
+
+    Ok(Connection {
+        participant,
+        general_stream,
+        ping_stream,
+        register_stream,
+        character_screen_stream,
+        in_game_stream,
+        terrain_stream,
+    })
+}
@@ -0,0 +1,300 @@
+use hashbrown::HashMap;
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    Certificate, ClientConfig, RootCertStore, ServerName,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+use tracing::{info, warn};
+
+/// SHA-256 fingerprints of server certificates, or [`CA_VERIFIED`], by the
+/// hostname they were first seen on
+pub type PinnedCerts = Arc<Mutex<HashMap<String, String>>>;
+
+/// Pinned instead of a fingerprint for hostnames whose certificate was verified
+/// by a CA, the certificates of these are never pinned afterwards so a self
+/// signed one can't replace it.
+pub const CA_VERIFIED: &str = "ca-verified";
+
+/// The only error rustls passes on for a certificate whose issuer is unknown
+const UNKNOWN_ISSUER: &str = "invalid peer certificate: UnknownIssuer";
+
+/// Servers only known by their ip can't present a certificate for it, their
+/// certificate gets pinned instead.
+const IP_SERVER_NAME: &str = "localhost";
+
+/// Trusts certificates signed by a root certificate of the OS. Certificates
+/// sent without a CA chain whose issuer is unknown, i.e. self signed ones, are
+/// only trusted if they match the one pinned for the hostname, the first one
+/// seen is pinned (trust on first use) unless the hostname was verified by a CA
+/// before. Every other validation error is returned as is.
+struct PinningVerifier {
+    webpki: WebPkiVerifier,
+    hostname: String,
+    pinned_certs: Option<PinnedCerts>,
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.webpki.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        );
+        let pinned_certs = match &self.pinned_certs {
+            Some(pinned_certs) => pinned_certs,
+            None => return verified,
+        };
+        let pinnable = matches!(&verified, Err(e) if may_pin(intermediates, e));
+        let mut pinned_certs = pinned_certs
+            .lock()
+            .map_err(|_| rustls::Error::General("pinned certificates poisoned".to_owned()))?;
+        pin(
+            &mut pinned_certs,
+            &self.hostname,
+            end_entity,
+            verified,
+            pinnable,
+        )
+    }
+}
+
+/// Checks the certificate of `hostname` against the one pinned for it, if it
+/// couldn't be verified and `may_pin` it. Hostnames verified by a CA are
+/// remembered.
+fn pin(
+    pinned_certs: &mut HashMap<String, String>,
+    hostname: &str,
+    end_entity: &Certificate,
+    verified: Result<ServerCertVerified, rustls::Error>,
+    may_pin: bool,
+) -> Result<ServerCertVerified, rustls::Error> {
+    let error = match verified {
+        Ok(verified) => {
+            if pinned_certs.get(hostname).map(String::as_str) != Some(CA_VERIFIED) {
+                info!(?hostname, "Server certificate is verified by a CA");
+                pinned_certs.insert(hostname.to_owned(), CA_VERIFIED.to_owned());
+            }
+            return Ok(verified);
+        },
+        Err(e) if !may_pin => return Err(e),
+        Err(e) => e,
+    };
+    let fingerprint = fingerprint(end_entity);
+    match pinned_certs.get(hostname) {
+        Some(pinned) if pinned == CA_VERIFIED => {
+            warn!(
+                ?hostname,
+                ?fingerprint,
+                "Server certificate isn't verified by a CA anymore"
+            );
+            Err(error)
+        },
+        Some(pinned) if *pinned == fingerprint => Ok(ServerCertVerified::assertion()),
+        Some(pinned) => {
+            warn!(
+                ?hostname,
+                ?pinned,
+                ?fingerprint,
+                "Server certificate doesn't match the pinned one"
+            );
+            Err(rustls::Error::InvalidCertificateData(
+                "certificate doesn't match the pinned one".to_owned(),
+            ))
+        },
+        None => {
+            info!(?hostname, ?fingerprint, "Pinning server certificate");
+            pinned_certs.insert(hostname.to_owned(), fingerprint);
+            Ok(ServerCertVerified::assertion())
+        },
+    }
+}
+
+/// Only certificates without a CA chain are pinned, a certificate which is
+/// expired, for another name or signed by an untrusted CA stays rejected.
+fn may_pin(intermediates: &[Certificate], error: &rustls::Error) -> bool {
+    // rustls 0.20.1 (`pki_error` in verify.rs) only passes on the webpki errors
+    // about the encoding or signature as their own variant, every other one is
+    // `InvalidCertificateData(format!("invalid peer certificate: {}", e))`, and
+    // the Display of webpki 0.22 errors is their Debug. Check this again when
+    // updating either of them.
+    intermediates.is_empty()
+        && matches!(
+            error,
+            rustls::Error::InvalidCertificateData(e) if e == UNKNOWN_ISSUER
+        )
+}
+
+fn fingerprint(cert: &Certificate) -> String {
+    Sha256::digest(&cert.0)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Config verifying the server with the OS root certificates. If
+/// `pinned_certs` are given, unverifiable certificates are pinned on first use
+/// instead of rejected.
+pub(crate) fn client_config(
+    hostname: &str,
+    pinned_certs: Option<PinnedCerts>,
+) -> Arc<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+    match rustls_native_certs::load_native_certs() {
+        Ok(certs) => {
+            let certs = certs.into_iter().map(|c| c.0).collect::<Vec<_>>();
+            roots.add_parsable_certificates(&certs);
+        },
+        Err(e) => warn!(?e, "Failed to load root certificates"),
+    }
+    let verifier = PinningVerifier {
+        webpki: WebPkiVerifier::new(roots, None),
+        hostname: hostname.to_owned(),
+        pinned_certs,
+    };
+    Arc::new(
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth(),
+    )
+}
+
+/// Name the server certificate is verified for: (hostname|ip):[<port>]
+/// without the port
+pub(crate) fn server_name(hostname: &str) -> String {
+    if hostname.parse::<SocketAddr>().is_ok() || hostname.parse::<IpAddr>().is_ok() {
+        return IP_SERVER_NAME.to_owned();
+    }
+    let name = match hostname.rsplit_once(':') {
+        Some((name, port)) if port.parse::<u16>().is_ok() => name,
+        _ => hostname,
+    };
+    if name.parse::<IpAddr>().is_ok() {
+        IP_SERVER_NAME.to_owned()
+    } else {
+        name.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_names() {
+        assert_eq!(server_name("server.veloren.net"), "server.veloren.net");
+        assert_eq!(
+            server_name("server.veloren.net:14004"),
+            "server.veloren.net"
+        );
+        assert_eq!(server_name("127.0.0.1"), IP_SERVER_NAME);
+        assert_eq!(server_name("127.0.0.1:14004"), IP_SERVER_NAME);
+        assert_eq!(server_name("[::1]:14004"), IP_SERVER_NAME);
+        assert_eq!(server_name("::1"), IP_SERVER_NAME);
+    }
+
+    #[test]
+    fn only_unknown_issuers_without_chain_are_pinned() {
+        let unknown_issuer = rustls::Error::InvalidCertificateData(
+            "invalid peer certificate: UnknownIssuer".to_owned(),
+        );
+        let expired = rustls::Error::InvalidCertificateData(
+            "invalid peer certificate: CertExpired".to_owned(),
+        );
+        let wrong_name = rustls::Error::InvalidCertificateData(
+            "invalid peer certificate: CertNotValidForName".to_owned(),
+        );
+        let chain = [Certificate(vec![0; 4])];
+
+        assert!(may_pin(&[], &unknown_issuer));
+        assert!(!may_pin(&chain, &unknown_issuer));
+        assert!(!may_pin(&[], &expired));
+        assert!(!may_pin(&[], &wrong_name));
+        assert!(!may_pin(&[], &rustls::Error::InvalidCertificateSignature));
+    }
+
+    #[test]
+    fn ca_verified_hosts_are_never_pinned() {
+        let unknown_issuer = || {
+            Err(rustls::Error::InvalidCertificateData(
+                "invalid peer certificate: UnknownIssuer".to_owned(),
+            ))
+        };
+        let ca_cert = Certificate(vec![1; 4]);
+        let self_signed = Certificate(vec![2; 4]);
+        let other_self_signed = Certificate(vec![3; 4]);
+        let mut pinned_certs = HashMap::new();
+
+        assert!(
+            pin(
+                &mut pinned_certs,
+                "ca.example",
+                &ca_cert,
+                Ok(ServerCertVerified::assertion()),
+                false
+            )
+            .is_ok()
+        );
+        assert_eq!(pinned_certs["ca.example"], CA_VERIFIED);
+        assert!(
+            pin(
+                &mut pinned_certs,
+                "ca.example",
+                &self_signed,
+                unknown_issuer(),
+                true
+            )
+            .is_err()
+        );
+        assert_eq!(pinned_certs["ca.example"], CA_VERIFIED);
+
+        // Self signed certificates are pinned on first use
+        assert!(
+            pin(
+                &mut pinned_certs,
+                "self.example",
+                &self_signed,
+                unknown_issuer(),
+                true
+            )
+            .is_ok()
+        );
+        assert!(
+            pin(
+                &mut pinned_certs,
+                "self.example",
+                &self_signed,
+                unknown_issuer(),
+                true
+            )
+            .is_ok()
+        );
+        assert!(
+            pin(
+                &mut pinned_certs,
+                "self.example",
+                &other_self_signed,
+                unknown_issuer(),
+                true
+            )
+            .is_err()
+        );
+        assert_eq!(pinned_certs["self.example"], fingerprint(&self_signed));
+    }
+}
@@ -41,6 +41,9 @@ pub enum ClientType {
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ClientRegister {
     pub token_or_username: String,
+    /// Resume the session of a character that lost its connection instead of
+    /// logging in anew
+    pub session: Option<super::SessionToken>,
 }
 
 /// Messages sent from the client to the server
@@ -85,6 +88,11 @@ pub enum ClientGeneral {
     //Always possible
     ChatMsg(String),
     Command(String, Vec<String>),
+    /// Report a player, by username, to the moderators of the server
+    ReportPlayer {
+        player: String,
+        reason: String,
+    },
     Terminate,
     RequestPlayerPhysics {
         server_authoritative: bool,
@@ -138,6 +146,7 @@ impl ClientMsg {
                         //Always possible
                         ClientGeneral::ChatMsg(_)
                         | ClientGeneral::Command(_, _)
+                        | ClientGeneral::ReportPlayer { .. }
                         | ClientGeneral::Terminate => true,
                     }
             },
@@ -1,6 +1,7 @@
 pub mod client;
 pub mod compression;
 pub mod ecs_packet;
+pub mod query;
 pub mod server;
 pub mod world_msg;
 
@@ -12,6 +13,7 @@ pub use self::{
         VoxelImageEncoding, WidePacking, WireChonk,
     },
     ecs_packet::EcsCompPacket,
+    query::{QueryRequest, QueryResponse, ServerStatus},
     server::{
         CharacterInfo, DisconnectReason, InviteAnswer, Notification, PlayerInfo, PlayerListUpdate,
         RegisterError, SerializedTerrainChunk, ServerGeneral, ServerInfo, ServerInit, ServerMsg,
@@ -34,6 +36,10 @@ pub enum PingMsg {
     Pong,
 }
 
+/// Lets a client resume its session after losing the connection, as long as
+/// the server still keeps its character around.
+pub type SessionToken = u128;
+
 pub const MAX_BYTES_CHAT_MSG: usize = 256;
 
 pub enum ChatMsgValidationError {
@@ -0,0 +1,71 @@
+//! Status queries of server browsers, answered over UDP without connecting to
+//! the server.
+//!
+//! Every packet starts with [`QUERY_MAGIC`], followed by the bincode encoded
+//! [`QueryRequest`] or [`QueryResponse`].
+//!
+//! The status is only sent to addresses which proved to receive the answers:
+//! a request without a valid challenge is answered with a
+//! [`QueryResponse::Challenge`], to be sent along with the request again.
+//! Otherwise the larger answers could be sent to spoofed addresses to flood
+//! them.
+
+use super::ServerInfo;
+use common::resources::BattleMode;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use vek::*;
+
+/// Identifies status query packets, other packets are ignored
+pub const QUERY_MAGIC: [u8; 4] = *b"VLRQ";
+/// Version of the query protocol, increased on incompatible changes
+pub const QUERY_VERSION: u16 = 0;
+/// Largest packet of the query protocol
+pub const MAX_QUERY_PACKET_SIZE: usize = 16384;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryRequest {
+    pub version: u16,
+    /// Whether the names of the online players should be listed, if the server
+    /// allows it
+    pub include_players: bool,
+    /// The challenge the server last answered with, if any
+    pub challenge: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum QueryResponse {
+    Status(Box<ServerStatus>),
+    /// The request has to be sent again with this challenge
+    Challenge(u64),
+    /// The server speaks another version of the query protocol
+    UnsupportedVersion(u16),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerStatus {
+    pub info: ServerInfo,
+    pub players_online: u32,
+    pub max_players: u32,
+    /// Size of the world in chunks
+    pub world_size: Vec2<u32>,
+    /// The battle mode of the server, or the default one if players can choose
+    pub battle_mode: BattleMode,
+    pub battle_mode_choosable: bool,
+    /// Aliases of the online players, if requested and the server lists them
+    pub players: Option<Vec<String>>,
+}
+
+/// Prefixes the message with [`QUERY_MAGIC`] and encodes it.
+pub fn encode_query_packet<M: Serialize>(msg: &M) -> Result<Vec<u8>, bincode::Error> {
+    let mut packet = QUERY_MAGIC.to_vec();
+    bincode::serialize_into(&mut packet, msg)?;
+    Ok(packet)
+}
+
+/// Decodes a packet encoded with [`encode_query_packet`], `None` if it isn't
+/// one.
+pub fn decode_query_packet<M: DeserializeOwned>(packet: &[u8]) -> Option<M> {
+    packet
+        .strip_prefix(&QUERY_MAGIC[..])
+        .and_then(|msg| bincode::deserialize(msg).ok())
+}
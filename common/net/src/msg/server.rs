@@ -6,6 +6,7 @@ use crate::sync;
 use common::{
     calendar::Calendar,
     character::{self, CharacterItem},
+    cmd::CommandSpec,
     comp::{self, invite::InviteKind, item::MaterialStatManifest},
     outcome::Outcome,
     recipe::RecipeBook,
@@ -176,6 +177,9 @@ pub enum ServerGeneral {
     /// formatting the message and turning it into a speech bubble.
     ChatMsg(comp::ChatMsg),
     ChatMode(comp::ChatMode),
+    /// Commands the server supports in addition to the built-in ones, e.g. the
+    /// ones declared by its plugins. Replaces the previous list.
+    ServerCommands(Vec<CommandSpec>),
     SetPlayerEntity(Uid),
     TimeOfDay(TimeOfDay, Calendar),
     EntitySync(sync::EntitySyncPackage),
@@ -189,6 +193,12 @@ pub enum ServerGeneral {
     FinishedTrade(TradeResult),
     /// Economic information about sites
     SiteEconomy(EconomyInfo),
+    /// Token to resume the session with if the connection is lost, the server
+    /// keeps the character around for `grace_period`
+    SessionToken {
+        token: super::SessionToken,
+        grace_period: Duration,
+    },
 }
 
 impl ServerGeneral {
@@ -256,6 +266,9 @@ pub enum RegisterError {
     Kicked(String),
     InvalidCharacter,
     NotOnWhitelist,
+    /// The session to resume is unknown or its grace period is over
+    SessionExpired,
+    TooManyPlayers,
     //TODO: InvalidAlias,
 }
 
@@ -298,13 +311,15 @@ impl ServerMsg {
                         | ServerGeneral::Knockback(_)
                         | ServerGeneral::UpdatePendingTrade(_, _, _)
                         | ServerGeneral::FinishedTrade(_)
-                        | ServerGeneral::SiteEconomy(_) => {
+                        | ServerGeneral::SiteEconomy(_)
+                        | ServerGeneral::SessionToken { .. } => {
                             c_type == ClientType::Game && presence.is_some()
                         },
                         // Always possible
                         ServerGeneral::PlayerListUpdate(_)
                         | ServerGeneral::ChatMsg(_)
                         | ServerGeneral::ChatMode(_)
+                        | ServerGeneral::ServerCommands(_)
                         | ServerGeneral::SetPlayerEntity(_)
                         | ServerGeneral::TimeOfDay(_, _)
                         | ServerGeneral::EntitySync(_)
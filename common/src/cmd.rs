@@ -47,6 +47,7 @@ pub enum ChatCommand {
     Alias,
     ApplyBuff,
     Ban,
+    BanIp,
     BattleMode,
     BattleModeForce,
     Build,
@@ -82,11 +83,15 @@ pub enum ChatCommand {
     MakeNpc,
     MakeSprite,
     Motd,
+    Mute,
     Object,
     PermitBuild,
     Players,
+    Plugin,
     Region,
     RemoveLights,
+    Report,
+    Reports,
     RevokeBuild,
     RevokeBuildAll,
     Safezone,
@@ -102,6 +107,8 @@ pub enum ChatCommand {
     Time,
     Tp,
     Unban,
+    UnbanIp,
+    Unmute,
     Version,
     Waypoint,
     Whitelist,
@@ -234,6 +241,18 @@ lazy_static! {
         .cloned()
         .collect();
 
+    static ref PLUGIN_ACTIONS: Vec<String> = ["list", "load", "reload", "unload"]
+        .iter()
+        .copied()
+        .map(Into::into)
+        .collect();
+
+    static ref REPORT_ACTIONS: Vec<String> = ["list", "show", "resolve"]
+        .iter()
+        .copied()
+        .map(Into::into)
+        .collect();
+
     static ref ROLES: Vec<String> = ["admin", "moderator"].iter().copied().map(Into::into).collect();
 
     /// List of item specifiers. Useful for tab completing
@@ -326,6 +345,18 @@ impl ChatCommand {
                  true for overwrite to alter an existing ban..",
                 Some(Moderator),
             ),
+            ChatCommand::BanIp => cmd(
+                vec![
+                    Any("address or player", Required),
+                    Boolean("overwrite", "true".to_string(), Optional),
+                    Any("ban duration", Optional),
+                    Message(Optional),
+                ],
+                "Ban an IP address, a range of addresses in CIDR notation (like 10.0.0.0/8) or \
+                 the address of an online player, for a given duration (if provided).  Pass true \
+                 for overwrite to alter an existing ban.",
+                Some(Moderator),
+            ),
             #[rustfmt::skip]
             ChatCommand::BattleMode => cmd(
                 vec![Enum(
@@ -513,6 +544,16 @@ impl ChatCommand {
                 Some(Admin),
             ),
             ChatCommand::Motd => cmd(vec![Message(Optional)], "View the server description", None),
+            ChatCommand::Mute => cmd(
+                vec![
+                    Any("username", Required),
+                    Any("mute duration", Optional),
+                    Message(Optional),
+                ],
+                "Prevent a player with a given username from chatting, for a given duration (if \
+                 provided).  Muting a player again replaces their existing mute.",
+                Some(Moderator),
+            ),
             ChatCommand::Object => cmd(
                 vec![Enum("object", OBJECTS.clone(), Required)],
                 "Spawn an object",
@@ -524,11 +565,35 @@ impl ChatCommand {
                 Some(Admin),
             ),
             ChatCommand::Players => cmd(vec![], "Lists players currently online", None),
+            ChatCommand::Plugin => cmd(
+                vec![
+                    Enum("action", PLUGIN_ACTIONS.clone(), Required),
+                    Any("plugin", Optional),
+                ],
+                "Lists the loaded plugins, or loads (by file name), reloads or unloads a plugin",
+                Some(Admin),
+            ),
             ChatCommand::RemoveLights => cmd(
                 vec![Float("radius", 20.0, Optional)],
                 "Removes all lights spawned by players",
                 Some(Admin),
             ),
+            ChatCommand::Report => cmd(
+                vec![PlayerName(Required), Message(Required)],
+                "Report a player breaking the rules to the moderators, along with what they said \
+                 recently",
+                None,
+            ),
+            ChatCommand::Reports => cmd(
+                vec![
+                    Enum("action", REPORT_ACTIONS.clone(), Required),
+                    Integer("report id", 1, Optional),
+                    Message(Optional),
+                ],
+                "Lists the open reports, shows a report with the chat attached to it, or resolves \
+                 a report with a note on what was done",
+                Some(Moderator),
+            ),
             ChatCommand::RevokeBuild => cmd(
                 vec![Any("area_name", Required)],
                 "Revokes build area permission for player",
@@ -622,6 +687,16 @@ impl ChatCommand {
                 "Remove the ban for the given username",
                 Some(Moderator),
             ),
+            ChatCommand::UnbanIp => cmd(
+                vec![Any("address", Required)],
+                "Remove the ban for the given IP address or range of addresses",
+                Some(Moderator),
+            ),
+            ChatCommand::Unmute => cmd(
+                vec![Any("username", Required)],
+                "Allow a muted player to chat again",
+                Some(Moderator),
+            ),
             ChatCommand::Version => cmd(vec![], "Prints server version", None),
             ChatCommand::Waypoint => cmd(
                 vec![],
@@ -651,6 +726,7 @@ impl ChatCommand {
             ChatCommand::Alias => "alias",
             ChatCommand::ApplyBuff => "buff",
             ChatCommand::Ban => "ban",
+            ChatCommand::BanIp => "ban_ip",
             ChatCommand::BattleMode => "battlemode",
             ChatCommand::BattleModeForce => "battlemode_force",
             ChatCommand::Build => "build",
@@ -686,11 +762,15 @@ impl ChatCommand {
             ChatCommand::MakeNpc => "make_npc",
             ChatCommand::MakeSprite => "make_sprite",
             ChatCommand::Motd => "motd",
+            ChatCommand::Mute => "mute",
             ChatCommand::Object => "object",
             ChatCommand::PermitBuild => "permit_build",
             ChatCommand::Players => "players",
+            ChatCommand::Plugin => "plugin",
             ChatCommand::Region => "region",
             ChatCommand::RemoveLights => "remove_lights",
+            ChatCommand::Report => "report",
+            ChatCommand::Reports => "reports",
             ChatCommand::RevokeBuild => "revoke_build",
             ChatCommand::RevokeBuildAll => "revoke_build_all",
             ChatCommand::Safezone => "safezone",
@@ -706,6 +786,8 @@ impl ChatCommand {
             ChatCommand::Time => "time",
             ChatCommand::Tp => "tp",
             ChatCommand::Unban => "unban",
+            ChatCommand::UnbanIp => "unban_ip",
+            ChatCommand::Unmute => "unmute",
             ChatCommand::Version => "version",
             ChatCommand::Waypoint => "waypoint",
             ChatCommand::Wiring => "wiring",
@@ -732,11 +814,7 @@ impl ChatCommand {
     /// A message that explains what the command does
     pub fn help_string(&self) -> String {
         let data = self.data();
-        let usage = std::iter::once(format!("/{}", self.keyword()))
-            .chain(data.args.iter().map(|arg| arg.usage_string()))
-            .collect::<Vec<_>>()
-            .join(" ");
-        format!("{}: {}", usage, data.description)
+        help_string(self.keyword(), &data.args, data.description)
     }
 
     /// A boolean that is used to check whether the command requires
@@ -797,28 +875,78 @@ impl FromStr for ChatCommand {
     }
 }
 
-#[derive(Eq, PartialEq, Debug)]
+/// A command that isn't known at compile time, such as the ones declared by
+/// server plugins. It is described the same way as a [`ChatCommand`] so it can
+/// show up in the help and the tab completion.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CommandSpec {
+    pub keyword: String,
+    /// A list of arguments useful for both tab completion and parsing
+    pub args: Vec<ArgumentSpec<String>>,
+    /// A one-line message that explains what the command does
+    pub description: String,
+    /// Whether the command requires administrator permissions.
+    pub needs_role: Option<Role>,
+}
+
+impl CommandSpec {
+    /// A message that explains what the command does
+    pub fn help_string(&self) -> String {
+        help_string(&self.keyword, &self.args, &self.description)
+    }
+
+    /// Checks that every required argument was given
+    pub fn check_args(&self, args: &[String]) -> Result<(), String> {
+        let required = self
+            .args
+            .iter()
+            .filter(|arg| arg.requirement() == Some(Requirement::Required))
+            .count();
+        if args.len() < required {
+            Err(format!("Missing arguments, usage:\n{}", self.help_string()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn help_string<S: AsRef<str>>(
+    keyword: &str,
+    args: &[ArgumentSpec<S>],
+    description: &str,
+) -> String {
+    let usage = std::iter::once(format!("/{}", keyword))
+        .chain(args.iter().map(|arg| arg.usage_string()))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!("{}: {}", usage, description)
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub enum Requirement {
     Required,
     Optional,
 }
 
-/// Representation for chat command arguments
-pub enum ArgumentSpec {
+/// Representation for chat command arguments, the labels and completions are
+/// `&'static str` for built-in commands and `String` for the ones declared at
+/// runtime
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ArgumentSpec<S = &'static str> {
     /// The argument refers to a player by alias
     PlayerName(Requirement),
     /// The argument is a float. The associated values are
     /// * label
     /// * suggested tab-completion
     /// * whether it's optional
-    Float(&'static str, f32, Requirement),
+    Float(S, f32, Requirement),
     /// The argument is an integer. The associated values are
     /// * label
     /// * suggested tab-completion
     /// * whether it's optional
-    Integer(&'static str, i32, Requirement),
+    Integer(S, i32, Requirement),
     /// The argument is any string that doesn't contain spaces
-    Any(&'static str, Requirement),
+    Any(S, Requirement),
     /// The argument is a command name (such as in /help)
     Command(Requirement),
     /// This is the final argument, consuming all characters until the end of
@@ -830,15 +958,29 @@ pub enum ArgumentSpec {
     /// * label
     /// * Predefined string completions
     /// * whether it's optional
-    Enum(&'static str, Vec<String>, Requirement),
+    Enum(S, Vec<String>, Requirement),
     /// The argument is likely a boolean. The associated values are
     /// * label
     /// * suggested tab-completion
     /// * whether it's optional
-    Boolean(&'static str, String, Requirement),
+    Boolean(S, String, Requirement),
 }
 
-impl ArgumentSpec {
+impl<S: AsRef<str>> ArgumentSpec<S> {
+    pub fn requirement(&self) -> Option<Requirement> {
+        match self {
+            ArgumentSpec::PlayerName(req)
+            | ArgumentSpec::Float(_, _, req)
+            | ArgumentSpec::Integer(_, _, req)
+            | ArgumentSpec::Any(_, req)
+            | ArgumentSpec::Command(req)
+            | ArgumentSpec::Message(req)
+            | ArgumentSpec::Enum(_, _, req)
+            | ArgumentSpec::Boolean(_, _, req) => Some(*req),
+            ArgumentSpec::SubCommand => None,
+        }
+    }
+
     pub fn usage_string(&self) -> String {
         match self {
             ArgumentSpec::PlayerName(req) => {
@@ -850,23 +992,23 @@ impl ArgumentSpec {
             },
             ArgumentSpec::Float(label, _, req) => {
                 if &Requirement::Required == req {
-                    format!("<{}>", label)
+                    format!("<{}>", label.as_ref())
                 } else {
-                    format!("[{}]", label)
+                    format!("[{}]", label.as_ref())
                 }
             },
             ArgumentSpec::Integer(label, _, req) => {
                 if &Requirement::Required == req {
-                    format!("<{}>", label)
+                    format!("<{}>", label.as_ref())
                 } else {
-                    format!("[{}]", label)
+                    format!("[{}]", label.as_ref())
                 }
             },
             ArgumentSpec::Any(label, req) => {
                 if &Requirement::Required == req {
-                    format!("<{}>", label)
+                    format!("<{}>", label.as_ref())
                 } else {
-                    format!("[{}]", label)
+                    format!("[{}]", label.as_ref())
                 }
             },
             ArgumentSpec::Command(req) => {
@@ -886,16 +1028,16 @@ impl ArgumentSpec {
             ArgumentSpec::SubCommand => "<[/]command> [args...]".to_string(),
             ArgumentSpec::Enum(label, _, req) => {
                 if &Requirement::Required == req {
-                    format!("<{}>", label)
+                    format!("<{}>", label.as_ref())
                 } else {
-                    format!("[{}]", label)
+                    format!("[{}]", label.as_ref())
                 }
             },
             ArgumentSpec::Boolean(label, _, req) => {
                 if &Requirement::Required == req {
-                    format!("<{}>", label)
+                    format!("<{}>", label.as_ref())
                 } else {
-                    format!("[{}]", label)
+                    format!("[{}]", label.as_ref())
                 }
             },
         }
@@ -919,4 +1061,27 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn command_spec_args() {
+        let spec = CommandSpec {
+            keyword: "warp".to_owned(),
+            args: vec![
+                ArgumentSpec::Any("destination".to_owned(), Requirement::Required),
+                ArgumentSpec::Float("speed".to_owned(), 1.0, Requirement::Optional),
+            ],
+            description: "Warps to a destination".to_owned(),
+            needs_role: None,
+        };
+        assert_eq!(
+            spec.help_string(),
+            "/warp <destination> [speed]: Warps to a destination"
+        );
+        assert!(spec.check_args(&[]).is_err());
+        assert!(spec.check_args(&["spawn".to_owned()]).is_ok());
+        assert!(
+            spec.check_args(&["spawn".to_owned(), "2.0".to_owned()])
+                .is_ok()
+        );
+    }
 }
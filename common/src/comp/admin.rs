@@ -1,9 +1,11 @@
 use clap::arg_enum;
+use hashbrown::HashSet;
+use serde::{Deserialize, Serialize};
 use specs::Component;
 use specs_idvs::IdvStorage;
 
 arg_enum! {
-    #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+    #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
     pub enum AdminRole {
         Moderator = 0,
         Admin = 1,
@@ -16,3 +18,25 @@ pub struct Admin(pub AdminRole);
 impl Component for Admin {
     type Storage = IdvStorage<Self>;
 }
+
+/// What a player can do besides what their role allows, as granted by the
+/// permission groups they are in.
+#[derive(Clone, Debug, Default)]
+pub struct Permissions {
+    /// Keywords of the commands the player can use
+    pub commands: HashSet<String>,
+    /// Whether the player can build in every build area
+    pub build: bool,
+    /// Whether safezones don't make the player invulnerable
+    pub bypass_safezone: bool,
+}
+
+impl Permissions {
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty() && !self.build && !self.bypass_safezone
+    }
+}
+
+impl Component for Permissions {
+    type Storage = IdvStorage<Self>;
+}
@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use vek::{Vec2, Vec3};
 
 use super::Item;
@@ -61,7 +62,7 @@ pub enum Subject {
 }
 
 /// Context of why a NPC has a specific mood (good, neutral, bad, ...)
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum MoodContext {
     /// The weather is good, sunny, appeasing, etc...
     GoodWeather,
@@ -81,7 +82,7 @@ pub enum MoodContext {
 
 // Note: You can add in-between states if needed
 /// NPC mood status indicator
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum MoodState {
     /// The NPC is happy!
     Good(MoodContext),
@@ -53,7 +53,7 @@ pub use self::{
         Ability, AbilityInput, ActiveAbilities, CharacterAbility, CharacterAbilityType,
         MAX_ABILITIES,
     },
-    admin::{Admin, AdminRole},
+    admin::{Admin, AdminRole, Permissions},
     agent::{Agent, Alignment, Behavior, BehaviorCapability, BehaviorState, PidController},
     anchor::Anchor,
     aura::{Aura, AuraChange, AuraKind, Auras},
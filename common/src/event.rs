@@ -121,6 +121,12 @@ pub enum ServerEvent {
     ExitIngame {
         entity: EcsEntity,
     },
+    /// Moves the client of `entity` to the `character` whose connection was
+    /// lost, resuming its session
+    ResumeSession {
+        entity: EcsEntity,
+        character: EcsEntity,
+    },
     // TODO: to avoid breakage when adding new fields, perhaps have an `NpcBuilder` type?
     CreateNpc {
         pos: comp::Pos,
@@ -149,6 +155,12 @@ pub enum ServerEvent {
     ClientDisconnect(EcsEntity, DisconnectReason),
     ClientDisconnectWithoutPersistence(EcsEntity),
     Command(EcsEntity, String, Vec<String>),
+    /// A player reports another player, by username, to the moderators
+    Report {
+        reporter: EcsEntity,
+        player: String,
+        reason: String,
+    },
     /// Send a chat message to the player from an npc or other player
     Chat(comp::UnresolvedChatMsg),
     Aura {
@@ -1,3 +1,4 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::{
     cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd},
     fmt, hash,
@@ -35,6 +36,16 @@ impl<T> fmt::Debug for Id<T> {
 impl<T> hash::Hash for Id<T> {
     fn hash<H: hash::Hasher>(&self, h: &mut H) { self.0.hash(h); }
 }
+impl<T> Serialize for Id<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+impl<'de, T> Deserialize<'de> for Id<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u64::deserialize(deserializer).map(|id| Id(id, PhantomData))
+    }
+}
 
 pub struct Store<T> {
     items: Vec<T>,
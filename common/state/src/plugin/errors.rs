@@ -1,5 +1,5 @@
 use bincode::ErrorKind;
-use wasmer::{ExportError, InstantiationError, RuntimeError};
+use wasmer::{CompileError, ExportError, InstantiationError, RuntimeError};
 
 #[derive(Debug)]
 pub enum PluginError {
@@ -13,11 +13,16 @@ pub enum PluginError {
 
 #[derive(Debug)]
 pub enum PluginModuleError {
+    Compile(CompileError),
     InstantiationError(InstantiationError),
     MemoryAllocation(MemoryAllocationError),
     MemoryUninit(ExportError),
     FindFunction(ExportError),
     RunFunction(RuntimeError),
+    /// The module executed more instructions than allowed for one event
+    OutOfFuel,
+    /// The module failed after its memory reached the size limit
+    MemoryLimit(RuntimeError),
     InvalidArgumentType(),
     Encoding(Box<ErrorKind>),
 }
@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+use std::{ptr::NonNull, sync::Arc};
+use wasmer::{
+    vm::{self, MemoryError, MemoryStyle, TableStyle, VMMemoryDefinition, VMTableDefinition},
+    MemoryType, Pages, TableType, Tunables,
+};
+
+/// Budget each plugin module has to stay within, a module exceeding it too
+/// many times is disabled until it is reloaded.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PluginLimits {
+    /// Number of wasm instructions a module can execute for each event
+    pub fuel_per_call: u64,
+    /// Maximum size of the memory of a module, in 64 KiB pages
+    pub max_memory_pages: u32,
+    /// Number of times a module can exceed its budget before being disabled
+    pub max_violations: u32,
+}
+
+impl Default for PluginLimits {
+    fn default() -> Self {
+        Self {
+            fuel_per_call: 50_000_000,
+            max_memory_pages: 1024,
+            max_violations: 3,
+        }
+    }
+}
+
+/// The budget a module exceeded
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LimitKind {
+    Fuel,
+    Memory,
+}
+
+impl LimitKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LimitKind::Fuel => "fuel",
+            LimitKind::Memory => "memory",
+        }
+    }
+}
+
+/// Tunables capping the maximum size of the memories created by a module,
+/// `memory.grow` fails once the cap is reached.
+pub struct LimitingTunables<T: Tunables> {
+    limit: Pages,
+    base: T,
+}
+
+impl<T: Tunables> LimitingTunables<T> {
+    pub fn new(base: T, limit: Pages) -> Self { Self { limit, base } }
+
+    /// Caps the maximum of a memory type to the limit
+    fn adjust_memory(&self, requested: &MemoryType) -> MemoryType {
+        let mut adjusted = *requested;
+        adjusted.maximum = Some(
+            requested
+                .maximum
+                .map_or(self.limit, |max| max.min(self.limit)),
+        );
+        adjusted
+    }
+
+    fn validate_memory(&self, ty: &MemoryType) -> Result<(), MemoryError> {
+        if ty.minimum > self.limit {
+            return Err(MemoryError::Generic(format!(
+                "Minimum of {} pages exceeds the limit of {} pages",
+                ty.minimum.0, self.limit.0
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl<T: Tunables> Tunables for LimitingTunables<T> {
+    fn memory_style(&self, memory: &MemoryType) -> MemoryStyle {
+        self.base.memory_style(&self.adjust_memory(memory))
+    }
+
+    fn table_style(&self, table: &TableType) -> TableStyle { self.base.table_style(table) }
+
+    fn create_host_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+    ) -> Result<Arc<dyn vm::Memory>, MemoryError> {
+        let adjusted = self.adjust_memory(ty);
+        self.validate_memory(&adjusted)?;
+        self.base.create_host_memory(&adjusted, style)
+    }
+
+    unsafe fn create_vm_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+        vm_definition_location: NonNull<VMMemoryDefinition>,
+    ) -> Result<Arc<dyn vm::Memory>, MemoryError> {
+        let adjusted = self.adjust_memory(ty);
+        self.validate_memory(&adjusted)?;
+        self.base
+            .create_vm_memory(&adjusted, style, vm_definition_location)
+    }
+
+    fn create_host_table(
+        &self,
+        ty: &TableType,
+        style: &TableStyle,
+    ) -> Result<Arc<dyn vm::Table>, String> {
+        self.base.create_host_table(ty, style)
+    }
+
+    unsafe fn create_vm_table(
+        &self,
+        ty: &TableType,
+        style: &TableStyle,
+        vm_definition_location: NonNull<VMTableDefinition>,
+    ) -> Result<Arc<dyn vm::Table>, String> {
+        self.base.create_vm_table(ty, style, vm_definition_location)
+    }
+}
@@ -2,23 +2,33 @@ use std::sync::atomic::{AtomicPtr, AtomicU32, AtomicU64, Ordering};
 
 use serde::{de::DeserializeOwned, Serialize};
 use specs::{
-    storage::GenericReadStorage, Component, Entities, Entity, Read, ReadStorage, WriteStorage,
+    storage::GenericReadStorage, Component, Entities, Entity, Read, ReadExpect, ReadStorage,
+    WriteStorage,
 };
 use wasmer::{Function, Memory, Value};
 
 use common::{
-    comp::{Health, Player},
+    comp::{Buffs, Health, Inventory, Player, Pos},
+    terrain::TerrainGrid,
     uid::{Uid, UidAllocator},
 };
 
-use super::errors::{MemoryAllocationError, PluginModuleError};
+use super::{
+    errors::{MemoryAllocationError, PluginModuleError},
+    storage::PluginStorage,
+};
 
 pub struct EcsWorld<'a, 'b> {
     pub entities: &'b Entities<'a>,
     pub health: EcsComponentAccess<'a, 'b, Health>,
     pub uid: EcsComponentAccess<'a, 'b, Uid>,
     pub player: EcsComponentAccess<'a, 'b, Player>,
+    pub pos: EcsComponentAccess<'a, 'b, Pos>,
+    pub inventory: EcsComponentAccess<'a, 'b, Inventory>,
+    pub buffs: EcsComponentAccess<'a, 'b, Buffs>,
     pub uid_allocator: &'b Read<'a, UidAllocator>,
+    pub terrain: &'b ReadExpect<'a, TerrainGrid>,
+    pub storage: &'b Read<'a, PluginStorage>,
 }
 
 pub enum EcsComponentAccess<'a, 'b, T: Component> {
@@ -1,9 +1,15 @@
 pub mod errors;
+pub mod limits;
 pub mod memory_manager;
 pub mod module;
+pub mod storage;
 pub mod wasm_env;
+pub mod watcher;
 
-use common::assets::ASSETS_PATH;
+use common::{
+    assets::ASSETS_PATH,
+    cmd::{ChatCommand, CommandSpec},
+};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, HashSet},
@@ -11,14 +17,16 @@ use std::{
     io::Read,
     path::{Path, PathBuf},
 };
-use tracing::{error, info};
+use tracing::{debug, error, info, warn};
 
-use plugin_api::Event;
+use plugin_api::{event::PluginCommandsEvent, Action, Event};
 
 use self::{
     errors::PluginError,
+    limits::{LimitKind, PluginLimits},
     memory_manager::EcsWorld,
     module::{PluginModule, PreparedEventQuery},
+    watcher::is_plugin_file,
 };
 
 use rayon::prelude::*;
@@ -36,10 +44,20 @@ pub struct Plugin {
     modules: Vec<PluginModule>,
     #[allow(dead_code)]
     files: HashMap<PathBuf, Vec<u8>>,
+    /// The file the plugin was loaded from, if any
+    path: Option<PathBuf>,
+    /// The chat commands declared by the plugin
+    commands: Vec<CommandSpec>,
 }
 
 impl Plugin {
-    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self, PluginError> {
+    pub fn from_path(path: &Path, limits: PluginLimits) -> Result<Self, PluginError> {
+        let mut plugin = Self::from_reader(fs::File::open(path).map_err(PluginError::Io)?, limits)?;
+        plugin.path = Some(path.to_path_buf());
+        Ok(plugin)
+    }
+
+    pub fn from_reader<R: Read>(mut reader: R, limits: PluginLimits) -> Result<Self, PluginError> {
         let mut buf = Vec::new();
         reader.read_to_end(&mut buf).map_err(PluginError::Io)?;
 
@@ -69,7 +87,7 @@ impl Plugin {
             .iter()
             .map(|path| {
                 let wasm_data = files.remove(path).ok_or(PluginError::NoSuchModule)?;
-                PluginModule::new(data.name.to_owned(), &wasm_data).map_err(|e| {
+                PluginModule::new(data.name.to_owned(), &wasm_data, limits).map_err(|e| {
                     PluginError::PluginModuleError(data.name.to_owned(), "<init>".to_owned(), e)
                 })
             })
@@ -79,9 +97,45 @@ impl Plugin {
             data,
             modules,
             files,
+            path: None,
+            commands: Vec::new(),
         })
     }
 
+    pub fn name(&self) -> &str { &self.data.name }
+
+    pub fn path(&self) -> Option<&Path> { self.path.as_deref() }
+
+    pub fn modules(&self) -> &[PluginModule] { &self.modules }
+
+    pub fn commands(&self) -> &[CommandSpec] { &self.commands }
+
+    /// Asks the plugin which chat commands it handles, commands clashing with
+    /// a built-in command are ignored.
+    pub fn load_commands(&mut self, ecs: &EcsWorld) -> Result<(), PluginError> {
+        let name = &self.data.name;
+        self.commands = self
+            .execute_event(ecs, &PluginCommandsEvent)?
+            .into_iter()
+            .flatten()
+            .filter(|command| {
+                let builtin = command.keyword.parse::<ChatCommand>().is_ok();
+                if builtin {
+                    warn!(
+                        "Plugin '{}' declared the built-in command '/{}', ignoring it",
+                        name, command.keyword
+                    );
+                }
+                !builtin
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// A plugin is disabled once one of its modules exceeded its budget too
+    /// many times, it stays disabled until it is reloaded.
+    pub fn is_disabled(&self) -> bool { self.modules.iter().any(PluginModule::is_disabled) }
+
     pub fn execute_prepared<T>(
         &self,
         ecs: &EcsWorld,
@@ -90,6 +144,9 @@ impl Plugin {
     where
         T: Event,
     {
+        if self.is_disabled() {
+            return Ok(Vec::new());
+        }
         self.modules
             .iter()
             .flat_map(|module| {
@@ -105,19 +162,130 @@ impl Plugin {
             })
             .collect::<Result<Vec<_>, _>>()
     }
+
+    pub fn execute_event<T>(
+        &self,
+        ecs: &EcsWorld,
+        event: &T,
+    ) -> Result<Vec<T::Response>, PluginError>
+    where
+        T: Event,
+    {
+        self.execute_prepared(ecs, &PreparedEventQuery::new(event)?)
+    }
+
+    pub fn drain_actions(&self) -> impl Iterator<Item = Action> + '_ {
+        self.modules
+            .iter()
+            .flat_map(|module| module.drain_actions())
+    }
+
+    pub fn drain_limit_violations(&self) -> impl Iterator<Item = LimitKind> + '_ {
+        self.modules
+            .iter()
+            .flat_map(|module| module.drain_limit_violations())
+    }
+
+    /// Tears the plugin down, dropping its wasm instances along with their
+    /// memory. Actions that were not applied yet are discarded.
+    pub fn unload(self) {
+        let discarded = self.drain_actions().count();
+        if discarded > 0 {
+            debug!(
+                "Discarded {} pending action(s) of plugin '{}'",
+                discarded, self.data.name
+            );
+        }
+        info!("Unloaded plugin '{}'", self.data.name);
+    }
 }
 
 #[derive(Clone, Default)]
 pub struct PluginMgr {
     plugins: Vec<Plugin>,
+    limits: PluginLimits,
 }
 
 impl PluginMgr {
-    pub fn from_assets() -> Result<Self, PluginError> {
+    /// The directory plugins are loaded from.
+    pub fn assets_dir() -> PathBuf {
         let mut assets_path = (&*ASSETS_PATH).clone();
         assets_path.push("plugins");
+        assets_path
+    }
+
+    /// A manager without plugins, loading them with the given budget.
+    pub fn new(limits: PluginLimits) -> Self {
+        Self {
+            plugins: Vec::new(),
+            limits,
+        }
+    }
+
+    pub fn from_assets(limits: PluginLimits) -> Result<Self, PluginError> {
+        let assets_path = Self::assets_dir();
         info!("Searching {:?} for plugins...", assets_path);
-        Self::from_dir(assets_path)
+        Self::from_dir(assets_path, limits)
+    }
+
+    /// The budget given to the modules of the plugins loaded by this manager.
+    pub fn limits(&self) -> PluginLimits { self.limits }
+
+    pub fn plugins(&self) -> impl Iterator<Item = &Plugin> { self.plugins.iter() }
+
+    pub fn get(&self, name: &str) -> Option<&Plugin> {
+        self.plugins.iter().find(|plugin| plugin.name() == name)
+    }
+
+    /// Finds the plugin loaded from the given file.
+    pub fn get_by_path(&self, path: &Path) -> Option<&Plugin> {
+        self.plugins
+            .iter()
+            .find(|plugin| plugin.path() == Some(path))
+    }
+
+    /// Adds a plugin, returns the plugin with the same name it replaces if
+    /// there was one.
+    pub fn insert(&mut self, plugin: Plugin) -> Option<Plugin> {
+        match self.plugins.iter_mut().find(|p| p.name() == plugin.name()) {
+            Some(old) => Some(std::mem::replace(old, plugin)),
+            None => {
+                self.plugins.push(plugin);
+                None
+            },
+        }
+    }
+
+    /// Asks every plugin which chat commands it handles.
+    pub fn load_commands(&mut self, ecs: &EcsWorld) {
+        for plugin in &mut self.plugins {
+            if let Err(e) = plugin.load_commands(ecs) {
+                error!(
+                    ?e,
+                    "Failed to load the commands of plugin '{}'",
+                    plugin.name()
+                );
+            }
+        }
+    }
+
+    /// All the chat commands declared by the loaded plugins.
+    pub fn commands(&self) -> impl Iterator<Item = &CommandSpec> {
+        self.plugins
+            .iter()
+            .filter(|plugin| !plugin.is_disabled())
+            .flat_map(|plugin| plugin.commands())
+    }
+
+    /// Finds the command with the given keyword among the ones declared by the
+    /// loaded plugins.
+    pub fn command(&self, keyword: &str) -> Option<&CommandSpec> {
+        self.commands().find(|command| command.keyword == keyword)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Plugin> {
+        let index = self.plugins.iter().position(|p| p.name() == name)?;
+        Some(self.plugins.remove(index))
     }
 
     pub fn execute_prepared<T>(
@@ -138,6 +306,33 @@ impl PluginMgr {
             .collect())
     }
 
+    /// Takes all the actions emitted by plugins since the last call that need
+    /// write access to the ECS, along with the name of the emitting plugin.
+    /// The server applies them at the start of each tick.
+    pub fn drain_actions(&self) -> Vec<(String, Action)> {
+        self.plugins
+            .iter()
+            .flat_map(|plugin| {
+                plugin
+                    .drain_actions()
+                    .map(move |action| (plugin.data.name.clone(), action))
+            })
+            .collect()
+    }
+
+    /// Takes the budgets exceeded by plugins since the last call, along with
+    /// the name of the plugin.
+    pub fn drain_limit_violations(&self) -> Vec<(String, LimitKind)> {
+        self.plugins
+            .iter()
+            .flat_map(|plugin| {
+                plugin
+                    .drain_limit_violations()
+                    .map(move |kind| (plugin.data.name.clone(), kind))
+            })
+            .collect()
+    }
+
     pub fn execute_event<T>(
         &self,
         ecs: &EcsWorld,
@@ -149,22 +344,16 @@ impl PluginMgr {
         self.execute_prepared(ecs, &PreparedEventQuery::new(event)?)
     }
 
-    pub fn from_dir<P: AsRef<Path>>(path: P) -> Result<Self, PluginError> {
+    pub fn from_dir<P: AsRef<Path>>(path: P, limits: PluginLimits) -> Result<Self, PluginError> {
         let plugins = fs::read_dir(path)
             .map_err(PluginError::Io)?
             .filter_map(|e| e.ok())
             .map(|entry| {
                 if entry.file_type().map(|ft| ft.is_file()).unwrap_or(false)
-                    && entry
-                        .path()
-                        .file_name()
-                        .and_then(|n| n.to_str())
-                        .map(|s| s.ends_with(".plugin.tar"))
-                        .unwrap_or(false)
+                    && is_plugin_file(&entry.path())
                 {
                     info!("Loading plugin at {:?}", entry.path());
-                    Plugin::from_reader(fs::File::open(entry.path()).map_err(PluginError::Io)?)
-                        .map(Some)
+                    Plugin::from_path(&entry.path(), limits).map(Some)
                 } else {
                     Ok(None)
                 }
@@ -183,6 +372,6 @@ impl PluginMgr {
             );
         }
 
-        Ok(Self { plugins })
+        Ok(Self { plugins, limits })
     }
 }
@@ -2,19 +2,34 @@ use std::{
     collections::HashSet,
     convert::TryInto,
     marker::PhantomData,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
 };
 
-use specs::saveload::MarkerAllocator;
-use wasmer::{imports, Cranelift, Function, Instance, Memory, Module, Store, Universal, Value};
+use specs::{saveload::MarkerAllocator, Entity};
+use wasmer::{
+    imports, wasmparser::Operator, BaseTunables, CompilerConfig, Cranelift, Function, Instance,
+    Memory, Module, Pages, Store, Target, Universal, Value,
+};
+use wasmer_middlewares::{
+    metering::{get_remaining_points, set_remaining_points, MeteringPoints},
+    Metering,
+};
 
 use super::{
-    errors::{PluginError, PluginModuleError},
+    errors::{MemoryAllocationError, PluginError, PluginModuleError},
+    limits::{LimitKind, LimitingTunables, PluginLimits},
     memory_manager::{self, EcsAccessManager, EcsWorld, MemoryManager},
     wasm_env::HostFunctionEnvironement,
 };
 
-use plugin_api::{Action, EcsAccessError, Event, Retrieve, RetrieveError, RetrieveResult};
+use common::{uid::Uid, vol::ReadVol};
+use plugin_api::{
+    Action, BlockInfo, BuffInfo, EcsAccessError, Event, ItemStack, Retrieve, RetrieveError,
+    RetrieveResult,
+};
 
 #[derive(Clone)]
 /// This structure represent the WASM State of the plugin.
@@ -22,26 +37,48 @@ pub struct PluginModule {
     ecs: Arc<EcsAccessManager>,
     wasm_state: Arc<Mutex<Instance>>,
     memory_manager: Arc<MemoryManager>,
+    pending_actions: Arc<Mutex<Vec<Action>>>,
     events: HashSet<String>,
     allocator: Function,
     memory: Memory,
-    #[allow(dead_code)]
     name: String,
+    limits: PluginLimits,
+    /// Number of times the module exceeded its budget
+    violation_count: Arc<AtomicU32>,
+    /// Budgets exceeded since the last call to
+    /// [`PluginModule::drain_limit_violations`]
+    limit_violations: Arc<Mutex<Vec<LimitKind>>>,
+    /// Set when the answer to a retrieve couldn't be written into the module
+    /// memory during the current event
+    write_error: Arc<Mutex<Option<PluginModuleError>>>,
 }
 
 impl PluginModule {
     /// This function takes bytes from a WASM File and compile them
-    pub fn new(name: String, wasm_data: &[u8]) -> Result<Self, PluginModuleError> {
+    pub fn new(
+        name: String,
+        wasm_data: &[u8],
+        limits: PluginLimits,
+    ) -> Result<Self, PluginModuleError> {
+        // Every instruction costs one point of fuel, the fuel is refilled before
+        // each event
+        let metering = Arc::new(Metering::new(limits.fuel_per_call, |_: &Operator| 1));
+        let mut compiler = Cranelift::default();
+        compiler.push_middleware(metering);
         // This is creating the engine is this case a JIT based on Cranelift
-        let engine = Universal::new(Cranelift::default()).engine();
-        // We are creating an enironnement
-        let store = Store::new(&engine);
+        let engine = Universal::new(compiler).engine();
+        // We are creating an enironnement, its memories can't grow past the limit
+        let tunables = LimitingTunables::new(
+            BaseTunables::for_target(&Target::default()),
+            Pages(limits.max_memory_pages),
+        );
+        let store = Store::new_with_tunables(&engine, tunables);
         // We are compiling the WASM file in the previously generated environement
-        let module = Module::new(&store, &wasm_data).expect("Can't compile");
+        let module = Module::new(&store, &wasm_data).map_err(PluginModuleError::Compile)?;
 
         // This is the function imported into the wasm environement
         fn raw_emit_actions(env: &HostFunctionEnvironement, ptr: i64, len: i64) {
-            handle_actions(match env.read_data(from_i64(ptr), from_i64(len)) {
+            handle_actions(env, match env.read_data(from_i64(ptr), from_i64(len)) {
                 Ok(e) => e,
                 Err(e) => {
                     tracing::error!(?e, "Can't decode action");
@@ -52,13 +89,21 @@ impl PluginModule {
 
         fn raw_retrieve_action(env: &HostFunctionEnvironement, ptr: i64, len: i64) -> i64 {
             let out = match env.read_data(from_i64(ptr), from_i64(len)) {
-                Ok(data) => retrieve_action(&env.ecs, data),
+                Ok(data) => retrieve_action(&env.ecs, &env.name, data),
                 Err(e) => Err(RetrieveError::BincodeError(e.to_string())),
             };
 
             // If an error happen set the i64 to 0 so the WASM side can tell an error
-            // occured
-            to_i64(env.write_data_as_pointer(&out).unwrap())
+            // occured, the allocator can run out of fuel or memory so the error is kept
+            // to be checked against the limits once the event returned
+            match env.write_data_as_pointer(&out) {
+                Ok(ptr) => to_i64(ptr),
+                Err(e) => {
+                    tracing::error!(?e, "Can't write the result of a retrieve");
+                    *env.write_error.lock().unwrap() = Some(e);
+                    0
+                },
+            }
         }
 
         fn dbg(a: i32) {
@@ -67,12 +112,14 @@ impl PluginModule {
 
         let ecs = Arc::new(EcsAccessManager::default());
         let memory_manager = Arc::new(MemoryManager::default());
+        let pending_actions = Arc::new(Mutex::new(Vec::new()));
+        let write_error = Arc::new(Mutex::new(None));
 
         // Create an import object.
         let import_object = imports! {
             "env" => {
-                "raw_emit_actions" => Function::new_native_with_env(&store, HostFunctionEnvironement::new(name.clone(), ecs.clone(),memory_manager.clone()), raw_emit_actions),
-                "raw_retrieve_action" => Function::new_native_with_env(&store, HostFunctionEnvironement::new(name.clone(), ecs.clone(),memory_manager.clone()), raw_retrieve_action),
+                "raw_emit_actions" => Function::new_native_with_env(&store, HostFunctionEnvironement::new(name.clone(), ecs.clone(),memory_manager.clone(), pending_actions.clone(), write_error.clone()), raw_emit_actions),
+                "raw_retrieve_action" => Function::new_native_with_env(&store, HostFunctionEnvironement::new(name.clone(), ecs.clone(),memory_manager.clone(), pending_actions.clone(), write_error.clone()), raw_retrieve_action),
                 "dbg" => Function::new_native(&store, dbg),
             }
         };
@@ -82,6 +129,7 @@ impl PluginModule {
             .map_err(PluginModuleError::InstantiationError)?;
         Ok(Self {
             memory_manager,
+            pending_actions,
             ecs,
             memory: instance
                 .exports
@@ -100,6 +148,10 @@ impl PluginModule {
                 .collect(),
             wasm_state: Arc::new(Mutex::new(instance)),
             name,
+            limits,
+            violation_count: Arc::new(AtomicU32::new(0)),
+            limit_violations: Arc::new(Mutex::new(Vec::new())),
+            write_error,
         })
     }
 
@@ -113,19 +165,72 @@ impl PluginModule {
     where
         T: Event,
     {
-        if !self.events.contains(&request.function_name) {
+        if !self.events.contains(&request.function_name) || self.is_disabled() {
             return None;
         }
         // Store the ECS Pointer for later use in `retreives`
         let bytes = match self.ecs.execute_with(ecs, || {
             let mut state = self.wasm_state.lock().unwrap();
-            execute_raw(self, &mut state, &request.function_name, &request.bytes)
+            set_remaining_points(&state, self.limits.fuel_per_call);
+            let result = execute_raw(self, &mut state, &request.function_name, &request.bytes);
+            // A retrieve the module couldn't receive fails the event, even if the module
+            // carried on without the answer
+            match (result, self.write_error.lock().unwrap().take()) {
+                (Err(e), _) | (Ok(_), Some(e)) => Err(self.check_limits(&state, e)),
+                (Ok(bytes), None) => Ok(bytes),
+            }
         }) {
             Ok(e) => e,
             Err(e) => return Some(Err(e)),
         };
         Some(bincode::deserialize(&bytes).map_err(PluginModuleError::Encoding))
     }
+
+    /// Tells whether a failure was caused by the module exceeding its budget,
+    /// counting it against the module if so
+    fn check_limits(&self, instance: &Instance, error: PluginModuleError) -> PluginModuleError {
+        let (kind, error) = match error {
+            _ if matches!(get_remaining_points(instance), MeteringPoints::Exhausted) => {
+                (LimitKind::Fuel, PluginModuleError::OutOfFuel)
+            },
+            PluginModuleError::RunFunction(e)
+            | PluginModuleError::MemoryAllocation(MemoryAllocationError::CantAllocate(e))
+                if self.memory.size() >= Pages(self.limits.max_memory_pages) =>
+            {
+                (LimitKind::Memory, PluginModuleError::MemoryLimit(e))
+            },
+            error => return error,
+        };
+        self.limit_violations.lock().unwrap().push(kind);
+        let count = self.violation_count.fetch_add(1, Ordering::Relaxed) + 1;
+        if count == self.limits.max_violations {
+            tracing::error!(
+                "Module of plugin '{}' exceeded its {} budget {} times, disabling it until it is \
+                 reloaded",
+                self.name,
+                kind.as_str(),
+                count
+            );
+        }
+        error
+    }
+
+    /// Whether the module exceeded its budget too many times, events aren't
+    /// executed by disabled modules
+    pub fn is_disabled(&self) -> bool {
+        self.violation_count.load(Ordering::Relaxed) >= self.limits.max_violations
+    }
+
+    /// Takes the budgets exceeded by this module since the last call
+    pub fn drain_limit_violations(&self) -> Vec<LimitKind> {
+        std::mem::take(&mut *self.limit_violations.lock().unwrap())
+    }
+
+    /// Takes all the actions emitted by this module that are waiting to be
+    /// applied to the ECS, in emission order
+    pub fn drain_actions(&self) -> Vec<Action> {
+        std::mem::take(&mut *self.pending_actions.lock().unwrap())
+    }
 }
 
 /// This structure represent a Pre-encoded event object (Useful to avoid
@@ -227,63 +332,122 @@ fn execute_raw(
     ))
 }
 
+/// Resolves an entity from its [`Uid`] in the ECS world shared with the plugin
+fn find_entity(world: &EcsWorld, uid: Uid) -> Result<Entity, RetrieveError> {
+    world
+        .uid_allocator
+        .retrieve_entity_internal(uid.0)
+        .ok_or(RetrieveError::EcsAccessError(
+            EcsAccessError::EcsEntityNotFound(uid),
+        ))
+}
+
+fn component_not_found(uid: Uid, component: &str) -> RetrieveError {
+    RetrieveError::EcsAccessError(EcsAccessError::EcsComponentNotFound(
+        uid,
+        component.to_owned(),
+    ))
+}
+
 fn retrieve_action(
     ecs: &EcsAccessManager,
+    plugin: &str,
     action: Retrieve,
 ) -> Result<RetrieveResult, RetrieveError> {
+    // Safety: No reference is leaked out the function so it is safe.
+    let world = unsafe {
+        ecs.get().ok_or(RetrieveError::EcsAccessError(
+            EcsAccessError::EcsPointerNotAvailable,
+        ))?
+    };
     match action {
         Retrieve::GetPlayerName(e) => {
-            // Safety: No reference is leaked out the function so it is safe.
-            let world = unsafe {
-                ecs.get().ok_or(RetrieveError::EcsAccessError(
-                    EcsAccessError::EcsPointerNotAvailable,
-                ))?
-            };
-            let player = world.uid_allocator.retrieve_entity_internal(e.0).ok_or(
-                RetrieveError::EcsAccessError(EcsAccessError::EcsEntityNotFound(e)),
-            )?;
-
+            let player = find_entity(world, e)?;
             Ok(RetrieveResult::GetPlayerName(
                 world
                     .player
                     .get(player)
-                    .ok_or_else(|| {
-                        RetrieveError::EcsAccessError(EcsAccessError::EcsComponentNotFound(
-                            e,
-                            "Player".to_owned(),
-                        ))
-                    })?
+                    .ok_or_else(|| component_not_found(e, "Player"))?
                     .alias
                     .to_owned(),
             ))
         },
         Retrieve::GetEntityHealth(e) => {
-            // Safety: No reference is leaked out the function so it is safe.
-            let world = unsafe {
-                ecs.get().ok_or(RetrieveError::EcsAccessError(
-                    EcsAccessError::EcsPointerNotAvailable,
-                ))?
-            };
-            let player = world.uid_allocator.retrieve_entity_internal(e.0).ok_or(
-                RetrieveError::EcsAccessError(EcsAccessError::EcsEntityNotFound(e)),
-            )?;
+            let player = find_entity(world, e)?;
             Ok(RetrieveResult::GetEntityHealth(
                 world
                     .health
                     .get(player)
-                    .ok_or_else(|| {
-                        RetrieveError::EcsAccessError(EcsAccessError::EcsComponentNotFound(
-                            e,
-                            "Health".to_owned(),
-                        ))
-                    })?
+                    .ok_or_else(|| component_not_found(e, "Health"))?
                     .clone(),
             ))
         },
+        Retrieve::GetEntityPosition(e) => {
+            let entity = find_entity(world, e)?;
+            Ok(RetrieveResult::GetEntityPosition(
+                world
+                    .pos
+                    .get(entity)
+                    .ok_or_else(|| component_not_found(e, "Pos"))?
+                    .0,
+            ))
+        },
+        Retrieve::GetEntityInventory(e) => {
+            let entity = find_entity(world, e)?;
+            let inventory = world
+                .inventory
+                .get(entity)
+                .ok_or_else(|| component_not_found(e, "Inventory"))?;
+            Ok(RetrieveResult::GetEntityInventory(
+                inventory
+                    .slots()
+                    .flatten()
+                    .chain(inventory.equipped_items())
+                    .map(|item| ItemStack {
+                        item: item.item_definition_id().to_owned(),
+                        amount: item.amount(),
+                    })
+                    .collect(),
+            ))
+        },
+        Retrieve::GetEntityBuffs(e) => {
+            let entity = find_entity(world, e)?;
+            Ok(RetrieveResult::GetEntityBuffs(
+                world
+                    .buffs
+                    .get(entity)
+                    .ok_or_else(|| component_not_found(e, "Buffs"))?
+                    .buffs
+                    .values()
+                    .map(|buff| BuffInfo {
+                        kind: buff.kind,
+                        strength: buff.data.strength,
+                        remaining: buff.time.map(|time| time.as_secs_f64()),
+                    })
+                    .collect(),
+            ))
+        },
+        Retrieve::GetBlock(pos) => {
+            let block = world.terrain.get(pos).map_err(|e| {
+                RetrieveError::OtherError(format!("Can't read block at {}: {:?}", pos, e))
+            })?;
+            Ok(RetrieveResult::GetBlock(BlockInfo::from(*block)))
+        },
+        Retrieve::GetStorageValue(key) => Ok(RetrieveResult::GetStorageValue(
+            world.storage.get(plugin, &key).map(<[u8]>::to_vec),
+        )),
+        Retrieve::GetStorageKeys => Ok(RetrieveResult::GetStorageKeys(
+            world.storage.keys(plugin).map(str::to_owned).collect(),
+        )),
     }
 }
 
-fn handle_actions(actions: Vec<Action>) {
+/// Executes the actions that don't need access to the ECS right away and
+/// queues the others so they can be applied by the server at the start of the
+/// next tick (see [`PluginMgr::drain_actions`])
+///
+/// [`PluginMgr::drain_actions`]: super::PluginMgr::drain_actions
+fn handle_actions(env: &HostFunctionEnvironement, actions: Vec<Action>) {
     for action in actions {
         match action {
             Action::ServerClose => {
@@ -293,12 +457,7 @@ fn handle_actions(actions: Vec<Action>) {
             Action::Print(e) => {
                 tracing::info!("{}", e);
             },
-            Action::PlayerSendMessage(a, b) => {
-                tracing::info!("SendMessage {} -> {}", a, b);
-            },
-            Action::KillEntity(e) => {
-                tracing::info!("Kill Entity {}", e);
-            },
+            action => env.pending_actions.lock().unwrap().push(action),
         }
     }
 }
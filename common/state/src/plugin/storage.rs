@@ -0,0 +1,51 @@
+use hashbrown::HashMap;
+
+/// The persistent key-value data of each plugin, namespaced by plugin name.
+///
+/// This is an in-memory copy that plugins can read during events, the server
+/// loads it from the database at startup and persists each change.
+#[derive(Debug, Default)]
+pub struct PluginStorage {
+    namespaces: HashMap<String, HashMap<String, Vec<u8>>>,
+}
+
+impl PluginStorage {
+    pub fn get(&self, plugin: &str, key: &str) -> Option<&[u8]> {
+        self.namespaces
+            .get(plugin)
+            .and_then(|namespace| namespace.get(key))
+            .map(Vec::as_slice)
+    }
+
+    pub fn keys(&self, plugin: &str) -> impl Iterator<Item = &str> {
+        self.namespaces
+            .get(plugin)
+            .into_iter()
+            .flat_map(|namespace| namespace.keys().map(String::as_str))
+    }
+
+    /// Number of keys and total size in bytes of keys and values stored by a
+    /// plugin.
+    pub fn usage(&self, plugin: &str) -> (usize, usize) {
+        self.namespaces.get(plugin).map_or((0, 0), |namespace| {
+            (
+                namespace.len(),
+                namespace.iter().map(|(k, v)| k.len() + v.len()).sum(),
+            )
+        })
+    }
+
+    /// Returns the previous value.
+    pub fn set(&mut self, plugin: &str, key: String, value: Vec<u8>) -> Option<Vec<u8>> {
+        self.namespaces
+            .entry(plugin.to_owned())
+            .or_default()
+            .insert(key, value)
+    }
+
+    pub fn remove(&mut self, plugin: &str, key: &str) -> Option<Vec<u8>> {
+        self.namespaces
+            .get_mut(plugin)
+            .and_then(|namespace| namespace.remove(key))
+    }
+}
@@ -1,4 +1,6 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+
+use plugin_api::Action;
 
 use serde::{de::DeserializeOwned, Serialize};
 use wasmer::{Function, HostEnvInitError, Instance, LazyInit, Memory, WasmerEnv};
@@ -17,6 +19,10 @@ pub struct HostFunctionEnvironement {
     pub allocator: LazyInit<Function>, // Linked to: wasm_prepare_buffer
     pub memory_manager: Arc<MemoryManager>, /* This object represent the current buffer size and
                                    * pointer */
+    pub pending_actions: Arc<Mutex<Vec<Action>>>, /* Actions waiting to be applied to the
+                                                   * ECS */
+    pub write_error: Arc<Mutex<Option<PluginModuleError>>>, /* Why the last answer to a
+                                                             * retrieve couldn't be written */
     pub name: String, // This represent the plugin name
 }
 
@@ -25,9 +31,13 @@ impl HostFunctionEnvironement {
         name: String,
         ecs: Arc<EcsAccessManager>,
         memory_manager: Arc<MemoryManager>,
+        pending_actions: Arc<Mutex<Vec<Action>>>,
+        write_error: Arc<Mutex<Option<PluginModuleError>>>,
     ) -> Self {
         Self {
             memory_manager,
+            pending_actions,
+            write_error,
             ecs,
             allocator: LazyInit::new(),
             memory: LazyInit::new(),
@@ -0,0 +1,100 @@
+use notify::{recommended_watcher, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+use tracing::error;
+
+/// Time without new events for a file before its change is reported, so that
+/// files still being written aren't loaded.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PluginFileChange {
+    /// The plugin file was created or modified and should be (re)loaded
+    Modified(PathBuf),
+    /// The plugin file was removed and the plugin should be unloaded
+    Removed(PathBuf),
+}
+
+impl PluginFileChange {
+    fn path(&self) -> &Path {
+        match self {
+            Self::Modified(path) | Self::Removed(path) => path,
+        }
+    }
+}
+
+/// Watches the plugin directory for changes to `.plugin.tar` files.
+pub struct PluginWatcher {
+    // The watcher stops when dropped
+    _watcher: RecommendedWatcher,
+    receiver: mpsc::Receiver<PluginFileChange>,
+    pending: HashMap<PathBuf, (PluginFileChange, Instant)>,
+}
+
+impl PluginWatcher {
+    pub fn new(dir: &Path) -> notify::Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = recommended_watcher(move |res| event_fn(res, &sender))?;
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+        Ok(Self {
+            _watcher: watcher,
+            receiver,
+            pending: HashMap::new(),
+        })
+    }
+
+    /// Returns the changes of plugin files that were not modified since
+    /// [`DEBOUNCE`], only the last change of each file is returned.
+    pub fn changes(&mut self) -> Vec<PluginFileChange> {
+        let now = Instant::now();
+        for change in self.receiver.try_iter() {
+            self.pending
+                .insert(change.path().to_path_buf(), (change, now));
+        }
+
+        let ready = self
+            .pending
+            .iter()
+            .filter(|(_, (_, time))| now.duration_since(*time) >= DEBOUNCE)
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        ready
+            .into_iter()
+            .filter_map(|path| self.pending.remove(&path))
+            .map(|(change, _)| change)
+            .collect()
+    }
+}
+
+pub fn is_plugin_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .map_or(false, |s| s.ends_with(".plugin.tar"))
+}
+
+/// This is called by the watcher to filter for events on plugin files before
+/// sending them back.
+fn event_fn(res: notify::Result<notify::Event>, sender: &mpsc::Sender<PluginFileChange>) {
+    match res {
+        Ok(event) => {
+            let change: fn(PathBuf) -> PluginFileChange = match event.kind {
+                EventKind::Create(_) | EventKind::Modify(_) => PluginFileChange::Modified,
+                EventKind::Remove(_) => PluginFileChange::Removed,
+                _ => return,
+            };
+            event
+                .paths
+                .into_iter()
+                .filter(|p| is_plugin_file(p))
+                .for_each(|p| {
+                    let _ = sender.send(change(p));
+                });
+        },
+        Err(e) => error!(?e, "Plugin watcher error"),
+    }
+}
@@ -1,7 +1,9 @@
 #[cfg(feature = "plugins")]
 use crate::plugin::memory_manager::EcsWorld;
 #[cfg(feature = "plugins")]
-use crate::plugin::PluginMgr;
+use crate::plugin::{
+    errors::PluginError, limits::PluginLimits, storage::PluginStorage, Plugin, PluginMgr,
+};
 #[cfg(feature = "plugins")]
 use common::uid::UidAllocator;
 use common::{
@@ -100,7 +102,28 @@ impl State {
     /// Create a new `State` in server mode.
     pub fn server() -> Self { Self::new(GameMode::Server) }
 
+    /// Create a new `State` in server mode, plugins can read the data they
+    /// persisted as soon as they are loaded and run within `plugin_limits`.
+    #[cfg(feature = "plugins")]
+    pub fn server_with_plugins(plugin_storage: PluginStorage, plugin_limits: PluginLimits) -> Self {
+        Self::build(GameMode::Server, plugin_storage, plugin_limits)
+    }
+
     pub fn new(game_mode: GameMode) -> Self {
+        Self::build(
+            game_mode,
+            #[cfg(feature = "plugins")]
+            PluginStorage::default(),
+            #[cfg(feature = "plugins")]
+            PluginLimits::default(),
+        )
+    }
+
+    fn build(
+        game_mode: GameMode,
+        #[cfg(feature = "plugins")] plugin_storage: PluginStorage,
+        #[cfg(feature = "plugins")] plugin_limits: PluginLimits,
+    ) -> Self {
         let thread_name_infix = match game_mode {
             GameMode::Server => "s",
             GameMode::Client => "c",
@@ -115,7 +138,14 @@ impl State {
                 .unwrap(),
         );
         Self {
-            ecs: Self::setup_ecs_world(game_mode, &thread_pool),
+            ecs: Self::setup_ecs_world(
+                game_mode,
+                &thread_pool,
+                #[cfg(feature = "plugins")]
+                plugin_storage,
+                #[cfg(feature = "plugins")]
+                plugin_limits,
+            ),
             thread_pool,
         }
     }
@@ -123,7 +153,12 @@ impl State {
     /// Creates ecs world and registers all the common components and resources
     // TODO: Split up registering into server and client (e.g. move
     // EventBus<ServerEvent> to the server)
-    fn setup_ecs_world(game_mode: GameMode, thread_pool: &Arc<ThreadPool>) -> specs::World {
+    fn setup_ecs_world(
+        game_mode: GameMode,
+        thread_pool: &Arc<ThreadPool>,
+        #[cfg(feature = "plugins")] plugin_storage: PluginStorage,
+        #[cfg(feature = "plugins")] plugin_limits: PluginLimits,
+    ) -> specs::World {
         let mut ecs = specs::World::new();
         // Uids for sync
         ecs.register_sync_marker();
@@ -190,6 +225,7 @@ impl State {
         ecs.register::<comp::ForceUpdate>();
         ecs.register::<comp::InventoryUpdate>();
         ecs.register::<comp::Admin>();
+        ecs.register::<comp::Permissions>();
         ecs.register::<comp::Waypoint>();
         ecs.register::<comp::Projectile>();
         ecs.register::<comp::Melee>();
@@ -238,14 +274,21 @@ impl State {
 
         // Load plugins from asset directory
         #[cfg(feature = "plugins")]
-        ecs.insert(match PluginMgr::from_assets() {
-            Ok(plugin_mgr) => {
+        ecs.insert(plugin_storage);
+        #[cfg(feature = "plugins")]
+        ecs.insert(match PluginMgr::from_assets(plugin_limits) {
+            Ok(mut plugin_mgr) => {
                 let ecs_world = EcsWorld {
                     entities: &ecs.entities(),
                     health: ecs.read_component().into(),
                     uid: ecs.read_component().into(),
                     uid_allocator: &ecs.read_resource::<UidAllocator>().into(),
                     player: ecs.read_component().into(),
+                    pos: ecs.read_component().into(),
+                    inventory: ecs.read_component().into(),
+                    buffs: ecs.read_component().into(),
+                    terrain: &ecs.read_resource::<TerrainGrid>().into(),
+                    storage: &ecs.read_resource::<PluginStorage>().into(),
                 };
                 if let Err(e) = plugin_mgr
                     .execute_event(&ecs_world, &plugin_api::event::PluginLoadEvent {
@@ -254,15 +297,16 @@ impl State {
                 {
                     tracing::debug!(?e, "Failed to run plugin init");
                     tracing::info!("Plugins disabled, enable debug logging for more information.");
-                    PluginMgr::default()
+                    PluginMgr::new(plugin_limits)
                 } else {
+                    plugin_mgr.load_commands(&ecs_world);
                     plugin_mgr
                 }
             },
             Err(e) => {
                 tracing::debug!(?e, "Failed to read plugins from assets");
                 tracing::info!("Plugins disabled, enable debug logging for more information.");
-                PluginMgr::default()
+                PluginMgr::new(plugin_limits)
             },
         });
 
@@ -370,6 +414,91 @@ impl State {
     /// Get a writable reference to this state's terrain.
     pub fn terrain_mut(&self) -> FetchMut<TerrainGrid> { self.ecs.write_resource() }
 
+    /// Gives `f` read access to the ECS, as seen by plugins.
+    #[cfg(feature = "plugins")]
+    fn with_plugin_ecs_world<R>(&self, f: impl FnOnce(&EcsWorld) -> R) -> R {
+        f(&EcsWorld {
+            entities: &self.ecs.entities(),
+            health: self.ecs.read_component().into(),
+            uid: self.ecs.read_component().into(),
+            player: self.ecs.read_component().into(),
+            pos: self.ecs.read_component().into(),
+            inventory: self.ecs.read_component().into(),
+            buffs: self.ecs.read_component().into(),
+            uid_allocator: &self.ecs.read_resource::<UidAllocator>().into(),
+            terrain: &self.ecs.read_resource::<TerrainGrid>().into(),
+            storage: &self.ecs.read_resource::<PluginStorage>().into(),
+        })
+    }
+
+    /// Execute an event for every loaded plugin, giving them read access to
+    /// the ECS.
+    #[cfg(feature = "plugins")]
+    pub fn execute_plugin_event<T: plugin_api::Event>(
+        &self,
+        event: &T,
+    ) -> Result<Vec<T::Response>, PluginError> {
+        self.with_plugin_ecs_world(|ecs_world| {
+            self.ecs
+                .read_resource::<PluginMgr>()
+                .execute_event(ecs_world, event)
+        })
+    }
+
+    /// Load the plugin at `path`, replacing the loaded plugin with the same
+    /// name if there is one. Returns the name of the plugin.
+    ///
+    /// If the new plugin can't be read or fails to load the old one stays
+    /// loaded, it is only unloaded once it has been replaced.
+    #[cfg(feature = "plugins")]
+    pub fn load_plugin(&self, path: &std::path::Path) -> Result<String, PluginError> {
+        let limits = self.ecs.read_resource::<PluginMgr>().limits();
+        let mut plugin = Plugin::from_path(path, limits)?;
+        let name = plugin.name().to_owned();
+
+        let game_mode = *self.ecs.read_resource::<GameMode>();
+        self.with_plugin_ecs_world(|ecs_world| {
+            plugin.execute_event(ecs_world, &plugin_api::event::PluginLoadEvent { game_mode })?;
+            plugin.load_commands(ecs_world)
+        })?;
+        tracing::info!(
+            "Loaded plugin '{}' with {} module(s)",
+            name,
+            plugin.modules().len()
+        );
+        let replaced = self.ecs.write_resource::<PluginMgr>().insert(plugin);
+        if let Some(old) = replaced {
+            self.shut_down_plugin(old);
+        }
+        Ok(name)
+    }
+
+    /// Unload the plugin with the given name, after letting it know through
+    /// `on_unload`. Returns `false` if no such plugin is loaded.
+    #[cfg(feature = "plugins")]
+    pub fn unload_plugin(&self, name: &str) -> bool {
+        let plugin = self.ecs.write_resource::<PluginMgr>().remove(name);
+        match plugin {
+            Some(plugin) => {
+                self.shut_down_plugin(plugin);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Lets a plugin which is no longer in the `PluginMgr` know through
+    /// `on_unload`, then unloads it.
+    #[cfg(feature = "plugins")]
+    fn shut_down_plugin(&self, plugin: Plugin) {
+        if let Err(e) = self.with_plugin_ecs_world(|ecs_world| {
+            plugin.execute_event(ecs_world, &plugin_api::event::PluginUnloadEvent)
+        }) {
+            tracing::warn!(?e, "Plugin '{}' failed to unload cleanly", plugin.name());
+        }
+        plugin.unload();
+    }
+
     /// Get a block in this state's terrain.
     pub fn get_block(&self, pos: Vec3<i32>) -> Option<Block> {
         self.terrain().get(pos).ok().copied()
@@ -565,3 +694,64 @@ impl State {
         self.ecs.write_resource::<TerrainChanges>().clear();
     }
 }
+
+#[cfg(all(test, feature = "plugins"))]
+mod tests {
+    use super::*;
+
+    const MODULE: &str = r#"(module
+        (memory (export "memory") 1)
+        (func (export "wasm_prepare_buffer") (param i32) (result i64) i64.const 0))"#;
+    const FAILING_MODULE: &str = r#"(module
+        (memory (export "memory") 1)
+        (func (export "wasm_prepare_buffer") (param i32) (result i64) i64.const 0)
+        (func (export "on_load") (param i64 i64) (result i64) unreachable))"#;
+
+    /// Writes a plugin archive with a single module to `path`
+    fn write_plugin(path: &std::path::Path, module: &str) {
+        let mut archive = tar::Builder::new(Vec::new());
+        let files: [(&str, &[u8]); 2] = [
+            (
+                "plugin.toml",
+                b"name = \"reload\"\nmodules = [\"module.wat\"]\ndependencies = []\n",
+            ),
+            ("module.wat", module.as_bytes()),
+        ];
+        for &(name, data) in files.iter() {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            archive.append_data(&mut header, name, data).unwrap();
+        }
+        std::fs::write(path, archive.into_inner().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn failing_reload_keeps_old_plugin() {
+        let dir =
+            std::env::temp_dir().join(format!("veloren-plugin-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("reload.plugin.tar");
+        let state = State::server();
+        let loaded = |state: &State| {
+            state
+                .ecs()
+                .read_resource::<PluginMgr>()
+                .get("reload")
+                .is_some()
+        };
+
+        write_plugin(&path, MODULE);
+        assert_eq!(state.load_plugin(&path).unwrap(), "reload");
+        assert!(loaded(&state));
+
+        write_plugin(&path, FAILING_MODULE);
+        assert!(state.load_plugin(&path).is_err());
+        assert!(loaded(&state));
+
+        assert!(state.unload_plugin("reload"));
+        assert!(!loaded(&state));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
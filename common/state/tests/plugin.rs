@@ -0,0 +1,198 @@
+#![cfg(feature = "plugins")]
+
+use common::{
+    comp::{Buffs, Health, Inventory, Player, Pos},
+    terrain::TerrainGrid,
+    uid::{Uid, UidAllocator},
+};
+use common_state::plugin::{
+    errors::PluginModuleError,
+    limits::{LimitKind, PluginLimits},
+    memory_manager::EcsWorld,
+    module::{PluginModule, PreparedEventQuery},
+    storage::PluginStorage,
+};
+use plugin_api::{Action, Event, Retrieve, RetrieveError, RetrieveResult, Vec3};
+use serde::{Deserialize, Serialize};
+use specs::{saveload::MarkerAllocator, Builder, World, WorldExt};
+
+/// Event used to trigger the test module, its handler answers with the result
+/// of the retrieve embedded in the module.
+#[derive(Serialize, Deserialize)]
+struct TestEvent;
+
+impl Event for TestEvent {
+    type Response = Result<RetrieveResult, RetrieveError>;
+
+    fn get_event_name(&self) -> String { "on_test".to_owned() }
+}
+
+fn escape(bytes: &[u8]) -> String { bytes.iter().map(|b| format!("\\{:02x}", b)).collect() }
+
+/// Builds a WASM module in the text format which, in its `on_test` handler,
+/// emits `actions` and then answers with the result of `retrieve`
+fn test_module(actions: &[Action], retrieve: &Retrieve) -> PluginModule {
+    let actions = bincode::serialize(actions).unwrap();
+    let retrieve = bincode::serialize(retrieve).unwrap();
+    let wat = format!(
+        r#"(module
+  (import "env" "raw_emit_actions" (func $emit (param i64 i64)))
+  (import "env" "raw_retrieve_action" (func $retrieve (param i64 i64) (result i64)))
+  (memory (export "memory") 1)
+  (data (i32.const 1024) "{actions}")
+  (data (i32.const 2048) "{retrieve}")
+  (func (export "wasm_prepare_buffer") (param i32) (result i64)
+    (i64.const 8192))
+  (func (export "on_test") (param i64 i64) (result i64)
+    (local $answer i64)
+    (call $emit (i64.const 1024) (i64.const {actions_len}))
+    (local.set $answer (call $retrieve (i64.const 2048) (i64.const {retrieve_len})))
+    (i64.store (i32.const 4096) (i64.add (local.get $answer) (i64.const 8)))
+    (i64.store (i32.const 4104) (i64.load (i32.wrap_i64 (local.get $answer))))
+    (i64.const 4096))
+)"#,
+        actions = escape(&actions),
+        actions_len = actions.len(),
+        retrieve = escape(&retrieve),
+        retrieve_len = retrieve.len(),
+    );
+    PluginModule::new("test".to_owned(), wat.as_bytes(), PluginLimits::default())
+        .expect("Can't load test module")
+}
+
+fn test_world() -> (World, Uid) {
+    let mut world = World::new();
+    world.register::<Health>();
+    world.register::<Uid>();
+    world.register::<Player>();
+    world.register::<Pos>();
+    world.register::<Inventory>();
+    world.register::<Buffs>();
+    world.insert(UidAllocator::new());
+    world.insert(TerrainGrid::new().unwrap());
+    world.insert(PluginStorage::default());
+
+    let entity = world
+        .create_entity()
+        .with(Pos(Vec3::new(1.0, 2.0, 3.0)))
+        .build();
+    let uid = world
+        .write_resource::<UidAllocator>()
+        .allocate(entity, None);
+    world.write_storage().insert(entity, uid).unwrap();
+    (world, uid)
+}
+
+fn try_execute(
+    module: &PluginModule,
+    world: &World,
+) -> Option<Result<Result<RetrieveResult, RetrieveError>, PluginModuleError>> {
+    let ecs_world = EcsWorld {
+        entities: &world.entities(),
+        health: world.read_component().into(),
+        uid: world.read_component().into(),
+        player: world.read_component().into(),
+        pos: world.read_component().into(),
+        inventory: world.read_component().into(),
+        buffs: world.read_component().into(),
+        uid_allocator: &world.read_resource::<UidAllocator>().into(),
+        terrain: &world.read_resource::<TerrainGrid>().into(),
+        storage: &world.read_resource::<PluginStorage>().into(),
+    };
+    module.try_execute(&ecs_world, &PreparedEventQuery::new(&TestEvent).unwrap())
+}
+
+fn execute(module: &PluginModule, world: &World) -> Option<RetrieveResult> {
+    try_execute(module, world)
+        .expect("Event not exported by the module")
+        .expect("Event execution failed")
+        .ok()
+}
+
+#[test]
+fn emitted_actions_are_queued() {
+    let (world, uid) = test_world();
+    let module = test_module(
+        &[
+            Action::Print("Hello from a test plugin".to_owned()),
+            Action::KillEntity(uid),
+            Action::Teleport {
+                entity: uid,
+                pos: Vec3::new(4.0, 5.0, 6.0),
+            },
+        ],
+        &Retrieve::GetEntityPosition(uid),
+    );
+
+    assert!(execute(&module, &world).is_some());
+
+    // `Print` is handled right away, the others wait for the server tick
+    let actions = module.drain_actions();
+    assert_eq!(actions.len(), 2);
+    assert!(matches!(actions[0], Action::KillEntity(e) if e == uid));
+    assert!(matches!(
+        actions[1],
+        Action::Teleport { entity, pos } if entity == uid && pos == Vec3::new(4.0, 5.0, 6.0)
+    ));
+    assert!(module.drain_actions().is_empty());
+}
+
+#[test]
+fn retrieve_entity_position() {
+    let (world, uid) = test_world();
+    let module = test_module(&[], &Retrieve::GetEntityPosition(uid));
+
+    assert!(matches!(
+        execute(&module, &world),
+        Some(RetrieveResult::GetEntityPosition(pos)) if pos == Vec3::new(1.0, 2.0, 3.0)
+    ));
+}
+
+#[test]
+fn retrieve_missing_component() {
+    let (world, uid) = test_world();
+    let module = test_module(&[], &Retrieve::GetEntityInventory(uid));
+
+    assert!(execute(&module, &world).is_none());
+}
+
+#[test]
+fn retrieve_unknown_entity() {
+    let (world, _) = test_world();
+    let module = test_module(&[], &Retrieve::GetEntityHealth(Uid(42)));
+
+    assert!(execute(&module, &world).is_none());
+}
+
+#[test]
+fn looping_module_is_disabled() {
+    let (world, _) = test_world();
+    let wat = r#"(module
+  (memory (export "memory") 1)
+  (func (export "wasm_prepare_buffer") (param i32) (result i64)
+    (i64.const 8192))
+  (func (export "on_test") (param i64 i64) (result i64)
+    (loop $forever (br $forever))
+    (i64.const 0))
+)"#;
+    let limits = PluginLimits {
+        fuel_per_call: 10_000,
+        max_violations: 2,
+        ..Default::default()
+    };
+    let module = PluginModule::new("test".to_owned(), wat.as_bytes(), limits)
+        .expect("Can't load test module");
+
+    for _ in 0..2 {
+        assert!(matches!(
+            try_execute(&module, &world),
+            Some(Err(PluginModuleError::OutOfFuel))
+        ));
+    }
+    assert!(module.is_disabled());
+    assert!(try_execute(&module, &world).is_none());
+    assert_eq!(module.drain_limit_violations(), vec![
+        LimitKind::Fuel,
+        LimitKind::Fuel
+    ]);
+}
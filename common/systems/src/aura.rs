@@ -4,7 +4,7 @@ use common::{
         aura::{AuraChange, AuraKey, AuraKind, AuraTarget},
         buff::{Buff, BuffCategory, BuffChange, BuffSource},
         group::Group,
-        Alignment, Aura, Auras, BuffKind, Buffs, CharacterState, Health, Player, Pos,
+        Alignment, Aura, Auras, BuffKind, Buffs, CharacterState, Health, Permissions, Player, Pos,
     },
     event::{Emitter, EventBus, ServerEvent},
     resources::DeltaTime,
@@ -21,6 +21,7 @@ use std::time::Duration;
 pub struct ReadData<'a> {
     entities: Entities<'a>,
     players: ReadStorage<'a, Player>,
+    permissions: ReadStorage<'a, Permissions>,
     dt: Read<'a, DeltaTime>,
     server_bus: Read<'a, EventBus<ServerEvent>>,
     uid_allocator: Read<'a, UidAllocator>,
@@ -125,7 +126,19 @@ impl<'a> System<'a> for Sys {
                             AuraTarget::All => true,
                         };
 
-                        if is_target {
+                        // Safezones are the only auras making entities invulnerable
+                        let is_safezone = matches!(aura.aura_kind, AuraKind::Buff {
+                            kind: BuffKind::Invulnerability,
+                            source: BuffSource::World,
+                            ..
+                        });
+                        let bypasses_safezone = is_safezone
+                            && read_data
+                                .permissions
+                                .get(target)
+                                .map_or(false, |permissions| permissions.bypass_safezone);
+
+                        if is_target && !bypasses_safezone {
                             activate_aura(
                                 aura,
                                 target,
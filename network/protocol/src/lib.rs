@@ -12,11 +12,10 @@
 //! std::mpsc::channel for unit tests without needing a actual tcp socket.
 //!
 //! This crate currently defines:
-//!  - TCP
+//!  - TCP, optionally with TLS
 //!  - MPSC
 //!  - QUIC
-//!
-//! eventually a pure UDP implementation will follow
+//!  - UDP
 //!
 //! warning: don't mix protocol, using the TCP variant for actual UDP socket
 //! will result in dropped data  using UDP with a TCP socket will be a waste of
@@ -61,6 +60,7 @@ mod prio;
 mod quic;
 mod tcp;
 mod types;
+mod udp;
 mod util;
 
 pub use error::{InitProtocolError, ProtocolError};
@@ -70,8 +70,11 @@ pub use metrics::ProtocolMetricCache;
 pub use metrics::ProtocolMetrics;
 pub use mpsc::{MpscMsg, MpscRecvProtocol, MpscSendProtocol};
 pub use quic::{QuicDataFormat, QuicDataFormatStream, QuicRecvProtocol, QuicSendProtocol};
+#[cfg(feature = "tls")]
+pub use tcp::tls::{tls_handshake, TlsDrain, TlsSink};
 pub use tcp::{TcpRecvProtocol, TcpSendProtocol};
 pub use types::{Bandwidth, Cid, Pid, Prio, Promises, Sid, HIGHEST_PRIO, VELOREN_NETWORK_VERSION};
+pub use udp::{UdpRecvProtocol, UdpSendProtocol};
 
 ///use at own risk, might change any time, for internal benchmarks
 pub mod _internal {
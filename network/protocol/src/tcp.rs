@@ -331,6 +331,186 @@ where
     }
 }
 
+#[cfg(feature = "tls")]
+impl<D> TcpSendProtocol<tls::TlsDrain<D>>
+where
+    D: UnreliableDrain<DataFormat = BytesMut>,
+{
+    /// like [`supported_promises`], but the TLS layer also encrypts the data
+    ///
+    /// [`supported_promises`]: TcpSendProtocol::supported_promises
+    pub fn supported_tls_promises() -> Promises { Self::supported_promises() | Promises::ENCRYPTED }
+}
+
+/// Optional TLS layer for the TCP protocol.
+/// It wraps the Drain and Sink of a TCP channel, so the protocol on top of it
+/// stays the same and this crate stays I/O free. The `rustls::Connection` is
+/// shared between both halves, as records received by the Sink can require an
+/// answer which is then sent with the next data on the Drain.
+#[cfg(feature = "tls")]
+pub(crate) mod tls {
+    use crate::{error::ProtocolError, UnreliableDrain, UnreliableSink};
+    use async_trait::async_trait;
+    use bytes::{Buf, BytesMut};
+    use rustls::Connection;
+    use std::{
+        io::{self, Read, Write},
+        sync::{Arc, Mutex},
+    };
+    use tracing::info;
+
+    /// encrypts everything before passing it to the inner Drain
+    pub struct TlsDrain<D> {
+        drain: D,
+        conn: Arc<Mutex<Connection>>,
+    }
+
+    /// decrypts everything received from the inner Sink
+    pub struct TlsSink<S> {
+        sink: S,
+        conn: Arc<Mutex<Connection>>,
+        /// received, but not yet decrypted data
+        incoming: BytesMut,
+        buffer: BytesMut,
+    }
+
+    /// Runs the TLS handshake on a fresh Drain and Sink. Afterwards all data
+    /// passing the returned halves is encrypted.
+    ///
+    /// The veloren handshake happens on top of it, so a [`Connection`] from
+    /// the listening side must be a `ServerConnection`.
+    pub async fn tls_handshake<D, S>(
+        mut conn: Connection,
+        mut drain: D,
+        mut sink: S,
+    ) -> Result<(TlsDrain<D>, TlsSink<S>), ProtocolError>
+    where
+        D: UnreliableDrain<DataFormat = BytesMut>,
+        S: UnreliableSink<DataFormat = BytesMut>,
+    {
+        let mut incoming = BytesMut::new();
+        while conn.is_handshaking() {
+            if conn.wants_write() {
+                drain.send(write_tls(&mut conn)?).await?;
+            } else if !incoming.is_empty() {
+                read_tls(&mut conn, &mut incoming)?;
+            } else {
+                incoming = sink.recv().await?;
+            }
+        }
+        // the client sends its last handshake message after it is done
+        if conn.wants_write() {
+            drain.send(write_tls(&mut conn)?).await?;
+        }
+        let conn = Arc::new(Mutex::new(conn));
+        Ok((
+            TlsDrain {
+                drain,
+                conn: Arc::clone(&conn),
+            },
+            TlsSink {
+                sink,
+                conn,
+                incoming,
+                buffer: BytesMut::new(),
+            },
+        ))
+    }
+
+    fn write_tls(conn: &mut Connection) -> Result<BytesMut, ProtocolError> {
+        let mut encrypted = Vec::new();
+        while conn.wants_write() {
+            conn.write_tls(&mut encrypted)
+                .map_err(|_| ProtocolError::Closed)?;
+        }
+        Ok(BytesMut::from(&encrypted[..]))
+    }
+
+    /// rustls only buffers a limited amount of received plaintext, so this
+    /// consumes only a part of `incoming` each call
+    fn read_tls(conn: &mut Connection, incoming: &mut BytesMut) -> Result<(), ProtocolError> {
+        let n = conn
+            .read_tls(&mut &incoming[..])
+            .map_err(|_| ProtocolError::Violated)?;
+        incoming.advance(n);
+        if let Err(e) = conn.process_new_packets() {
+            info!(?e, "remote side violated tls");
+            return Err(ProtocolError::Violated);
+        }
+        Ok(())
+    }
+
+    #[async_trait]
+    impl<D> UnreliableDrain for TlsDrain<D>
+    where
+        D: UnreliableDrain<DataFormat = BytesMut>,
+    {
+        type DataFormat = BytesMut;
+
+        async fn send(&mut self, data: Self::DataFormat) -> Result<(), ProtocolError> {
+            let encrypted = {
+                let mut conn = self.conn.lock().map_err(|_| ProtocolError::Closed)?;
+                let mut plain = &data[..];
+                let mut encrypted = BytesMut::new();
+                // rustls only buffers a limited amount, so encrypt it piece by piece
+                while !plain.is_empty() {
+                    let n = conn
+                        .writer()
+                        .write(plain)
+                        .map_err(|_| ProtocolError::Closed)?;
+                    plain = &plain[n..];
+                    encrypted.extend_from_slice(&write_tls(&mut conn)?);
+                }
+                encrypted
+            };
+            self.drain.send(encrypted).await
+        }
+    }
+
+    #[async_trait]
+    impl<S> UnreliableSink for TlsSink<S>
+    where
+        S: UnreliableSink<DataFormat = BytesMut>,
+    {
+        type DataFormat = BytesMut;
+
+        async fn recv(&mut self) -> Result<Self::DataFormat, ProtocolError> {
+            loop {
+                {
+                    let mut conn = self.conn.lock().map_err(|_| ProtocolError::Closed)?;
+                    self.buffer.resize(1500, 0u8);
+                    match conn.reader().read(&mut self.buffer) {
+                        // remote side sent close_notify
+                        Ok(0) => return Err(ProtocolError::Closed),
+                        Ok(n) => return Ok(self.buffer.split_to(n)),
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => (),
+                        Err(_) => return Err(ProtocolError::Closed),
+                    }
+                    if !self.incoming.is_empty() {
+                        read_tls(&mut conn, &mut self.incoming)?;
+                        continue;
+                    }
+                }
+                self.incoming = self.sink.recv().await?;
+            }
+        }
+    }
+
+    impl<D: std::fmt::Debug> std::fmt::Debug for TlsDrain<D> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("TlsDrain")
+                .field("drain", &self.drain)
+                .finish()
+        }
+    }
+
+    impl<S: std::fmt::Debug> std::fmt::Debug for TlsSink<S> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("TlsSink").field("sink", &self.sink).finish()
+        }
+    }
+}
+
 #[cfg(test)]
 mod test_utils {
     //TCP protocol based on Channel
@@ -709,4 +889,81 @@ mod tests {
         let e = p1.1.recv().await.unwrap();
         assert_eq!(event, e);
     }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn tls_handshake_and_long_msg() {
+        use crate::tcp::{tls::tls_handshake, TcpRecvProtocol, TcpSendProtocol};
+        use std::convert::TryFrom;
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let key = rustls::PrivateKey(cert.serialize_private_key_der());
+        let cert = rustls::Certificate(cert.serialize_der().unwrap());
+        let mut root_store = rustls::RootCertStore::empty();
+        root_store.add(&cert).unwrap();
+        let server_config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], key)
+            .unwrap();
+        let client_config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(root_store)
+            .with_no_client_auth();
+        let server = rustls::ServerConnection::new(Arc::new(server_config)).unwrap();
+        let client = rustls::ClientConnection::new(
+            Arc::new(client_config),
+            rustls::ServerName::try_from("localhost").unwrap(),
+        )
+        .unwrap();
+
+        let (s1, r1) = async_channel::bounded(10);
+        let (s2, r2) = async_channel::bounded(10);
+        let server = tokio::spawn(tls_handshake(
+            server.into(),
+            TcpDrain { sender: s1 },
+            TcpSink { receiver: r2 },
+        ));
+        let client = tls_handshake(client.into(), TcpDrain { sender: s2 }, TcpSink {
+            receiver: r1,
+        });
+        let (server, client) = tokio::join!(server, client);
+        let (server_drain, server_sink) = server.unwrap().unwrap();
+        let (client_drain, client_sink) = client.unwrap();
+
+        let m = ProtocolMetricCache::new("tls", Arc::new(ProtocolMetrics::new().unwrap()));
+        let mut p1 = (
+            TcpSendProtocol::new(server_drain, m.clone()),
+            TcpRecvProtocol::new(server_sink, m.clone()),
+        );
+        let mut p2 = (
+            TcpSendProtocol::new(client_drain, m.clone()),
+            TcpRecvProtocol::new(client_sink, m),
+        );
+        let (r1, r2) = tokio::join!(
+            p1.initialize(true, Pid::fake(2), 1337),
+            p2.initialize(false, Pid::fake(3), 42)
+        );
+        assert_eq!(r1, Ok((Pid::fake(3), STREAM_ID_OFFSET1, 42)));
+        assert_eq!(r2, Ok((Pid::fake(2), STREAM_ID_OFFSET2, 1337)));
+
+        let event = ProtocolEvent::OpenStream {
+            sid: Sid::new(10),
+            prio: 3u8,
+            promises: Promises::ORDERED | Promises::ENCRYPTED,
+            guaranteed_bandwidth: 1_000_000,
+        };
+        p2.0.send(event.clone()).await.unwrap();
+        assert_eq!(event, p1.1.recv().await.unwrap());
+        let event = ProtocolEvent::Message {
+            sid: Sid::new(10),
+            data: Bytes::from(&[99u8; 500_000][..]),
+        };
+        p2.0.send(event.clone()).await.unwrap();
+        tokio::spawn(async move {
+            p2.0.flush(1_000_000_000, Duration::from_secs(1))
+                .await
+                .unwrap();
+        });
+        assert_eq!(event, p1.1.recv().await.unwrap());
+    }
 }
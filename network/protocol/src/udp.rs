@@ -1,37 +1,1396 @@
-// TODO: quick and dirty which activly waits for an ack!
+use crate::{
+    error::ProtocolError,
+    event::ProtocolEvent,
+    frame::{ITFrame, InitFrame, OTFrame},
+    handshake::{ReliableDrain, ReliableSink},
+    message::{ITMessage, ALLOC_BLOCK},
+    metrics::{ProtocolMetricCache, RemoveReason},
+    prio::PrioManager,
+    types::{Bandwidth, Mid, Prio, Promises, Sid},
+    RecvProtocol, SendProtocol, UnreliableDrain, UnreliableSink,
+};
+use async_trait::async_trait;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use hashbrown::HashMap;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+#[cfg(feature = "trace_pedantic")]
+use tracing::trace;
+use tracing::{debug, info};
+
 /*
 UDP protocol
 
-All Good Case:
-S --HEADER--> R
-S --DATA--> R
-S --DATA--> R
-S <--FINISHED-- R
+Every datagram starts with a checksum of the rest of the datagram, corrupted
+datagrams are dropped. It is followed by segments:
+ - CONTROL:     seq, len, Handshake/Init/OpenStream/CloseStream/Shutdown frame
+ - STREAM:      sid, seq, len, DataHeader/Data frame
+ - ACK_CONTROL: seq
+ - ACK_STREAM:  sid, seq
 
+CONTROL segments are numbered in one sequence, the STREAM segments of every
+Stream in their own. So a lost segment only holds back its own Stream.
 
-Delayed HEADER:
-S --HEADER-->
+CONTROL segments and STREAM segments of GUARANTEED_DELIVERY Streams are
+acknowledged and retransmitted until they are, the connection is closed if too
+many pile up:
+S --HEADER--> R
+S --DATA--> !
 S --DATA--> R // STORE IT
-  --HEADER--> R // apply left data and continue
-S --DATA--> R
-S <--FINISHED-- R
+S <--ACK HEADER-- R
+S <--ACK DATA2-- R
+S --DATA--> R // retransmitted, apply DATA1 and DATA2
+S <--ACK DATA1-- R
 
+All other STREAM segments are sent once, messages missing a segment are
+dropped. Segments overtaken by a later one are dropped as well, so messages
+are always received in order.
+*/
 
-NO HEADER:
-S --HEADER--> !
-S --DATA--> R // STORE IT
-S --DATA--> R // STORE IT
-S <--MISSING_HEADER-- R // SEND AFTER 10 ms after DATA1
-S --HEADER--> R
-S <--FINISHED-- R
+const SEGMENT_CONTROL: u8 = 1;
+const SEGMENT_STREAM: u8 = 2;
+const SEGMENT_ACK_CONTROL: u8 = 3;
+const SEGMENT_ACK_STREAM: u8 = 4;
+const CHECKSUM_SIZE: usize = 4;
+/// Fits into a single ethernet frame together with the IPv4 and UDP headers
+const MAX_DATAGRAM_SIZE: usize = 1472;
+const INITIAL_RTT: Duration = Duration::from_millis(100);
+const MIN_RETRANSMIT_TIMEOUT: Duration = Duration::from_millis(20);
+const MAX_RETRANSMIT_TIMEOUT: Duration = Duration::from_secs(2);
+/// The remote side is considered gone once a segment wasn't acknowledged after
+/// this many retransmissions
+const MAX_RETRANSMITS: u32 = 15;
+/// Segments received for Streams whose OpenStream didn't arrive yet
+const MAX_EARLY_SEGMENTS: usize = 256;
+/// The remote side is considered gone once more segments weren't acknowledged.
+/// So a well-behaved remote side is never further ahead than this of the next
+/// segment we expect.
+const MAX_UNACKED: usize = 4096;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum SegmentId {
+    Control(u64),
+    Stream(Sid, u64),
+}
 
-NO DATA:
-S --HEADER--> R
-S --DATA--> R
-S --DATA--> !
-S --STATUS--> R
-S <--MISSING_DATA -- R
-S --DATA--> R
-S <--FINISHED-- R
-*/
+#[derive(Debug)]
+enum Segment {
+    Frame { id: SegmentId, frame: BytesMut },
+    Ack(SegmentId),
+}
+
+#[derive(Debug)]
+enum Received {
+    /// Handshake frame, only readable as [`InitFrame`]
+    Init(BytesMut),
+    Event(ProtocolEvent),
+}
+
+#[derive(Debug)]
+struct Unacked {
+    segment: Bytes,
+    sent: Instant,
+    retransmits: u32,
+}
+
+/// State shared between the [`UdpSendProtocol`] and [`UdpRecvProtocol`] of a
+/// channel, as acknowledgements are received by the latter but concern what
+/// the former sent.
+#[derive(Debug)]
+struct Shared {
+    /// Promises of the open Streams, opened by either side
+    promises: HashMap<Sid, Promises>,
+    unacked: HashMap<SegmentId, Unacked>,
+    /// smoothed round trip time
+    rtt: Duration,
+}
+
+#[derive(Debug)]
+struct SendStream {
+    promises: Promises,
+    next_seq: u64,
+}
+
+#[derive(Debug)]
+struct RecvStream {
+    promises: Promises,
+    next_seq: u64,
+    /// segments of a GUARANTEED_DELIVERY Stream received out of order
+    pending: BTreeMap<u64, BytesMut>,
+    /// message currently received and the seq of its next segment, as
+    /// messages of a Stream are sent one after another
+    incoming: Option<(Mid, ITMessage, u64)>,
+}
+
+/// UDP implementation of [`SendProtocol`]
+///
+/// [`SendProtocol`]: crate::SendProtocol
+#[derive(Debug)]
+pub struct UdpSendProtocol<D>
+where
+    D: UnreliableDrain<DataFormat = BytesMut>,
+{
+    store: PrioManager,
+    streams: HashMap<Sid, SendStream>,
+    next_mid: Mid,
+    next_control_seq: u64,
+    closing_streams: Vec<Sid>,
+    notify_closing_streams: Vec<Sid>,
+    pending_shutdown: bool,
+    shared: Arc<Mutex<Shared>>,
+    drain: D,
+    metrics: ProtocolMetricCache,
+}
+
+/// UDP implementation of [`RecvProtocol`]
+///
+/// [`RecvProtocol`]: crate::RecvProtocol
+#[derive(Debug)]
+pub struct UdpRecvProtocol<S, D>
+where
+    S: UnreliableSink<DataFormat = BytesMut>,
+    D: UnreliableDrain<DataFormat = BytesMut>,
+{
+    next_control_seq: u64,
+    pending_control: BTreeMap<u64, BytesMut>,
+    streams: HashMap<Sid, RecvStream>,
+    early_segments: VecDeque<(Sid, u64, BytesMut)>,
+    received: VecDeque<Received>,
+    itmsg_allocator: BytesMut,
+    shared: Arc<Mutex<Shared>>,
+    sink: S,
+    /// used to acknowledge received segments right away
+    drain: D,
+    metrics: ProtocolMetricCache,
+}
+
+/// FNV-1a, cheap and good enough to detect corrupted datagrams
+fn checksum(data: &[u8]) -> u32 {
+    data.iter().fold(0x811c_9dc5, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+fn write_segment(id: SegmentId, frame: &[u8]) -> Bytes {
+    let mut bytes = BytesMut::with_capacity(frame.len() + 19);
+    match id {
+        SegmentId::Control(seq) => {
+            bytes.put_u8(SEGMENT_CONTROL);
+            bytes.put_u64_le(seq);
+        },
+        SegmentId::Stream(sid, seq) => {
+            bytes.put_u8(SEGMENT_STREAM);
+            sid.to_bytes(&mut bytes);
+            bytes.put_u64_le(seq);
+        },
+    }
+    bytes.put_u16_le(frame.len() as u16);
+    bytes.put_slice(frame);
+    bytes.freeze()
+}
+
+fn write_ack(id: SegmentId) -> Bytes {
+    let mut bytes = BytesMut::with_capacity(17);
+    match id {
+        SegmentId::Control(seq) => {
+            bytes.put_u8(SEGMENT_ACK_CONTROL);
+            bytes.put_u64_le(seq);
+        },
+        SegmentId::Stream(sid, seq) => {
+            bytes.put_u8(SEGMENT_ACK_STREAM);
+            sid.to_bytes(&mut bytes);
+            bytes.put_u64_le(seq);
+        },
+    }
+    bytes.freeze()
+}
+
+/// Err => remote side violated the protocol
+/// Ok(vec![]) => empty or corrupted datagram
+fn read_segments(mut datagram: BytesMut) -> Result<Vec<Segment>, ()> {
+    if datagram.len() < CHECKSUM_SIZE {
+        return Ok(vec![]);
+    }
+    let expected = datagram.get_u32_le();
+    if checksum(&datagram) != expected {
+        debug!("dropping corrupted datagram");
+        return Ok(vec![]);
+    }
+    let mut segments = vec![];
+    while !datagram.is_empty() {
+        let kind = datagram.get_u8();
+        let id = match kind {
+            SEGMENT_CONTROL | SEGMENT_ACK_CONTROL if datagram.len() >= 8 => {
+                SegmentId::Control(datagram.get_u64_le())
+            },
+            SEGMENT_STREAM | SEGMENT_ACK_STREAM if datagram.len() >= 16 => {
+                let sid = Sid::from_bytes(&mut datagram);
+                SegmentId::Stream(sid, datagram.get_u64_le())
+            },
+            _ => return Err(()),
+        };
+        if kind == SEGMENT_ACK_CONTROL || kind == SEGMENT_ACK_STREAM {
+            segments.push(Segment::Ack(id));
+            continue;
+        }
+        if datagram.len() < 2 {
+            return Err(());
+        }
+        let length = datagram.get_u16_le() as usize;
+        if datagram.len() < length {
+            return Err(());
+        }
+        segments.push(Segment::Frame {
+            id,
+            frame: datagram.split_to(length),
+        });
+    }
+    Ok(segments)
+}
+
+/// Packs the segments into as few datagrams as possible
+async fn send_segments<D>(drain: &mut D, segments: &[Bytes]) -> Result<(), ProtocolError>
+where
+    D: UnreliableDrain<DataFormat = BytesMut>,
+{
+    let mut datagram = BytesMut::with_capacity(MAX_DATAGRAM_SIZE);
+    for segment in segments {
+        if !datagram.is_empty() && datagram.len() + segment.len() > MAX_DATAGRAM_SIZE {
+            send_datagram(drain, &mut datagram).await?;
+        }
+        if datagram.is_empty() {
+            datagram.put_u32_le(0);
+        }
+        datagram.extend_from_slice(segment);
+    }
+    if !datagram.is_empty() {
+        send_datagram(drain, &mut datagram).await?;
+    }
+    Ok(())
+}
+
+async fn send_datagram<D>(drain: &mut D, datagram: &mut BytesMut) -> Result<(), ProtocolError>
+where
+    D: UnreliableDrain<DataFormat = BytesMut>,
+{
+    let checksum = checksum(&datagram[CHECKSUM_SIZE..]);
+    datagram[..CHECKSUM_SIZE].copy_from_slice(&checksum.to_le_bytes());
+    drain.send(datagram.split()).await
+}
+
+impl Shared {
+    fn new() -> Self {
+        Self {
+            promises: HashMap::new(),
+            unacked: HashMap::new(),
+            rtt: INITIAL_RTT,
+        }
+    }
+
+    fn add_unacked(
+        &mut self,
+        id: SegmentId,
+        segment: Bytes,
+        now: Instant,
+    ) -> Result<(), ProtocolError> {
+        if self.unacked.len() >= MAX_UNACKED {
+            info!("remote side doesn't keep up acknowledging, closing");
+            return Err(ProtocolError::Closed);
+        }
+        self.unacked.insert(id, Unacked {
+            segment,
+            sent: now,
+            retransmits: 0,
+        });
+        Ok(())
+    }
+
+    fn acknowledge(&mut self, id: SegmentId) {
+        if let Some(unacked) = self.unacked.remove(&id) {
+            // the round trip of retransmitted segments is ambiguous
+            if unacked.retransmits == 0 {
+                self.rtt = self.rtt.mul_f64(0.875) + unacked.sent.elapsed().mul_f64(0.125);
+            }
+        }
+    }
+
+    fn has_unacked(&self, sid: Sid) -> bool {
+        self.unacked
+            .keys()
+            .any(|id| matches!(id, SegmentId::Stream(s, _) if *s == sid))
+    }
+
+    /// Segments which weren't acknowledged in time, marked as sent again
+    fn retransmits(&mut self, now: Instant) -> Result<Vec<Bytes>, ProtocolError> {
+        let timeout = (self.rtt * 2).clamp(MIN_RETRANSMIT_TIMEOUT, MAX_RETRANSMIT_TIMEOUT);
+        let mut segments = vec![];
+        for unacked in self.unacked.values_mut() {
+            let backoff =
+                (timeout * 2u32.pow(unacked.retransmits.min(6))).min(MAX_RETRANSMIT_TIMEOUT);
+            if now.duration_since(unacked.sent) < backoff {
+                continue;
+            }
+            if unacked.retransmits >= MAX_RETRANSMITS {
+                info!("remote side stopped acknowledging, closing");
+                return Err(ProtocolError::Closed);
+            }
+            unacked.retransmits += 1;
+            unacked.sent = now;
+            segments.push(unacked.segment.clone());
+        }
+        Ok(segments)
+    }
+}
+
+impl RecvStream {
+    fn new(promises: Promises) -> Self {
+        Self {
+            promises,
+            next_seq: 0,
+            pending: BTreeMap::new(),
+            incoming: None,
+        }
+    }
+
+    fn drop_incoming(&mut self, metrics: &mut ProtocolMetricCache) {
+        if let Some((mid, m, _)) = self.incoming.take() {
+            debug!(?mid, "dropping incomplete message");
+            metrics.rmsg_ob(m.sid, RemoveReason::Dropped, m.data.len() as u64);
+        }
+    }
+
+    /// Applies a segment, which MUST be the next one of this Stream in case
+    /// of GUARANTEED_DELIVERY.
+    fn apply(
+        &mut self,
+        sid: Sid,
+        seq: u64,
+        mut bytes: BytesMut,
+        allocator: &mut BytesMut,
+        received: &mut VecDeque<Received>,
+        metrics: &mut ProtocolMetricCache,
+    ) -> Result<(), ProtocolError> {
+        let frame = match ITFrame::read_frame(&mut bytes) {
+            Ok(Some(frame)) if bytes.is_empty() => frame,
+            _ => return Err(ProtocolError::Violated),
+        };
+        #[cfg(feature = "trace_pedantic")]
+        trace!(?frame, "recv");
+        match frame {
+            ITFrame::DataHeader {
+                mid,
+                sid: header_sid,
+                length,
+            } => {
+                if header_sid != sid {
+                    return Err(ProtocolError::Violated);
+                }
+                self.drop_incoming(metrics);
+                metrics.rmsg_ib(sid, length);
+                let m = ITMessage::new(sid, length, allocator);
+                self.incoming = Some((mid, m, seq + 1));
+            },
+            ITFrame::Data { mid, data } => {
+                metrics.rdata_frames_b(data.len() as u64);
+                match &mut self.incoming {
+                    Some((incoming_mid, m, next_seq))
+                        if *incoming_mid == mid && *next_seq == seq =>
+                    {
+                        m.data.extend_from_slice(&data);
+                        *next_seq += 1;
+                        if m.data.len() > m.length as usize {
+                            return Err(ProtocolError::Violated);
+                        }
+                    },
+                    _ => {
+                        if self.promises.contains(Promises::GUARANTEED_DELIVERY) {
+                            info!(
+                                ?mid,
+                                "protocol violation by remote side: send Data before Header"
+                            );
+                            return Err(ProtocolError::Violated);
+                        }
+                        // an earlier segment of this message got lost
+                        self.drop_incoming(metrics);
+                        return Ok(());
+                    },
+                }
+            },
+            _ => return Err(ProtocolError::Violated),
+        }
+        if matches!(&self.incoming, Some((_, m, _)) if m.data.len() == m.length as usize) {
+            if let Some((_, m, _)) = self.incoming.take() {
+                metrics.rmsg_ob(m.sid, RemoveReason::Finished, m.data.len() as u64);
+                received.push_back(Received::Event(ProtocolEvent::Message {
+                    sid: m.sid,
+                    data: m.data.freeze(),
+                }));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<D> UdpSendProtocol<D>
+where
+    D: UnreliableDrain<DataFormat = BytesMut>,
+{
+    pub fn new(drain: D, metrics: ProtocolMetricCache) -> Self {
+        Self {
+            store: PrioManager::new(metrics.clone()),
+            streams: HashMap::new(),
+            next_mid: 0u64,
+            next_control_seq: 0u64,
+            closing_streams: vec![],
+            notify_closing_streams: vec![],
+            pending_shutdown: false,
+            shared: Arc::new(Mutex::new(Shared::new())),
+            drain,
+            metrics,
+        }
+    }
+
+    /// returns all promises that this Protocol can take care of
+    /// If you open a Stream anyway, unsupported promises are ignored.
+    pub fn supported_promises() -> Promises {
+        Promises::ORDERED
+            | Promises::CONSISTENCY
+            | Promises::GUARANTEED_DELIVERY
+            | Promises::COMPRESSED
+    }
+
+    fn open_stream(
+        &mut self,
+        sid: Sid,
+        prio: Prio,
+        promises: Promises,
+        guaranteed_bandwidth: Bandwidth,
+    ) {
+        self.store
+            .open_stream(sid, prio, promises, guaranteed_bandwidth);
+        self.streams.insert(sid, SendStream {
+            promises,
+            next_seq: 0,
+        });
+        self.shared.lock().unwrap().promises.insert(sid, promises);
+    }
+
+    /// A Stream can only be closed once all its messages are sent AND
+    /// acknowledged, so that they arrive before the close.
+    fn try_close_stream(&mut self, sid: Sid) -> bool {
+        let mut shared = self.shared.lock().unwrap();
+        if shared.has_unacked(sid) || !self.store.try_close_stream(sid) {
+            return false;
+        }
+        shared.promises.remove(&sid);
+        self.streams.remove(&sid);
+        true
+    }
+
+    /// Turns the frames into segments, keeping the reliable ones until they
+    /// are acknowledged, followed by the segments to retransmit.
+    fn segments(&mut self, frames: Vec<(Sid, OTFrame)>) -> Result<Vec<Bytes>, ProtocolError> {
+        let now = Instant::now();
+        let mut shared = self.shared.lock().unwrap();
+        let mut segments = Vec::with_capacity(frames.len());
+        let mut buffer = BytesMut::new();
+        for (sid, frame) in frames {
+            let stream = match self.streams.get_mut(&sid) {
+                Some(stream) => stream,
+                None => continue,
+            };
+            let id = SegmentId::Stream(sid, stream.next_seq);
+            stream.next_seq += 1;
+            frame.write_bytes(&mut buffer);
+            let segment = write_segment(id, &buffer.split());
+            if stream.promises.contains(Promises::GUARANTEED_DELIVERY) {
+                shared.add_unacked(id, segment.clone(), now)?;
+            }
+            segments.push(segment);
+        }
+        segments.extend(shared.retransmits(now)?);
+        Ok(segments)
+    }
+
+    async fn send_control(&mut self, frame: BytesMut) -> Result<(), ProtocolError> {
+        let id = SegmentId::Control(self.next_control_seq);
+        self.next_control_seq += 1;
+        let segment = write_segment(id, &frame);
+        self.shared
+            .lock()
+            .unwrap()
+            .add_unacked(id, segment.clone(), Instant::now())?;
+        send_segments(&mut self.drain, &[segment]).await
+    }
+}
+
+impl<S, D> UdpRecvProtocol<S, D>
+where
+    S: UnreliableSink<DataFormat = BytesMut>,
+    D: UnreliableDrain<DataFormat = BytesMut> + Clone,
+{
+    /// The receiving end shares the acknowledgements with the sending end of
+    /// the same channel, and sends its own through a clone of its drain.
+    pub fn new(sink: S, send: &UdpSendProtocol<D>, metrics: ProtocolMetricCache) -> Self {
+        Self {
+            next_control_seq: 0,
+            pending_control: BTreeMap::new(),
+            streams: HashMap::new(),
+            early_segments: VecDeque::new(),
+            received: VecDeque::new(),
+            itmsg_allocator: BytesMut::with_capacity(ALLOC_BLOCK),
+            shared: Arc::clone(&send.shared),
+            sink,
+            drain: send.drain.clone(),
+            metrics,
+        }
+    }
+}
+
+impl<S, D> UdpRecvProtocol<S, D>
+where
+    S: UnreliableSink<DataFormat = BytesMut>,
+    D: UnreliableDrain<DataFormat = BytesMut>,
+{
+    /// Handles a datagram from the sink, which might be empty in case the
+    /// sink just wants to give the protocol a chance to retransmit.
+    async fn recv_datagram(&mut self) -> Result<(), ProtocolError> {
+        let datagram = self.sink.recv().await?;
+        let acks = self.handle_datagram(datagram)?;
+        send_segments(&mut self.drain, &acks).await
+    }
+
+    /// returns the acknowledgements to send
+    fn handle_datagram(&mut self, datagram: BytesMut) -> Result<Vec<Bytes>, ProtocolError> {
+        let segments = read_segments(datagram).map_err(|()| {
+            info!("protocol violation by remote side: malformed datagram");
+            ProtocolError::Violated
+        })?;
+        let mut acks = vec![];
+        for segment in segments {
+            match segment {
+                Segment::Ack(id) => self.shared.lock().unwrap().acknowledge(id),
+                Segment::Frame {
+                    id: SegmentId::Control(seq),
+                    frame,
+                } => {
+                    if seq >= self.next_control_seq + MAX_UNACKED as u64 {
+                        info!("protocol violation by remote side: too many segments unacked");
+                        return Err(ProtocolError::Violated);
+                    }
+                    acks.push(write_ack(SegmentId::Control(seq)));
+                    if seq >= self.next_control_seq {
+                        self.pending_control.insert(seq, frame);
+                    }
+                    while let Some(frame) = self.pending_control.remove(&self.next_control_seq) {
+                        self.next_control_seq += 1;
+                        self.handle_control(frame, &mut acks)?;
+                    }
+                },
+                Segment::Frame {
+                    id: SegmentId::Stream(sid, seq),
+                    frame,
+                } => {
+                    if self.handle_stream(sid, seq, frame)? {
+                        acks.push(write_ack(SegmentId::Stream(sid, seq)));
+                    }
+                },
+            }
+        }
+        Ok(acks)
+    }
+
+    fn handle_control(
+        &mut self,
+        frame: BytesMut,
+        acks: &mut Vec<Bytes>,
+    ) -> Result<(), ProtocolError> {
+        let event = match ITFrame::read_frame(&mut frame.clone()) {
+            Ok(Some(ITFrame::Shutdown)) => ProtocolEvent::Shutdown,
+            Ok(Some(ITFrame::OpenStream {
+                sid,
+                prio,
+                promises,
+                guaranteed_bandwidth,
+            })) => {
+                self.shared.lock().unwrap().promises.insert(sid, promises);
+                ProtocolEvent::OpenStream {
+                    sid,
+                    prio: prio.min(crate::types::HIGHEST_PRIO),
+                    promises,
+                    guaranteed_bandwidth,
+                }
+            },
+            Ok(Some(ITFrame::CloseStream { sid })) => {
+                if let Some(mut stream) = self.streams.remove(&sid) {
+                    stream.drop_incoming(&mut self.metrics);
+                }
+                self.shared.lock().unwrap().promises.remove(&sid);
+                ProtocolEvent::CloseStream { sid }
+            },
+            // only the handshake isn't made of ITFrames
+            Err(()) => {
+                self.received.push_back(Received::Init(frame));
+                return Ok(());
+            },
+            Ok(_) => return Err(ProtocolError::Violated),
+        };
+        #[cfg(feature = "trace_pedantic")]
+        trace!(?event, "recv");
+        let opened = matches!(event, ProtocolEvent::OpenStream { .. });
+        self.received.push_back(Received::Event(event));
+        if opened {
+            // segments which overtook the OpenStream
+            for (sid, seq, frame) in std::mem::take(&mut self.early_segments) {
+                if self.handle_stream(sid, seq, frame)? {
+                    acks.push(write_ack(SegmentId::Stream(sid, seq)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// returns if the segment is to be acknowledged
+    fn handle_stream(
+        &mut self,
+        sid: Sid,
+        seq: u64,
+        frame: BytesMut,
+    ) -> Result<bool, ProtocolError> {
+        if !self.streams.contains_key(&sid) {
+            let promises = self.shared.lock().unwrap().promises.get(&sid).copied();
+            match promises {
+                Some(promises) => {
+                    self.streams.insert(sid, RecvStream::new(promises));
+                },
+                None => {
+                    // not acknowledged, so it's retransmitted if this gets dropped
+                    if self.early_segments.len() >= MAX_EARLY_SEGMENTS {
+                        self.early_segments.pop_front();
+                    }
+                    self.early_segments.push_back((sid, seq, frame));
+                    return Ok(false);
+                },
+            }
+        }
+        let stream = match self.streams.get_mut(&sid) {
+            Some(stream) => stream,
+            None => return Ok(false),
+        };
+        let guaranteed = stream.promises.contains(Promises::GUARANTEED_DELIVERY);
+        if seq < stream.next_seq {
+            // duplicate, or overtaken by a later segment
+            return Ok(guaranteed);
+        }
+        if guaranteed {
+            if seq >= stream.next_seq + MAX_UNACKED as u64 {
+                info!("protocol violation by remote side: too many segments unacked");
+                return Err(ProtocolError::Violated);
+            }
+            stream.pending.insert(seq, frame);
+            while let Some(frame) = stream.pending.remove(&stream.next_seq) {
+                let seq = stream.next_seq;
+                stream.next_seq += 1;
+                stream.apply(
+                    sid,
+                    seq,
+                    frame,
+                    &mut self.itmsg_allocator,
+                    &mut self.received,
+                    &mut self.metrics,
+                )?;
+            }
+        } else {
+            stream.next_seq = seq + 1;
+            stream.apply(
+                sid,
+                seq,
+                frame,
+                &mut self.itmsg_allocator,
+                &mut self.received,
+                &mut self.metrics,
+            )?;
+        }
+        Ok(guaranteed)
+    }
+}
+
+#[async_trait]
+impl<D> SendProtocol for UdpSendProtocol<D>
+where
+    D: UnreliableDrain<DataFormat = BytesMut>,
+{
+    fn notify_from_recv(&mut self, event: ProtocolEvent) {
+        match event {
+            ProtocolEvent::OpenStream {
+                sid,
+                prio,
+                promises,
+                guaranteed_bandwidth,
+            } => self.open_stream(sid, prio, promises, guaranteed_bandwidth),
+            ProtocolEvent::CloseStream { sid } => {
+                if !self.try_close_stream(sid) {
+                    #[cfg(feature = "trace_pedantic")]
+                    trace!(?sid, "hold back notify close stream");
+                    self.notify_closing_streams.push(sid);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    async fn send(&mut self, event: ProtocolEvent) -> Result<(), ProtocolError> {
+        #[cfg(feature = "trace_pedantic")]
+        trace!(?event, "send");
+        let mut buffer = BytesMut::new();
+        match event {
+            ProtocolEvent::OpenStream {
+                sid,
+                prio,
+                promises,
+                guaranteed_bandwidth,
+            } => {
+                self.open_stream(sid, prio, promises, guaranteed_bandwidth);
+                event.to_frame().write_bytes(&mut buffer);
+                self.send_control(buffer).await?;
+            },
+            ProtocolEvent::CloseStream { sid } => {
+                if self.try_close_stream(sid) {
+                    event.to_frame().write_bytes(&mut buffer);
+                    self.send_control(buffer).await?;
+                } else {
+                    #[cfg(feature = "trace_pedantic")]
+                    trace!(?sid, "hold back close stream");
+                    self.closing_streams.push(sid);
+                }
+            },
+            ProtocolEvent::Shutdown => {
+                if self.store.is_empty() {
+                    event.to_frame().write_bytes(&mut buffer);
+                    self.send_control(buffer).await?;
+                } else {
+                    #[cfg(feature = "trace_pedantic")]
+                    trace!("hold back shutdown");
+                    self.pending_shutdown = true;
+                }
+            },
+            ProtocolEvent::Message { data, sid } => {
+                self.metrics.smsg_ib(sid, data.len() as u64);
+                self.store.add(data, self.next_mid, sid);
+                self.next_mid += 1;
+            },
+        }
+        Ok(())
+    }
+
+    async fn flush(
+        &mut self,
+        bandwidth: Bandwidth,
+        dt: Duration,
+    ) -> Result</* actual */ Bandwidth, ProtocolError> {
+        let (frames, _) = self.store.grab(bandwidth, dt);
+        let mut data_frames = 0;
+        let mut data_bandwidth = 0;
+        for (_, frame) in &frames {
+            if let OTFrame::Data { mid: _, data } = frame {
+                data_bandwidth += data.len();
+                data_frames += 1;
+            }
+        }
+        let segments = self.segments(frames)?;
+        send_segments(&mut self.drain, &segments).await?;
+        self.metrics
+            .sdata_frames_b(data_frames, data_bandwidth as u64);
+
+        for sid in std::mem::take(&mut self.closing_streams) {
+            if self.try_close_stream(sid) {
+                #[cfg(feature = "trace_pedantic")]
+                trace!(?sid, "close stream, as it's now empty");
+                let mut buffer = BytesMut::new();
+                OTFrame::CloseStream { sid }.write_bytes(&mut buffer);
+                self.send_control(buffer).await?;
+            } else {
+                self.closing_streams.push(sid);
+            }
+        }
+
+        for sid in std::mem::take(&mut self.notify_closing_streams) {
+            if self.try_close_stream(sid) {
+                #[cfg(feature = "trace_pedantic")]
+                trace!(?sid, "close stream, as it's now empty");
+            } else {
+                self.notify_closing_streams.push(sid);
+            }
+        }
+
+        if self.pending_shutdown && self.store.is_empty() {
+            #[cfg(feature = "trace_pedantic")]
+            trace!("shutdown, as it's now empty");
+            let mut buffer = BytesMut::new();
+            OTFrame::Shutdown {}.write_bytes(&mut buffer);
+            self.send_control(buffer).await?;
+            self.pending_shutdown = false;
+        }
+        Ok(data_bandwidth as u64)
+    }
+}
+
+#[async_trait]
+impl<S, D> RecvProtocol for UdpRecvProtocol<S, D>
+where
+    S: UnreliableSink<DataFormat = BytesMut>,
+    D: UnreliableDrain<DataFormat = BytesMut>,
+{
+    async fn recv(&mut self) -> Result<ProtocolEvent, ProtocolError> {
+        loop {
+            match self.received.pop_front() {
+                Some(Received::Event(event)) => return Ok(event),
+                Some(Received::Init(_)) => {
+                    info!("protocol violation by remote side: handshake after it completed");
+                    return Err(ProtocolError::Violated);
+                },
+                None => {},
+            }
+            self.recv_datagram().await?;
+        }
+    }
+}
+
+#[async_trait]
+impl<D> ReliableDrain for UdpSendProtocol<D>
+where
+    D: UnreliableDrain<DataFormat = BytesMut>,
+{
+    async fn send(&mut self, frame: InitFrame) -> Result<(), ProtocolError> {
+        let mut buffer = BytesMut::with_capacity(500);
+        frame.write_bytes(&mut buffer);
+        self.send_control(buffer).await
+    }
+}
+
+#[async_trait]
+impl<S, D> ReliableSink for UdpRecvProtocol<S, D>
+where
+    S: UnreliableSink<DataFormat = BytesMut>,
+    D: UnreliableDrain<DataFormat = BytesMut>,
+{
+    async fn recv(&mut self) -> Result<InitFrame, ProtocolError> {
+        loop {
+            match self.received.pop_front() {
+                Some(Received::Init(mut frame)) => {
+                    return InitFrame::read_frame(&mut frame).ok_or(ProtocolError::Violated);
+                },
+                Some(Received::Event(_)) => return Err(ProtocolError::Violated),
+                None => {},
+            }
+            self.recv_datagram().await?;
+            // nobody flushes the send side during the handshake
+            let segments = self.shared.lock().unwrap().retransmits(Instant::now())?;
+            send_segments(&mut self.drain, &segments).await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_utils {
+    //Udp protocol based on Channel
+    use super::*;
+    use crate::metrics::{ProtocolMetricCache, ProtocolMetrics};
+    use async_channel::*;
+    use std::sync::Arc;
+
+    #[derive(Clone)]
+    pub struct UdpDrain {
+        pub sender: Sender<BytesMut>,
+        pub drop_ratio: f32,
+    }
+
+    pub struct UdpSink {
+        pub receiver: Receiver<BytesMut>,
+    }
+
+    /// emulate Udp protocol on Channels, dropping datagrams with `drop_ratio`
+    pub fn udp_bound(
+        cap: usize,
+        drop_ratio: f32,
+        metrics: Option<ProtocolMetricCache>,
+    ) -> [(
+        UdpSendProtocol<UdpDrain>,
+        UdpRecvProtocol<UdpSink, UdpDrain>,
+    ); 2] {
+        let (s1, r1) = async_channel::bounded(cap);
+        let (s2, r2) = async_channel::bounded(cap);
+        let m = metrics.unwrap_or_else(|| {
+            ProtocolMetricCache::new("udp", Arc::new(ProtocolMetrics::new().unwrap()))
+        });
+        let send1 = UdpSendProtocol::new(
+            UdpDrain {
+                sender: s1,
+                drop_ratio,
+            },
+            m.clone(),
+        );
+        let recv1 = UdpRecvProtocol::new(UdpSink { receiver: r2 }, &send1, m.clone());
+        let send2 = UdpSendProtocol::new(
+            UdpDrain {
+                sender: s2,
+                drop_ratio,
+            },
+            m.clone(),
+        );
+        let recv2 = UdpRecvProtocol::new(UdpSink { receiver: r1 }, &send2, m);
+        [(send1, recv1), (send2, recv2)]
+    }
+
+    #[async_trait]
+    impl UnreliableDrain for UdpDrain {
+        type DataFormat = BytesMut;
+
+        async fn send(&mut self, data: Self::DataFormat) -> Result<(), ProtocolError> {
+            use rand::Rng;
+            if rand::thread_rng().gen::<f32>() < self.drop_ratio {
+                return Ok(());
+            }
+            self.sender
+                .send(data)
+                .await
+                .map_err(|_| ProtocolError::Closed)
+        }
+    }
+
+    #[async_trait]
+    impl UnreliableSink for UdpSink {
+        type DataFormat = BytesMut;
+
+        /// like a socket with a read timeout
+        async fn recv(&mut self) -> Result<Self::DataFormat, ProtocolError> {
+            match tokio::time::timeout(Duration::from_millis(10), self.receiver.recv()).await {
+                Ok(Ok(datagram)) => Ok(datagram),
+                Ok(Err(_)) => Err(ProtocolError::Closed),
+                Err(_) => Ok(BytesMut::new()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::ProtocolError,
+        metrics::{ProtocolMetricCache, ProtocolMetrics, RemoveReason},
+        types::{Pid, Promises, Sid, STREAM_ID_OFFSET1, STREAM_ID_OFFSET2},
+        udp::test_utils::*,
+        InitProtocol, ProtocolEvent, RecvProtocol, SendProtocol,
+    };
+    use bytes::{BufMut, Bytes, BytesMut};
+    use std::{sync::Arc, time::Duration};
+
+    #[tokio::test]
+    async fn handshake_all_good() {
+        let [mut p1, mut p2] = udp_bound(10, 0.0, None);
+        let r1 = tokio::spawn(async move { p1.initialize(true, Pid::fake(2), 1337).await });
+        let r2 = tokio::spawn(async move { p2.initialize(false, Pid::fake(3), 42).await });
+        let (r1, r2) = tokio::join!(r1, r2);
+        assert_eq!(r1.unwrap(), Ok((Pid::fake(3), STREAM_ID_OFFSET1, 42)));
+        assert_eq!(r2.unwrap(), Ok((Pid::fake(2), STREAM_ID_OFFSET2, 1337)));
+    }
+
+    #[tokio::test]
+    async fn open_stream() {
+        let [p1, p2] = udp_bound(10, 0.0, None);
+        let (mut s, mut r) = (p1.0, p2.1);
+        let event = ProtocolEvent::OpenStream {
+            sid: Sid::new(10),
+            prio: 0u8,
+            promises: Promises::ORDERED,
+            guaranteed_bandwidth: 1_000_000,
+        };
+        s.send(event.clone()).await.unwrap();
+        let e = r.recv().await.unwrap();
+        assert_eq!(event, e);
+    }
+
+    #[tokio::test]
+    async fn send_short_msg() {
+        let [p1, p2] = udp_bound(10, 0.0, None);
+        let (mut s, mut r) = (p1.0, p2.1);
+        let event = ProtocolEvent::OpenStream {
+            sid: Sid::new(10),
+            prio: 3u8,
+            promises: Promises::ORDERED,
+            guaranteed_bandwidth: 1_000_000,
+        };
+        s.send(event).await.unwrap();
+        let _ = r.recv().await.unwrap();
+        let event = ProtocolEvent::Message {
+            sid: Sid::new(10),
+            data: Bytes::from(&[188u8; 600][..]),
+        };
+        s.send(event.clone()).await.unwrap();
+        s.flush(1_000_000, Duration::from_secs(1)).await.unwrap();
+        let e = r.recv().await.unwrap();
+        assert_eq!(event, e);
+        // 2nd short message
+        let event = ProtocolEvent::Message {
+            sid: Sid::new(10),
+            data: Bytes::from(&[7u8; 30][..]),
+        };
+        s.send(event.clone()).await.unwrap();
+        s.flush(1_000_000, Duration::from_secs(1)).await.unwrap();
+        let e = r.recv().await.unwrap();
+        assert_eq!(event, e)
+    }
+
+    #[tokio::test]
+    async fn send_long_msg() {
+        let mut metrics =
+            ProtocolMetricCache::new("long_udp", Arc::new(ProtocolMetrics::new().unwrap()));
+        let sid = Sid::new(1);
+        let [p1, p2] = udp_bound(10000, 0.0, Some(metrics.clone()));
+        let (mut s, mut r) = (p1.0, p2.1);
+        let event = ProtocolEvent::OpenStream {
+            sid,
+            prio: 5u8,
+            promises: Promises::COMPRESSED | Promises::ORDERED,
+            guaranteed_bandwidth: 1_000_000,
+        };
+        s.send(event).await.unwrap();
+        let _ = r.recv().await.unwrap();
+        let event = ProtocolEvent::Message {
+            sid,
+            data: Bytes::from(&[99u8; 500_000][..]),
+        };
+        s.send(event.clone()).await.unwrap();
+        s.flush(1_000_000, Duration::from_secs(1)).await.unwrap();
+        let e = r.recv().await.unwrap();
+        assert_eq!(event, e);
+        metrics.assert_msg(sid, 1, RemoveReason::Finished);
+        metrics.assert_msg_bytes(sid, 500_000, RemoveReason::Finished);
+        metrics.assert_data_frames(358);
+        metrics.assert_data_frames_bytes(500_000);
+    }
+
+    #[tokio::test]
+    async fn msg_finishes_after_close() {
+        let sid = Sid::new(1);
+        let [p1, p2] = udp_bound(10000, 0.0, None);
+        let (mut s, mut r) = (p1.0, p2.1);
+        let event = ProtocolEvent::OpenStream {
+            sid,
+            prio: 5u8,
+            promises: Promises::COMPRESSED,
+            guaranteed_bandwidth: 0,
+        };
+        s.send(event).await.unwrap();
+        let _ = r.recv().await.unwrap();
+        let event = ProtocolEvent::Message {
+            sid,
+            data: Bytes::from(&[99u8; 500_000][..]),
+        };
+        s.send(event).await.unwrap();
+        let event = ProtocolEvent::CloseStream { sid };
+        s.send(event).await.unwrap();
+        //send
+        s.flush(1_000_000, Duration::from_secs(1)).await.unwrap();
+        let e = r.recv().await.unwrap();
+        assert!(matches!(e, ProtocolEvent::Message { .. }));
+        let e = r.recv().await.unwrap();
+        assert!(matches!(e, ProtocolEvent::CloseStream { .. }));
+    }
+
+    #[tokio::test]
+    async fn msg_finishes_after_shutdown() {
+        let sid = Sid::new(1);
+        let [p1, p2] = udp_bound(10000, 0.0, None);
+        let (mut s, mut r) = (p1.0, p2.1);
+        let event = ProtocolEvent::OpenStream {
+            sid,
+            prio: 5u8,
+            promises: Promises::COMPRESSED,
+            guaranteed_bandwidth: 0,
+        };
+        s.send(event).await.unwrap();
+        let _ = r.recv().await.unwrap();
+        let event = ProtocolEvent::Message {
+            sid,
+            data: Bytes::from(&[99u8; 500_000][..]),
+        };
+        s.send(event).await.unwrap();
+        let event = ProtocolEvent::Shutdown {};
+        s.send(event).await.unwrap();
+        let event = ProtocolEvent::CloseStream { sid };
+        s.send(event).await.unwrap();
+        //send
+        s.flush(1_000_000, Duration::from_secs(1)).await.unwrap();
+        let e = r.recv().await.unwrap();
+        assert!(matches!(e, ProtocolEvent::Message { .. }));
+        let e = r.recv().await.unwrap();
+        assert!(matches!(e, ProtocolEvent::CloseStream { .. }));
+        let e = r.recv().await.unwrap();
+        assert!(matches!(e, ProtocolEvent::Shutdown { .. }));
+    }
+
+    #[tokio::test]
+    async fn msg_finishes_after_drop() {
+        let sid = Sid::new(1);
+        let [p1, p2] = udp_bound(10000, 0.0, None);
+        let (mut s, mut r) = (p1.0, p2.1);
+        let event = ProtocolEvent::OpenStream {
+            sid,
+            prio: 5u8,
+            promises: Promises::COMPRESSED,
+            guaranteed_bandwidth: 0,
+        };
+        s.send(event).await.unwrap();
+        let event = ProtocolEvent::Message {
+            sid,
+            data: Bytes::from(&[99u8; 500_000][..]),
+        };
+        s.send(event).await.unwrap();
+        s.flush(1_000_000, Duration::from_secs(1)).await.unwrap();
+        let event = ProtocolEvent::Message {
+            sid,
+            data: Bytes::from(&[100u8; 500_000][..]),
+        };
+        s.send(event).await.unwrap();
+        s.flush(1_000_000, Duration::from_secs(1)).await.unwrap();
+        drop(s);
+        let e = r.recv().await.unwrap();
+        assert!(matches!(e, ProtocolEvent::OpenStream { .. }));
+        let e = r.recv().await.unwrap();
+        assert!(matches!(e, ProtocolEvent::Message { .. }));
+        let e = r.recv().await.unwrap();
+        assert!(matches!(e, ProtocolEvent::Message { .. }));
+    }
+
+    #[tokio::test]
+    async fn guaranteed_delivery_with_loss() {
+        let sid = Sid::new(1);
+        let [p1, p2] = udp_bound(10000, 0.2, None);
+        let ((mut s, mut ack_r), (_, mut r)) = (p1, p2);
+        // the acknowledgements for `s` are received by its own recv side
+        let acks = tokio::spawn(async move { ack_r.recv().await });
+        s.send(ProtocolEvent::OpenStream {
+            sid,
+            prio: 5u8,
+            promises: Promises::GUARANTEED_DELIVERY | Promises::ORDERED,
+            guaranteed_bandwidth: 0,
+        })
+        .await
+        .unwrap();
+        for i in 0..50u8 {
+            s.send(ProtocolEvent::Message {
+                sid,
+                data: Bytes::from(vec![i; 2000]),
+            })
+            .await
+            .unwrap();
+        }
+        let flush = tokio::spawn(async move {
+            loop {
+                s.flush(1_000_000, Duration::from_millis(10)).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        });
+        let e = r.recv().await.unwrap();
+        assert!(matches!(e, ProtocolEvent::OpenStream { .. }));
+        for i in 0..50u8 {
+            let e = r.recv().await.unwrap();
+            assert_eq!(e, ProtocolEvent::Message {
+                sid,
+                data: Bytes::from(vec![i; 2000]),
+            });
+        }
+        flush.abort();
+        acks.abort();
+    }
+
+    #[tokio::test]
+    async fn unreliable_msgs_stay_ordered() {
+        let sid = Sid::new(1);
+        let [p1, p2] = udp_bound(10000, 0.0, None);
+        let ((mut s, _r1), (_s2, mut r)) = (p1, p2);
+        s.send(ProtocolEvent::OpenStream {
+            sid,
+            prio: 5u8,
+            promises: Promises::ORDERED,
+            guaranteed_bandwidth: 0,
+        })
+        .await
+        .unwrap();
+        let e = r.recv().await.unwrap();
+        assert!(matches!(e, ProtocolEvent::OpenStream { .. }));
+        s.drain.drop_ratio = 0.5;
+        for i in 0..100u8 {
+            s.send(ProtocolEvent::Message {
+                sid,
+                data: Bytes::from(vec![i; 3000]),
+            })
+            .await
+            .unwrap();
+            s.flush(1_000_000, Duration::from_secs(1)).await.unwrap();
+        }
+        let mut last = None;
+        while let Ok(e) = tokio::time::timeout(Duration::from_millis(200), r.recv()).await {
+            match e.unwrap() {
+                ProtocolEvent::Message { data, .. } => {
+                    assert_eq!(data.len(), 3000);
+                    assert!(data.iter().all(|b| *b == data[0]));
+                    assert!(last < Some(data[0]));
+                    last = Some(data[0]);
+                },
+                e => panic!("unexpected event {:?}", e),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn corrupted_datagram_dropped() {
+        let sid = Sid::new(1);
+        let (s1, r1) = async_channel::bounded(10);
+        let (s2, r2) = async_channel::bounded(10);
+        let m = ProtocolMetricCache::new("udp", Arc::new(ProtocolMetrics::new().unwrap()));
+        let mut s = super::UdpSendProtocol::new(
+            UdpDrain {
+                sender: s1,
+                drop_ratio: 0.0,
+            },
+            m.clone(),
+        );
+        let mut r = super::UdpRecvProtocol::new(UdpSink { receiver: r2 }, &s, m);
+        s.send(ProtocolEvent::OpenStream {
+            sid,
+            prio: 5u8,
+            promises: Promises::CONSISTENCY,
+            guaranteed_bandwidth: 0,
+        })
+        .await
+        .unwrap();
+        s2.send(r1.recv().await.unwrap()).await.unwrap();
+        let e = r.recv().await.unwrap();
+        assert!(matches!(e, ProtocolEvent::OpenStream { .. }));
+        // the acknowledgement of the OpenStream went through the same drain
+        let _ = r1.recv().await.unwrap();
+
+        let event = ProtocolEvent::Message {
+            sid,
+            data: Bytes::from(&[42u8; 100][..]),
+        };
+        s.send(event.clone()).await.unwrap();
+        s.flush(1_000_000, Duration::from_secs(1)).await.unwrap();
+        let mut datagram = r1.recv().await.unwrap();
+        let last = datagram.len() - 1;
+        datagram[last] ^= 0b1000;
+        s2.send(datagram).await.unwrap();
+        s.send(event.clone()).await.unwrap();
+        s.flush(1_000_000, Duration::from_secs(1)).await.unwrap();
+        s2.send(r1.recv().await.unwrap()).await.unwrap();
+
+        let e = r.recv().await.unwrap();
+        assert_eq!(event, e);
+        assert!(r1.is_empty());
+        drop(s2);
+        let e = r.recv().await;
+        assert_eq!(e, Err(ProtocolError::Closed));
+    }
+
+    #[tokio::test]
+    async fn closes_when_unacked_pile_up() {
+        let sid = Sid::new(1);
+        let [p1, _p2] = udp_bound(10000, 0.0, None);
+        let mut s = p1.0;
+        s.send(ProtocolEvent::OpenStream {
+            sid,
+            prio: 5u8,
+            promises: Promises::GUARANTEED_DELIVERY,
+            guaranteed_bandwidth: 0,
+        })
+        .await
+        .unwrap();
+        // nobody acknowledges these
+        s.send(ProtocolEvent::Message {
+            sid,
+            data: Bytes::from(vec![1u8; 6_000_000]),
+        })
+        .await
+        .unwrap();
+        let e = s.flush(100_000_000, Duration::from_secs(1)).await;
+        assert_eq!(e, Err(ProtocolError::Closed));
+    }
+
+    #[tokio::test]
+    async fn control_segment_too_far_ahead() {
+        let (s1, _r1) = async_channel::bounded(10);
+        let (s2, r2) = async_channel::bounded(10);
+        let m = ProtocolMetricCache::new("udp", Arc::new(ProtocolMetrics::new().unwrap()));
+        let s = super::UdpSendProtocol::new(
+            UdpDrain {
+                sender: s1,
+                drop_ratio: 0.0,
+            },
+            m.clone(),
+        );
+        let mut r = super::UdpRecvProtocol::new(UdpSink { receiver: r2 }, &s, m);
+        let segment = super::write_segment(
+            super::SegmentId::Control(super::MAX_UNACKED as u64),
+            &[0u8; 8],
+        );
+        let mut datagram = BytesMut::new();
+        datagram.put_u32_le(super::checksum(&segment));
+        datagram.extend_from_slice(&segment);
+        s2.send(datagram).await.unwrap();
+        let e = r.recv().await;
+        assert_eq!(e, Err(ProtocolError::Violated));
+    }
+
+    #[tokio::test]
+    #[should_panic]
+    async fn send_on_stream_from_remote_without_notify() {
+        //remote opens stream
+        //we send on it
+        let [mut p1, mut p2] = udp_bound(10, 0.0, None);
+        let event = ProtocolEvent::OpenStream {
+            sid: Sid::new(10),
+            prio: 3u8,
+            promises: Promises::ORDERED,
+            guaranteed_bandwidth: 1_000_000,
+        };
+        p1.0.send(event).await.unwrap();
+        let _ = p2.1.recv().await.unwrap();
+        let event = ProtocolEvent::Message {
+            sid: Sid::new(10),
+            data: Bytes::from(&[188u8; 600][..]),
+        };
+        p2.0.send(event.clone()).await.unwrap();
+        p2.0.flush(1_000_000, Duration::from_secs(1)).await.unwrap();
+        let e = p1.1.recv().await.unwrap();
+        assert_eq!(event, e);
+    }
+
+    #[tokio::test]
+    async fn send_on_stream_from_remote() {
+        //remote opens stream
+        //we send on it
+        let [mut p1, mut p2] = udp_bound(10, 0.0, None);
+        let event = ProtocolEvent::OpenStream {
+            sid: Sid::new(10),
+            prio: 3u8,
+            promises: Promises::ORDERED,
+            guaranteed_bandwidth: 1_000_000,
+        };
+        p1.0.send(event).await.unwrap();
+        let e = p2.1.recv().await.unwrap();
+        p2.0.notify_from_recv(e);
+        let event = ProtocolEvent::Message {
+            sid: Sid::new(10),
+            data: Bytes::from(&[188u8; 600][..]),
+        };
+        p2.0.send(event.clone()).await.unwrap();
+        p2.0.flush(1_000_000, Duration::from_secs(1)).await.unwrap();
+        let e = p1.1.recv().await.unwrap();
+        assert_eq!(event, e);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::{
+    impairment::Impairment,
     message::{partial_eq_bincode, Message},
     participant::{A2bStreamOpen, S2bShutdownBparticipant},
     scheduler::{A2sConnect, Scheduler},
@@ -28,26 +29,67 @@ use tracing::*;
 
 type A2sDisconnect = Arc<Mutex<Option<mpsc::UnboundedSender<(Pid, S2bShutdownBparticipant)>>>>;
 
-/// Represents a Tcp, Quic, Udp or Mpsc connection address
-#[derive(Clone, Debug)]
+/// Represents a Tcp, Tls, Quic, Udp or Mpsc connection address
+#[derive(Clone)]
 pub enum ConnectAddr {
     Tcp(SocketAddr),
+    /// Tcp encrypted with TLS, the certificate of the server is verified for
+    /// the given server name
+    Tls(SocketAddr, Arc<rustls::ClientConfig>, String),
     Udp(SocketAddr),
     #[cfg(feature = "quic")]
     Quic(SocketAddr, quinn::ClientConfig, String),
     Mpsc(u64),
 }
 
-/// Represents a Tcp, Quic, Udp or Mpsc listen address
-#[derive(Clone, Debug)]
+/// Represents a Tcp, Tls, Quic, Udp or Mpsc listen address
+#[derive(Clone)]
 pub enum ListenAddr {
     Tcp(SocketAddr),
+    /// Tcp on which every connection has to be encrypted with TLS
+    Tls(SocketAddr, Arc<rustls::ServerConfig>),
     Udp(SocketAddr),
     #[cfg(feature = "quic")]
     Quic(SocketAddr, quinn::ServerConfig),
     Mpsc(u64),
 }
 
+// rustls configs don't implement Debug
+impl std::fmt::Debug for ConnectAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectAddr::Tcp(addr) => f.debug_tuple("Tcp").field(addr).finish(),
+            ConnectAddr::Tls(addr, _, name) => {
+                f.debug_tuple("Tls").field(addr).field(name).finish()
+            },
+            ConnectAddr::Udp(addr) => f.debug_tuple("Udp").field(addr).finish(),
+            #[cfg(feature = "quic")]
+            ConnectAddr::Quic(addr, config, name) => f
+                .debug_tuple("Quic")
+                .field(addr)
+                .field(config)
+                .field(name)
+                .finish(),
+            ConnectAddr::Mpsc(addr) => f.debug_tuple("Mpsc").field(addr).finish(),
+        }
+    }
+}
+
+impl std::fmt::Debug for ListenAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => f.debug_tuple("Tcp").field(addr).finish(),
+            ListenAddr::Tls(addr, _) => f.debug_tuple("Tls").field(addr).finish(),
+            ListenAddr::Udp(addr) => f.debug_tuple("Udp").field(addr).finish(),
+            #[cfg(feature = "quic")]
+            ListenAddr::Quic(addr, config) => {
+                f.debug_tuple("Quic").field(addr).field(config).finish()
+            },
+            ListenAddr::Mpsc(addr) => f.debug_tuple("Mpsc").field(addr).finish(),
+        }
+    }
+}
+
 /// `Participants` are generated by the [`Network`] and represent a connection
 /// to a remote Participant. Look at the [`connect`] and [`connected`] method of
 /// [`Networks`] on how to generate `Participants`
@@ -58,6 +100,7 @@ pub enum ListenAddr {
 pub struct Participant {
     local_pid: Pid,
     remote_pid: Pid,
+    remote_addr: Option<SocketAddr>,
     a2b_open_stream_s: Mutex<mpsc::UnboundedSender<A2bStreamOpen>>,
     b2a_stream_opened_r: Mutex<mpsc::UnboundedReceiver<Stream>>,
     b2a_bandwidth_stats_r: watch::Receiver<f32>,
@@ -230,6 +273,41 @@ impl Network {
             runtime,
             #[cfg(feature = "metrics")]
             None,
+            Impairment::from_env(),
+        )
+    }
+
+    /// See [`new`]
+    ///
+    /// # additional Arguments
+    /// * `impairment` - Simulate a bad connection for all traffic received by
+    ///   this `Network`, see [`Impairment`]. Only meant for testing!
+    ///
+    /// # Examples
+    /// ```rust
+    /// use std::time::Duration;
+    /// use tokio::runtime::Runtime;
+    /// use veloren_network::{Impairment, Network, Pid};
+    ///
+    /// let runtime = Runtime::new().unwrap();
+    /// let network = Network::new_with_impairment(Pid::new(), &runtime, Impairment {
+    ///     latency: Duration::from_millis(200),
+    ///     ..Impairment::default()
+    /// });
+    /// ```
+    /// [`new`]: crate::api::Network::new
+    /// [`Impairment`]: crate::Impairment
+    pub fn new_with_impairment(
+        participant_id: Pid,
+        runtime: &Runtime,
+        impairment: Impairment,
+    ) -> Self {
+        Self::internal_new(
+            participant_id,
+            runtime,
+            #[cfg(feature = "metrics")]
+            None,
+            Some(impairment),
         )
     }
 
@@ -253,22 +331,32 @@ impl Network {
     /// [`new`]: crate::api::Network::new
     #[cfg(feature = "metrics")]
     pub fn new_with_registry(participant_id: Pid, runtime: &Runtime, registry: &Registry) -> Self {
-        Self::internal_new(participant_id, runtime, Some(registry))
+        Self::internal_new(
+            participant_id,
+            runtime,
+            Some(registry),
+            Impairment::from_env(),
+        )
     }
 
     fn internal_new(
         participant_id: Pid,
         runtime: &Runtime,
         #[cfg(feature = "metrics")] registry: Option<&Registry>,
+        impairment: Option<Impairment>,
     ) -> Self {
         let p = participant_id;
         let span = tracing::info_span!("network", ?p);
         span.in_scope(|| trace!("Starting Network"));
+        if let Some(impairment) = impairment {
+            span.in_scope(|| warn!(?impairment, "Simulating a bad connection"));
+        }
         let (scheduler, listen_sender, connect_sender, connected_receiver, shutdown_sender) =
             Scheduler::new(
                 participant_id,
                 #[cfg(feature = "metrics")]
                 registry,
+                impairment,
             );
         let participant_disconnect_sender = Arc::new(Mutex::new(HashMap::new()));
         let (shutdown_network_s, shutdown_network_r) = oneshot::channel();
@@ -363,9 +451,6 @@ impl Network {
     ///     let p1 = network
     ///         .connect(ConnectAddr::Tcp("127.0.0.1:2010".parse().unwrap()))
     ///         .await?;
-    ///     # //this doesn't work yet, so skip the test
-    ///     # //TODO fixme!
-    ///     # return Ok(());
     ///     let p2 = network
     ///         .connect(ConnectAddr::Udp("127.0.0.1:2011".parse().unwrap()))
     ///         .await?;
@@ -517,6 +602,7 @@ impl Participant {
     pub(crate) fn new(
         local_pid: Pid,
         remote_pid: Pid,
+        remote_addr: Option<SocketAddr>,
         a2b_open_stream_s: mpsc::UnboundedSender<A2bStreamOpen>,
         b2a_stream_opened_r: mpsc::UnboundedReceiver<Stream>,
         b2a_bandwidth_stats_r: watch::Receiver<f32>,
@@ -525,6 +611,7 @@ impl Participant {
         Self {
             local_pid,
             remote_pid,
+            remote_addr,
             a2b_open_stream_s: Mutex::new(a2b_open_stream_s),
             b2a_stream_opened_r: Mutex::new(b2a_stream_opened_r),
             b2a_bandwidth_stats_r,
@@ -757,6 +844,10 @@ impl Participant {
 
     /// Returns the remote [`Pid`](network_protocol::Pid)
     pub fn remote_pid(&self) -> Pid { self.remote_pid }
+
+    /// Returns the address the first channel of this `Participant` was
+    /// established from, `None` for in-process (mpsc) channels.
+    pub fn remote_address(&self) -> Option<SocketAddr> { self.remote_addr }
 }
 
 impl Stream {
@@ -1190,8 +1281,8 @@ impl std::fmt::Debug for Participant {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "Participant {{ local_pid: {:?}, remote_pid: {:?} }}",
-            &self.local_pid, &self.remote_pid,
+            "Participant {{ local_pid: {:?}, remote_pid: {:?}, remote_addr: {:?} }}",
+            &self.local_pid, &self.remote_pid, &self.remote_addr,
         )
     }
 }
@@ -1,4 +1,4 @@
-use crate::api::NetworkConnectError;
+use crate::{api::NetworkConnectError, impairment::ImpairedRecvProtocol};
 use async_trait::async_trait;
 use bytes::BytesMut;
 use futures_util::FutureExt;
@@ -8,25 +8,31 @@ use hashbrown::HashMap;
 use network_protocol::{
     Bandwidth, Cid, InitProtocolError, MpscMsg, MpscRecvProtocol, MpscSendProtocol, Pid,
     ProtocolError, ProtocolEvent, ProtocolMetricCache, ProtocolMetrics, Sid, TcpRecvProtocol,
-    TcpSendProtocol, UnreliableDrain, UnreliableSink,
+    TcpSendProtocol, TlsDrain, TlsSink, UdpRecvProtocol, UdpSendProtocol, UnreliableDrain,
+    UnreliableSink,
 };
 #[cfg(feature = "quic")]
 use network_protocol::{QuicDataFormat, QuicDataFormatStream, QuicRecvProtocol, QuicSendProtocol};
 use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash, Hasher},
     io,
     net::SocketAddr,
     sync::{
         atomic::{AtomicU64, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net,
     net::tcp::{OwnedReadHalf, OwnedWriteHalf},
     select,
-    sync::{mpsc, oneshot, Mutex},
+    sync::{
+        mpsc::{self, error::TrySendError},
+        oneshot, Mutex,
+    },
 };
 use tracing::{error, info, trace, warn};
 
@@ -34,6 +40,18 @@ use tracing::{error, info, trace, warn};
 #[derive(Debug)]
 pub(crate) enum Protocols {
     Tcp((TcpSendProtocol<TcpDrain>, TcpRecvProtocol<TcpSink>)),
+    Tls(
+        (
+            TcpSendProtocol<TlsDrain<TcpDrain>>,
+            TcpRecvProtocol<TlsSink<TcpSink>>,
+        ),
+    ),
+    Udp(
+        (
+            UdpSendProtocol<UdpDrain>,
+            UdpRecvProtocol<UdpSink, UdpDrain>,
+        ),
+    ),
     Mpsc((MpscSendProtocol<MpscDrain>, MpscRecvProtocol<MpscSink>)),
     #[cfg(feature = "quic")]
     Quic((QuicSendProtocol<QuicDrain>, QuicRecvProtocol<QuicSink>)),
@@ -42,6 +60,8 @@ pub(crate) enum Protocols {
 #[derive(Debug)]
 pub(crate) enum SendProtocols {
     Tcp(TcpSendProtocol<TcpDrain>),
+    Tls(TcpSendProtocol<TlsDrain<TcpDrain>>),
+    Udp(UdpSendProtocol<UdpDrain>),
     Mpsc(MpscSendProtocol<MpscDrain>),
     #[cfg(feature = "quic")]
     Quic(QuicSendProtocol<QuicDrain>),
@@ -50,9 +70,12 @@ pub(crate) enum SendProtocols {
 #[derive(Debug)]
 pub(crate) enum RecvProtocols {
     Tcp(TcpRecvProtocol<TcpSink>),
+    Tls(TcpRecvProtocol<TlsSink<TcpSink>>),
+    Udp(UdpRecvProtocol<UdpSink, UdpDrain>),
     Mpsc(MpscRecvProtocol<MpscSink>),
     #[cfg(feature = "quic")]
     Quic(QuicRecvProtocol<QuicSink>),
+    Impaired(Box<ImpairedRecvProtocol>),
 }
 
 lazy_static::lazy_static! {
@@ -68,6 +91,9 @@ pub(crate) type C2cMpscConnect = (
 
 impl Protocols {
     const MPSC_CHANNEL_BOUND: usize = 1000;
+    const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+    const UDP_CONNECT_ATTEMPTS: u32 = 10;
+    const UDP_CONNECT_INTERVAL: Duration = Duration::from_millis(500);
 
     pub(crate) async fn with_tcp_connect(
         addr: SocketAddr,
@@ -92,7 +118,7 @@ impl Protocols {
         cids: Arc<AtomicU64>,
         metrics: Arc<ProtocolMetrics>,
         s2s_stop_listening_r: oneshot::Receiver<()>,
-        c2s_protocol_s: mpsc::UnboundedSender<(Self, Cid)>,
+        c2s_protocol_s: mpsc::UnboundedSender<(Self, Option<SocketAddr>, Cid)>,
     ) -> std::io::Result<()> {
         let listener = net::TcpListener::bind(addr).await?;
         trace!(?addr, "Tcp Listener bound");
@@ -118,7 +144,11 @@ impl Protocols {
                 let cid = cids.fetch_add(1, Ordering::Relaxed);
                 info!(?remote_addr, ?cid, "Accepting Tcp from");
                 let metrics = ProtocolMetricCache::new(&cid.to_string(), Arc::clone(&metrics));
-                let _ = c2s_protocol_s.send((Self::new_tcp(stream, metrics.clone()), cid));
+                let _ = c2s_protocol_s.send((
+                    Self::new_tcp(stream, metrics.clone()),
+                    Some(remote_addr),
+                    cid,
+                ));
             }
         });
         Ok(())
@@ -137,6 +167,310 @@ impl Protocols {
         Protocols::Tcp((sp, rp))
     }
 
+    pub(crate) async fn with_tls_connect(
+        addr: SocketAddr,
+        config: Arc<rustls::ClientConfig>,
+        name: String,
+        metrics: ProtocolMetricCache,
+    ) -> Result<Self, NetworkConnectError> {
+        use std::convert::TryFrom;
+
+        let server_name = rustls::ServerName::try_from(name.as_str())
+            .map_err(|e| NetworkConnectError::Io(io::Error::new(io::ErrorKind::InvalidInput, e)))?;
+        let conn = rustls::ClientConnection::new(config, server_name)
+            .map_err(|e| NetworkConnectError::Io(io::Error::new(io::ErrorKind::InvalidInput, e)))?;
+        let stream = net::TcpStream::connect(addr)
+            .await
+            .and_then(|s| {
+                s.set_nodelay(true)?;
+                Ok(s)
+            })
+            .map_err(NetworkConnectError::Io)?;
+        info!("Connecting Tls to: {}", &addr);
+        Self::new_tls(stream, conn.into(), metrics)
+            .await
+            .map_err(|e| {
+                trace!(?e, "error with tls");
+                NetworkConnectError::Io(io::Error::new(io::ErrorKind::ConnectionAborted, e))
+            })
+    }
+
+    pub(crate) async fn with_tls_listen(
+        addr: SocketAddr,
+        server_config: Arc<rustls::ServerConfig>,
+        cids: Arc<AtomicU64>,
+        metrics: Arc<ProtocolMetrics>,
+        s2s_stop_listening_r: oneshot::Receiver<()>,
+        c2s_protocol_s: mpsc::UnboundedSender<(Self, Option<SocketAddr>, Cid)>,
+    ) -> std::io::Result<()> {
+        let listener = net::TcpListener::bind(addr).await?;
+        trace!(?addr, "Tls Listener bound");
+        let mut end_receiver = s2s_stop_listening_r.fuse();
+        tokio::spawn(async move {
+            while let Some(data) = select! {
+                    next = listener.accept().fuse() => Some(next),
+                    _ = &mut end_receiver => None,
+            } {
+                let (stream, remote_addr) = match data {
+                    Ok((s, p)) => (s, p),
+                    Err(e) => {
+                        trace!(?e, "TcpStream Error, ignoring connection attempt");
+                        continue;
+                    },
+                };
+                if let Err(e) = stream.set_nodelay(true) {
+                    warn!(
+                        ?e,
+                        "Failed to set TCP_NODELAY, client may have degraded latency"
+                    );
+                }
+                let cid = cids.fetch_add(1, Ordering::Relaxed);
+                let metrics = ProtocolMetricCache::new(&cid.to_string(), Arc::clone(&metrics));
+                let server_config = Arc::clone(&server_config);
+                let c2s_protocol_s = c2s_protocol_s.clone();
+                // don't block other connections while waiting for the TLS handshake
+                tokio::spawn(async move {
+                    info!(?remote_addr, ?cid, "Accepting Tls from");
+                    let conn = match rustls::ServerConnection::new(server_config) {
+                        Ok(conn) => conn,
+                        Err(e) => {
+                            error!(?e, "failed to start tls");
+                            return;
+                        },
+                    };
+                    let protocol = match tokio::time::timeout(
+                        Self::TLS_HANDSHAKE_TIMEOUT,
+                        Self::new_tls(stream, conn.into(), metrics),
+                    )
+                    .await
+                    {
+                        Ok(Ok(tls)) => tls,
+                        Ok(Err(e)) => {
+                            trace!(?e, ?remote_addr, "tls handshake failed");
+                            return;
+                        },
+                        Err(_) => {
+                            trace!(?remote_addr, "tls handshake timed out");
+                            return;
+                        },
+                    };
+                    let _ = c2s_protocol_s.send((protocol, Some(remote_addr), cid));
+                });
+            }
+        });
+        Ok(())
+    }
+
+    pub(crate) async fn new_tls(
+        stream: tokio::net::TcpStream,
+        conn: rustls::Connection,
+        metrics: ProtocolMetricCache,
+    ) -> Result<Self, ProtocolError> {
+        let (r, w) = stream.into_split();
+        let (drain, sink) = network_protocol::tls_handshake(conn, TcpDrain { half: w }, TcpSink {
+            half: r,
+            buffer: BytesMut::new(),
+        })
+        .await?;
+        let sp = TcpSendProtocol::new(drain, metrics.clone());
+        let rp = TcpRecvProtocol::new(sink, metrics);
+        Ok(Protocols::Tls((sp, rp)))
+    }
+
+    pub(crate) async fn with_udp_connect(
+        addr: SocketAddr,
+        metrics: ProtocolMetricCache,
+    ) -> Result<Self, NetworkConnectError> {
+        use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+        let bindsock = match addr {
+            SocketAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+            SocketAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0),
+        };
+        let socket = net::UdpSocket::bind(bindsock)
+            .await
+            .map_err(NetworkConnectError::Io)?;
+        info!("Connecting Udp to: {}", &addr);
+        // Udp has no connection, so we first ask the listener for a cookie, proving
+        // that we receive what is sent to our address, then send it back until the
+        // listener answers with its handshake
+        let mut buffer = [0u8; UDP_BUFFER_SIZE];
+        let mut cookie = None;
+        let mut first_datagram = None;
+        'attempts: for _ in 0..Self::UDP_CONNECT_ATTEMPTS {
+            let request = match cookie {
+                Some(cookie) => udp_handshake_datagram(UDP_CONNECT, cookie),
+                None => udp_handshake_datagram(UDP_HELLO, 0),
+            };
+            socket
+                .send_to(&request, addr)
+                .await
+                .map_err(NetworkConnectError::Io)?;
+            let deadline = tokio::time::Instant::now() + Self::UDP_CONNECT_INTERVAL;
+            while let Ok(r) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buffer)).await
+            {
+                let (n, remote_addr) = r.map_err(NetworkConnectError::Io)?;
+                if remote_addr != addr {
+                    continue;
+                }
+                match read_udp_handshake_datagram(UDP_COOKIE, &buffer[..n]) {
+                    Some(new_cookie) => {
+                        cookie = Some(new_cookie);
+                        continue 'attempts;
+                    },
+                    None if cookie.is_some() => {
+                        first_datagram = Some(BytesMut::from(&buffer[..n]));
+                        break 'attempts;
+                    },
+                    None => {},
+                }
+            }
+        }
+        let first_datagram = first_datagram.ok_or_else(|| {
+            NetworkConnectError::Io(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "no udp listener answered",
+            ))
+        })?;
+
+        let socket = Arc::new(socket);
+        let (datagrams_s, datagrams_r) = mpsc::channel(UDP_CHANNEL_BOUND);
+        let _ = datagrams_s.try_send(first_datagram);
+        let read_socket = Arc::clone(&socket);
+        tokio::spawn(async move {
+            let mut buffer = [0u8; UDP_BUFFER_SIZE];
+            while let Some(r) = select! {
+                next = read_socket.recv_from(&mut buffer).fuse() => Some(next),
+                _ = datagrams_s.closed().fuse() => None,
+            } {
+                match r {
+                    Ok((n, remote_addr)) if remote_addr == addr => {
+                        match datagrams_s.try_send(BytesMut::from(&buffer[..n])) {
+                            Ok(()) => (),
+                            Err(TrySendError::Full(_)) => {
+                                trace!("Udp channel is full, dropping datagram")
+                            },
+                            Err(TrySendError::Closed(_)) => break,
+                        }
+                    },
+                    Ok(_) => (),
+                    Err(e) => trace!(?e, "UdpSocket Error, ignoring datagram"),
+                }
+            }
+        });
+        Ok(Self::new_udp(socket, addr, datagrams_r, metrics))
+    }
+
+    pub(crate) async fn with_udp_listen(
+        addr: SocketAddr,
+        cids: Arc<AtomicU64>,
+        metrics: Arc<ProtocolMetrics>,
+        s2s_stop_listening_r: oneshot::Receiver<()>,
+        c2s_protocol_s: mpsc::UnboundedSender<(Self, Option<SocketAddr>, Cid)>,
+    ) -> std::io::Result<()> {
+        let socket = Arc::new(net::UdpSocket::bind(addr).await?);
+        trace!(?addr, "Udp Listener bound");
+        let mut end_receiver = s2s_stop_listening_r.fuse();
+        tokio::spawn(async move {
+            // all remotes share one socket, so it needs to stay open until the last
+            // accepted channel is gone, even if we stopped listening
+            let mut listening = true;
+            let mut remotes: HashMap<SocketAddr, UdpRemote> = HashMap::new();
+            let cookies = UdpCookies::new();
+            let mut buffer = [0u8; UDP_BUFFER_SIZE];
+            while listening || !remotes.is_empty() {
+                let (n, remote_addr) = select! {
+                    next = socket.recv_from(&mut buffer).fuse() => match next {
+                        Ok(v) => v,
+                        Err(e) => {
+                            trace!(?e, "UdpSocket Error, ignoring datagram");
+                            continue;
+                        },
+                    },
+                    _ = &mut end_receiver => {
+                        listening = false;
+                        remotes.retain(|_, remote| !remote.datagrams_s.is_closed());
+                        continue;
+                    },
+                };
+                if let Some(remote) = remotes.get(&remote_addr) {
+                    match remote.datagrams_s.try_send(BytesMut::from(&buffer[..n])) {
+                        Ok(()) => (),
+                        Err(TrySendError::Full(_)) => {
+                            trace!(?remote_addr, "Udp channel is full, dropping datagram")
+                        },
+                        Err(TrySendError::Closed(_)) => {
+                            remotes.remove(&remote_addr);
+                        },
+                    }
+                    continue;
+                }
+                if !listening {
+                    continue;
+                }
+                // Nothing is allocated for a remote until it proved to receive what is
+                // sent to its address, otherwise spoofed addresses could be flooded with
+                // our handshake. The cookie answer isn't larger than the hello.
+                let now = Instant::now();
+                if read_udp_handshake_datagram(UDP_HELLO, &buffer[..n]).is_some() {
+                    let answer =
+                        udp_handshake_datagram(UDP_COOKIE, cookies.cookie(remote_addr, now));
+                    if let Err(e) = socket.send_to(&answer, remote_addr).await {
+                        trace!(?e, "UdpSocket Error, couldn't send cookie");
+                    }
+                    continue;
+                }
+                match read_udp_handshake_datagram(UDP_CONNECT, &buffer[..n]) {
+                    Some(cookie) if cookies.verify(remote_addr, cookie, now) => {},
+                    _ => {
+                        trace!(?remote_addr, "Ignoring Udp datagram without a valid cookie");
+                        continue;
+                    },
+                }
+                remotes.retain(|_, remote| !remote.datagrams_s.is_closed());
+                let pending = remotes
+                    .values()
+                    .filter(|remote| now.duration_since(remote.accepted) < UDP_PENDING_DURATION)
+                    .count();
+                if pending >= MAX_PENDING_UDP_REMOTES {
+                    warn!(?remote_addr, "Too many pending Udp connections, ignoring");
+                    continue;
+                }
+                let cid = cids.fetch_add(1, Ordering::Relaxed);
+                info!(?remote_addr, ?cid, "Accepting Udp from");
+                let (datagrams_s, datagrams_r) = mpsc::channel(UDP_CHANNEL_BOUND);
+                remotes.insert(remote_addr, UdpRemote {
+                    datagrams_s,
+                    accepted: now,
+                });
+                let metrics = ProtocolMetricCache::new(&cid.to_string(), Arc::clone(&metrics));
+                let _ = c2s_protocol_s.send((
+                    Self::new_udp(Arc::clone(&socket), remote_addr, datagrams_r, metrics),
+                    Some(remote_addr),
+                    cid,
+                ));
+            }
+        });
+        Ok(())
+    }
+
+    pub(crate) fn new_udp(
+        socket: Arc<net::UdpSocket>,
+        remote_addr: SocketAddr,
+        receiver: mpsc::Receiver<BytesMut>,
+        metrics: ProtocolMetricCache,
+    ) -> Self {
+        let sp = UdpSendProtocol::new(
+            UdpDrain {
+                socket,
+                remote_addr,
+            },
+            metrics.clone(),
+        );
+        let rp = UdpRecvProtocol::new(UdpSink { receiver }, &sp, metrics);
+        Protocols::Udp((sp, rp))
+    }
+
     pub(crate) async fn with_mpsc_connect(
         addr: u64,
         metrics: ProtocolMetricCache,
@@ -178,7 +512,7 @@ impl Protocols {
         cids: Arc<AtomicU64>,
         metrics: Arc<ProtocolMetrics>,
         s2s_stop_listening_r: oneshot::Receiver<()>,
-        c2s_protocol_s: mpsc::UnboundedSender<(Self, Cid)>,
+        c2s_protocol_s: mpsc::UnboundedSender<(Self, Option<SocketAddr>, Cid)>,
     ) -> std::io::Result<()> {
         let (mpsc_s, mut mpsc_r) = mpsc::unbounded_channel();
         MPSC_POOL.lock().await.insert(addr, mpsc_s);
@@ -200,6 +534,7 @@ impl Protocols {
                 let metrics = ProtocolMetricCache::new(&cid.to_string(), Arc::clone(&metrics));
                 let _ = c2s_protocol_s.send((
                     Self::new_mpsc(local_to_remote_s, remote_to_local_r, metrics.clone()),
+                    None,
                     cid,
                 ));
             }
@@ -271,7 +606,7 @@ impl Protocols {
         cids: Arc<AtomicU64>,
         metrics: Arc<ProtocolMetrics>,
         s2s_stop_listening_r: oneshot::Receiver<()>,
-        c2s_protocol_s: mpsc::UnboundedSender<(Self, Cid)>,
+        c2s_protocol_s: mpsc::UnboundedSender<(Self, Option<SocketAddr>, Cid)>,
     ) -> std::io::Result<()> {
         let (_endpoint, mut listener) = match quinn::Endpoint::server(server_config, addr) {
             Ok(v) => v,
@@ -298,7 +633,7 @@ impl Protocols {
                 let metrics = ProtocolMetricCache::new(&cid.to_string(), Arc::clone(&metrics));
                 match Protocols::new_quic(connection, true, metrics).await {
                     Ok(quic) => {
-                        let _ = c2s_protocol_s.send((quic, cid));
+                        let _ = c2s_protocol_s.send((quic, Some(remote_addr), cid));
                     },
                     Err(e) => {
                         trace!(?e, "failed to start quic");
@@ -355,6 +690,8 @@ impl Protocols {
     pub(crate) fn split(self) -> (SendProtocols, RecvProtocols) {
         match self {
             Protocols::Tcp((s, r)) => (SendProtocols::Tcp(s), RecvProtocols::Tcp(r)),
+            Protocols::Tls((s, r)) => (SendProtocols::Tls(s), RecvProtocols::Tls(r)),
+            Protocols::Udp((s, r)) => (SendProtocols::Udp(s), RecvProtocols::Udp(r)),
             Protocols::Mpsc((s, r)) => (SendProtocols::Mpsc(s), RecvProtocols::Mpsc(r)),
             #[cfg(feature = "quic")]
             Protocols::Quic((s, r)) => (SendProtocols::Quic(s), RecvProtocols::Quic(r)),
@@ -372,6 +709,8 @@ impl network_protocol::InitProtocol for Protocols {
     ) -> Result<(Pid, Sid, u128), InitProtocolError> {
         match self {
             Protocols::Tcp(p) => p.initialize(initializer, local_pid, secret).await,
+            Protocols::Tls(p) => p.initialize(initializer, local_pid, secret).await,
+            Protocols::Udp(p) => p.initialize(initializer, local_pid, secret).await,
             Protocols::Mpsc(p) => p.initialize(initializer, local_pid, secret).await,
             #[cfg(feature = "quic")]
             Protocols::Quic(p) => p.initialize(initializer, local_pid, secret).await,
@@ -384,6 +723,8 @@ impl network_protocol::SendProtocol for SendProtocols {
     fn notify_from_recv(&mut self, event: ProtocolEvent) {
         match self {
             SendProtocols::Tcp(s) => s.notify_from_recv(event),
+            SendProtocols::Tls(s) => s.notify_from_recv(event),
+            SendProtocols::Udp(s) => s.notify_from_recv(event),
             SendProtocols::Mpsc(s) => s.notify_from_recv(event),
             #[cfg(feature = "quic")]
             SendProtocols::Quic(s) => s.notify_from_recv(event),
@@ -393,6 +734,8 @@ impl network_protocol::SendProtocol for SendProtocols {
     async fn send(&mut self, event: ProtocolEvent) -> Result<(), ProtocolError> {
         match self {
             SendProtocols::Tcp(s) => s.send(event).await,
+            SendProtocols::Tls(s) => s.send(event).await,
+            SendProtocols::Udp(s) => s.send(event).await,
             SendProtocols::Mpsc(s) => s.send(event).await,
             #[cfg(feature = "quic")]
             SendProtocols::Quic(s) => s.send(event).await,
@@ -406,6 +749,8 @@ impl network_protocol::SendProtocol for SendProtocols {
     ) -> Result<Bandwidth, ProtocolError> {
         match self {
             SendProtocols::Tcp(s) => s.flush(bandwidth, dt).await,
+            SendProtocols::Tls(s) => s.flush(bandwidth, dt).await,
+            SendProtocols::Udp(s) => s.flush(bandwidth, dt).await,
             SendProtocols::Mpsc(s) => s.flush(bandwidth, dt).await,
             #[cfg(feature = "quic")]
             SendProtocols::Quic(s) => s.flush(bandwidth, dt).await,
@@ -418,9 +763,12 @@ impl network_protocol::RecvProtocol for RecvProtocols {
     async fn recv(&mut self) -> Result<ProtocolEvent, ProtocolError> {
         match self {
             RecvProtocols::Tcp(r) => r.recv().await,
+            RecvProtocols::Tls(r) => r.recv().await,
+            RecvProtocols::Udp(r) => r.recv().await,
             RecvProtocols::Mpsc(r) => r.recv().await,
             #[cfg(feature = "quic")]
             RecvProtocols::Quic(r) => r.recv().await,
+            RecvProtocols::Impaired(r) => r.recv().await,
         }
     }
 }
@@ -464,6 +812,122 @@ impl UnreliableSink for TcpSink {
     }
 }
 
+///////////////////////////////////////
+//// UDP
+/// bigger than the largest datagram the protocol sends
+const UDP_BUFFER_SIZE: usize = 2048;
+/// the protocol retransmits while waiting on the sink, so it must wake up
+/// regularly even if no datagram arrives
+const UDP_TICK: Duration = Duration::from_millis(10);
+/// A client asks for a cookie with a hello, which is as large as the answer
+const UDP_HELLO: [u8; 8] = *b"VLR_HELO";
+const UDP_COOKIE: [u8; 8] = *b"VLR_COOK";
+/// A client connects by sending the cookie it got back
+const UDP_CONNECT: [u8; 8] = *b"VLR_CONN";
+/// Time a cookie is accepted for at least, it is valid for up to twice as long
+const UDP_COOKIE_LIFETIME: Duration = Duration::from_secs(30);
+/// Remotes count as pending for this long after they were accepted, as the
+/// listener doesn't know when their handshake completes
+const UDP_PENDING_DURATION: Duration = Duration::from_secs(10);
+const MAX_PENDING_UDP_REMOTES: usize = 128;
+/// Datagrams queued for a remote whose protocol doesn't keep up are dropped,
+/// the protocol retransmits what it needs
+const UDP_CHANNEL_BOUND: usize = 1024;
+
+fn udp_handshake_datagram(magic: [u8; 8], cookie: u64) -> [u8; 16] {
+    let mut datagram = [0u8; 16];
+    datagram[..8].copy_from_slice(&magic);
+    datagram[8..].copy_from_slice(&cookie.to_le_bytes());
+    datagram
+}
+
+fn read_udp_handshake_datagram(magic: [u8; 8], datagram: &[u8]) -> Option<u64> {
+    if datagram.len() != 16 || datagram[..8] != magic {
+        return None;
+    }
+    let mut cookie = [0u8; 8];
+    cookie.copy_from_slice(&datagram[8..]);
+    Some(u64::from_le_bytes(cookie))
+}
+
+/// Cookies proving that a remote receives what is sent to its address, derived
+/// from the address with a secret key so they don't need to be stored
+struct UdpCookies {
+    key: RandomState,
+    start: Instant,
+}
+
+impl UdpCookies {
+    fn new() -> Self {
+        Self {
+            key: RandomState::new(),
+            start: Instant::now(),
+        }
+    }
+
+    fn period(&self, now: Instant) -> u64 {
+        now.duration_since(self.start).as_secs() / UDP_COOKIE_LIFETIME.as_secs()
+    }
+
+    fn cookie(&self, addr: SocketAddr, now: Instant) -> u64 {
+        self.cookie_of_period(addr, self.period(now))
+    }
+
+    fn verify(&self, addr: SocketAddr, cookie: u64, now: Instant) -> bool {
+        let period = self.period(now);
+        cookie == self.cookie_of_period(addr, period)
+            || (period > 0 && cookie == self.cookie_of_period(addr, period - 1))
+    }
+
+    fn cookie_of_period(&self, addr: SocketAddr, period: u64) -> u64 {
+        let mut hasher = self.key.build_hasher();
+        (addr, period).hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// A remote of a Udp listener
+struct UdpRemote {
+    datagrams_s: mpsc::Sender<BytesMut>,
+    accepted: Instant,
+}
+
+#[derive(Debug, Clone)]
+pub struct UdpDrain {
+    socket: Arc<net::UdpSocket>,
+    remote_addr: SocketAddr,
+}
+
+#[derive(Debug)]
+pub struct UdpSink {
+    receiver: mpsc::Receiver<BytesMut>,
+}
+
+#[async_trait]
+impl UnreliableDrain for UdpDrain {
+    type DataFormat = BytesMut;
+
+    async fn send(&mut self, data: Self::DataFormat) -> Result<(), ProtocolError> {
+        match self.socket.send_to(&data, self.remote_addr).await {
+            Ok(_) => Ok(()),
+            Err(_) => Err(ProtocolError::Closed),
+        }
+    }
+}
+
+#[async_trait]
+impl UnreliableSink for UdpSink {
+    type DataFormat = BytesMut;
+
+    async fn recv(&mut self) -> Result<Self::DataFormat, ProtocolError> {
+        match tokio::time::timeout(UDP_TICK, self.receiver.recv()).await {
+            Ok(Some(datagram)) => Ok(datagram),
+            Ok(None) => Err(ProtocolError::Closed),
+            Err(_) => Ok(BytesMut::new()),
+        }
+    }
+}
+
 ///////////////////////////////////////
 //// MPSC
 #[derive(Debug)]
@@ -727,4 +1191,172 @@ mod tests {
         assert!(e.is_err());
         assert_eq!(e.unwrap_err(), ProtocolError::Closed);
     }
+
+    #[tokio::test]
+    async fn tokio_udp_sockets() {
+        use network_protocol::InitProtocol;
+        let addr: SocketAddr = "127.0.0.1:5002".parse().unwrap();
+        let metrics = Arc::new(ProtocolMetrics::new().unwrap());
+        let (_stop_listening_s, stop_listening_r) = oneshot::channel();
+        let (c2s_protocol_s, mut c2s_protocol_r) = mpsc::unbounded_channel();
+        Protocols::with_udp_listen(
+            addr,
+            Arc::new(AtomicU64::new(0)),
+            Arc::clone(&metrics),
+            stop_listening_r,
+            c2s_protocol_s,
+        )
+        .await
+        .unwrap();
+        let r1 = tokio::spawn(async move {
+            let (mut server, _, _) = c2s_protocol_r.recv().await.unwrap();
+            server.initialize(true, Pid::fake(0), 1337).await.unwrap();
+            server
+        });
+        let metrics = ProtocolMetricCache::new("0", metrics);
+        let mut client = Protocols::with_udp_connect(addr, metrics).await.unwrap();
+        let (pid, _, secret) = client.initialize(false, Pid::fake(1), 42).await.unwrap();
+        assert_eq!(pid, Pid::fake(0));
+        assert_eq!(secret, 1337);
+        let server = r1.await.unwrap();
+        let (mut s, _r) = client.split();
+        let (_s, mut r) = server.split();
+        let event = ProtocolEvent::OpenStream {
+            sid: Sid::new(1),
+            prio: 4u8,
+            promises: Promises::GUARANTEED_DELIVERY,
+            guaranteed_bandwidth: 1_000,
+        };
+        s.send(event).await.unwrap();
+        s.send(ProtocolEvent::Message {
+            sid: Sid::new(1),
+            data: Bytes::from(&[8u8; 8][..]),
+        })
+        .await
+        .unwrap();
+        s.flush(1_000_000, Duration::from_secs(1)).await.unwrap();
+        let res = r.recv().await;
+        match res {
+            Ok(ProtocolEvent::OpenStream { sid, promises, .. }) => {
+                assert_eq!(sid, Sid::new(1));
+                assert_eq!(promises, Promises::GUARANTEED_DELIVERY);
+            },
+            _ => {
+                panic!("wrong type {:?}", res);
+            },
+        }
+        match r.recv().await {
+            Ok(ProtocolEvent::Message { sid, data }) => {
+                assert_eq!(sid, Sid::new(1));
+                assert_eq!(data, Bytes::from(&[8u8; 8][..]));
+            },
+            res => panic!("wrong type {:?}", res),
+        }
+    }
+
+    #[tokio::test]
+    async fn tokio_tls_listener_requires_tls() {
+        use network_protocol::InitProtocol;
+        use std::convert::TryFrom;
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let key = rustls::PrivateKey(cert.serialize_private_key_der());
+        let cert = rustls::Certificate(cert.serialize_der().unwrap());
+        let mut root_store = rustls::RootCertStore::empty();
+        root_store.add(&cert).unwrap();
+        let server_config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], key)
+            .unwrap();
+        let client_config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(root_store)
+            .with_no_client_auth();
+
+        let addr: SocketAddr = "127.0.0.1:5003".parse().unwrap();
+        let metrics = Arc::new(ProtocolMetrics::new().unwrap());
+        let (_stop_listening_s, stop_listening_r) = oneshot::channel();
+        let (c2s_protocol_s, mut c2s_protocol_r) = mpsc::unbounded_channel();
+        Protocols::with_tls_listen(
+            addr,
+            Arc::new(server_config),
+            Arc::new(AtomicU64::new(0)),
+            Arc::clone(&metrics),
+            stop_listening_r,
+            c2s_protocol_s,
+        )
+        .await
+        .unwrap();
+        let metrics = ProtocolMetricCache::new("0", metrics);
+
+        let mut client = Protocols::with_tls_connect(
+            addr,
+            Arc::new(client_config),
+            "localhost".to_owned(),
+            metrics.clone(),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(client, Protocols::Tls(_)));
+        let (mut server, _, _) = c2s_protocol_r.recv().await.unwrap();
+        assert!(matches!(server, Protocols::Tls(_)));
+        let (r1, r2) = tokio::join!(
+            server.initialize(true, Pid::fake(0), 1337),
+            client.initialize(false, Pid::fake(1), 42)
+        );
+        assert_eq!(r1.unwrap().0, Pid::fake(1));
+        assert_eq!(r2.unwrap().0, Pid::fake(0));
+
+        // Plain Tcp is refused rather than waited for.
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut client = Protocols::new_tcp(stream, metrics);
+        let r1 = tokio::spawn(async move { client.initialize(false, Pid::fake(2), 42).await });
+        assert!(r1.await.unwrap().is_err());
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), c2s_protocol_r.recv())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn udp_listener_requires_cookie() {
+        let addr: SocketAddr = "127.0.0.1:5004".parse().unwrap();
+        let (_stop_listening_s, stop_listening_r) = oneshot::channel();
+        let (c2s_protocol_s, mut c2s_protocol_r) = mpsc::unbounded_channel();
+        Protocols::with_udp_listen(
+            addr,
+            Arc::new(AtomicU64::new(0)),
+            Arc::new(ProtocolMetrics::new().unwrap()),
+            stop_listening_r,
+            c2s_protocol_s,
+        )
+        .await
+        .unwrap();
+        let socket = net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut buffer = [0u8; UDP_BUFFER_SIZE];
+
+        // Neither arbitrary datagrams nor made up cookies are accepted
+        socket.send_to(&[0u8; 100], addr).await.unwrap();
+        socket
+            .send_to(&udp_handshake_datagram(UDP_CONNECT, 42), addr)
+            .await
+            .unwrap();
+        // A hello is answered with a cookie of the same size, nothing else
+        socket
+            .send_to(&udp_handshake_datagram(UDP_HELLO, 0), addr)
+            .await
+            .unwrap();
+        let (n, _) = socket.recv_from(&mut buffer).await.unwrap();
+        assert_eq!(n, 16);
+        let cookie = read_udp_handshake_datagram(UDP_COOKIE, &buffer[..n]).unwrap();
+        assert!(c2s_protocol_r.try_recv().is_err());
+
+        socket
+            .send_to(&udp_handshake_datagram(UDP_CONNECT, cookie), addr)
+            .await
+            .unwrap();
+        let (_, remote_addr, _) = c2s_protocol_r.recv().await.unwrap();
+        assert_eq!(remote_addr, Some(socket.local_addr().unwrap()));
+    }
 }
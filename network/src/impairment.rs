@@ -0,0 +1,396 @@
+use crate::channel::RecvProtocols;
+use async_trait::async_trait;
+use hashbrown::HashMap;
+use network_protocol::{Promises, ProtocolError, ProtocolEvent, RecvProtocol, Sid};
+use rand::Rng;
+use std::{collections::BTreeMap, time::Duration};
+use tokio::{
+    select,
+    sync::mpsc,
+    task::JoinHandle,
+    time::{sleep_until, Instant},
+};
+use tracing::{trace, warn};
+
+/// Simulates a bad connection by impairing all traffic a [`Network`] receives.
+/// Use it to reproduce lag and rubber-banding locally, e.g. over MPSC. The
+/// impairment only affects incoming traffic, so give it to both sides for a
+/// symmetric connection.
+///
+/// It is read from the environment by [`Network::new`], see
+/// [`Impairment::from_env`], or provided via
+/// [`Network::new_with_impairment`].
+///
+/// Messages are only dropped on streams without
+/// [`Promises::GUARANTEED_DELIVERY`] and only reordered on streams without
+/// [`Promises::ORDERED`], the others are delayed instead, like a reliable
+/// protocol would.
+///
+/// # Examples
+/// ```rust
+/// use std::time::Duration;
+/// use tokio::runtime::Runtime;
+/// use veloren_network::{Impairment, Network, Pid};
+///
+/// let runtime = Runtime::new().unwrap();
+/// let network = Network::new_with_impairment(Pid::new(), &runtime, Impairment {
+///     latency: Duration::from_millis(150),
+///     jitter: Duration::from_millis(50),
+///     drop_chance: 0.05,
+///     ..Impairment::default()
+/// });
+/// ```
+///
+/// [`Network`]: crate::api::Network
+/// [`Network::new`]: crate::api::Network::new
+/// [`Network::new_with_impairment`]: crate::api::Network::new_with_impairment
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Impairment {
+    /// added to every received event
+    pub latency: Duration,
+    /// random additional latency, up to this
+    pub jitter: Duration,
+    /// bytes per second which can be received, `None` for unlimited
+    pub bandwidth: Option<u64>,
+    /// chance (0.0 - 1.0) of a message being dropped
+    pub drop_chance: f32,
+    /// chance (0.0 - 1.0) of a message being held back behind later ones
+    pub reorder_chance: f32,
+}
+
+impl Impairment {
+    const ENV_BANDWIDTH: &'static str = "VELOREN_NETWORK_BANDWIDTH";
+    const ENV_DROP: &'static str = "VELOREN_NETWORK_DROP";
+    const ENV_JITTER: &'static str = "VELOREN_NETWORK_JITTER_MS";
+    const ENV_LATENCY: &'static str = "VELOREN_NETWORK_LATENCY_MS";
+    const ENV_REORDER: &'static str = "VELOREN_NETWORK_REORDER";
+
+    /// Reads an `Impairment` from the environment, `None` if none of the
+    /// variables is set:
+    /// * `VELOREN_NETWORK_LATENCY_MS` - latency in milliseconds
+    /// * `VELOREN_NETWORK_JITTER_MS` - jitter in milliseconds
+    /// * `VELOREN_NETWORK_BANDWIDTH` - bandwidth in bytes per second
+    /// * `VELOREN_NETWORK_DROP` - drop chance, 0.0 - 1.0
+    /// * `VELOREN_NETWORK_REORDER` - reorder chance, 0.0 - 1.0
+    pub fn from_env() -> Option<Self> {
+        fn var<T: std::str::FromStr>(key: &str) -> Option<T> {
+            let value = std::env::var(key).ok()?;
+            match value.parse() {
+                Ok(value) => Some(value),
+                Err(_) => {
+                    warn!(?key, ?value, "Ignoring invalid network impairment");
+                    None
+                },
+            }
+        }
+
+        let latency = var(Self::ENV_LATENCY).map(Duration::from_millis);
+        let jitter = var(Self::ENV_JITTER).map(Duration::from_millis);
+        let bandwidth = var(Self::ENV_BANDWIDTH);
+        let drop_chance = var(Self::ENV_DROP);
+        let reorder_chance = var(Self::ENV_REORDER);
+        if latency.is_none()
+            && jitter.is_none()
+            && bandwidth.is_none()
+            && drop_chance.is_none()
+            && reorder_chance.is_none()
+        {
+            return None;
+        }
+        Some(Self {
+            latency: latency.unwrap_or_default(),
+            jitter: jitter.unwrap_or_default(),
+            bandwidth,
+            drop_chance: drop_chance.unwrap_or_default(),
+            reorder_chance: reorder_chance.unwrap_or_default(),
+        })
+    }
+}
+
+/// Decides when a received event is delivered, keeping the order where the
+/// streams require it.
+#[derive(Debug)]
+struct Schedule {
+    impairment: Impairment,
+    promises: HashMap<Sid, Promises>,
+    /// the bandwidth is used up till then
+    link_free: Instant,
+    /// delivery of the last message which must keep its order
+    ordered: Instant,
+    /// delivery of the last event which isn't a message, e.g. opening a
+    /// stream
+    control: Instant,
+    /// delivery of the last event
+    latest: Instant,
+}
+
+impl Schedule {
+    /// additional delay of reordered messages
+    const REORDER_DELAY: Duration = Duration::from_millis(10);
+
+    fn new(impairment: Impairment, now: Instant) -> Self {
+        Self {
+            impairment,
+            promises: HashMap::new(),
+            link_free: now,
+            ordered: now,
+            control: now,
+            latest: now,
+        }
+    }
+
+    /// when to deliver an event received `now`, `None` if it's dropped
+    fn deliver_at(
+        &mut self,
+        now: Instant,
+        event: &Result<ProtocolEvent, ProtocolError>,
+    ) -> Option<Instant> {
+        let mut rng = rand::thread_rng();
+        let (len, promises) = match event {
+            Ok(ProtocolEvent::OpenStream { sid, promises, .. }) => {
+                self.promises.insert(*sid, *promises);
+                (0, None)
+            },
+            Ok(ProtocolEvent::CloseStream { sid }) => {
+                self.promises.remove(sid);
+                (0, None)
+            },
+            Ok(ProtocolEvent::Message { data, sid }) => (
+                data.len(),
+                // messages of unknown streams are treated like control events
+                self.promises.get(sid).copied(),
+            ),
+            Ok(ProtocolEvent::Shutdown) | Err(_) => (0, None),
+        };
+
+        if let Some(promises) = promises {
+            if !promises.contains(Promises::GUARANTEED_DELIVERY)
+                && rng.gen::<f32>() < self.impairment.drop_chance
+            {
+                return None;
+            }
+        }
+
+        // a limited link transmits one event after the other
+        self.link_free = self.link_free.max(now);
+        if let Some(bandwidth) = self.impairment.bandwidth {
+            self.link_free += Duration::from_secs_f64(len as f64 / bandwidth.max(1) as f64);
+        }
+        let jitter = self.impairment.jitter.mul_f32(rng.gen::<f32>());
+        let mut at = self.link_free + self.impairment.latency + jitter;
+
+        match promises {
+            Some(promises) if !promises.contains(Promises::ORDERED) => {
+                if rng.gen::<f32>() < self.impairment.reorder_chance {
+                    at += self.impairment.latency + self.impairment.jitter + Self::REORDER_DELAY;
+                }
+                // never before the stream is opened
+                at = at.max(self.control);
+            },
+            Some(_) => {
+                at = at.max(self.ordered);
+                self.ordered = at;
+            },
+            None => {
+                // never before any earlier event, e.g. a message of a closed stream
+                at = at.max(self.latest);
+                self.control = at;
+                self.ordered = at;
+            },
+        }
+        self.latest = self.latest.max(at);
+        Some(at)
+    }
+}
+
+type Scheduled = (Instant, Result<ProtocolEvent, ProtocolError>);
+
+/// Wraps any [`RecvProtocols`] and delivers its events according to an
+/// [`Impairment`]. The wrapped protocol is polled in its own task, so that
+/// events are timestamped when they arrive.
+#[derive(Debug)]
+pub(crate) struct ImpairedRecvProtocol {
+    pump: JoinHandle<()>,
+    scheduled_r: mpsc::UnboundedReceiver<Scheduled>,
+    pump_done: bool,
+    /// ordered by delivery time, then by arrival
+    pending: BTreeMap<(Instant, u64), Result<ProtocolEvent, ProtocolError>>,
+    next_seq: u64,
+}
+
+impl ImpairedRecvProtocol {
+    pub(crate) fn new(inner: RecvProtocols, impairment: Impairment) -> Self {
+        let (scheduled_s, scheduled_r) = mpsc::unbounded_channel();
+        let pump = tokio::spawn(Self::pump(inner, impairment, scheduled_s));
+        Self {
+            pump,
+            scheduled_r,
+            pump_done: false,
+            pending: BTreeMap::new(),
+            next_seq: 0,
+        }
+    }
+
+    async fn pump(
+        mut inner: RecvProtocols,
+        impairment: Impairment,
+        scheduled_s: mpsc::UnboundedSender<Scheduled>,
+    ) {
+        let mut schedule = Schedule::new(impairment, Instant::now());
+        loop {
+            let event = inner.recv().await;
+            let failed = event.is_err();
+            match schedule.deliver_at(Instant::now(), &event) {
+                Some(at) => {
+                    if scheduled_s.send((at, event)).is_err() {
+                        break;
+                    }
+                },
+                None => trace!("dropped message"),
+            }
+            if failed {
+                break;
+            }
+        }
+    }
+}
+
+impl Drop for ImpairedRecvProtocol {
+    fn drop(&mut self) { self.pump.abort(); }
+}
+
+#[async_trait]
+impl RecvProtocol for ImpairedRecvProtocol {
+    async fn recv(&mut self) -> Result<ProtocolEvent, ProtocolError> {
+        loop {
+            let next = self.pending.keys().next().copied();
+            if next.is_none() && self.pump_done {
+                return Err(ProtocolError::Closed);
+            }
+            let due = async {
+                match next {
+                    Some((at, _)) => sleep_until(at).await,
+                    None => futures_util::future::pending().await,
+                }
+            };
+            select! {
+                scheduled = self.scheduled_r.recv(), if !self.pump_done => match scheduled {
+                    Some((at, event)) => {
+                        self.pending.insert((at, self.next_seq), event);
+                        self.next_seq += 1;
+                    },
+                    None => self.pump_done = true,
+                },
+                _ = due => {
+                    if let Some(event) = next.and_then(|key| self.pending.remove(&key)) {
+                        return event;
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn open(sid: u64, promises: Promises) -> Result<ProtocolEvent, ProtocolError> {
+        Ok(ProtocolEvent::OpenStream {
+            sid: Sid::new(sid),
+            prio: 0,
+            promises,
+            guaranteed_bandwidth: 0,
+        })
+    }
+
+    fn msg(sid: u64, len: usize) -> Result<ProtocolEvent, ProtocolError> {
+        Ok(ProtocolEvent::Message {
+            data: Bytes::from(vec![0u8; len]),
+            sid: Sid::new(sid),
+        })
+    }
+
+    #[test]
+    fn latency_and_bandwidth() {
+        let now = Instant::now();
+        let mut schedule = Schedule::new(
+            Impairment {
+                latency: Duration::from_millis(100),
+                bandwidth: Some(1000),
+                ..Impairment::default()
+            },
+            now,
+        );
+        assert_eq!(
+            schedule.deliver_at(now, &open(1, Promises::ORDERED)),
+            Some(now + Duration::from_millis(100))
+        );
+        assert_eq!(
+            schedule.deliver_at(now, &msg(1, 500)),
+            Some(now + Duration::from_millis(600))
+        );
+        assert_eq!(
+            schedule.deliver_at(now, &msg(1, 100)),
+            Some(now + Duration::from_millis(700))
+        );
+    }
+
+    #[test]
+    fn drop_only_unreliable() {
+        let now = Instant::now();
+        let mut schedule = Schedule::new(
+            Impairment {
+                drop_chance: 1.0,
+                ..Impairment::default()
+            },
+            now,
+        );
+        schedule.deliver_at(now, &open(1, Promises::ORDERED));
+        schedule.deliver_at(now, &open(2, Promises::GUARANTEED_DELIVERY));
+        assert_eq!(schedule.deliver_at(now, &msg(1, 10)), None);
+        assert_eq!(schedule.deliver_at(now, &msg(2, 10)), Some(now));
+        assert_eq!(schedule.deliver_at(now, &msg(3, 10)), Some(now));
+    }
+
+    #[test]
+    fn reorder_only_unordered() {
+        let now = Instant::now();
+        let mut schedule = Schedule::new(
+            Impairment {
+                reorder_chance: 1.0,
+                ..Impairment::default()
+            },
+            now,
+        );
+        schedule.deliver_at(now, &open(1, Promises::empty()));
+        schedule.deliver_at(now, &open(2, Promises::ORDERED));
+        let reordered = schedule.deliver_at(now, &msg(1, 10)).unwrap();
+        let ordered = schedule.deliver_at(now, &msg(2, 10)).unwrap();
+        assert!(ordered < reordered);
+        // closing waits for the reordered message
+        let close = Ok(ProtocolEvent::CloseStream { sid: Sid::new(1) });
+        assert_eq!(schedule.deliver_at(now, &close), Some(reordered));
+    }
+
+    #[test]
+    fn jitter_keeps_order() {
+        let now = Instant::now();
+        let mut schedule = Schedule::new(
+            Impairment {
+                jitter: Duration::from_millis(100),
+                ..Impairment::default()
+            },
+            now,
+        );
+        schedule.deliver_at(now, &open(1, Promises::ORDERED));
+        let mut last = now;
+        for _ in 0..100 {
+            let at = schedule.deliver_at(now, &msg(1, 10)).unwrap();
+            assert!(at >= last);
+            assert!(at <= now + Duration::from_millis(100));
+            last = at;
+        }
+    }
+}
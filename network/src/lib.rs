@@ -100,6 +100,7 @@
 
 mod api;
 mod channel;
+mod impairment;
 mod message;
 mod metrics;
 mod participant;
@@ -110,5 +111,6 @@ pub use api::{
     ConnectAddr, ListenAddr, Network, NetworkConnectError, NetworkError, Participant,
     ParticipantError, Stream, StreamError, StreamParams,
 };
+pub use impairment::Impairment;
 pub use message::Message;
 pub use network_protocol::{InitProtocolError, Pid, Promises};
@@ -7,6 +7,7 @@ use std::{error::Error, net::SocketAddr};
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub(crate) enum ProtocolInfo {
     Tcp(SocketAddr),
+    Tls(SocketAddr),
     Udp(SocketAddr),
     #[cfg(feature = "quic")]
     Quic(SocketAddr),
@@ -17,6 +18,7 @@ impl From<ListenAddr> for ProtocolInfo {
     fn from(other: ListenAddr) -> ProtocolInfo {
         match other {
             ListenAddr::Tcp(s) => ProtocolInfo::Tcp(s),
+            ListenAddr::Tls(s, _) => ProtocolInfo::Tls(s),
             ListenAddr::Udp(s) => ProtocolInfo::Udp(s),
             #[cfg(feature = "quic")]
             ListenAddr::Quic(s, _) => ProtocolInfo::Quic(s),
@@ -247,6 +249,7 @@ impl NetworkMetrics {
 fn protocolconnect_name(protocol: &ConnectAddr) -> &str {
     match protocol {
         ConnectAddr::Tcp(_) => "tcp",
+        ConnectAddr::Tls(_, _, _) => "tls",
         ConnectAddr::Udp(_) => "udp",
         ConnectAddr::Mpsc(_) => "mpsc",
         #[cfg(feature = "quic")]
@@ -258,6 +261,7 @@ fn protocolconnect_name(protocol: &ConnectAddr) -> &str {
 fn protocollisten_name(protocol: &ListenAddr) -> &str {
     match protocol {
         ListenAddr::Tcp(_) => "tcp",
+        ListenAddr::Tls(_, _) => "tls",
         ListenAddr::Udp(_) => "udp",
         ListenAddr::Mpsc(_) => "mpsc",
         #[cfg(feature = "quic")]
@@ -1,6 +1,7 @@
 use crate::{
     api::{ParticipantError, Stream},
     channel::{Protocols, RecvProtocols, SendProtocols},
+    impairment::{ImpairedRecvProtocol, Impairment},
     metrics::NetworkMetrics,
     util::DeferredTracer,
 };
@@ -75,6 +76,7 @@ pub struct BParticipant {
     shutdown_barrier: AtomicI32,
     metrics: Arc<NetworkMetrics>,
     open_stream_channels: Arc<Mutex<Option<OpenStreamInfo>>>,
+    impairment: Option<Impairment>,
 }
 
 impl BParticipant {
@@ -91,6 +93,7 @@ impl BParticipant {
         remote_pid: Pid,
         offset_sid: Sid,
         metrics: Arc<NetworkMetrics>,
+        impairment: Option<Impairment>,
     ) -> (
         Self,
         mpsc::UnboundedSender<A2bStreamOpen>,
@@ -127,6 +130,7 @@ impl BParticipant {
                 run_channels,
                 metrics,
                 open_stream_channels: Arc::new(Mutex::new(None)),
+                impairment,
             },
             a2b_open_stream_s,
             b2a_stream_opened_r,
@@ -197,6 +201,25 @@ impl BParticipant {
     fn best_protocol(all: &SortedVec<Cid, SendProtocols>, promises: Promises) -> Option<Cid> {
         // check for mpsc
         all.data.iter().find(|(_, p)| matches!(p, SendProtocols::Mpsc(_))).map(|(c, _)| *c).or_else(
+            // check for udp, streams which may lose messages shouldn't be blocked by tcp
+            || if !promises.contains(Promises::GUARANTEED_DELIVERY)
+                && network_protocol::UdpSendProtocol::<crate::channel::UdpDrain>::supported_promises()
+                .contains(promises)
+            {
+                all.data.iter().find(|(_, p)| matches!(p, SendProtocols::Udp(_))).map(|(c, _)| *c)
+            } else {
+                None
+            }
+        ).or_else(
+            || if network_protocol::TcpSendProtocol::<network_protocol::TlsDrain<crate::channel::TcpDrain>>::supported_tls_promises()
+                .contains(promises)
+            {
+                // check for tls
+                all.data.iter().find(|(_, p)| matches!(p, SendProtocols::Tls(_))).map(|(c, _)| *c)
+            } else {
+                None
+            }
+        ).or_else(
             || if network_protocol::TcpSendProtocol::<crate::channel::TcpDrain>::supported_promises()
                 .contains(promises)
             {
@@ -214,6 +237,14 @@ impl BParticipant {
             } else {
                 None
             }
+        ).or_else(
+            || if network_protocol::UdpSendProtocol::<crate::channel::UdpDrain>::supported_promises()
+                .contains(promises)
+            {
+                all.data.iter().find(|(_, p)| matches!(p, SendProtocols::Udp(_))).map(|(c, _)| *c)
+            } else {
+                None
+            }
         ).or_else(
             || {
                 warn!("couldn't satisfy promises");
@@ -579,7 +610,12 @@ impl BParticipant {
                         }),
                     );
                     drop(lock);
-                    let (send, recv) = protocol.split();
+                    let (send, mut recv) = protocol.split();
+                    if let Some(impairment) = self.impairment {
+                        recv = RecvProtocols::Impaired(Box::new(ImpairedRecvProtocol::new(
+                            recv, impairment,
+                        )));
+                    }
                     b2b_add_send_protocol_s.send((cid, send)).unwrap();
                     b2b_add_recv_protocol_s.send((cid, recv)).unwrap();
                     b2s_create_channel_done_s.send(()).unwrap();
@@ -811,7 +847,7 @@ mod tests {
             let sid = Sid::new(1000);
             let metrics = Arc::new(NetworkMetrics::new(&local_pid).unwrap());
 
-            BParticipant::new(local_pid, remote_pid, sid, Arc::clone(&metrics))
+            BParticipant::new(local_pid, remote_pid, sid, Arc::clone(&metrics), None)
         });
 
         let handle = runtime_clone.spawn(bparticipant.run(b2s_prio_statistic_s));
@@ -1,6 +1,7 @@
 use crate::{
     api::{ConnectAddr, ListenAddr, NetworkConnectError, Participant},
     channel::Protocols,
+    impairment::Impairment,
     metrics::{NetworkMetrics, ProtocolInfo},
     participant::{B2sPrioStatistic, BParticipant, S2bCreateChannel, S2bShutdownBparticipant},
 };
@@ -11,6 +12,7 @@ use network_protocol::{Cid, Pid, ProtocolMetricCache, ProtocolMetrics};
 use prometheus::Registry;
 use rand::Rng;
 use std::{
+    net::SocketAddr,
     sync::{
         atomic::{AtomicBool, AtomicU64, Ordering},
         Arc,
@@ -76,12 +78,14 @@ pub struct Scheduler {
     channel_listener: Mutex<HashMap<ProtocolInfo, oneshot::Sender<()>>>,
     metrics: Arc<NetworkMetrics>,
     protocol_metrics: Arc<ProtocolMetrics>,
+    impairment: Option<Impairment>,
 }
 
 impl Scheduler {
     pub fn new(
         local_pid: Pid,
         #[cfg(feature = "metrics")] registry: Option<&Registry>,
+        impairment: Option<Impairment>,
     ) -> (
         Self,
         mpsc::UnboundedSender<A2sListen>,
@@ -137,6 +141,7 @@ impl Scheduler {
                 channel_listener: Mutex::new(HashMap::new()),
                 metrics,
                 protocol_metrics,
+                impairment,
             },
             a2s_listen_s,
             a2s_connect_s,
@@ -209,6 +214,27 @@ impl Scheduler {
                             )
                             .await
                         },
+                        ListenAddr::Tls(addr, ref server_config) => {
+                            Protocols::with_tls_listen(
+                                addr,
+                                Arc::clone(server_config),
+                                cids,
+                                metrics,
+                                s2s_stop_listening_r,
+                                c2s_protocol_s,
+                            )
+                            .await
+                        },
+                        ListenAddr::Udp(addr) => {
+                            Protocols::with_udp_listen(
+                                addr,
+                                cids,
+                                metrics,
+                                s2s_stop_listening_r,
+                                c2s_protocol_s,
+                            )
+                            .await
+                        },
                         ListenAddr::Mpsc(addr) => {
                             Protocols::with_mpsc_listen(
                                 addr,
@@ -219,12 +245,11 @@ impl Scheduler {
                             )
                             .await
                         },
-                        _ => unimplemented!(),
                     };
                     let _ = s2a_listen_result_s.send(res);
 
-                    while let Some((prot, cid)) = c2s_protocol_r.recv().await {
-                        self.init_protocol(prot, cid, None, true).await;
+                    while let Some((prot, remote_addr, cid)) = c2s_protocol_r.recv().await {
+                        self.init_protocol(prot, remote_addr, cid, None, true).await;
                     }
                 }
             })
@@ -239,14 +264,25 @@ impl Scheduler {
             let metrics =
                 ProtocolMetricCache::new(&cid.to_string(), Arc::clone(&self.protocol_metrics));
             self.metrics.connect_request(&addr);
+            let remote_addr = match &addr {
+                ConnectAddr::Tcp(addr) | ConnectAddr::Udp(addr) | ConnectAddr::Tls(addr, ..) => {
+                    Some(*addr)
+                },
+                #[cfg(feature = "quic")]
+                ConnectAddr::Quic(addr, ..) => Some(*addr),
+                ConnectAddr::Mpsc(_) => None,
+            };
             let protocol = match addr {
                 ConnectAddr::Tcp(addr) => Protocols::with_tcp_connect(addr, metrics).await,
                 #[cfg(feature = "quic")]
                 ConnectAddr::Quic(addr, ref config, name) => {
                     Protocols::with_quic_connect(addr, config.clone(), name, metrics).await
                 },
+                ConnectAddr::Tls(addr, config, name) => {
+                    Protocols::with_tls_connect(addr, config, name, metrics).await
+                },
+                ConnectAddr::Udp(addr) => Protocols::with_udp_connect(addr, metrics).await,
                 ConnectAddr::Mpsc(addr) => Protocols::with_mpsc_connect(addr, metrics).await,
-                _ => unimplemented!(),
             };
             let protocol = match protocol {
                 Ok(p) => p,
@@ -255,7 +291,7 @@ impl Scheduler {
                     continue;
                 },
             };
-            self.init_protocol(protocol, cid, Some(pid_sender), false)
+            self.init_protocol(protocol, remote_addr, cid, Some(pid_sender), false)
                 .await;
         }
         trace!("Stop connect_mgr");
@@ -375,6 +411,7 @@ impl Scheduler {
     async fn init_protocol(
         &self,
         mut protocol: Protocols,
+        remote_addr: Option<SocketAddr>,
         cid: Cid,
         s2a_return_pid_s: Option<oneshot::Sender<Result<Participant, NetworkConnectError>>>,
         send_handshake: bool,
@@ -394,6 +431,7 @@ impl Scheduler {
         let metrics = Arc::clone(&self.metrics);
         let local_pid = self.local_pid;
         let local_secret = self.local_secret;
+        let impairment = self.impairment;
         // this is necessary for UDP to work at all and to remove code duplication
         tokio::spawn(
             async move {
@@ -420,11 +458,18 @@ impl Scheduler {
                                 s2b_create_channel_s,
                                 s2b_shutdown_bparticipant_s,
                                 b2a_bandwidth_stats_r,
-                            ) = BParticipant::new(local_pid, pid, sid, Arc::clone(&metrics));
+                            ) = BParticipant::new(
+                                local_pid,
+                                pid,
+                                sid,
+                                Arc::clone(&metrics),
+                                impairment,
+                            );
 
                             let participant = Participant::new(
                                 local_pid,
                                 pid,
+                                remote_addr,
                                 a2b_open_stream_s,
                                 b2a_stream_opened_r,
                                 b2a_bandwidth_stats_r,
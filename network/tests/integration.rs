@@ -4,7 +4,7 @@ use veloren_network::{NetworkError, StreamError};
 mod helper;
 use helper::{mpsc, network_participant_stream, quic, tcp, udp, SLEEP_EXTERNAL, SLEEP_INTERNAL};
 use std::io::ErrorKind;
-use veloren_network::{ConnectAddr, ListenAddr, Network, Pid, Promises};
+use veloren_network::{ConnectAddr, Impairment, ListenAddr, Network, Pid, Promises};
 
 #[test]
 fn stream_simple() {
@@ -90,7 +90,6 @@ fn stream_simple_quic_3msg() {
 }
 
 #[test]
-#[ignore]
 fn stream_simple_udp() {
     let (_, _) = helper::setup(false, 0);
     let (r, _n_a, _p_a, mut s1_a, _n_b, _p_b, mut s1_b) = network_participant_stream(udp());
@@ -101,7 +100,6 @@ fn stream_simple_udp() {
 }
 
 #[test]
-#[ignore]
 fn stream_simple_udp_3msg() {
     let (_, _) = helper::setup(false, 0);
     let (r, _n_a, _p_a, mut s1_a, _n_b, _p_b, mut s1_b) = network_participant_stream(udp());
@@ -116,7 +114,6 @@ fn stream_simple_udp_3msg() {
 }
 
 #[test]
-#[ignore]
 fn tcp_and_udp_2_connections() -> std::result::Result<(), Box<dyn std::error::Error>> {
     let (_, _) = helper::setup(false, 0);
     let r = Arc::new(Runtime::new().unwrap());
@@ -143,7 +140,6 @@ fn tcp_and_udp_2_connections() -> std::result::Result<(), Box<dyn std::error::Er
 }
 
 #[test]
-#[ignore]
 fn failed_listen_on_used_ports() -> std::result::Result<(), Box<dyn std::error::Error>> {
     let (_, _) = helper::setup(false, 0);
     let r = Arc::new(Runtime::new().unwrap());
@@ -262,3 +258,45 @@ fn multiple_try_recv() {
     assert_eq!(s1_b.try_recv::<String>(), Err(StreamError::StreamClosed));
     drop((_n_a, _n_b, _p_a, _p_b)); //clean teardown
 }
+
+#[test]
+fn stream_mpsc_impaired() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let (_, _) = helper::setup(false, 0);
+    let latency = std::time::Duration::from_millis(200);
+    let r = Arc::new(Runtime::new().unwrap());
+    let impairment = Impairment {
+        latency,
+        drop_chance: 1.0,
+        ..Impairment::default()
+    };
+    let server = Network::new_with_impairment(Pid::fake(0), &r, impairment);
+    let client = Network::new_with_impairment(Pid::fake(1), &r, impairment);
+    let (server_addr, client_addr) = mpsc();
+    r.block_on(async {
+        server.listen(server_addr).await?;
+        let p_client = client.connect(client_addr).await?;
+        let p_server = server.connected().await?;
+        let mut reliable_s = p_server
+            .open(4, Promises::ORDERED | Promises::GUARANTEED_DELIVERY, 0)
+            .await?;
+        let mut unreliable_s = p_server.open(4, Promises::empty(), 0).await?;
+        let mut reliable_c = p_client.opened().await?;
+        let mut unreliable_c = p_client.opened().await?;
+
+        // messages of unreliable streams are dropped, reliable ones are delayed
+        let start = std::time::Instant::now();
+        unreliable_s.send("dropped")?;
+        reliable_s.send("Hello World")?;
+        assert_eq!(reliable_c.recv().await, Ok("Hello World".to_string()));
+        assert!(start.elapsed() >= latency);
+        assert_eq!(unreliable_c.try_recv::<String>(), Ok(None));
+
+        // the other direction is impaired as well
+        let start = std::time::Instant::now();
+        reliable_c.send(1337u32)?;
+        assert_eq!(reliable_s.recv().await, Ok(1337u32));
+        assert!(start.elapsed() >= latency);
+        drop((p_client, p_server));
+        Ok::<(), Box<dyn std::error::Error>>(())
+    })
+}
@@ -1,9 +1,13 @@
 pub extern crate common;
 
-pub use common::comp::Health;
+pub use common::{
+    cmd::{ArgumentSpec, CommandSpec, Requirement},
+    comp::{buff::BuffKind, AdminRole, Health},
+};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 pub use common::{resources::GameMode, uid::Uid};
+pub use vek::{Vec2, Vec3};
 
 mod errors;
 
@@ -35,6 +39,60 @@ pub enum Action {
     Print(String),
     PlayerSendMessage(Uid, String),
     KillEntity(Uid),
+    /// Heals (positive `amount`) or damages (negative `amount`) an entity
+    ChangeHealth {
+        entity: Uid,
+        amount: f32,
+    },
+    /// Moves an entity to an absolute world position
+    Teleport {
+        entity: Uid,
+        pos: Vec3<f32>,
+    },
+    /// Gives `amount` of the item with the asset specifier `item` (e.g.
+    /// `common.items.food.apple`) to an entity inventory, none are given if
+    /// they don't all fit
+    GiveItem {
+        entity: Uid,
+        item: String,
+        amount: u32,
+    },
+    /// Removes `amount` of the item with the asset specifier `item` from an
+    /// entity inventory, none are removed if it holds fewer than that
+    RemoveItem {
+        entity: Uid,
+        item: String,
+        amount: u32,
+    },
+    /// Applies a buff to an entity, `duration` is in seconds and `None` means
+    /// the buff never expires
+    ApplyBuff {
+        entity: Uid,
+        kind: BuffKind,
+        strength: f32,
+        duration: Option<f64>,
+    },
+    /// Removes every buff of this kind from an entity
+    RemoveBuff {
+        entity: Uid,
+        kind: BuffKind,
+    },
+    /// Replaces the terrain block at a world position
+    SetBlock {
+        pos: Vec3<i32>,
+        block: BlockInfo,
+    },
+    /// Spawns a new NPC in the world
+    SpawnNpc(NpcSpawn),
+    /// Stores `value` under `key` in the persistent storage of the plugin,
+    /// replacing the previous value. The write is dropped if it would exceed
+    /// the storage quota of the plugin.
+    SetStorageValue {
+        key: String,
+        value: Vec<u8>,
+    },
+    /// Removes `key` from the persistent storage of the plugin
+    RemoveStorageValue(String),
 }
 
 /// The [`Retrieve`] enum represents read of the ECS is sync and blocking.
@@ -68,6 +126,14 @@ pub enum Action {
 pub enum Retrieve {
     GetPlayerName(Uid),
     GetEntityHealth(Uid),
+    GetEntityPosition(Uid),
+    GetEntityInventory(Uid),
+    GetEntityBuffs(Uid),
+    GetBlock(Vec3<i32>),
+    /// Reads a value from the persistent storage of the plugin
+    GetStorageValue(String),
+    /// Lists the keys in the persistent storage of the plugin
+    GetStorageKeys,
 }
 
 /// The [`RetrieveResult`] struct is generated while using the `retrieve_action`
@@ -95,6 +161,69 @@ pub enum Retrieve {
 pub enum RetrieveResult {
     GetPlayerName(String),
     GetEntityHealth(Health),
+    GetEntityPosition(Vec3<f32>),
+    GetEntityInventory(Vec<ItemStack>),
+    GetEntityBuffs(Vec<BuffInfo>),
+    GetBlock(BlockInfo),
+    GetStorageValue(Option<Vec<u8>>),
+    GetStorageKeys(Vec<String>),
+}
+
+/// A stack of items in an inventory, identified by the item asset specifier
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct ItemStack {
+    pub item: String,
+    pub amount: u32,
+}
+
+/// A buff currently active on an entity
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BuffInfo {
+    pub kind: BuffKind,
+    pub strength: f32,
+    /// Remaining time of the buff in seconds, `None` if it never expires
+    pub remaining: Option<f64>,
+}
+
+/// A terrain block, `kind` is the name of a `BlockKind` (e.g. `Rock`, `Air`)
+/// and `color` is only meaningful for filled blocks
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct BlockInfo {
+    pub kind: String,
+    pub color: Option<[u8; 3]>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl From<common::terrain::Block> for BlockInfo {
+    fn from(block: common::terrain::Block) -> Self {
+        Self {
+            kind: block.kind().to_string(),
+            color: block.get_color().map(|c| [c.r, c.g, c.b]),
+        }
+    }
+}
+
+/// The alignment of an NPC spawned with [`Action::SpawnNpc`]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum NpcAlignment {
+    Wild,
+    Enemy,
+    Npc,
+    Tame,
+    Passive,
+}
+
+/// Description of an NPC spawned with [`Action::SpawnNpc`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NpcSpawn {
+    pub pos: Vec3<f32>,
+    /// The name of the NPC body as used by `/spawn` (e.g. `wolf`, `cyclops`)
+    pub body: String,
+    /// Name displayed over the NPC, a random one is picked if `None`
+    pub name: Option<String>,
+    pub alignment: NpcAlignment,
+    /// Whether the NPC gets an AI agent
+    pub agent: bool,
 }
 
 /// This trait is implement by all events and ensure type safety of FFI.
@@ -111,6 +240,8 @@ pub mod event {
 
     /// This event is called when a chat command is run.
     /// Your event should be named `on_command_<Your command>`
+    /// Declare the command in `on_commands` so it shows up in the help and tab
+    /// completion, see [`PluginCommandsEvent`]
     ///
     /// If you return an Error the displayed message will be the error message
     /// in red You can return a Vec<String> that will be print to player
@@ -220,6 +351,354 @@ pub mod event {
         fn get_event_name(&self) -> String { "on_load".to_owned() }
     }
 
+    /// This event is called before the plugin is unloaded or replaced by a
+    /// newer version. Actions that need write access to the ECS and are
+    /// emitted while handling it are discarded.
+    /// Your event should be named `on_unload`
+    #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+    pub struct PluginUnloadEvent;
+
+    impl Event for PluginUnloadEvent {
+        type Response = ();
+
+        fn get_event_name(&self) -> String { "on_unload".to_owned() }
+    }
+
+    /// This event is called right after `on_load` to know which chat commands
+    /// the plugin handles, they show up in `/help` and in the tab completion
+    /// of the players like built-in commands. Each command is then run through
+    /// the `on_command_<keyword>` event, see [`ChatCommandEvent`].
+    /// Your event should be named `on_commands`
+    ///
+    /// # Example
+    /// ```ignore
+    /// #[event_handler]
+    /// pub fn on_commands(_: PluginCommandsEvent) -> Vec<CommandSpec> {
+    ///     vec![CommandSpec {
+    ///         keyword: "heal".to_owned(),
+    ///         args: vec![ArgumentSpec::PlayerName(Requirement::Required)],
+    ///         description: "Heals a player".to_owned(),
+    ///         needs_role: Some(AdminRole::Moderator),
+    ///     }]
+    /// }
+    /// ```
+    #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+    pub struct PluginCommandsEvent;
+
+    impl Event for PluginCommandsEvent {
+        type Response = Vec<CommandSpec>;
+
+        fn get_event_name(&self) -> String { "on_commands".to_owned() }
+    }
+
+    /// This is the return type of events that can be cancelled, like
+    /// [`BlockBreakEvent`] or [`ItemPickupEvent`]
+    ///
+    /// Variants:
+    ///  - `Cancel` will prevent the action from happening.
+    ///  - `None` will let the action happen.
+    ///
+    /// The action is cancelled if any plugin returns `Cancel`.
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+    #[repr(u8)]
+    pub enum CancelResult {
+        Cancel,
+        None,
+    }
+
+    impl Default for CancelResult {
+        fn default() -> Self { Self::None }
+    }
+
+    /// This event is called every server tick, before clients inputs are
+    /// handled.
+    /// Your event should be named `on_tick`
+    ///
+    /// # Example
+    /// ```ignore
+    /// #[event_handler]
+    /// pub fn on_tick(tick: ServerTickEvent, state: &mut State) {
+    ///     state.elapsed += tick.dt;
+    /// }
+    /// ```
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    pub struct ServerTickEvent {
+        pub tick: u64,
+        /// Duration of the last tick in seconds
+        pub dt: f64,
+    }
+
+    impl Event for ServerTickEvent {
+        type Response = ();
+
+        fn get_event_name(&self) -> String { "on_tick".to_owned() }
+    }
+
+    /// The type of kill, mirrors `common::comp::chat::KillType`
+    #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+    pub enum KillType {
+        Buff(BuffKind),
+        Melee,
+        Projectile,
+        Explosion,
+        Energy,
+        Other,
+    }
+
+    /// What killed an entity, mirrors `common::comp::chat::KillSource`
+    #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+    pub enum KillSource {
+        Player(Uid, KillType),
+        NonPlayer(String, KillType),
+        NonExistent(KillType),
+        Environment(String),
+        FallDamage,
+        Suicide,
+        Other,
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    impl From<common::comp::chat::KillType> for KillType {
+        fn from(kill_type: common::comp::chat::KillType) -> Self {
+            use common::comp::chat::KillType as K;
+            match kill_type {
+                K::Buff(kind) => Self::Buff(kind),
+                K::Melee => Self::Melee,
+                K::Projectile => Self::Projectile,
+                K::Explosion => Self::Explosion,
+                K::Energy => Self::Energy,
+                K::Other => Self::Other,
+            }
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    impl From<common::comp::chat::KillSource> for KillSource {
+        fn from(source: common::comp::chat::KillSource) -> Self {
+            use common::comp::chat::KillSource as K;
+            match source {
+                K::Player(uid, kill_type) => Self::Player(uid, kill_type.into()),
+                K::NonPlayer(name, kill_type) => Self::NonPlayer(name, kill_type.into()),
+                K::NonExistent(kill_type) => Self::NonExistent(kill_type.into()),
+                K::Environment(name) => Self::Environment(name),
+                K::FallDamage => Self::FallDamage,
+                K::Suicide => Self::Suicide,
+                K::Other => Self::Other,
+            }
+        }
+    }
+
+    /// This event is called when an entity (player or NPC) dies.
+    /// Your event should be named `on_death`
+    ///
+    /// # Example
+    /// ```ignore
+    /// #[event_handler]
+    /// pub fn on_death(death: EntityDeathEvent) {
+    ///     if let KillSource::Player(killer, _) = death.killer {
+    ///         emit_action(Action::GiveItem {
+    ///             entity: killer,
+    ///             item: "common.items.utility.coins".to_owned(),
+    ///             amount: 10,
+    ///         });
+    ///     }
+    /// }
+    /// ```
+    #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+    pub struct EntityDeathEvent {
+        pub entity: Uid,
+        pub killer: KillSource,
+    }
+
+    impl Event for EntityDeathEvent {
+        type Response = ();
+
+        fn get_event_name(&self) -> String { "on_death".to_owned() }
+    }
+
+    /// The cause of damage, mirrors `common::combat::DamageSource`
+    #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+    pub enum DamageSource {
+        Buff(BuffKind),
+        Melee,
+        Projectile,
+        Explosion,
+        Falling,
+        Shockwave,
+        Energy,
+        Other,
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    impl From<common::combat::DamageSource> for DamageSource {
+        fn from(source: common::combat::DamageSource) -> Self {
+            use common::combat::DamageSource as D;
+            match source {
+                D::Buff(kind) => Self::Buff(kind),
+                D::Melee => Self::Melee,
+                D::Projectile => Self::Projectile,
+                D::Explosion => Self::Explosion,
+                D::Falling => Self::Falling,
+                D::Shockwave => Self::Shockwave,
+                D::Energy => Self::Energy,
+                D::Other => Self::Other,
+            }
+        }
+    }
+
+    /// This event is called when an entity is about to take damage, including
+    /// every tick of damage over time from buffs, which can be told apart by
+    /// their `source`.
+    /// Your event should be named `on_damage`
+    ///
+    /// Returning [`CancelResult::Cancel`] prevents the damage.
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    pub struct EntityDamageEvent {
+        pub entity: Uid,
+        pub attacker: Option<Uid>,
+        /// The amount of health lost, always positive
+        pub amount: f32,
+        /// What caused the damage, if known
+        pub source: Option<DamageSource>,
+    }
+
+    impl Event for EntityDamageEvent {
+        type Response = CancelResult;
+
+        fn get_event_name(&self) -> String { "on_damage".to_owned() }
+    }
+
+    /// This event is called when a player tries to pick up an item from the
+    /// ground.
+    /// Your event should be named `on_item_pickup`
+    ///
+    /// Returning [`CancelResult::Cancel`] leaves the item on the ground.
+    #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+    pub struct ItemPickupEvent {
+        pub player: Player,
+        pub item: ItemStack,
+    }
+
+    impl Event for ItemPickupEvent {
+        type Response = CancelResult;
+
+        fn get_event_name(&self) -> String { "on_item_pickup".to_owned() }
+    }
+
+    /// This event is called when a player drops an item on the ground.
+    /// Your event should be named `on_item_drop`
+    #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+    pub struct ItemDropEvent {
+        pub player: Player,
+        pub item: ItemStack,
+    }
+
+    impl Event for ItemDropEvent {
+        type Response = ();
+
+        fn get_event_name(&self) -> String { "on_item_drop".to_owned() }
+    }
+
+    /// This event is called when a player tries to break a block, either by
+    /// mining it or in build mode.
+    /// Your event should be named `on_block_break`
+    ///
+    /// Returning [`CancelResult::Cancel`] keeps the block in place.
+    ///
+    /// # Example
+    /// ```ignore
+    /// #[event_handler]
+    /// pub fn on_block_break(event: BlockBreakEvent) -> CancelResult {
+    ///     if event.pos.z < 0 {
+    ///         CancelResult::Cancel
+    ///     } else {
+    ///         CancelResult::None
+    ///     }
+    /// }
+    /// ```
+    #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+    pub struct BlockBreakEvent {
+        pub player: Player,
+        pub pos: Vec3<i32>,
+        pub block: BlockInfo,
+    }
+
+    impl Event for BlockBreakEvent {
+        type Response = CancelResult;
+
+        fn get_event_name(&self) -> String { "on_block_break".to_owned() }
+    }
+
+    /// This event is called when a player tries to place a block in build
+    /// mode.
+    /// Your event should be named `on_block_place`
+    ///
+    /// Returning [`CancelResult::Cancel`] prevents the block from being placed.
+    #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+    pub struct BlockPlaceEvent {
+        pub player: Player,
+        pub pos: Vec3<i32>,
+        pub block: BlockInfo,
+    }
+
+    impl Event for BlockPlaceEvent {
+        type Response = CancelResult;
+
+        fn get_event_name(&self) -> String { "on_block_place".to_owned() }
+    }
+
+    /// This event is called when a trade between two parties completed
+    /// successfully.
+    /// Your event should be named `on_trade_complete`
+    #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+    pub struct TradeCompleteEvent {
+        pub parties: [Player; 2],
+    }
+
+    impl Event for TradeCompleteEvent {
+        type Response = ();
+
+        fn get_event_name(&self) -> String { "on_trade_complete".to_owned() }
+    }
+
+    /// A change of the group of a player, see [`GroupChangeEvent`]
+    #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+    pub enum GroupChange {
+        Joined,
+        Left,
+        Kicked { by: Uid },
+        BecameLeader,
+    }
+
+    /// This event is called when a player joins, leaves or is kicked from a
+    /// group, or becomes its leader.
+    /// Your event should be named `on_group_change`
+    #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+    pub struct GroupChangeEvent {
+        pub player: Player,
+        pub change: GroupChange,
+    }
+
+    impl Event for GroupChangeEvent {
+        type Response = ();
+
+        fn get_event_name(&self) -> String { "on_group_change".to_owned() }
+    }
+
+    /// This event is called when a new terrain chunk is generated and inserted
+    /// in the world.
+    /// Your event should be named `on_chunk_generate`
+    #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+    pub struct ChunkGenerateEvent {
+        pub key: Vec2<i32>,
+    }
+
+    impl Event for ChunkGenerateEvent {
+        type Response = ();
+
+        fn get_event_name(&self) -> String { "on_chunk_generate".to_owned() }
+    }
+
     // impl Default for PlayerJoinResult {
     //     fn default() -> Self {
     //         Self::None
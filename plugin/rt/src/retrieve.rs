@@ -1,4 +1,4 @@
-use plugin_api::{Health, RetrieveError};
+use plugin_api::{BlockInfo, BuffInfo, Health, ItemStack, RetrieveError, Vec3};
 
 use crate::api::{Retrieve, RetrieveResult};
 
@@ -33,3 +33,60 @@ impl GetPlayerName for crate::api::event::Player {
         }
     }
 }
+
+pub trait GetEntityPosition {
+    fn get_entity_position(&self) -> Result<Vec3<f32>, RetrieveError>;
+}
+
+pub trait GetEntityInventory {
+    fn get_entity_inventory(&self) -> Result<Vec<ItemStack>, RetrieveError>;
+}
+
+pub trait GetEntityBuffs {
+    fn get_entity_buffs(&self) -> Result<Vec<BuffInfo>, RetrieveError>;
+}
+
+impl GetEntityPosition for crate::api::event::Player {
+    fn get_entity_position(&self) -> Result<Vec3<f32>, RetrieveError> {
+        if let RetrieveResult::GetEntityPosition(e) =
+            crate::retrieve_action(&Retrieve::GetEntityPosition(self.id))?
+        {
+            Ok(e)
+        } else {
+            Err(RetrieveError::InvalidType)
+        }
+    }
+}
+
+impl GetEntityInventory for crate::api::event::Player {
+    fn get_entity_inventory(&self) -> Result<Vec<ItemStack>, RetrieveError> {
+        if let RetrieveResult::GetEntityInventory(e) =
+            crate::retrieve_action(&Retrieve::GetEntityInventory(self.id))?
+        {
+            Ok(e)
+        } else {
+            Err(RetrieveError::InvalidType)
+        }
+    }
+}
+
+impl GetEntityBuffs for crate::api::event::Player {
+    fn get_entity_buffs(&self) -> Result<Vec<BuffInfo>, RetrieveError> {
+        if let RetrieveResult::GetEntityBuffs(e) =
+            crate::retrieve_action(&Retrieve::GetEntityBuffs(self.id))?
+        {
+            Ok(e)
+        } else {
+            Err(RetrieveError::InvalidType)
+        }
+    }
+}
+
+/// Reads the terrain block at a world position
+pub fn get_block(pos: Vec3<i32>) -> Result<BlockInfo, RetrieveError> {
+    if let RetrieveResult::GetBlock(e) = crate::retrieve_action(&Retrieve::GetBlock(pos))? {
+        Ok(e)
+    } else {
+        Err(RetrieveError::InvalidType)
+    }
+}
@@ -0,0 +1,58 @@
+//! Persistent key-value storage of the plugin, kept by the server across
+//! restarts.
+//!
+//! Reads see the values as they were at the start of the current tick, writes
+//! are applied at the start of the next one.
+
+use plugin_api::{Action, RetrieveError};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::api::{Retrieve, RetrieveResult};
+
+/// Reads the raw value stored under `key`
+pub fn get_raw(key: &str) -> Result<Option<Vec<u8>>, RetrieveError> {
+    if let RetrieveResult::GetStorageValue(e) =
+        crate::retrieve_action(&Retrieve::GetStorageValue(key.to_owned()))?
+    {
+        Ok(e)
+    } else {
+        Err(RetrieveError::InvalidType)
+    }
+}
+
+/// Reads and deserializes the value stored under `key`
+pub fn get<T: DeserializeOwned>(key: &str) -> Result<Option<T>, RetrieveError> {
+    get_raw(key)?
+        .map(|value| {
+            bincode::deserialize(&value).map_err(|e| RetrieveError::BincodeError(e.to_string()))
+        })
+        .transpose()
+}
+
+/// Lists the keys of the storage
+pub fn keys() -> Result<Vec<String>, RetrieveError> {
+    if let RetrieveResult::GetStorageKeys(e) = crate::retrieve_action(&Retrieve::GetStorageKeys)? {
+        Ok(e)
+    } else {
+        Err(RetrieveError::InvalidType)
+    }
+}
+
+/// Stores a raw value under `key`
+pub fn set_raw(key: &str, value: Vec<u8>) {
+    crate::emit_action(Action::SetStorageValue {
+        key: key.to_owned(),
+        value,
+    })
+}
+
+/// Serializes and stores a value under `key`
+pub fn set<T: Serialize>(key: &str, value: &T) {
+    set_raw(
+        key,
+        bincode::serialize(value).expect("Can't serialize storage value"),
+    )
+}
+
+/// Removes the value stored under `key`
+pub fn remove(key: &str) { crate::emit_action(Action::RemoveStorageValue(key.to_owned())) }
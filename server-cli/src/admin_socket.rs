@@ -0,0 +1,301 @@
+//! Local TCP socket to run console commands from scripts.
+//!
+//! A connection starts with a line holding the token from the data directory,
+//! followed by one command per line: either a console command, as typed in the
+//! tui, or a chat command starting with `/`.  The output of each command is
+//! sent back followed by an empty line.
+
+use crate::cli::{self, Message};
+use common::cmd::ChatCommand;
+use rand::{distributions::Alphanumeric, Rng};
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::Path,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    time::Duration,
+};
+use tracing::{debug, error, info, warn};
+
+/// Time a connection may stay idle before being closed
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Time to wait for the server to run a command
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+const TOKEN_LENGTH: usize = 32;
+/// Connections handled at once, further ones are refused
+const MAX_CONNECTIONS: usize = 8;
+
+pub enum Request {
+    Message(Message),
+    ChatCommand(ChatCommand, Vec<String>),
+}
+
+pub struct AdminSocket {
+    /// Requests from the connections, along with where to send their output
+    pub request_r: mpsc::Receiver<(Request, mpsc::Sender<String>)>,
+}
+
+impl AdminSocket {
+    /// Starts listening on `address`, which has to be a loopback address as
+    /// the commands are only authenticated, not encrypted.
+    pub fn run(address: SocketAddr, token_path: &Path) -> io::Result<Self> {
+        if !address.ip().is_loopback() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the admin socket can only listen on a loopback address",
+            ));
+        }
+        let token = Arc::new(load_or_create_token(token_path)?);
+        let listener = TcpListener::bind(address)?;
+        info!(
+            ?address,
+            ?token_path,
+            "Listening for console commands on the admin socket"
+        );
+
+        let (request_s, request_r) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("admin_socket".to_owned())
+            .spawn(move || accept_connections(listener, token, request_s))?;
+
+        Ok(Self { request_r })
+    }
+}
+
+fn accept_connections(
+    listener: TcpListener,
+    token: Arc<String>,
+    request_s: mpsc::Sender<(Request, mpsc::Sender<String>)>,
+) {
+    let connections = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!(?e, "Failed to accept an admin socket connection");
+                continue;
+            },
+        };
+        if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            connections.fetch_sub(1, Ordering::SeqCst);
+            warn!(peer = ?stream.peer_addr().ok(), "Too many admin socket connections");
+            let _ = writeln!(stream, "Too many connections");
+            continue;
+        }
+        let guard = ConnectionGuard(Arc::clone(&connections));
+        let token = Arc::clone(&token);
+        let request_s = request_s.clone();
+        let spawned = std::thread::Builder::new()
+            .name("admin_socket_connection".to_owned())
+            .spawn(move || {
+                let _guard = guard;
+                if let Err(e) = handle_connection(stream, &token, &request_s) {
+                    debug!(?e, "Admin socket connection closed");
+                }
+            });
+        if let Err(e) = spawned {
+            error!(?e, "Failed to spawn an admin socket connection thread");
+        }
+    }
+}
+
+/// Counts a connection as handled until dropped
+struct ConnectionGuard(Arc<AtomicUsize>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) { self.0.fetch_sub(1, Ordering::SeqCst); }
+}
+
+fn load_or_create_token(path: &Path) -> io::Result<String> {
+    match fs::read_to_string(path) {
+        Ok(token) => {
+            check_token_permissions(path)?;
+            if !token.trim().is_empty() {
+                return Ok(token.trim().to_owned());
+            }
+            // An empty token file is replaced with a new token
+            fs::remove_file(path)?;
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+        Err(e) => return Err(e),
+    }
+
+    let token = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .map(char::from)
+        .collect::<String>();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    // Only the user running the server should be able to read the token, from
+    // the moment the file is created.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(token.as_bytes())?;
+    info!(?path, "Created a new admin socket token");
+    Ok(token)
+}
+
+/// Refuses token files other users can read, anyone able to read the token can
+/// run commands as the server.
+fn check_token_permissions(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(path)?.permissions().mode();
+        if mode & 0o077 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "the admin socket token file {:?} is readable by other users (mode {:o}), \
+                     restrict it to the server's user",
+                    path,
+                    mode & 0o777
+                ),
+            ));
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Compares the tokens in constant time, so their content can't be guessed
+/// from the time it takes to reject them.
+fn token_matches(given: &str, token: &str) -> bool {
+    let (given, token) = (given.as_bytes(), token.as_bytes());
+    given.len() == token.len()
+        && given
+            .iter()
+            .zip(token)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    token: &str,
+    request_s: &mpsc::Sender<(Request, mpsc::Sender<String>)>,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !token_matches(line.trim(), token) {
+        warn!(peer = ?writer.peer_addr().ok(), "Invalid token on the admin socket");
+        return writeln!(writer, "Invalid token");
+    }
+
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let output = match parse_request(line) {
+            Ok(request) => {
+                let (output_s, output_r) = mpsc::channel();
+                if request_s.send((request, output_s)).is_err() {
+                    return writeln!(writer, "The server is shutting down\n");
+                }
+                output_r
+                    .recv_timeout(RESPONSE_TIMEOUT)
+                    .unwrap_or_else(|_| "The server did not respond in time".to_owned())
+            },
+            Err(e) => e,
+        };
+        writeln!(writer, "{}\n", output.trim_end())?;
+    }
+    Ok(())
+}
+
+fn parse_request(line: &str) -> Result<Request, String> {
+    if line.starts_with('/') {
+        let mut words = shell_words::split(line)
+            .map_err(|e| format!("Failed to parse the command: {}", e))?
+            .into_iter();
+        let keyword = words.next().unwrap_or_default();
+        let cmd =
+            ChatCommand::from_str(&keyword).map_err(|_| format!("Unknown command: {}", keyword))?;
+        Ok(Request::ChatCommand(cmd, words.collect()))
+    } else {
+        cli::parse_message(line).map(Request::Message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    const TOKEN: &str = "secret";
+
+    /// Handles a single connection, to which `input` is sent, returning what
+    /// was sent back and the requests made.
+    fn connect(input: &str) -> (String, Vec<Request>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let (request_s, request_r) = mpsc::channel();
+        let handler = std::thread::spawn(move || {
+            let _ = handle_connection(stream, TOKEN, &request_s);
+        });
+
+        client.write_all(input.as_bytes()).unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+        let mut requests = Vec::new();
+        for (request, output_s) in request_r.iter() {
+            output_s.send("done".to_owned()).unwrap();
+            requests.push(request);
+        }
+        handler.join().unwrap();
+        let mut output = String::new();
+        client.read_to_string(&mut output).unwrap();
+        (output, requests)
+    }
+
+    #[test]
+    fn accepts_valid_token() {
+        let (output, requests) = connect("secret\n/help\n");
+        assert_eq!(output, "done\n\n");
+        assert!(matches!(requests[..], [Request::ChatCommand(
+            ChatCommand::Help,
+            _
+        )]));
+    }
+
+    #[test]
+    fn rejects_invalid_token() {
+        let (output, requests) = connect("secreT\n/help\n");
+        assert_eq!(output, "Invalid token\n");
+        assert!(requests.is_empty());
+    }
+
+    #[test]
+    fn rejects_command_instead_of_token() {
+        let (output, requests) = connect("/help\nsecret\n/help\n");
+        assert_eq!(output, "Invalid token\n");
+        assert!(requests.is_empty());
+    }
+
+    #[test]
+    fn compares_tokens() {
+        assert!(token_matches("secret", TOKEN));
+        assert!(!token_matches("secre", TOKEN));
+        assert!(!token_matches("secrets", TOKEN));
+        assert!(!token_matches("", TOKEN));
+    }
+}
@@ -1,6 +1,6 @@
 use common::comp;
 use server::persistence::SqlLogMode;
-use std::sync::mpsc::Sender;
+use std::{path::PathBuf, sync::mpsc::Sender};
 use structopt::StructOpt;
 use tracing::error;
 
@@ -20,6 +20,26 @@ pub enum Admin {
     },
 }
 
+#[derive(Clone, Debug, StructOpt)]
+pub enum PermissionGroup {
+    /// Lists the permission groups, what they allow and their members
+    List,
+    /// Adds a user to a permission group
+    Assign {
+        /// Name of the user to add to the group
+        username: String,
+        /// Name of the group, as defined in the permission groups settings
+        group: String,
+    },
+    /// Removes a user from a permission group
+    Unassign {
+        /// Name of the user to remove from the group
+        username: String,
+        /// Name of the group
+        group: String,
+    },
+}
+
 #[derive(Clone, Debug, StructOpt)]
 pub enum Shutdown {
     /// Closes the server immediately
@@ -36,6 +56,39 @@ pub enum Shutdown {
     Cancel,
 }
 
+#[derive(Clone, Debug, StructOpt)]
+pub enum Schedule {
+    /// Lists the scheduled tasks
+    List,
+    /// Cancels a scheduled task until the server restarts
+    Cancel {
+        /// Number of the task, as listed
+        id: usize,
+    },
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub enum Plugin {
+    /// Lists the loaded plugins
+    List,
+    /// Loads a plugin from the plugin directory, replacing the loaded plugin
+    /// with the same name
+    Load {
+        /// File name of the plugin, e.g. `example.plugin.tar`
+        file: String,
+    },
+    /// Reloads a plugin from the file it was loaded from
+    Reload {
+        /// Name of the plugin
+        name: String,
+    },
+    /// Unloads a plugin
+    Unload {
+        /// Name of the plugin
+        name: String,
+    },
+}
+
 #[derive(Clone, Debug, StructOpt)]
 pub enum SharedCommand {
     /// Perform operations on the admin list
@@ -43,6 +96,23 @@ pub enum SharedCommand {
         #[structopt(subcommand)]
         command: Admin,
     },
+    /// Manage which users are in which permission groups
+    PermissionGroup {
+        #[structopt(subcommand)]
+        command: PermissionGroup,
+    },
+    /// Show the most recent moderation commands from the audit log
+    AuditLog {
+        /// Number of entries to show
+        #[structopt(short, long, default_value = "20")]
+        count: usize,
+        /// Only show the entries involving this player
+        #[structopt(short, long)]
+        player: Option<String>,
+    },
+    /// Back up the character database, which can be done while the server
+    /// runs
+    Backup,
 }
 
 #[derive(Debug, Clone, StructOpt)]
@@ -54,6 +124,11 @@ pub enum Message {
         #[structopt(subcommand)]
         command: Shutdown,
     },
+    /// View or cancel the scheduled restarts and announcements
+    Schedule {
+        #[structopt(subcommand)]
+        command: Schedule,
+    },
     /// Loads up the chunks at map center and adds a entity that mimics a
     /// player to keep them from despawning
     LoadArea {
@@ -67,6 +142,11 @@ pub enum Message {
     },
     /// Disconnects all connected clients
     DisconnectAllClients,
+    /// Manage the server plugins
+    Plugin {
+        #[structopt(subcommand)]
+        command: Plugin,
+    },
 }
 
 #[derive(StructOpt)]
@@ -86,6 +166,31 @@ pub struct TuiApp {
 pub enum ArgvCommand {
     #[structopt(flatten)]
     Shared(SharedCommand),
+    /// Replace the character database with a backup, the server must not be
+    /// running
+    Restore {
+        /// Path of the backup, or its file name in the backup directory
+        backup: PathBuf,
+    },
+    /// Export a character to a file, to import it on another server
+    ExportCharacter {
+        /// Name of the account owning the character
+        username: String,
+        /// Name or id of the character
+        character: String,
+        /// File to export the character to
+        file: PathBuf,
+    },
+    /// Import a character exported from another server
+    ImportCharacter {
+        /// Name of the account to give the character to
+        username: String,
+        /// File the character was exported to
+        file: PathBuf,
+        /// Rename the character
+        #[structopt(short, long)]
+        alias: Option<String>,
+    },
 }
 
 #[derive(StructOpt)]
@@ -116,13 +221,19 @@ pub struct ArgvApp {
     pub command: Option<ArgvCommand>,
 }
 
+pub fn parse_message(input: &str) -> Result<Message, String> {
+    TuiApp::from_iter_safe(shell_words::split(input).unwrap_or_default())
+        .map(|app| app.command)
+        .map_err(|err| err.message)
+}
+
 pub fn parse_command(input: &str, msg_s: &mut Sender<Message>) {
-    match TuiApp::from_iter_safe(shell_words::split(input).unwrap_or_default()) {
+    match parse_message(input) {
         Ok(message) => {
             msg_s
-                .send(message.command)
+                .send(message)
                 .unwrap_or_else(|err| error!("Failed to send CLI message, err: {:?}", err));
         },
-        Err(err) => error!("{}", err.message),
+        Err(err) => error!("{}", err),
     }
 }
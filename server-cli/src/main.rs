@@ -4,13 +4,17 @@
 
 /// `server-cli` interface commands not to be confused with the commands sent
 /// from the client to the server
+mod admin_socket;
 mod cli;
+mod scheduler;
 mod settings;
 mod shutdown_coordinator;
 mod tui_runner;
 mod tuilog;
 use crate::{
-    cli::{Admin, ArgvApp, ArgvCommand, Message, SharedCommand, Shutdown},
+    admin_socket::{AdminSocket, Request},
+    cli::{Admin, ArgvApp, ArgvCommand, Message, PermissionGroup, SharedCommand, Shutdown},
+    scheduler::Scheduler,
     shutdown_coordinator::ShutdownCoordinator,
     tui_runner::Tui,
     tuilog::TuiLog,
@@ -18,14 +22,20 @@ use crate::{
 use common::{clock::Clock, consts::MIN_RECOMMENDED_TOKIO_THREADS};
 use common_base::span;
 use core::sync::atomic::{AtomicUsize, Ordering};
-use server::{persistence::DatabaseSettings, Event, Input, Server};
+use server::{
+    alias_validator::AliasValidator,
+    login_provider::LoginProvider,
+    persistence::{character_export, DatabaseSettings},
+    Event, Input, Server,
+};
 use std::{
     io,
+    path::Path,
     sync::{atomic::AtomicBool, mpsc, Arc},
     time::Duration,
 };
 use structopt::StructOpt;
-use tracing::{info, trace};
+use tracing::{error, info, trace};
 
 lazy_static::lazy_static! {
     pub static ref LOG: TuiLog<'static> = TuiLog::default();
@@ -137,6 +147,135 @@ fn main() -> io::Result<()> {
                 }
                 Ok(())
             },
+            ArgvCommand::Shared(SharedCommand::PermissionGroup { command }) => {
+                let login_provider = server::login_provider::LoginProvider::new(
+                    server_settings.auth_server_address,
+                    runtime,
+                );
+
+                match command {
+                    PermissionGroup::List => {
+                        for line in editable_settings.permission_groups.summary().lines() {
+                            info!("{}", line);
+                        }
+                    },
+                    // The reason of a failure was logged already.
+                    PermissionGroup::Assign { username, group } => {
+                        server::assign_permission_group(
+                            &username,
+                            &group,
+                            &login_provider,
+                            &mut editable_settings,
+                            &server_data_dir,
+                        )
+                        .ok_or_else(|| {
+                            io::Error::new(
+                                io::ErrorKind::Other,
+                                format!("Failed to add {} to the group {}", username, group),
+                            )
+                        })?;
+                    },
+                    PermissionGroup::Unassign { username, group } => {
+                        server::unassign_permission_group(
+                            &username,
+                            &group,
+                            &login_provider,
+                            &mut editable_settings,
+                            &server_data_dir,
+                        )
+                        .ok_or_else(|| {
+                            io::Error::new(
+                                io::ErrorKind::Other,
+                                format!("Failed to remove {} from the group {}", username, group),
+                            )
+                        })?;
+                    },
+                }
+                Ok(())
+            },
+            ArgvCommand::Shared(SharedCommand::AuditLog { count, player }) => {
+                print_audit_log(&server_data_dir, count, player.as_deref());
+                Ok(())
+            },
+            ArgvCommand::Shared(SharedCommand::Backup) => server::persistence::backup::backup(
+                &database_settings,
+                server_settings.database_backups.retention,
+            )
+            .map(|_| ())
+            .map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("Failed to back up the database: {}", e),
+                )
+            }),
+            ArgvCommand::Restore { backup } => {
+                let backup = if backup.exists() {
+                    backup
+                } else {
+                    server::persistence::backup::backup_dir(&database_settings).join(backup)
+                };
+                let previous = server::persistence::backup::restore(&database_settings, &backup)
+                    .map_err(|e| {
+                        io::Error::new(
+                            io::ErrorKind::Other,
+                            format!("Failed to restore the database: {}", e),
+                        )
+                    })?;
+                if let Some(previous) = previous {
+                    info!("The previous database was saved to {}", previous.display());
+                }
+                Ok(())
+            },
+            ArgvCommand::ExportCharacter {
+                username,
+                character,
+                file,
+            } => {
+                let login_provider =
+                    LoginProvider::new(server_settings.auth_server_address, runtime);
+                let uuid = player_uuid(&login_provider, &username)?;
+                character_export::export_character(&database_settings, &uuid, &character)
+                    .and_then(|export| export.save(&file))
+                    .map_err(|e| {
+                        io::Error::new(
+                            io::ErrorKind::Other,
+                            format!("Failed to export the character: {}", e),
+                        )
+                    })?;
+                info!("Exported the character to {}", file.display());
+                Ok(())
+            },
+            ArgvCommand::ImportCharacter {
+                username,
+                file,
+                alias,
+            } => {
+                let login_provider =
+                    LoginProvider::new(server_settings.auth_server_address, runtime);
+                let uuid = player_uuid(&login_provider, &username)?;
+                let alias_validator =
+                    AliasValidator::from_files(&server_settings.banned_words_files)
+                        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                // Make sure the database is ready for the character.
+                server::persistence::run_migrations(&database_settings);
+                character_export::CharacterExport::load(&file)
+                    .and_then(|export| {
+                        character_export::import_character(
+                            &database_settings,
+                            &alias_validator,
+                            &uuid,
+                            alias.as_deref(),
+                            export,
+                        )
+                    })
+                    .map_err(|e| {
+                        io::Error::new(
+                            io::ErrorKind::Other,
+                            format!("Failed to import the character: {}", e),
+                        )
+                    })?;
+                Ok(())
+            },
         };
     }
 
@@ -152,6 +291,12 @@ fn main() -> io::Result<()> {
 
     let tui = (!noninteractive).then(|| Tui::run(basic));
 
+    let admin_socket = settings.admin_socket_address.and_then(|address| {
+        AdminSocket::run(address, &settings::admin_token_path())
+            .map_err(|e| error!(?e, ?address, "Failed to start the admin socket"))
+            .ok()
+    });
+
     info!("Starting server...");
 
     let server_port = &server_settings.gameserver_address.port();
@@ -173,6 +318,7 @@ fn main() -> io::Result<()> {
     );
 
     let mut shutdown_coordinator = ShutdownCoordinator::new(Arc::clone(&sigusr1_signal));
+    let mut scheduler = Scheduler::new(&settings.schedules, &settings.restart_hooks);
 
     // Set up an fps clock
     let mut clock = Clock::new(Duration::from_secs_f64(1.0 / TPS as f64));
@@ -186,6 +332,7 @@ fn main() -> io::Result<()> {
         if shutdown_coordinator.check(&mut server, &settings) {
             break;
         }
+        scheduler.check(&mut server, &mut shutdown_coordinator);
 
         let events = server
             .tick(Input::default(), clock.dt())
@@ -208,50 +355,56 @@ fn main() -> io::Result<()> {
 
         if let Some(tui) = tui.as_ref() {
             match tui.msg_r.try_recv() {
-                Ok(msg) => match msg {
-                    Message::Shutdown {
-                        command: Shutdown::Cancel,
-                    } => shutdown_coordinator.abort_shutdown(&mut server),
-                    Message::Shutdown {
-                        command: Shutdown::Graceful { seconds, reason },
-                    } => {
-                        shutdown_coordinator.initiate_shutdown(
-                            &mut server,
-                            Duration::from_secs(seconds),
-                            reason,
-                        );
-                    },
-                    Message::Shutdown {
-                        command: Shutdown::Immediate,
-                    } => {
-                        info!("Closing the server");
+                Ok(msg) => {
+                    if let MessageResult::Exit = handle_message(
+                        &mut server,
+                        &mut shutdown_coordinator,
+                        &mut scheduler,
+                        &server_data_dir,
+                        msg,
+                    ) {
                         break;
-                    },
-                    Message::Shared(SharedCommand::Admin {
-                        command: Admin::Add { username, role },
-                    }) => {
-                        server.add_admin(&username, role);
-                    },
-                    Message::Shared(SharedCommand::Admin {
-                        command: Admin::Remove { username },
-                    }) => {
-                        server.remove_admin(&username);
-                    },
-                    Message::LoadArea { view_distance } => {
-                        #[cfg(feature = "worldgen")]
-                        server.create_centered_persister(view_distance);
-                    },
-                    Message::SqlLogMode { mode } => {
-                        server.set_sql_log_mode(mode);
-                    },
-                    Message::DisconnectAllClients => {
-                        server.disconnect_all_clients();
-                    },
+                    }
                 },
                 Err(mpsc::TryRecvError::Empty) | Err(mpsc::TryRecvError::Disconnected) => {},
             }
         }
 
+        if let Some(admin_socket) = admin_socket.as_ref() {
+            let mut exit = false;
+            for (request, output_s) in admin_socket.request_r.try_iter() {
+                let output = match request {
+                    Request::Message(msg) => match handle_message(
+                        &mut server,
+                        &mut shutdown_coordinator,
+                        &mut scheduler,
+                        &server_data_dir,
+                        msg,
+                    ) {
+                        MessageResult::Continue(output) => {
+                            output.unwrap_or_else(|| "Ok".to_owned())
+                        },
+                        MessageResult::Exit => {
+                            exit = true;
+                            "Closing the server".to_owned()
+                        },
+                    },
+                    Request::ChatCommand(cmd, args) => {
+                        let output = server.execute_console_command(&cmd, args);
+                        if output.is_empty() {
+                            "Ok".to_owned()
+                        } else {
+                            output.join("\n")
+                        }
+                    },
+                };
+                let _ = output_s.send(output);
+            }
+            if exit {
+                break;
+            }
+        }
+
         drop(guard);
         // Wait for the next tick.
         clock.tick();
@@ -259,5 +412,167 @@ fn main() -> io::Result<()> {
         common_base::tracy_client::finish_continuous_frame!();
     }
 
+    // Let the server save everything before running the hooks.
+    drop(server);
+    scheduler.run_restart_hooks();
+
     Ok(())
 }
+
+/// What the main loop should do after handling a console message
+enum MessageResult {
+    /// Keep the server running, with the output of the command when it has
+    /// any besides what it logged
+    Continue(Option<String>),
+    Exit,
+}
+
+fn handle_message(
+    server: &mut Server,
+    shutdown_coordinator: &mut ShutdownCoordinator,
+    scheduler: &mut Scheduler,
+    data_dir: &Path,
+    msg: Message,
+) -> MessageResult {
+    match msg {
+        Message::Shutdown {
+            command: Shutdown::Cancel,
+        } => shutdown_coordinator.abort_shutdown(server),
+        Message::Shutdown {
+            command: Shutdown::Graceful { seconds, reason },
+        } => {
+            shutdown_coordinator.initiate_shutdown(server, Duration::from_secs(seconds), reason);
+        },
+        Message::Shutdown {
+            command: Shutdown::Immediate,
+        } => {
+            info!("Closing the server");
+            return MessageResult::Exit;
+        },
+        Message::Shared(SharedCommand::Admin {
+            command: Admin::Add { username, role },
+        }) => {
+            server.add_admin(&username, role);
+        },
+        Message::Shared(SharedCommand::Admin {
+            command: Admin::Remove { username },
+        }) => {
+            server.remove_admin(&username);
+        },
+        Message::Shared(SharedCommand::PermissionGroup {
+            command: PermissionGroup::List,
+        }) => {
+            let summary = server.permission_groups_summary();
+            for line in summary.lines() {
+                info!("{}", line);
+            }
+            return MessageResult::Continue(Some(summary));
+        },
+        Message::Shared(SharedCommand::PermissionGroup {
+            command: PermissionGroup::Assign { username, group },
+        }) => {
+            server.assign_permission_group(&username, &group);
+        },
+        Message::Shared(SharedCommand::PermissionGroup {
+            command: PermissionGroup::Unassign { username, group },
+        }) => {
+            server.unassign_permission_group(&username, &group);
+        },
+        Message::Shared(SharedCommand::AuditLog { count, player }) => {
+            return MessageResult::Continue(Some(print_audit_log(
+                data_dir,
+                count,
+                player.as_deref(),
+            )));
+        },
+        Message::Shared(SharedCommand::Backup) => {
+            server.backup_database();
+            return MessageResult::Continue(Some(
+                "Started a database backup, see the server logs for the result".to_owned(),
+            ));
+        },
+        Message::Schedule {
+            command: cli::Schedule::List,
+        } => {
+            let list = scheduler.list();
+            for line in list.lines() {
+                info!("{}", line);
+            }
+            return MessageResult::Continue(Some(list));
+        },
+        Message::Schedule {
+            command: cli::Schedule::Cancel { id },
+        } => {
+            let output = scheduler.cancel(id).unwrap_or_else(|e| e);
+            info!("{}", output);
+            return MessageResult::Continue(Some(output));
+        },
+        Message::LoadArea { view_distance } => {
+            #[cfg(feature = "worldgen")]
+            server.create_centered_persister(view_distance);
+        },
+        Message::SqlLogMode { mode } => {
+            server.set_sql_log_mode(mode);
+        },
+        Message::DisconnectAllClients => {
+            server.disconnect_all_clients();
+        },
+        #[cfg(feature = "plugins")]
+        Message::Plugin { command } => {
+            let result = match command {
+                cli::Plugin::List => {
+                    let plugins = server.plugin_names();
+                    info!(?plugins, "Loaded plugins");
+                    return MessageResult::Continue(Some(format!("Loaded plugins: {:?}", plugins)));
+                },
+                cli::Plugin::Load { file } => server.load_plugin(&file).map(|_| ()),
+                cli::Plugin::Reload { name } => server.reload_plugin(&name),
+                cli::Plugin::Unload { name } => server.unload_plugin(&name),
+            };
+            if let Err(e) = result {
+                error!("{}", e);
+                return MessageResult::Continue(Some(e));
+            }
+        },
+        #[cfg(not(feature = "plugins"))]
+        Message::Plugin { .. } => {
+            let e = "The server was built without plugins support";
+            error!("{}", e);
+            return MessageResult::Continue(Some(e.to_owned()));
+        },
+    }
+    MessageResult::Continue(None)
+}
+
+/// Looks up the UUID of the account, as a string as stored in the database.
+fn player_uuid(login_provider: &LoginProvider, username: &str) -> io::Result<String> {
+    login_provider
+        .username_to_uuid(username)
+        .map(|uuid| uuid.to_string())
+        .map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::Other,
+                format!("Failed to look up the account {}: {:?}", username, e),
+            )
+        })
+}
+
+/// Logs the matching audit log entries, and returns them.
+fn print_audit_log(data_dir: &Path, count: usize, player: Option<&str>) -> String {
+    let output = match server::audit_log::recent(data_dir, count, player) {
+        Ok(entries) if entries.is_empty() => "No matching audit log entries".to_owned(),
+        Ok(entries) => entries
+            .iter()
+            .map(|entry| entry.to_string())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Err(err) => {
+            error!(?err, "Failed to read the audit log");
+            return format!("Failed to read the audit log: {}", err);
+        },
+    };
+    for line in output.lines() {
+        info!("{}", line);
+    }
+    output
+}
@@ -0,0 +1,320 @@
+use crate::shutdown_coordinator::ShutdownCoordinator;
+use chrono::{DateTime, Datelike, Local, NaiveTime, TimeZone, Weekday};
+use common::comp::chat::ChatType;
+use common_net::msg::ServerGeneral;
+use serde::{Deserialize, Serialize};
+use server::Server;
+use std::{fmt, process::Command, time::Duration};
+use tracing::{error, info, warn};
+
+/// When a scheduled task runs, in the local time of the server
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Schedule {
+    /// Every day at the given time
+    Daily(NaiveTime),
+    /// Every week on the given day, at the given time
+    Weekly(Weekday, NaiveTime),
+    /// At regular intervals since the server started
+    Interval(Duration),
+}
+
+impl Schedule {
+    /// Checks that the schedule can be run
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Schedule::Interval(interval) if *interval == Duration::ZERO => {
+                Err("The interval must not be zero".to_owned())
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// The first time the task runs after `now`
+    fn next_after<Tz: TimeZone>(&self, now: DateTime<Tz>) -> DateTime<Tz> {
+        let (weekday, time) = match self {
+            Schedule::Daily(time) => (None, time),
+            Schedule::Weekly(weekday, time) => (Some(*weekday), time),
+            Schedule::Interval(interval) => {
+                return now
+                    + chrono::Duration::from_std(*interval)
+                        .unwrap_or_else(|_| chrono::Duration::days(1));
+            },
+        };
+        let today = now.naive_local().date();
+        let timezone = now.timezone();
+        // Look 8 days ahead, in case the time is skipped by a daylight saving
+        // time change.
+        (0..=8)
+            .map(|days| today + chrono::Duration::days(days))
+            .filter(|date| weekday.map_or(true, |weekday| date.weekday() == weekday))
+            .filter_map(|date| {
+                timezone
+                    .from_local_datetime(&date.and_time(*time))
+                    .earliest()
+            })
+            .find(|next| *next > now)
+            .unwrap_or_else(|| now.clone() + chrono::Duration::days(1))
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Schedule::Daily(time) => write!(f, "daily at {}", time.format("%H:%M")),
+            Schedule::Weekly(weekday, time) => {
+                write!(f, "every {} at {}", weekday, time.format("%H:%M"))
+            },
+            Schedule::Interval(interval) => write!(f, "every {}s", interval.as_secs()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ScheduledAction {
+    /// Broadcasts the message to all the players
+    Announce(String),
+    /// Shuts the server down after warning the players during the grace
+    /// period, then runs the restart hooks.  The server is expected to be
+    /// started again by whatever supervises it.
+    Restart {
+        grace_period_secs: u32,
+        message: String,
+    },
+}
+
+impl fmt::Display for ScheduledAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduledAction::Announce(message) => write!(f, "announce \"{}\"", message),
+            ScheduledAction::Restart {
+                grace_period_secs,
+                message,
+            } => write!(
+                f,
+                "restart after {}s with \"{}\"",
+                grace_period_secs, message
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScheduledTask {
+    pub schedule: Schedule,
+    pub action: ScheduledAction,
+}
+
+struct Task {
+    id: usize,
+    task: ScheduledTask,
+    next: DateTime<Local>,
+}
+
+/// Runs the tasks scheduled in the settings, which can be listed and
+/// cancelled with the `schedule` console command.
+pub(crate) struct Scheduler {
+    tasks: Vec<Task>,
+    /// Commands to run once the server shuts down for a scheduled restart
+    restart_hooks: Vec<String>,
+    /// Whether the shutdown in progress is a scheduled restart
+    restarting: bool,
+}
+
+impl Scheduler {
+    pub fn new(tasks: &[ScheduledTask], restart_hooks: &[String]) -> Self {
+        let now = Local::now();
+        Self {
+            tasks: tasks
+                .iter()
+                .enumerate()
+                .map(|(id, task)| Task {
+                    id,
+                    next: task.schedule.next_after(now),
+                    task: task.clone(),
+                })
+                .collect(),
+            restart_hooks: restart_hooks.to_vec(),
+            restarting: false,
+        }
+    }
+
+    /// Called once per tick to run the tasks that are due.
+    pub fn check(&mut self, server: &mut Server, shutdown_coordinator: &mut ShutdownCoordinator) {
+        if self.restarting && !shutdown_coordinator.shutdown_in_progress() {
+            // The restart was aborted.
+            self.restarting = false;
+        }
+
+        let now = Local::now();
+        for task in self.tasks.iter_mut().filter(|task| task.next <= now) {
+            task.next = task.task.schedule.next_after(now);
+            info!(id = task.id, action = %task.task.action, "Running scheduled task");
+            match &task.task.action {
+                ScheduledAction::Announce(message) => {
+                    server
+                        .notify_players(ServerGeneral::server_msg(ChatType::Meta, message.clone()));
+                },
+                ScheduledAction::Restart {
+                    grace_period_secs,
+                    message,
+                } => {
+                    if shutdown_coordinator.shutdown_in_progress() {
+                        warn!("Skipping the scheduled restart, a shutdown is already in progress");
+                    } else {
+                        shutdown_coordinator.initiate_shutdown(
+                            server,
+                            Duration::from_secs(u64::from(*grace_period_secs)),
+                            message.clone(),
+                        );
+                        self.restarting = true;
+                    }
+                },
+            }
+        }
+    }
+
+    /// Lists the scheduled tasks, along with when they run next.
+    pub fn list(&self) -> String {
+        if self.tasks.is_empty() {
+            return "No scheduled tasks".to_owned();
+        }
+        self.tasks
+            .iter()
+            .map(|task| {
+                format!(
+                    "#{} {}: {}, next at {}",
+                    task.id,
+                    task.task.schedule,
+                    task.task.action,
+                    task.next.format("%Y-%m-%d %H:%M")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Cancels the task until the server restarts.
+    pub fn cancel(&mut self, id: usize) -> Result<String, String> {
+        let index = self
+            .tasks
+            .iter()
+            .position(|task| task.id == id)
+            .ok_or_else(|| format!("There is no scheduled task #{}", id))?;
+        let task = self.tasks.remove(index);
+        Ok(format!(
+            "Cancelled the scheduled task #{}: {} {}",
+            task.id, task.task.schedule, task.task.action
+        ))
+    }
+
+    /// Runs the restart hooks if the server is shutting down for a scheduled
+    /// restart.  Should be called once the server has shut down.
+    pub fn run_restart_hooks(&self) {
+        if !self.restarting {
+            return;
+        }
+        for hook in &self.restart_hooks {
+            let args = match shell_words::split(hook) {
+                Ok(args) if !args.is_empty() => args,
+                Ok(_) => continue,
+                Err(e) => {
+                    error!(?e, ?hook, "Failed to parse the restart hook");
+                    continue;
+                },
+            };
+            info!(?hook, "Running restart hook");
+            match Command::new(&args[0]).args(&args[1..]).status() {
+                Ok(status) if status.success() => {},
+                Ok(status) => error!(?hook, ?status, "Restart hook failed"),
+                Err(e) => error!(?e, ?hook, "Failed to run the restart hook"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use chrono_tz::{Europe::Berlin, Tz};
+
+    fn berlin(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Tz> {
+        Berlin
+            .from_local_datetime(&NaiveDate::from_ymd(y, m, d).and_hms(h, min, 0))
+            .earliest()
+            .unwrap()
+    }
+
+    fn time(h: u32, min: u32) -> NaiveTime { NaiveTime::from_hms(h, min, 0) }
+
+    #[test]
+    fn daily() {
+        let schedule = Schedule::Daily(time(8, 0));
+        assert_eq!(
+            schedule.next_after(berlin(2021, 12, 15, 7, 0)),
+            berlin(2021, 12, 15, 8, 0)
+        );
+        assert_eq!(
+            schedule.next_after(berlin(2021, 12, 15, 8, 0)),
+            berlin(2021, 12, 16, 8, 0)
+        );
+    }
+
+    #[test]
+    fn weekly() {
+        let schedule = Schedule::Weekly(Weekday::Mon, time(10, 0));
+        // Wednesday
+        assert_eq!(
+            schedule.next_after(berlin(2021, 12, 15, 12, 0)),
+            berlin(2021, 12, 20, 10, 0)
+        );
+        // Monday, before and at the time
+        assert_eq!(
+            schedule.next_after(berlin(2021, 12, 20, 9, 0)),
+            berlin(2021, 12, 20, 10, 0)
+        );
+        assert_eq!(
+            schedule.next_after(berlin(2021, 12, 20, 10, 0)),
+            berlin(2021, 12, 27, 10, 0)
+        );
+    }
+
+    #[test]
+    fn daylight_saving_time() {
+        // 02:30 is skipped on the 28th of March 2021
+        let schedule = Schedule::Daily(time(2, 30));
+        assert_eq!(
+            schedule.next_after(berlin(2021, 3, 27, 12, 0)),
+            berlin(2021, 3, 29, 2, 30)
+        );
+        // 02:30 happens twice on the 31st of October 2021, the task only runs
+        // the first time.
+        let first = schedule.next_after(berlin(2021, 10, 30, 12, 0));
+        assert_eq!(first, berlin(2021, 10, 31, 2, 30));
+        assert_eq!(
+            schedule.next_after(first + chrono::Duration::hours(1)),
+            berlin(2021, 11, 1, 2, 30)
+        );
+        // A weekly task on the skipped day runs the week after.
+        let schedule = Schedule::Weekly(Weekday::Sun, time(2, 30));
+        assert_eq!(
+            schedule.next_after(berlin(2021, 3, 27, 12, 0)),
+            berlin(2021, 4, 4, 2, 30)
+        );
+    }
+
+    #[test]
+    fn interval() {
+        let now = berlin(2021, 12, 15, 12, 0);
+        assert_eq!(
+            Schedule::Interval(Duration::from_secs(90)).next_after(now),
+            now + chrono::Duration::seconds(90)
+        );
+        assert!(
+            Schedule::Interval(Duration::from_secs(90))
+                .validate()
+                .is_ok()
+        );
+        assert!(Schedule::Interval(Duration::ZERO).validate().is_err());
+    }
+}
@@ -1,12 +1,21 @@
+use crate::scheduler::ScheduledTask;
 use serde::{Deserialize, Serialize};
-use std::{fs, path::PathBuf};
-use tracing::warn;
+use std::{fs, net::SocketAddr, path::PathBuf};
+use tracing::{error, warn};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(default)]
 pub struct Settings {
     pub update_shutdown_grace_period_secs: u32,
     pub update_shutdown_message: String,
+    /// Loopback address to listen on for console commands from scripts, see
+    /// [`crate::admin_socket`]
+    pub admin_socket_address: Option<SocketAddr>,
+    /// Recurring restarts and announcements
+    pub schedules: Vec<ScheduledTask>,
+    /// Commands run after the server shuts down for a scheduled restart, e.g.
+    /// to back the saves up
+    pub restart_hooks: Vec<String>,
 }
 
 impl Default for Settings {
@@ -14,6 +23,9 @@ impl Default for Settings {
         Self {
             update_shutdown_grace_period_secs: 120,
             update_shutdown_message: "The server is restarting for an update".to_owned(),
+            admin_socket_address: None,
+            schedules: Vec::new(),
+            restart_hooks: Vec::new(),
         }
     }
 }
@@ -23,8 +35,17 @@ impl Settings {
         let path = Self::get_settings_path();
 
         if let Ok(file) = fs::File::open(&path) {
-            match ron::de::from_reader(file) {
-                Ok(s) => return s,
+            match ron::de::from_reader::<_, Self>(file) {
+                Ok(mut s) => {
+                    s.schedules.retain(|task| match task.schedule.validate() {
+                        Ok(()) => true,
+                        Err(e) => {
+                            error!(?e, schedule = %task.schedule, "Ignoring an invalid schedule");
+                            false
+                        },
+                    });
+                    return s;
+                },
                 Err(e) => {
                     warn!(?e, "Failed to parse setting file! Fallback to default.");
                     // Rename the corrupted settings file
@@ -68,6 +89,12 @@ impl Settings {
     }
 }
 
+pub fn admin_token_path() -> PathBuf {
+    let mut path = data_dir();
+    path.push("admin_token");
+    path
+}
+
 pub fn data_dir() -> PathBuf {
     let mut path = common_base::userdata_dir_workspace!();
     path.push("server-cli");
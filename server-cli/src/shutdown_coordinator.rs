@@ -76,6 +76,8 @@ impl ShutdownCoordinator {
         }
     }
 
+    pub fn shutdown_in_progress(&self) -> bool { self.shutdown_initiated_at.is_some() }
+
     /// Called once per tick to process any pending actions related to server
     /// shutdown. If the grace period for an initiated shutdown has expired,
     /// returns `true` which triggers the loop in `main.rs` to break and
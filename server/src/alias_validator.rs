@@ -1,5 +1,9 @@
 use common::character::MAX_NAME_LENGTH;
-use std::fmt::{self, Display};
+use std::{
+    fmt::{self, Display},
+    path::PathBuf,
+};
+use tracing::{debug, trace, warn};
 
 #[derive(Debug, Default)]
 pub struct AliasValidator {
@@ -16,6 +20,38 @@ impl AliasValidator {
         AliasValidator { banned_substrings }
     }
 
+    /// Reads the banned substrings from the given RON files.
+    pub fn from_files(paths: &[PathBuf]) -> Result<Self, String> {
+        let mut banned_words = Vec::new();
+        for path in paths {
+            let mut list = match std::fs::File::open(&path) {
+                Ok(file) => match ron::de::from_reader(&file) {
+                    Ok(vec) => vec,
+                    Err(error) => {
+                        warn!(?error, ?file, "Couldn't deserialize banned words file");
+                        return Err(format!(
+                            "Couldn't read banned words file \"{}\"",
+                            path.to_string_lossy()
+                        ));
+                    },
+                },
+                Err(error) => {
+                    warn!(?error, ?path, "Couldn't open banned words file");
+                    return Err(format!(
+                        "Couldn't open banned words file \"{}\". Error: {}",
+                        path.to_string_lossy(),
+                        error
+                    ));
+                },
+            };
+            banned_words.append(&mut list);
+        }
+        let banned_words_count = banned_words.len();
+        debug!(?banned_words_count);
+        trace!(?banned_words);
+        Ok(Self::new(banned_words))
+    }
+
     pub fn validate(&self, alias: &str) -> Result<(), ValidatorError> {
         if alias.len() > MAX_NAME_LENGTH {
             return Err(ValidatorError::TooLong(alias.to_owned(), alias.len()));
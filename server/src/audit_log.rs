@@ -0,0 +1,184 @@
+//! Append-only log of the administrative commands performed on the server, as
+//! JSON lines in the data directory.
+
+use authc::Uuid;
+use chrono::{DateTime, Utc};
+use common::comp::AdminRole;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+use tracing::warn;
+
+const AUDIT_LOG_FILENAME: &str = "audit_log.jsonl";
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AuditEntry {
+    pub date: DateTime<Utc>,
+    /// The player who issued the command.
+    pub actor: Option<Uuid>,
+    pub actor_username: Option<String>,
+    /// The role of the actor when issuing the command, if any.
+    pub actor_role: Option<AdminRole>,
+    /// The player the command was performed on, which differs from the actor
+    /// when using `/sudo`.
+    pub target: Option<Uuid>,
+    pub target_username: Option<String>,
+    pub command: String,
+    pub args: Vec<String>,
+    /// The error message sent back to the actor if the command failed.
+    pub result: Result<(), String>,
+}
+
+impl fmt::Display for AuditEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {} ({})",
+            self.date.format("%Y-%m-%d %H:%M:%S"),
+            self.actor_username.as_deref().unwrap_or("<unknown>"),
+            self.actor
+                .map_or_else(|| "-".to_owned(), |uuid| uuid.to_string()),
+        )?;
+        if self.target != self.actor {
+            write!(
+                f,
+                " as {}",
+                self.target_username.as_deref().unwrap_or("<unknown>")
+            )?;
+        }
+        write!(f, ": /{}", self.command)?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        match &self.result {
+            Ok(()) => write!(f, " -> ok"),
+            Err(err) => write!(f, " -> error: {}", err),
+        }
+    }
+}
+
+fn path(data_dir: &Path) -> PathBuf { data_dir.join(AUDIT_LOG_FILENAME) }
+
+/// Appends the entry to the audit log.
+pub fn record(data_dir: &Path, entry: &AuditEntry) -> io::Result<()> {
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path(data_dir))?
+        .write_all(line.as_bytes())
+}
+
+/// Reads the last `count` entries of the audit log, oldest first, optionally
+/// only those performed by or on the player with the given username.
+pub fn recent(data_dir: &Path, count: usize, player: Option<&str>) -> io::Result<Vec<AuditEntry>> {
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    let file = match File::open(path(data_dir)) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut entries = VecDeque::with_capacity(count);
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let entry = match serde_json::from_str::<AuditEntry>(&line?) {
+            Ok(entry) => entry,
+            Err(err) => {
+                warn!(?err, "Skipping malformed line {} of the audit log", i + 1);
+                continue;
+            },
+        };
+        let involves_player = player.map_or(true, |player| {
+            entry.actor_username.as_deref() == Some(player)
+                || entry.target_username.as_deref() == Some(player)
+                || entry.args.iter().any(|arg| arg == player)
+        });
+        if involves_player {
+            if entries.len() == count {
+                entries.pop_front();
+            }
+            entries.push_back(entry);
+        }
+    }
+    Ok(entries.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn entry(actor: &str, command: &str, args: &[&str]) -> AuditEntry {
+        AuditEntry {
+            date: Utc::now(),
+            actor: Some(Uuid::from_u128(1)),
+            actor_username: Some(actor.to_owned()),
+            actor_role: Some(AdminRole::Moderator),
+            target: Some(Uuid::from_u128(1)),
+            target_username: Some(actor.to_owned()),
+            command: command.to_owned(),
+            args: args.iter().map(|arg| (*arg).to_owned()).collect(),
+            result: Ok(()),
+        }
+    }
+
+    fn commands(entries: &[AuditEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.command.as_str()).collect()
+    }
+
+    #[test]
+    fn recent_entries() {
+        let data_dir =
+            std::env::temp_dir().join(format!("veloren-audit-log-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&data_dir);
+        std::fs::create_dir_all(&data_dir).unwrap();
+        assert!(recent(&data_dir, 10, None).unwrap().is_empty());
+
+        record(&data_dir, &entry("alice", "kick", &["mallory"])).unwrap();
+        record(&data_dir, &entry("bob", "tp", &[])).unwrap();
+        // Malformed lines are skipped
+        OpenOptions::new()
+            .append(true)
+            .open(path(&data_dir))
+            .unwrap()
+            .write_all(b"not json\n")
+            .unwrap();
+        record(&data_dir, &entry("alice", "ban", &["mallory", "spam"])).unwrap();
+
+        assert_eq!(commands(&recent(&data_dir, 10, None).unwrap()), vec![
+            "kick", "tp", "ban"
+        ]);
+        assert_eq!(commands(&recent(&data_dir, 2, None).unwrap()), vec![
+            "tp", "ban"
+        ]);
+        assert_eq!(
+            commands(&recent(&data_dir, 10, Some("mallory")).unwrap()),
+            vec!["kick", "ban"]
+        );
+        assert_eq!(
+            commands(&recent(&data_dir, 10, Some("bob")).unwrap()),
+            vec!["tp"]
+        );
+        assert!(recent(&data_dir, 0, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn display() {
+        let mut entry = entry("alice", "ban", &["mallory"]);
+        entry.date = Utc.ymd(2021, 12, 15).and_hms(12, 30, 0);
+        entry.result = Err("No such player".to_owned());
+        assert_eq!(
+            entry.to_string(),
+            "[2021-12-15 12:30:00] alice (00000000-0000-0000-0000-000000000001): /ban mallory -> \
+             error: No such player"
+        );
+    }
+}
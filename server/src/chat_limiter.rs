@@ -0,0 +1,209 @@
+//! Protection against players flooding the chat.
+
+use crate::settings::ChatLimits;
+use authc::Uuid;
+use hashbrown::HashMap;
+
+/// Why a chat message was dropped
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ChatLimited {
+    /// The player sends messages faster than the rate limit allows
+    TooFast,
+    /// The player keeps sending the same message
+    Repeated,
+    /// The player was muted for breaking the limits too often, for the given
+    /// number of seconds
+    Muted(f64),
+}
+
+impl ChatLimited {
+    /// Label of the reason in the metrics
+    pub fn reason(&self) -> &'static str {
+        match self {
+            ChatLimited::TooFast => "too_fast",
+            ChatLimited::Repeated => "repeated",
+            ChatLimited::Muted(_) => "muted",
+        }
+    }
+
+    /// The explanation sent to the player
+    pub fn info(&self) -> String {
+        match self {
+            ChatLimited::TooFast => "You are sending messages too quickly.".to_owned(),
+            ChatLimited::Repeated => "Please don't repeat the same message.".to_owned(),
+            ChatLimited::Muted(remaining) => format!(
+                "You are muted for spamming for another {} seconds.",
+                remaining.ceil()
+            ),
+        }
+    }
+}
+
+struct PlayerChat {
+    /// Messages the player can send right away, refilled over time up to the
+    /// burst allowance
+    tokens: f32,
+    last_update: f64,
+    last_message: String,
+    repeats: u32,
+    violations: u32,
+    last_violation: f64,
+    muted_until: Option<f64>,
+}
+
+/// Keeps track of how much each player chats, by UUID so that it persists
+/// when the player reconnects.
+#[derive(Default)]
+pub struct ChatLimiter {
+    players: HashMap<Uuid, PlayerChat>,
+}
+
+impl ChatLimiter {
+    /// Checks whether the player may send the message at `time`, recording it
+    /// if so.
+    pub fn check(
+        &mut self,
+        limits: &ChatLimits,
+        uuid: Uuid,
+        message: &str,
+        time: f64,
+    ) -> Result<(), ChatLimited> {
+        let player = self.players.entry(uuid).or_insert_with(|| PlayerChat {
+            tokens: limits.burst as f32,
+            last_update: time,
+            last_message: String::new(),
+            repeats: 0,
+            violations: 0,
+            last_violation: time,
+            muted_until: None,
+        });
+
+        if let Some(remaining) = player.muted_until.map(|until| until - time) {
+            if remaining > 0.0 {
+                return Err(ChatLimited::Muted(remaining));
+            }
+            player.muted_until = None;
+        }
+
+        player.tokens = (player.tokens
+            + (time - player.last_update) as f32 * limits.messages_per_second)
+            .min(limits.burst as f32);
+        player.last_update = time;
+        if time - player.last_violation >= refill_time(limits) {
+            // The player calmed down, forgive them.
+            player.violations = 0;
+        }
+
+        let message = message.trim().to_lowercase();
+        let result = if player.tokens < 1.0 {
+            Err(ChatLimited::TooFast)
+        } else if message == player.last_message && player.repeats >= limits.max_repeats {
+            Err(ChatLimited::Repeated)
+        } else {
+            player.tokens -= 1.0;
+            if message == player.last_message {
+                player.repeats += 1;
+            } else {
+                player.last_message = message;
+                player.repeats = 0;
+            }
+            Ok(())
+        };
+
+        result.map_err(|limited| {
+            player.violations += 1;
+            player.last_violation = time;
+            if limits.violations_before_mute > 0
+                && player.violations >= limits.violations_before_mute
+            {
+                let duration = limits.mute_duration.as_secs_f64();
+                player.violations = 0;
+                player.muted_until = Some(time + duration);
+                ChatLimited::Muted(duration)
+            } else {
+                limited
+            }
+        })
+    }
+
+    /// The remaining time of the automatic mute of the player, if any.
+    pub fn muted_for(&self, uuid: &Uuid, time: f64) -> Option<f64> {
+        self.players
+            .get(uuid)
+            .and_then(|player| player.muted_until)
+            .map(|until| until - time)
+            .filter(|remaining| *remaining > 0.0)
+    }
+
+    /// Forgets about the players who are back within the limits.
+    pub fn maintain(&mut self, limits: &ChatLimits, time: f64) {
+        let refill_time = refill_time(limits);
+        self.players.retain(|_, player| {
+            player.muted_until.map_or(false, |until| until > time)
+                || time - player.last_update < refill_time
+        });
+    }
+}
+
+/// Time it takes for a player to get their whole burst allowance back.
+fn refill_time(limits: &ChatLimits) -> f64 {
+    limits.burst as f64 / limits.messages_per_second.max(0.001) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn limits() -> ChatLimits {
+        ChatLimits {
+            messages_per_second: 1.0,
+            burst: 3,
+            max_repeats: 1,
+            violations_before_mute: 3,
+            mute_duration: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn burst_then_rate() {
+        let limits = limits();
+        let mut limiter = ChatLimiter::default();
+        let uuid = Uuid::new_v4();
+        for i in 0..3 {
+            assert_eq!(limiter.check(&limits, uuid, &i.to_string(), 0.0), Ok(()));
+        }
+        assert_eq!(
+            limiter.check(&limits, uuid, "3", 0.0),
+            Err(ChatLimited::TooFast)
+        );
+        assert_eq!(limiter.check(&limits, uuid, "4", 1.0), Ok(()));
+    }
+
+    #[test]
+    fn repeats_and_mute() {
+        let limits = limits();
+        let mut limiter = ChatLimiter::default();
+        let uuid = Uuid::new_v4();
+        assert_eq!(limiter.check(&limits, uuid, "spam", 0.0), Ok(()));
+        assert_eq!(limiter.check(&limits, uuid, "SPAM ", 1.0), Ok(()));
+        assert_eq!(
+            limiter.check(&limits, uuid, "spam", 2.0),
+            Err(ChatLimited::Repeated)
+        );
+        assert_eq!(
+            limiter.check(&limits, uuid, "spam", 2.0),
+            Err(ChatLimited::Repeated)
+        );
+        assert_eq!(
+            limiter.check(&limits, uuid, "spam", 2.0),
+            Err(ChatLimited::Muted(60.0))
+        );
+        assert_eq!(
+            limiter.check(&limits, uuid, "hello", 32.0),
+            Err(ChatLimited::Muted(30.0))
+        );
+        assert_eq!(limiter.muted_for(&uuid, 62.0), None);
+        assert_eq!(limiter.check(&limits, uuid, "hello", 62.0), Ok(()));
+    }
+}
@@ -3,7 +3,10 @@ use network::{Message, Participant, Stream, StreamError, StreamParams};
 use serde::{de::DeserializeOwned, Serialize};
 use specs::Component;
 use specs_idvs::IdvStorage;
-use std::sync::{atomic::AtomicBool, Mutex};
+use std::{
+    net::IpAddr,
+    sync::{atomic::AtomicBool, Mutex},
+};
 
 /// Client handles ALL network related information of everything that connects
 /// to the server Client DOES NOT handle game states
@@ -82,6 +85,15 @@ impl Client {
         }
     }
 
+    /// The IP address the client connected from, if it connected over the
+    /// network.
+    pub fn remote_ip(&self) -> Option<IpAddr> {
+        self.participant
+            .as_ref()?
+            .remote_address()
+            .map(|addr| addr.ip())
+    }
+
     pub(crate) fn send<M: Into<ServerMsg>>(&self, msg: M) -> Result<(), StreamError> {
         // TODO: hack to avoid locking stream mutex while serializing the message,
         // remove this when the mutexes on the Streams are removed
@@ -114,7 +126,8 @@ impl Client {
                     | ServerGeneral::Outcomes(_)
                     | ServerGeneral::Knockback(_)
                     | ServerGeneral::UpdatePendingTrade(_, _, _)
-                    | ServerGeneral::FinishedTrade(_) => {
+                    | ServerGeneral::FinishedTrade(_)
+                    | ServerGeneral::SessionToken { .. } => {
                         self.in_game_stream.lock().unwrap().send(g)
                     },
                     //Ingame related, terrain
@@ -126,6 +139,7 @@ impl Client {
                     ServerGeneral::PlayerListUpdate(_)
                     | ServerGeneral::ChatMsg(_)
                     | ServerGeneral::ChatMode(_)
+                    | ServerGeneral::ServerCommands(_)
                     | ServerGeneral::SetPlayerEntity(_)
                     | ServerGeneral::TimeOfDay(_, _)
                     | ServerGeneral::EntitySync(_)
@@ -186,7 +200,8 @@ impl Client {
                     | ServerGeneral::Knockback(_)
                     | ServerGeneral::SiteEconomy(_)
                     | ServerGeneral::UpdatePendingTrade(_, _, _)
-                    | ServerGeneral::FinishedTrade(_) => {
+                    | ServerGeneral::FinishedTrade(_)
+                    | ServerGeneral::SessionToken { .. } => {
                         PreparedMsg::new(2, &g, &self.in_game_stream_params)
                     },
                     //Ingame related, terrain
@@ -198,6 +213,7 @@ impl Client {
                     ServerGeneral::PlayerListUpdate(_)
                     | ServerGeneral::ChatMsg(_)
                     | ServerGeneral::ChatMode(_)
+                    | ServerGeneral::ServerCommands(_)
                     | ServerGeneral::SetPlayerEntity(_)
                     | ServerGeneral::TimeOfDay(_, _)
                     | ServerGeneral::EntitySync(_)
@@ -3,10 +3,15 @@
 //! in [do_command].
 
 use crate::{
+    audit_log::{self, AuditEntry},
+    chat_limiter::ChatLimiter,
     client::Client,
     login_provider::LoginProvider,
+    metrics::PlayerMetrics,
+    reports::{Reports, Resolution},
     settings::{
-        Ban, BanAction, BanInfo, EditableSetting, SettingError, WhitelistInfo, WhitelistRecord,
+        Ban, BanAction, BanInfo, EditableSetting, IpRange, MuteInfo, MuteRecord, SettingError,
+        WhitelistInfo, WhitelistRecord,
     },
     sys::terrain::NpcData,
     wiring,
@@ -53,7 +58,8 @@ use hashbrown::{HashMap, HashSet};
 use humantime::Duration as HumanDuration;
 use rand::Rng;
 use specs::{
-    saveload::MarkerAllocator, storage::StorageEntry, Builder, Entity as EcsEntity, Join, WorldExt,
+    saveload::MarkerAllocator, storage::StorageEntry, Builder, Component, Entity as EcsEntity,
+    HashMapStorage, Join, WorldExt,
 };
 use std::{str::FromStr, sync::Arc};
 use vek::*;
@@ -68,7 +74,7 @@ pub trait ChatCommandExt {
 }
 impl ChatCommandExt for ChatCommand {
     fn execute(&self, server: &mut Server, entity: EcsEntity, args: Vec<String>) {
-        if let Err(err) = do_command(server, entity, entity, args, self) {
+        if let Err(err) = do_audited_command(server, entity, entity, args, self) {
             server.notify_client(
                 entity,
                 ServerGeneral::server_msg(ChatType::CommandError, err),
@@ -77,6 +83,15 @@ impl ChatCommandExt for ChatCommand {
     }
 }
 
+/// Collects the chat messages sent to the server console while it runs a
+/// command, see [`Server::execute_console_command`].
+#[derive(Default)]
+pub struct ConsoleOutput(pub Vec<String>);
+
+impl Component for ConsoleOutput {
+    type Storage = HashMapStorage<Self>;
+}
+
 type CmdResult<T> = Result<T, String>;
 
 /// Handler function called when the command is executed.
@@ -100,6 +115,47 @@ type CmdResult<T> = Result<T, String>;
 type CommandHandler =
     fn(&mut Server, EcsEntity, EcsEntity, Vec<String>, &ChatCommand) -> CmdResult<()>;
 
+/// Runs the command, recording it in the audit log if it requires an
+/// [`AdminRole`].
+fn do_audited_command(
+    server: &mut Server,
+    client: EcsEntity,
+    target: EcsEntity,
+    args: Vec<String>,
+    cmd: &ChatCommand,
+) -> CmdResult<()> {
+    let audited_args = cmd.needs_role().is_some().then(|| args.clone());
+    // Taken before running the command, which may change it.
+    let actor_role = server.entity_admin_role(client);
+    let result = do_command(server, client, target, args, cmd);
+    if let Some(args) = audited_args {
+        let player_info = |entity| {
+            let players = server.state.ecs().read_storage::<comp::Player>();
+            match players.get(entity) {
+                Some(player) => (Some(player.uuid()), Some(player.alias.clone())),
+                None => (None, None),
+            }
+        };
+        let (actor, actor_username) = player_info(client);
+        let (target, target_username) = player_info(target);
+        let entry = AuditEntry {
+            date: Utc::now(),
+            actor,
+            actor_username,
+            actor_role,
+            target,
+            target_username,
+            command: cmd.keyword().to_owned(),
+            args,
+            result: result.clone(),
+        };
+        if let Err(err) = audit_log::record(server.data_dir().as_ref(), &entry) {
+            error!(?err, %entry, "Failed to write to the audit log");
+        }
+    }
+    result
+}
+
 fn do_command(
     server: &mut Server,
     client: EcsEntity,
@@ -107,8 +163,8 @@ fn do_command(
     args: Vec<String>,
     cmd: &ChatCommand,
 ) -> CmdResult<()> {
-    // Make sure your role is at least high enough to execute this command.
-    if cmd.needs_role() > server.entity_admin_role(client) {
+    // Make sure your role or permission groups allow you to execute this command.
+    if !server.entity_can_use_command(client, cmd.keyword(), cmd.needs_role()) {
         return Err(format!(
             "You don't have permission to use '/{}'.",
             cmd.keyword()
@@ -121,6 +177,7 @@ fn do_command(
         ChatCommand::Alias => handle_alias,
         ChatCommand::ApplyBuff => handle_apply_buff,
         ChatCommand::Ban => handle_ban,
+        ChatCommand::BanIp => handle_ban_ip,
         ChatCommand::BattleMode => handle_battlemode,
         ChatCommand::BattleModeForce => handle_battlemode_force,
         ChatCommand::Build => handle_build,
@@ -156,11 +213,15 @@ fn do_command(
         ChatCommand::MakeNpc => handle_make_npc,
         ChatCommand::MakeSprite => handle_make_sprite,
         ChatCommand::Motd => handle_motd,
+        ChatCommand::Mute => handle_mute,
         ChatCommand::Object => handle_object,
         ChatCommand::PermitBuild => handle_permit_build,
         ChatCommand::Players => handle_players,
+        ChatCommand::Plugin => handle_plugin,
         ChatCommand::Region => handle_region,
         ChatCommand::RemoveLights => handle_remove_lights,
+        ChatCommand::Report => handle_report,
+        ChatCommand::Reports => handle_reports,
         ChatCommand::RevokeBuild => handle_revoke_build,
         ChatCommand::RevokeBuildAll => handle_revoke_build_all,
         ChatCommand::Safezone => handle_safezone,
@@ -176,6 +237,8 @@ fn do_command(
         ChatCommand::Time => handle_time,
         ChatCommand::Tp => handle_tp,
         ChatCommand::Unban => handle_unban,
+        ChatCommand::UnbanIp => handle_unban_ip,
+        ChatCommand::Unmute => handle_unmute,
         ChatCommand::Version => handle_version,
         ChatCommand::Waypoint => handle_waypoint,
         ChatCommand::Wiring => handle_spawn_wiring,
@@ -261,13 +324,24 @@ fn uuid(server: &Server, entity: EcsEntity, descriptor: &str) -> CmdResult<Uuid>
         .ok_or_else(|| format!("Cannot get player information for {:?}", descriptor))
 }
 
-fn real_role(server: &Server, uuid: Uuid, descriptor: &str) -> CmdResult<comp::AdminRole> {
-    server
-        .editable_settings()
-        .admins
-        .get(&uuid)
-        .map(|record| record.role.into())
-        .ok_or_else(|| format!("Cannot get administrator roles for {:?} uuid", descriptor))
+/// The role of the user in the settings file, rather than their temporary one.
+/// Users who may use the command thanks to their permission groups act with
+/// the role configured for these groups.
+fn real_role(
+    server: &Server,
+    uuid: Uuid,
+    action: &ChatCommand,
+    descriptor: &str,
+) -> CmdResult<comp::AdminRole> {
+    let editable_settings = server.editable_settings();
+    if let Some(record) = editable_settings.admins.get(&uuid) {
+        Ok(record.role.into())
+    } else {
+        editable_settings
+            .permission_groups
+            .role_of(&uuid, action.keyword())
+            .ok_or_else(|| format!("Cannot get administrator roles for {:?} uuid", descriptor))
+    }
 }
 
 // Fallibly get uid of entity with the given descriptor (used for error
@@ -344,6 +418,36 @@ fn verify_above_role(
     }
 }
 
+/// Ensure that the target is allowed to send the chat message, i.e. that they
+/// are not muted and within the chat limits.
+fn verify_can_chat(server: &Server, target: EcsEntity, msg: &str) -> CmdResult<()> {
+    let players = server.state.ecs().read_storage::<comp::Player>();
+    let uuid = match players.get(target) {
+        Some(player) => player.uuid(),
+        None => return Ok(()),
+    };
+    if let Some(mute) = server
+        .editable_settings()
+        .mutelist
+        .mute_of(&uuid, Utc::now())
+    {
+        return Err(mute.info());
+    }
+    // The same limits as for chat messages, which also mute players spamming
+    // the chat automatically.
+    let ecs = server.state.ecs();
+    let time = ecs.read_resource::<Time>().0;
+    ecs.write_resource::<ChatLimiter>()
+        .check(&server.settings().chat_limits, uuid, msg, time)
+        .map_err(|limited| {
+            ecs.read_resource::<PlayerMetrics>()
+                .chat_messages_dropped
+                .with_label_values(&[limited.reason()])
+                .inc();
+            limited.info()
+        })
+}
+
 fn find_alias(ecs: &specs::World, alias: &str) -> CmdResult<(EcsEntity, Uuid)> {
     (&ecs.entities(), &ecs.read_storage::<comp::Player>())
         .join()
@@ -744,13 +848,13 @@ fn handle_set_motd(
     client: EcsEntity,
     _target: EcsEntity,
     args: Vec<String>,
-    _action: &ChatCommand,
+    action: &ChatCommand,
 ) -> CmdResult<()> {
     let data_dir = server.data_dir();
     let client_uuid = uuid(server, client, "client")?;
     // Ensure the person setting this has a real role in the settings file, since
     // it's persistent.
-    let _client_real_role = real_role(server, client_uuid, "client")?;
+    let _client_real_role = real_role(server, client_uuid, action, "client")?;
     match parse_args!(args, String) {
         Some(msg) => {
             let edit =
@@ -1570,6 +1674,51 @@ fn handle_players(
     Ok(())
 }
 
+#[cfg(not(feature = "plugins"))]
+fn handle_plugin(
+    _server: &mut Server,
+    _client: EcsEntity,
+    _target: EcsEntity,
+    _args: Vec<String>,
+    _action: &ChatCommand,
+) -> CmdResult<()> {
+    Err("Unsupported without plugins enabled".into())
+}
+
+#[cfg(feature = "plugins")]
+fn handle_plugin(
+    server: &mut Server,
+    client: EcsEntity,
+    _target: EcsEntity,
+    args: Vec<String>,
+    action: &ChatCommand,
+) -> CmdResult<()> {
+    let msg = match parse_args!(args, String, String) {
+        (Some(plugin_action), _) if plugin_action == "list" => {
+            let names = server.plugin_names();
+            format!("{} loaded plugins:\n{}", names.len(), names.join("\n"))
+        },
+        (Some(plugin_action), Some(file)) if plugin_action == "load" => {
+            let name = server.load_plugin(&file)?;
+            format!("Loaded plugin {}", name)
+        },
+        (Some(plugin_action), Some(name)) if plugin_action == "reload" => {
+            server.reload_plugin(&name)?;
+            format!("Reloaded plugin {}", name)
+        },
+        (Some(plugin_action), Some(name)) if plugin_action == "unload" => {
+            server.unload_plugin(&name)?;
+            format!("Unloaded plugin {}", name)
+        },
+        _ => return Err(action.help_string()),
+    };
+    server.notify_client(
+        client,
+        ServerGeneral::server_msg(ChatType::CommandInfo, msg),
+    );
+    Ok(())
+}
+
 fn handle_build(
     server: &mut Server,
     client: EcsEntity,
@@ -1577,12 +1726,19 @@ fn handle_build(
     _args: Vec<String>,
     _action: &ChatCommand,
 ) -> CmdResult<()> {
-    if let Some(mut can_build) = server
+    // Players whose permission groups let them build anywhere don't need to be
+    // permitted to build first.
+    let builds_anywhere = server
         .state
         .ecs()
-        .write_storage::<comp::CanBuild>()
-        .get_mut(target)
-    {
+        .read_storage::<comp::Permissions>()
+        .get(target)
+        .map_or(false, |permissions| permissions.build);
+    let mut can_builds = server.state.ecs().write_storage::<comp::CanBuild>();
+    if builds_anywhere && !can_builds.contains(target) {
+        let _ = can_builds.insert(target, comp::CanBuild::default());
+    }
+    if let Some(mut can_build) = can_builds.get_mut(target) {
         can_build.enabled ^= true;
 
         let toggle_string = if can_build.enabled { "on" } else { "off" };
@@ -1701,18 +1857,36 @@ fn handle_help(
     args: Vec<String>,
     _action: &ChatCommand,
 ) -> CmdResult<()> {
+    #[cfg(feature = "plugins")]
+    let plugin_commands = server.plugin_commands();
+    #[cfg(not(feature = "plugins"))]
+    let plugin_commands = Vec::<common::cmd::CommandSpec>::new();
+
     if let Some(cmd) = parse_args!(args, ChatCommand) {
         server.notify_client(
             client,
             ServerGeneral::server_msg(ChatType::CommandInfo, cmd.help_string()),
         )
+    } else if let Some(cmd) = args.first().and_then(|keyword| {
+        let keyword = keyword.strip_prefix('/').unwrap_or(keyword);
+        plugin_commands.iter().find(|cmd| cmd.keyword == keyword)
+    }) {
+        server.notify_client(
+            client,
+            ServerGeneral::server_msg(ChatType::CommandInfo, cmd.help_string()),
+        )
     } else {
         let mut message = String::new();
-        let entity_role = server.entity_admin_role(client);
-
         // Iterate through all commands you have permission to use.
         ChatCommand::iter()
-            .filter(|cmd| cmd.needs_role() <= entity_role)
+            .filter(|cmd| server.entity_can_use_command(client, cmd.keyword(), cmd.needs_role()))
+            .for_each(|cmd| {
+                message += &cmd.help_string();
+                message += "\n";
+            });
+        plugin_commands
+            .iter()
+            .filter(|cmd| server.entity_can_use_command(client, &cmd.keyword, cmd.needs_role))
             .for_each(|cmd| {
                 message += &cmd.help_string();
                 message += "\n";
@@ -2335,7 +2509,7 @@ fn handle_adminify(
 
         // Your permanent role, not your temporary role, is what's used to determine
         // what temporary roles you can grant.
-        let client_real_role = real_role(server, client_uuid, "client")?;
+        let client_real_role = real_role(server, client_uuid, action, "client")?;
 
         // This appears to prevent de-mod / de-admin for mods / admins with access to
         // this command, but it does not in the case where the target is
@@ -2435,15 +2609,16 @@ fn handle_tell(
         if player == target {
             return Err("You can't /tell yourself.".into());
         }
-        let target_uid = uid(server, target, "target")?;
-        let player_uid = uid(server, player, "player")?;
-        let mode = comp::ChatMode::Tell(player_uid);
-        insert_or_replace_component(server, target, mode.clone(), "target")?;
         let msg = if message_opt.is_empty() {
             format!("{} wants to talk to you.", alias)
         } else {
             message_opt.join(" ")
         };
+        verify_can_chat(server, target, &msg)?;
+        let target_uid = uid(server, target, "target")?;
+        let player_uid = uid(server, player, "player")?;
+        let mode = comp::ChatMode::Tell(player_uid);
+        insert_or_replace_component(server, target, mode.clone(), "target")?;
         server.state.send_chat(mode.new_message(target_uid, msg));
         server.notify_client(target, ServerGeneral::ChatMode(mode));
         Ok(())
@@ -2460,13 +2635,16 @@ fn handle_faction(
     _action: &ChatCommand,
 ) -> CmdResult<()> {
     no_sudo(client, target)?;
+    let msg = args.join(" ");
+    if !msg.is_empty() {
+        verify_can_chat(server, target, &msg)?;
+    }
 
     let factions = server.state.ecs().read_storage();
     if let Some(comp::Faction(faction)) = factions.get(target) {
         let mode = comp::ChatMode::Faction(faction.to_string());
         drop(factions);
         insert_or_replace_component(server, target, mode.clone(), "target")?;
-        let msg = args.join(" ");
         if !msg.is_empty() {
             if let Some(uid) = server.state.ecs().read_storage().get(target) {
                 server.state.send_chat(mode.new_message(*uid, msg));
@@ -2487,13 +2665,16 @@ fn handle_group(
     _action: &ChatCommand,
 ) -> CmdResult<()> {
     no_sudo(client, target)?;
+    let msg = args.join(" ");
+    if !msg.is_empty() {
+        verify_can_chat(server, target, &msg)?;
+    }
 
     let groups = server.state.ecs().read_storage::<comp::Group>();
     if let Some(group) = groups.get(target) {
         let mode = comp::ChatMode::Group(*group);
         drop(groups);
         insert_or_replace_component(server, target, mode.clone(), "target")?;
-        let msg = args.join(" ");
         if !msg.is_empty() {
             if let Some(uid) = server.state.ecs().read_storage().get(target) {
                 server.state.send_chat(mode.new_message(*uid, msg));
@@ -2614,10 +2795,13 @@ fn handle_region(
     _action: &ChatCommand,
 ) -> CmdResult<()> {
     no_sudo(client, target)?;
+    let msg = args.join(" ");
+    if !msg.is_empty() {
+        verify_can_chat(server, target, &msg)?;
+    }
 
     let mode = comp::ChatMode::Region;
     insert_or_replace_component(server, target, mode.clone(), "target")?;
-    let msg = args.join(" ");
     if !msg.is_empty() {
         if let Some(uid) = server.state.ecs().read_storage().get(target) {
             server.state.send_chat(mode.new_message(*uid, msg));
@@ -2635,10 +2819,13 @@ fn handle_say(
     _action: &ChatCommand,
 ) -> CmdResult<()> {
     no_sudo(client, target)?;
+    let msg = args.join(" ");
+    if !msg.is_empty() {
+        verify_can_chat(server, target, &msg)?;
+    }
 
     let mode = comp::ChatMode::Say;
     insert_or_replace_component(server, target, mode.clone(), "target")?;
-    let msg = args.join(" ");
     if !msg.is_empty() {
         if let Some(uid) = server.state.ecs().read_storage().get(target) {
             server.state.send_chat(mode.new_message(*uid, msg));
@@ -2656,10 +2843,13 @@ fn handle_world(
     _action: &ChatCommand,
 ) -> CmdResult<()> {
     no_sudo(client, target)?;
+    let msg = args.join(" ");
+    if !msg.is_empty() {
+        verify_can_chat(server, target, &msg)?;
+    }
 
     let mode = comp::ChatMode::World;
     insert_or_replace_component(server, target, mode.clone(), "target")?;
-    let msg = args.join(" ");
     if !msg.is_empty() {
         if let Some(uid) = server.state.ecs().read_storage().get(target) {
             server.state.send_chat(mode.new_message(*uid, msg));
@@ -2812,11 +3002,11 @@ fn handle_disconnect_all_players(
     client: EcsEntity,
     _target: EcsEntity,
     args: Vec<String>,
-    _action: &ChatCommand,
+    action: &ChatCommand,
 ) -> CmdResult<()> {
     let client_uuid = uuid(server, client, "client")?;
     // Make sure temporary mods/admins can't run this command.
-    let _role = real_role(server, client_uuid, "role")?;
+    let _role = real_role(server, client_uuid, action, "role")?;
 
     if parse_args!(args, String).as_deref() != Some("confirm") {
         return Err(
@@ -2939,6 +3129,110 @@ fn handle_remove_lights(
     Ok(())
 }
 
+fn handle_report(
+    server: &mut Server,
+    client: EcsEntity,
+    target: EcsEntity,
+    args: Vec<String>,
+    action: &ChatCommand,
+) -> CmdResult<()> {
+    no_sudo(client, target)?;
+
+    if let (Some(player), reason) = parse_args!(args, String, ..Vec<String>) {
+        // Reports filed with the command are handled like those sent by the
+        // client directly.
+        server
+            .state
+            .ecs()
+            .read_resource::<EventBus<ServerEvent>>()
+            .emit_now(ServerEvent::Report {
+                reporter: client,
+                player,
+                reason: reason.join(" "),
+            });
+        Ok(())
+    } else {
+        Err(action.help_string())
+    }
+}
+
+fn handle_reports(
+    server: &mut Server,
+    client: EcsEntity,
+    _target: EcsEntity,
+    args: Vec<String>,
+    action: &ChatCommand,
+) -> CmdResult<()> {
+    let (report_action, id, note) = parse_args!(args, String, u64, ..Vec<String>);
+    let msg = match (report_action.as_deref(), id) {
+        (Some("list"), _) => {
+            let reports = server.state.ecs().read_resource::<Reports>();
+            let open = reports
+                .open()
+                .map(|report| report.to_string())
+                .collect::<Vec<_>>();
+            if open.is_empty() {
+                "There are no open reports.".to_owned()
+            } else {
+                format!("{} open reports:\n{}", open.len(), open.join("\n"))
+            }
+        },
+        (Some("show"), Some(id)) => {
+            let reports = server.state.ecs().read_resource::<Reports>();
+            let report = reports
+                .get(id)
+                .ok_or_else(|| format!("There is no report #{}", id))?;
+            let mut msg = report.to_string();
+            if report.chat.is_empty() {
+                msg += "\nNo chat was attached to the report.";
+            }
+            for line in &report.chat {
+                msg += &format!("\n{}", line);
+            }
+            msg
+        },
+        (Some("resolve"), Some(id)) => {
+            let (moderator, moderator_username) = server
+                .state
+                .ecs()
+                .read_storage::<comp::Player>()
+                .get(client)
+                .map_or((None, "the server console".to_owned()), |player| {
+                    (Some(player.uuid()), player.alias.clone())
+                });
+            let resolution = Resolution {
+                date: Utc::now(),
+                moderator,
+                moderator_username,
+                note: note.join(" "),
+            };
+            let (msg, reporter) = server
+                .state
+                .ecs()
+                .write_resource::<Reports>()
+                .resolve(id, resolution)
+                .map(|report| (format!("Resolved {}", report), report.reporter))?;
+            // Let the reporter know that their report was looked into.
+            if let Ok(reporter) = find_uuid(server.state.ecs(), reporter) {
+                server.notify_client(
+                    reporter,
+                    ServerGeneral::server_msg(
+                        ChatType::CommandInfo,
+                        format!("Your report #{} was resolved by a moderator.", id),
+                    ),
+                );
+            }
+            msg
+        },
+        _ => return Err(action.help_string()),
+    };
+    server.notify_client(
+        client,
+        ServerGeneral::server_msg(ChatType::CommandInfo, msg),
+    );
+    Ok(())
+}
+
 fn handle_sudo(
     server: &mut Server,
     client: EcsEntity,
@@ -2962,7 +3256,7 @@ fn handle_sudo(
             // TODO: consider making this into a tail call or loop (to avoid the potential
             // stack overflow, although it's less of a risk coming from only mods and
             // admins).
-            do_command(server, client, player, cmd_args, &action)
+            do_audited_command(server, client, player, cmd_args, &action)
         } else {
             Err(format!("Unknown command: /{}", cmd))
         }
@@ -3004,7 +3298,7 @@ fn handle_whitelist(
     if let (Some(whitelist_action), Some(username)) = parse_args!(args, String, String) {
         let client_uuid = uuid(server, client, "client")?;
         let client_username = uuid_to_username(server, client, client_uuid)?;
-        let client_role = real_role(server, client_uuid, "client")?;
+        let client_role = real_role(server, client_uuid, action, "client")?;
 
         if whitelist_action.eq_ignore_ascii_case("add") {
             let uuid = find_username(server, &username)?;
@@ -3035,7 +3329,7 @@ fn handle_whitelist(
             })
         } else if whitelist_action.eq_ignore_ascii_case("remove") {
             let client_uuid = uuid(server, client, "client")?;
-            let client_role = real_role(server, client_uuid, "client")?;
+            let client_role = real_role(server, client_uuid, action, "client")?;
 
             let uuid = find_username(server, &username)?;
             let mut err_info = "not part of whitelist: ";
@@ -3137,7 +3431,7 @@ fn handle_ban(
 
         let client_uuid = uuid(server, client, "client")?;
         let client_username = uuid_to_username(server, client, client_uuid)?;
-        let client_role = real_role(server, client_uuid, "client")?;
+        let client_role = real_role(server, client_uuid, action, "client")?;
 
         let now = Utc::now();
         let end_date = parse_duration
@@ -3198,6 +3492,147 @@ fn handle_ban(
     }
 }
 
+fn handle_ban_ip(
+    server: &mut Server,
+    client: EcsEntity,
+    _target: EcsEntity,
+    args: Vec<String>,
+    action: &ChatCommand,
+) -> CmdResult<()> {
+    if let (Some(address_or_player), overwrite, parse_duration, reason_opt) =
+        parse_args!(args, String, bool, HumanDuration, String)
+    {
+        let reason = reason_opt.unwrap_or_default();
+        let overwrite = overwrite.unwrap_or(false);
+
+        // Ban either the given addresses, or the address of an online player
+        let ip_range = match address_or_player.parse::<IpRange>() {
+            Ok(ip_range) => ip_range,
+            Err(_) => {
+                let (player, _) = find_alias(server.state.ecs(), &address_or_player)?;
+                server
+                    .state
+                    .ecs()
+                    .read_storage::<Client>()
+                    .get(player)
+                    .and_then(Client::remote_ip)
+                    .map(IpRange::single)
+                    .ok_or_else(|| format!("Cannot get the address of {}", address_or_player))?
+            },
+        };
+
+        let client_uuid = uuid(server, client, "client")?;
+        let client_username = uuid_to_username(server, client, client_uuid)?;
+        let client_role = real_role(server, client_uuid, action, "client")?;
+
+        // Wide ranges may cover many innocent players
+        let ip_bans = server.settings().ip_bans;
+        if client_role < comp::AdminRole::Admin && !ip_bans.allows_moderators(&ip_range) {
+            return Err(format!(
+                "Only admins can ban ranges wider than /{} for IPv4 or /{} for IPv6 addresses",
+                ip_bans.min_ipv4_prefix_len, ip_bans.min_ipv6_prefix_len
+            ));
+        }
+        // Players whose role is at least as high as our own can't be banned, even
+        // through their address
+        let protected_player = {
+            let ecs = server.state.ecs();
+            let editable_settings = server.editable_settings();
+            let role_of = |player: &comp::Player, admin: Option<&comp::Admin>| {
+                let perm = editable_settings
+                    .admins
+                    .get(&player.uuid())
+                    .map(|record| comp::AdminRole::from(record.role));
+                perm.max(admin.map(|admin| admin.0))
+            };
+            (
+                &ecs.read_storage::<Client>(),
+                &ecs.read_storage::<comp::Player>(),
+                ecs.read_storage::<comp::Admin>().maybe(),
+            )
+                .join()
+                .find(|(target_client, player, admin)| {
+                    target_client
+                        .remote_ip()
+                        .map_or(false, |ip| ip_range.contains(ip))
+                        && role_of(player, *admin) >= Some(client_role)
+                })
+                .map(|(_, player, _)| player.alias.clone())
+        };
+        if let Some(alias) = protected_player {
+            return Err(format!(
+                "Cannot ban {}, {} is connected from it and has a role at least as high as your \
+                 own",
+                ip_range, alias
+            ));
+        }
+
+        let now = Utc::now();
+        let end_date = parse_duration
+            .map(|duration| chrono::Duration::from_std(duration.into()))
+            .transpose()
+            .map_err(|err| format!("Error converting to duration: {}", err))?
+            // On overflow (someone adding some ridiculous timespan), just make the ban infinite.
+            .and_then(|duration| now.checked_add_signed(duration));
+
+        let ban_info = BanInfo {
+            performed_by: client_uuid,
+            performed_by_username: client_username,
+            performed_by_role: client_role.into(),
+        };
+
+        let ban = Ban {
+            reason: reason.clone(),
+            info: Some(ban_info),
+            end_date,
+        };
+
+        let edit = server
+            .editable_settings_mut()
+            .banlist
+            .ip_ban_action(
+                server.data_dir().as_ref(),
+                now,
+                ip_range,
+                address_or_player,
+                BanAction::Ban(ban),
+                overwrite,
+            )
+            .map(|result| {
+                (
+                    format!("Added {} to the banlist with reason: {}", ip_range, reason),
+                    result,
+                )
+            });
+
+        edit_setting_feedback(server, client, edit, || {
+            format!("{} is already on the banlist", ip_range)
+        })?;
+        // Kick the players connected from the banned addresses, none of them has a
+        // role at least as high as our own.
+        let ecs = server.state.ecs();
+        let targets = (
+            &ecs.entities(),
+            &ecs.read_storage::<Client>(),
+            &ecs.read_storage::<comp::Player>(),
+        )
+            .join()
+            .filter(|(_, target_client, _)| {
+                target_client
+                    .remote_ip()
+                    .map_or(false, |ip| ip_range.contains(ip))
+            })
+            .map(|(entity, _, player)| (entity, player.uuid()))
+            .collect::<Vec<_>>();
+        for target_player in targets {
+            let _ = kick_player(server, (client, client_uuid), target_player, &reason);
+        }
+        Ok(())
+    } else {
+        Err(action.help_string())
+    }
+}
+
 fn handle_battlemode(
     server: &mut Server,
     client: EcsEntity,
@@ -3349,7 +3784,7 @@ fn handle_unban(
 
         let client_uuid = uuid(server, client, "client")?;
         let client_username = uuid_to_username(server, client, client_uuid)?;
-        let client_role = real_role(server, client_uuid, "client")?;
+        let client_role = real_role(server, client_uuid, action, "client")?;
 
         let now = Utc::now();
 
@@ -3382,6 +3817,194 @@ fn handle_unban(
     }
 }
 
+fn handle_unban_ip(
+    server: &mut Server,
+    client: EcsEntity,
+    _target: EcsEntity,
+    args: Vec<String>,
+    action: &ChatCommand,
+) -> CmdResult<()> {
+    if let Some(address) = parse_args!(args, String) {
+        let ip_range = address.parse::<IpRange>()?;
+
+        let client_uuid = uuid(server, client, "client")?;
+        let client_username = uuid_to_username(server, client, client_uuid)?;
+        let client_role = real_role(server, client_uuid, action, "client")?;
+
+        let now = Utc::now();
+
+        let ban_info = BanInfo {
+            performed_by: client_uuid,
+            performed_by_username: client_username,
+            performed_by_role: client_role.into(),
+        };
+
+        let unban = BanAction::Unban(ban_info);
+
+        let edit = server
+            .editable_settings_mut()
+            .banlist
+            .ip_ban_action(
+                server.data_dir().as_ref(),
+                now,
+                ip_range,
+                address,
+                unban,
+                false,
+            )
+            .map(|result| (format!("{} was successfully unbanned", ip_range), result));
+
+        edit_setting_feedback(server, client, edit, || {
+            format!("{} was already unbanned", ip_range)
+        })
+    } else {
+        Err(action.help_string())
+    }
+}
+
+fn handle_mute(
+    server: &mut Server,
+    client: EcsEntity,
+    _target: EcsEntity,
+    args: Vec<String>,
+    action: &ChatCommand,
+) -> CmdResult<()> {
+    if let (Some(username), parse_duration, reason_opt) =
+        parse_args!(args, String, HumanDuration, String)
+    {
+        let reason = reason_opt.unwrap_or_default();
+
+        let player_uuid = find_username(server, &username)?;
+
+        let client_uuid = uuid(server, client, "client")?;
+        let client_username = uuid_to_username(server, client, client_uuid)?;
+        let client_role = real_role(server, client_uuid, action, "client")?;
+
+        if server
+            .editable_settings()
+            .admins
+            .get(&player_uuid)
+            .map_or(false, |record| AdminRole::from(record.role) >= client_role)
+        {
+            return Err(format!(
+                "Cannot mute {}, whose role is at least as high as your own",
+                username
+            ));
+        }
+
+        let now = Utc::now();
+        if server
+            .editable_settings()
+            .mutelist
+            .mute_of(&player_uuid, now)
+            .map_or(false, |mute| {
+                AdminRole::from(mute.performed_by_role()) > client_role
+            })
+        {
+            return Err(format!(
+                "{} was muted by someone with a higher role than your own",
+                username
+            ));
+        }
+
+        let end_date = parse_duration
+            .map(|duration| chrono::Duration::from_std(duration.into()))
+            .transpose()
+            .map_err(|err| format!("Error converting to duration: {}", err))?
+            // On overflow (someone adding some ridiculous timespan), just make the mute infinite.
+            .and_then(|duration| now.checked_add_signed(duration));
+
+        let mute = MuteRecord {
+            username_when_muted: username.clone(),
+            date: now,
+            reason: reason.clone(),
+            end_date,
+            info: MuteInfo {
+                performed_by: client_uuid,
+                performed_by_username: client_username,
+                performed_by_role: client_role.into(),
+            },
+        };
+        let info = mute.info();
+
+        let edit = server
+            .editable_settings_mut()
+            .mutelist
+            .mute(server.data_dir().as_ref(), player_uuid, mute, true)
+            .map(|result| {
+                (
+                    format!("Muted {} with reason: {}", username, reason),
+                    result,
+                )
+            });
+
+        edit_setting_feedback(server, client, edit, || {
+            format!("{} is already muted", username)
+        })?;
+        // Let the player know, if they are online.
+        let ecs = server.state.ecs();
+        if let Ok(target_player) = find_uuid(ecs, player_uuid) {
+            server.notify_client(
+                target_player,
+                ServerGeneral::server_msg(ChatType::CommandError, info),
+            );
+        }
+        Ok(())
+    } else {
+        Err(action.help_string())
+    }
+}
+
+fn handle_unmute(
+    server: &mut Server,
+    client: EcsEntity,
+    _target: EcsEntity,
+    args: Vec<String>,
+    action: &ChatCommand,
+) -> CmdResult<()> {
+    if let Some(username) = parse_args!(args, String) {
+        let player_uuid = find_username(server, &username)?;
+
+        let client_uuid = uuid(server, client, "client")?;
+        let client_role = real_role(server, client_uuid, action, "client")?;
+
+        let now = Utc::now();
+        if server
+            .editable_settings()
+            .mutelist
+            .mute_of(&player_uuid, now)
+            .map_or(false, |mute| {
+                AdminRole::from(mute.performed_by_role()) > client_role
+            })
+        {
+            return Err(format!(
+                "{} was muted by someone with a higher role than your own",
+                username
+            ));
+        }
+
+        let edit = server
+            .editable_settings_mut()
+            .mutelist
+            .unmute(server.data_dir().as_ref(), now, &player_uuid)
+            .map(|result| (format!("{} was successfully unmuted", username), result));
+
+        edit_setting_feedback(server, client, edit, || {
+            format!("{} is not muted", username)
+        })?;
+        let ecs = server.state.ecs();
+        if let Ok(target_player) = find_uuid(ecs, player_uuid) {
+            server.notify_client(
+                target_player,
+                ServerGeneral::server_msg(ChatType::CommandInfo, "You are no longer muted"),
+            );
+        }
+        Ok(())
+    } else {
+        Err(action.help_string())
+    }
+}
+
 fn handle_server_physics(
     server: &mut Server,
     client: EcsEntity,
@@ -1,4 +1,6 @@
-use crate::{client::Client, persistence::PersistedComponents, sys, Server, StateExt};
+use crate::{
+    client::Client, persistence::PersistedComponents, session::Session, sys, Server, StateExt,
+};
 use common::{
     character::CharacterId,
     comp::{
@@ -41,6 +43,20 @@ pub fn handle_loaded_character_data(
         .state
         .update_character_data(entity, loaded_components);
     sys::subscription::initialize_region_subscription(server.state.ecs(), entity);
+
+    // Let the client resume this character if it loses its connection
+    let grace_period = server.settings().reconnect_grace_period;
+    if grace_period > Duration::ZERO {
+        let session = Session::new();
+        let ecs = server.state.ecs();
+        if let Some(client) = ecs.read_storage::<Client>().get(entity) {
+            client.send_fallible(ServerGeneral::SessionToken {
+                token: session.token,
+                grace_period,
+            });
+        }
+        let _ = ecs.write_storage().insert(entity, session);
+    }
 }
 
 #[allow(clippy::too_many_arguments)] // TODO: Pending review in #587
@@ -76,6 +76,16 @@ pub fn handle_poise(server: &Server, entity: EcsEntity, change: comp::PoiseChang
 
 pub fn handle_health_change(server: &Server, entity: EcsEntity, change: HealthChange) {
     let ecs = &server.state.ecs();
+    // Give plugins a chance to prevent the damage
+    #[cfg(feature = "plugins")]
+    {
+        let uid = ecs.read_storage::<Uid>().get(entity).copied();
+        if let Some(event) = uid.and_then(|uid| super::plugin::damage_event(uid, &change)) {
+            if super::plugin::is_cancelled(&server.state, &event) {
+                return;
+            }
+        }
+    }
     if let Some(mut health) = ecs.write_storage::<Health>().get_mut(entity) {
         health.change_by(change);
     }
@@ -174,28 +184,33 @@ pub fn handle_destroy(server: &mut Server, entity: EcsEntity, last_change: Healt
         }
     }
 
+    let kill_source = match (last_change.cause, last_change.by.map(|x| x.uid())) {
+        (Some(DamageSource::Melee), Some(by)) => get_attacker_name(KillType::Melee, by),
+        (Some(DamageSource::Projectile), Some(by)) => get_attacker_name(KillType::Projectile, by),
+        (Some(DamageSource::Explosion), Some(by)) => get_attacker_name(KillType::Explosion, by),
+        (Some(DamageSource::Energy), Some(by)) => get_attacker_name(KillType::Energy, by),
+        (Some(DamageSource::Buff(buff_kind)), Some(by)) => {
+            get_attacker_name(KillType::Buff(buff_kind), by)
+        },
+        (Some(DamageSource::Other), Some(by)) => get_attacker_name(KillType::Other, by),
+        (Some(DamageSource::Falling), _) => KillSource::FallDamage,
+        // HealthSource::Suicide => KillSource::Suicide,
+        _ => KillSource::Other,
+    };
+
+    // Let plugins know about the death
+    #[cfg(feature = "plugins")]
+    if let Some(uid) = state.ecs().read_storage::<Uid>().get(entity).copied() {
+        super::plugin::execute_event(state, &plugin_api::event::EntityDeathEvent {
+            entity: uid,
+            killer: kill_source.clone().into(),
+        });
+    }
+
     // Chat message
     // If it was a player that died
     if let Some(_player) = state.ecs().read_storage::<Player>().get(entity) {
         if let Some(uid) = state.ecs().read_storage::<Uid>().get(entity) {
-            let kill_source = match (last_change.cause, last_change.by.map(|x| x.uid())) {
-                (Some(DamageSource::Melee), Some(by)) => get_attacker_name(KillType::Melee, by),
-                (Some(DamageSource::Projectile), Some(by)) => {
-                    get_attacker_name(KillType::Projectile, by)
-                },
-                (Some(DamageSource::Explosion), Some(by)) => {
-                    get_attacker_name(KillType::Explosion, by)
-                },
-                (Some(DamageSource::Energy), Some(by)) => get_attacker_name(KillType::Energy, by),
-                (Some(DamageSource::Buff(buff_kind)), Some(by)) => {
-                    get_attacker_name(KillType::Buff(buff_kind), by)
-                },
-                (Some(DamageSource::Other), Some(by)) => get_attacker_name(KillType::Other, by),
-                (Some(DamageSource::Falling), _) => KillSource::FallDamage,
-                // HealthSource::Suicide => KillSource::Suicide,
-                _ => KillSource::Other,
-            };
-
             state.send_chat(GenericChatMsg {
                 chat_type: comp::ChatType::Kill(kill_source, *uid),
                 message: "".to_string(),
@@ -10,6 +10,8 @@ use common::{
 };
 use common_net::{msg::ServerGeneral, sync::WorldSyncExt};
 use common_state::State;
+#[cfg(feature = "plugins")]
+use plugin_api::event::{GroupChange, GroupChangeEvent, Player};
 use specs::{
     world::{Entity, WorldExt},
     ReadStorage, WriteStorage,
@@ -106,6 +108,10 @@ pub fn handle_group(server: &mut Server, entity: specs::Entity, manip: GroupMani
                         .map(|(g, c)| c.send(ServerGeneral::GroupUpdate(g)));
                 },
             );
+            #[cfg(feature = "plugins")]
+            if let Some(uid) = uids.get(entity) {
+                notify_plugins(state, *uid, GroupChange::Left);
+            }
         },
         GroupManip::Kick(uid) => {
             let state = server.state_mut();
@@ -176,6 +182,11 @@ pub fn handle_group(server: &mut Server, entity: specs::Entity, manip: GroupMani
                         },
                     );
 
+                    #[cfg(feature = "plugins")]
+                    if let Some(by) = uids.get(entity) {
+                        notify_plugins(state, uid, GroupChange::Kicked { by: *by });
+                    }
+
                     // Tell them the have been kicked
                     if let Some(client) = clients.get(target) {
                         client.send_fallible(ServerGeneral::server_msg(
@@ -254,6 +265,9 @@ pub fn handle_group(server: &mut Server, entity: specs::Entity, manip: GroupMani
                                 .map(|(g, c)| c.send(ServerGeneral::GroupUpdate(g)));
                         },
                     );
+                    #[cfg(feature = "plugins")]
+                    notify_plugins(state, uid, GroupChange::BecameLeader);
+
                     // Tell them they are the leader
                     if let Some(client) = clients.get(target) {
                         client.send_fallible(ServerGeneral::server_msg(
@@ -291,3 +305,12 @@ pub fn handle_group(server: &mut Server, entity: specs::Entity, manip: GroupMani
         },
     }
 }
+
+/// Lets plugins know that the group of a player changed
+#[cfg(feature = "plugins")]
+pub fn notify_plugins(state: &State, uid: Uid, change: GroupChange) {
+    super::plugin::execute_event(state, &GroupChangeEvent {
+        player: Player { id: uid },
+        change,
+    });
+}
@@ -219,6 +219,7 @@ pub fn handle_possess(server: &mut Server, possessor_uid: Uid, possesse_uid: Uid
             let mut presence = ecs.write_storage::<Presence>();
             let mut subscriptions = ecs.write_storage::<RegionSubscription>();
             let mut admins = ecs.write_storage::<comp::Admin>();
+            let mut permissions = ecs.write_storage::<comp::Permissions>();
             let mut waypoints = ecs.write_storage::<comp::Waypoint>();
             players
                 .remove(possessor)
@@ -232,6 +233,9 @@ pub fn handle_possess(server: &mut Server, possessor_uid: Uid, possesse_uid: Uid
             admins
                 .remove(possessor)
                 .map(|a| admins.insert(possesse, a).ok()?);
+            permissions
+                .remove(possessor)
+                .map(|p| permissions.insert(possesse, p).ok()?);
             waypoints
                 .remove(possessor)
                 .map(|w| waypoints.insert(possesse, w).ok()?);
@@ -312,6 +316,19 @@ pub fn handle_mine_block(
     if state.can_set_block(pos) {
         let block = state.terrain().get(pos).ok().copied();
         if let Some(block) = block.filter(|b| b.mine_tool().map_or(false, |t| Some(t) == tool)) {
+            // Give plugins a chance to prevent the block from being mined
+            #[cfg(feature = "plugins")]
+            if let Some(uid) = state.ecs().uid_from_entity(entity) {
+                let event = plugin_api::event::BlockBreakEvent {
+                    player: plugin_api::event::Player { id: uid },
+                    pos,
+                    block: block.into(),
+                };
+                if super::plugin::is_cancelled(state, &event) {
+                    return;
+                }
+            }
+
             // Drop item if one is recoverable from the block
             if let Some(mut item) = comp::Item::try_reclaim_from_block(block) {
                 if let Some(mut skillset) = state
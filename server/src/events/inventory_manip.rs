@@ -86,6 +86,30 @@ pub fn handle_inventory(server: &mut Server, entity: EcsEntity, manip: comp::Inv
         })
     };
 
+    // Give plugins a chance to prevent the pickup
+    #[cfg(feature = "plugins")]
+    if let comp::InventoryManip::Pickup(item_uid) = &manip {
+        let item = state
+            .ecs()
+            .entity_from_uid((*item_uid).into())
+            .and_then(|item_entity| {
+                state
+                    .ecs()
+                    .read_storage::<comp::Item>()
+                    .get(item_entity)
+                    .map(plugin_item_stack)
+            });
+        if let Some(item) = item {
+            let event = plugin_api::event::ItemPickupEvent {
+                player: plugin_api::event::Player { id: uid },
+                item,
+            };
+            if super::plugin::is_cancelled(state, &event) {
+                return;
+            }
+        }
+    }
+
     let mut inventories = state.ecs().write_storage::<comp::Inventory>();
     let mut inventory = if let Some(inventory) = inventories.get_mut(entity) {
         inventory
@@ -700,6 +724,12 @@ pub fn handle_inventory(server: &mut Server, entity: EcsEntity, manip: comp::Inv
         .into_iter()
         .filter(|(_, _, i)| !matches!(i.quality(), item::Quality::Debug))
     {
+        #[cfg(feature = "plugins")]
+        super::plugin::execute_event(state, &plugin_api::event::ItemDropEvent {
+            player: plugin_api::event::Player { id: uid },
+            item: plugin_item_stack(&item),
+        });
+
         // hack: special case coins for now
         let body = match item.item_definition_id() {
             "common.items.utility.coins" => comp::object::Body::Coins,
@@ -852,3 +882,11 @@ mod tests {
         },),);
     }
 }
+
+#[cfg(feature = "plugins")]
+fn plugin_item_stack(item: &comp::Item) -> plugin_api::ItemStack {
+    plugin_api::ItemStack {
+        item: item.item_definition_id().to_owned(),
+        amount: item.amount(),
+    }
+}
@@ -1,5 +1,5 @@
 use super::group_manip;
-use crate::{client::Client, Server};
+use crate::{client::Client, rtsim::SiteEconomies, Server};
 use common::{
     comp::{
         self,
@@ -221,6 +221,14 @@ pub fn handle_invite_accept(server: &mut Server, entity: specs::Entity) {
                             .map(|(g, c)| c.send(ServerGeneral::GroupUpdate(g)));
                     },
                 );
+                #[cfg(feature = "plugins")]
+                if let Some(uid) = uids.get(entity) {
+                    super::group_manip::notify_plugins(
+                        state,
+                        *uid,
+                        plugin_api::event::GroupChange::Joined,
+                    );
+                }
             },
             InviteKind::Trade => {
                 if let (Some(inviter_uid), Some(invitee_uid)) =
@@ -247,20 +255,23 @@ pub fn handle_invite_accept(server: &mut Server, entity: specs::Entity) {
                             .push_back(AgentEvent::TradeAccepted(invitee_uid));
                     }
                     #[cfg(feature = "worldgen")]
-                    let pricing = agents
-                        .get(inviter)
-                        .and_then(|a| {
-                            a.behavior
-                                .trade_site
-                                .and_then(|id| index.get_site_prices(id))
-                        })
-                        .or_else(|| {
-                            agents.get(entity).and_then(|a| {
+                    let pricing = {
+                        let economies = state.ecs().read_resource::<SiteEconomies>();
+                        agents
+                            .get(inviter)
+                            .and_then(|a| {
                                 a.behavior
                                     .trade_site
-                                    .and_then(|id| index.get_site_prices(id))
+                                    .and_then(|id| economies.get_site_prices(&index, id))
+                            })
+                            .or_else(|| {
+                                agents.get(entity).and_then(|a| {
+                                    a.behavior
+                                        .trade_site
+                                        .and_then(|id| economies.get_site_prices(&index, id))
+                                })
                             })
-                        });
+                    };
                     #[cfg(not(feature = "worldgen"))]
                     let pricing = None;
 
@@ -22,7 +22,8 @@ use interaction::{
 };
 use inventory_manip::handle_inventory;
 use invite::{handle_invite, handle_invite_response};
-use player::{handle_client_disconnect, handle_exit_ingame};
+use player::{handle_client_disconnect, handle_exit_ingame, handle_resume_session};
+use report::handle_report;
 use specs::{Builder, Entity as EcsEntity, WorldExt};
 use trade::{cancel_trade_for, handle_process_trade_action};
 
@@ -34,6 +35,8 @@ mod interaction;
 mod inventory_manip;
 mod invite;
 mod player;
+#[cfg(feature = "plugins")] mod plugin;
+mod report;
 mod trade;
 
 pub enum Event {
@@ -149,6 +152,9 @@ impl Server {
                     cancel_trade_for(self, entity);
                     handle_exit_ingame(self, entity);
                 },
+                ServerEvent::ResumeSession { entity, character } => {
+                    handle_resume_session(self, entity, character)
+                },
                 ServerEvent::CreateNpc {
                     pos,
                     stats,
@@ -203,6 +209,11 @@ impl Server {
                 ServerEvent::Command(entity, name, args) => {
                     commands.push((entity, name, args));
                 },
+                ServerEvent::Report {
+                    reporter,
+                    player,
+                    reason,
+                } => handle_report(self, reporter, player, reason),
                 ServerEvent::Chat(msg) => {
                     chat_messages.push(msg);
                 },
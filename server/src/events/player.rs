@@ -1,18 +1,24 @@
 use super::Event;
 use crate::{
     client::Client, events::trade::cancel_trade_for, metrics::PlayerMetrics,
-    persistence::character_updater::CharacterUpdater, presence::Presence, state_ext::StateExt,
-    BattleModeBuffer, Server,
+    persistence::character_updater::CharacterUpdater, presence::Presence, session::Session,
+    state_ext::StateExt, BattleModeBuffer, Server,
 };
 use common::{
     comp,
     comp::{group, pet::is_tameable},
+    event::{EventBus, ServerEvent},
+    resources::Time,
     uid::{Uid, UidAllocator},
 };
 use common_base::span;
-use common_net::msg::{PlayerListUpdate, PresenceKind, ServerGeneral};
+use common_net::msg::{
+    CharacterInfo, PlayerInfo, PlayerListUpdate, PresenceKind, RegisterError, ServerGeneral,
+    ServerRegisterAnswer,
+};
 use common_state::State;
 use specs::{saveload::MarkerAllocator, Builder, Entity as EcsEntity, Join, WorldExt};
+use std::time::Duration;
 use tracing::{debug, error, trace, warn, Instrument};
 
 pub fn handle_exit_ingame(server: &mut Server, entity: EcsEntity) {
@@ -29,6 +35,10 @@ pub fn handle_exit_ingame(server: &mut Server, entity: EcsEntity) {
     // disrupted
 
     let maybe_admin = state.ecs().write_storage::<comp::Admin>().remove(entity);
+    let maybe_permissions = state
+        .ecs()
+        .write_storage::<comp::Permissions>()
+        .remove(entity);
     let maybe_group = state
         .ecs()
         .write_storage::<group::Group>()
@@ -60,6 +70,12 @@ pub fn handle_exit_ingame(server: &mut Server, entity: EcsEntity) {
             None => entity_builder,
         };
 
+        // Preserve permissions component if present
+        let entity_builder = match maybe_permissions {
+            Some(permissions) => entity_builder.with(permissions),
+            None => entity_builder,
+        };
+
         // Ensure UidAllocator maps this uid to the new entity
         let uid = entity_builder
             .world
@@ -98,6 +114,80 @@ pub fn handle_exit_ingame(server: &mut Server, entity: EcsEntity) {
     }
 }
 
+/// Moves the client of a new connection to the character whose session it
+/// resumes, and resyncs the client with the world around it
+pub fn handle_resume_session(server: &mut Server, entity: EcsEntity, character: EcsEntity) {
+    span!(_guard, "handle_resume_session");
+    let ecs = server.state.ecs();
+    let client = match ecs.write_storage::<Client>().remove(entity) {
+        Some(client) => client,
+        None => return,
+    };
+
+    // The character might have been removed or resumed by another client since
+    // the session was looked up
+    let resumed = match ecs.write_storage::<Session>().get_mut(character) {
+        Some(session)
+            if session.disconnected_at.is_some()
+                && !ecs.read_storage::<Client>().contains(character) =>
+        {
+            session.disconnected_at = None;
+            true
+        },
+        _ => false,
+    };
+    if !resumed {
+        client.send_fallible(ServerRegisterAnswer::Err(RegisterError::SessionExpired));
+        let _ = ecs.write_storage().insert(entity, client);
+        ecs.read_resource::<EventBus<ServerEvent>>()
+            .emit_now(ServerEvent::ClientDisconnect(
+                entity,
+                comp::DisconnectReason::Kicked,
+            ));
+        return;
+    }
+
+    let state = server.state_mut();
+
+    let player_list = {
+        let ecs = state.ecs();
+        (
+            &ecs.read_storage::<Uid>(),
+            &ecs.read_storage::<comp::Player>(),
+            ecs.read_storage::<comp::Stats>().maybe(),
+            ecs.read_storage::<comp::Admin>().maybe(),
+        )
+            .join()
+            .map(|(uid, player, stats, admin)| {
+                (*uid, PlayerInfo {
+                    is_online: true,
+                    is_moderator: admin.is_some(),
+                    player_alias: player.alias.clone(),
+                    character: stats.map(|stats| CharacterInfo {
+                        name: stats.name.clone(),
+                    }),
+                })
+            })
+            .collect()
+    };
+    client.send_fallible(ServerRegisterAnswer::Ok(()));
+    client.send_fallible(ServerGeneral::PlayerListUpdate(PlayerListUpdate::Init(
+        player_list,
+    )));
+
+    if let Err(e) = state.delete_entity_recorded(entity) {
+        error!(?e, ?entity, "Failed to delete entity of resumed session");
+    }
+    let _ = state.ecs().write_storage().insert(character, client);
+    state.write_component_ignore_entity_dead(
+        character,
+        comp::InventoryUpdate::new(comp::InventoryUpdateEvent::default()),
+    );
+    // Send the client its own entity and everything around it again
+    crate::sys::subscription::initialize_region_subscription(state.ecs(), character);
+    debug!(?character, "Resumed session of reconnected client");
+}
+
 fn get_reason_str(reason: &comp::DisconnectReason) -> &str {
     match reason {
         comp::DisconnectReason::Timeout => "timeout",
@@ -116,6 +206,25 @@ pub fn handle_client_disconnect(
 ) -> Event {
     span!(_guard, "handle_client_disconnect");
     cancel_trade_for(server, entity);
+
+    // Characters that lost their connection are kept around for a while so their
+    // client can resume the session
+    let keep_for_reconnect = matches!(
+        reason,
+        comp::DisconnectReason::Timeout | comp::DisconnectReason::NetworkError
+    ) && server.settings().reconnect_grace_period > Duration::ZERO
+        && {
+            let ecs = server.state().ecs();
+            let time = ecs.read_resource::<Time>().0;
+            let mut sessions = ecs.write_storage::<Session>();
+            match sessions.get_mut(entity) {
+                Some(session) if session.disconnected_at.is_none() => {
+                    session.disconnected_at = Some(time);
+                    true
+                },
+                _ => false,
+            }
+        };
     if let Some(client) = server
         .state()
         .ecs()
@@ -164,6 +273,19 @@ pub fn handle_client_disconnect(
         }
     }
 
+    if keep_for_reconnect {
+        debug!(
+            ?entity,
+            "Keeping character of disconnected client for reconnecting"
+        );
+        server
+            .state()
+            .ecs()
+            .write_storage::<Client>()
+            .remove(entity);
+        return Event::ClientDisconnected { entity };
+    }
+
     let state = server.state_mut();
 
     // Tell other clients to remove from player list
@@ -0,0 +1,638 @@
+use crate::{
+    metrics::PluginMetrics,
+    persistence::plugin_storage::{PluginStorageUpdate, PluginStorageUpdater},
+    state_ext::StateExt,
+    Server, Tick,
+};
+use common::{
+    cmd::CommandSpec,
+    comp::{
+        self,
+        buff::{Buff, BuffChange, BuffData, BuffSource},
+        inventory::item::{tool::AbilityMap, MaterialStatManifest},
+        ChatType, Item,
+    },
+    event::{EventBus, ServerEvent},
+    npc::{self, get_npc_name},
+    resources::Time,
+    terrain::{Block, BlockKind},
+    uid::Uid,
+    LoadoutBuilder,
+};
+use common_net::{msg::ServerGeneral, sync::WorldSyncExt};
+use common_state::{
+    plugin::{storage::PluginStorage, watcher::PluginFileChange, PluginMgr},
+    State,
+};
+use plugin_api::{
+    event::{CancelResult, ChunkGenerateEvent, EntityDamageEvent, ServerTickEvent},
+    Action, Event, NpcAlignment, NpcSpawn,
+};
+use specs::{Builder, Entity as EcsEntity, WorldExt};
+use std::{path::Path, str::FromStr, time::Duration};
+use tracing::{error, info, warn};
+use vek::*;
+
+impl Server {
+    /// Applies the actions emitted by plugins since the last tick, in the
+    /// order they were emitted.
+    pub fn handle_plugin_actions(&mut self) {
+        let actions = self
+            .state
+            .ecs()
+            .read_resource::<PluginMgr>()
+            .drain_actions();
+
+        for (plugin, action) in actions {
+            if let Err(e) = handle_plugin_action(self, &plugin, action) {
+                warn!(?plugin, ?e, "Failed to apply plugin action");
+            }
+        }
+    }
+
+    /// Reports the budgets exceeded by plugins since the last tick.
+    pub fn update_plugin_metrics(&self) {
+        let ecs = self.state.ecs();
+        let plugin_mgr = ecs.read_resource::<PluginMgr>();
+        let metrics = ecs.read_resource::<PluginMetrics>();
+        for (plugin, kind) in plugin_mgr.drain_limit_violations() {
+            warn!(?plugin, "Plugin exceeded its {} budget", kind.as_str());
+            metrics
+                .limits_exceeded
+                .with_label_values(&[&plugin, kind.as_str()])
+                .inc();
+        }
+        metrics
+            .plugins_disabled
+            .set(plugin_mgr.plugins().filter(|p| p.is_disabled()).count() as i64);
+    }
+
+    /// Names of the loaded plugins.
+    pub fn plugin_names(&self) -> Vec<String> {
+        self.state
+            .ecs()
+            .read_resource::<PluginMgr>()
+            .plugins()
+            .map(|plugin| plugin.name().to_owned())
+            .collect()
+    }
+
+    /// Loads the plugin file with the given name from the plugin directory,
+    /// replacing the loaded plugin with the same name if there is one.
+    /// Returns the name of the plugin.
+    pub fn load_plugin(&mut self, file: &str) -> Result<String, String> {
+        // Only allow loading files from the plugin directory
+        if Path::new(file).file_name() != Some(file.as_ref()) {
+            return Err(format!("Invalid plugin file name: {}", file));
+        }
+        let path = PluginMgr::assets_dir().join(file);
+        let name = self
+            .state
+            .load_plugin(&path)
+            .map_err(|e| format!("Failed to load plugin {}: {:?}", file, e))?;
+        self.broadcast_plugin_commands();
+        Ok(name)
+    }
+
+    /// Reloads a loaded plugin from the file it was loaded from.
+    pub fn reload_plugin(&mut self, name: &str) -> Result<(), String> {
+        let path = self
+            .state
+            .ecs()
+            .read_resource::<PluginMgr>()
+            .get(name)
+            .ok_or_else(|| format!("No plugin named {} is loaded", name))?
+            .path()
+            .ok_or_else(|| format!("Plugin {} wasn't loaded from a file", name))?
+            .to_path_buf();
+        self.state
+            .load_plugin(&path)
+            .map_err(|e| format!("Failed to reload plugin {}: {:?}", name, e))?;
+        self.broadcast_plugin_commands();
+        Ok(())
+    }
+
+    pub fn unload_plugin(&mut self, name: &str) -> Result<(), String> {
+        if self.state.unload_plugin(name) {
+            self.broadcast_plugin_commands();
+            Ok(())
+        } else {
+            Err(format!("No plugin named {} is loaded", name))
+        }
+    }
+
+    /// Reloads or unloads the plugins whose file changed on disk.
+    pub fn handle_plugin_file_changes(&mut self) {
+        let changes = match self.plugin_watcher.as_mut() {
+            Some(watcher) => watcher.changes(),
+            None => return,
+        };
+
+        if changes.is_empty() {
+            return;
+        }
+        for change in changes {
+            match change {
+                // Renaming a file away also counts as a modification
+                PluginFileChange::Modified(path) if path.exists() => {
+                    info!(?path, "Plugin file changed, reloading it");
+                    if let Err(e) = self.state.load_plugin(&path) {
+                        error!(?e, ?path, "Failed to reload plugin");
+                    }
+                },
+                PluginFileChange::Modified(path) | PluginFileChange::Removed(path) => {
+                    let name = self
+                        .state
+                        .ecs()
+                        .read_resource::<PluginMgr>()
+                        .get_by_path(&path)
+                        .map(|plugin| plugin.name().to_owned());
+                    if let Some(name) = name {
+                        info!(?path, "Plugin file removed, unloading it");
+                        self.state.unload_plugin(&name);
+                    }
+                },
+            }
+        }
+        self.broadcast_plugin_commands();
+    }
+
+    /// The chat commands declared by the loaded plugins.
+    pub fn plugin_commands(&self) -> Vec<CommandSpec> {
+        self.state
+            .ecs()
+            .read_resource::<PluginMgr>()
+            .commands()
+            .cloned()
+            .collect()
+    }
+
+    /// Sends the commands of the loaded plugins to every player, for their
+    /// tab completion.
+    fn broadcast_plugin_commands(&self) {
+        self.state
+            .notify_players(ServerGeneral::ServerCommands(self.plugin_commands()));
+    }
+
+    /// Lets plugins know that a new tick started.
+    pub fn plugins_tick(&self, dt: Duration) {
+        let tick = self.state.ecs().read_resource::<Tick>().0;
+        execute_event(&self.state, &ServerTickEvent {
+            tick,
+            dt: dt.as_secs_f64(),
+        });
+    }
+
+    /// Lets plugins know about the chunks generated during this tick.
+    pub fn plugins_new_chunks(&self) {
+        let new_chunks = self
+            .state
+            .terrain_changes()
+            .new_chunks
+            .iter()
+            .copied()
+            .collect::<Vec<_>>();
+        for key in new_chunks {
+            execute_event(&self.state, &ChunkGenerateEvent { key });
+        }
+    }
+}
+
+/// Executes an event for every loaded plugin, errors are logged and dropped.
+pub fn execute_event<T: Event>(state: &State, event: &T) -> Vec<T::Response> {
+    state.execute_plugin_event(event).unwrap_or_else(|e| {
+        error!(?e, event = %event.get_event_name(), "Failed to execute plugin event");
+        Vec::new()
+    })
+}
+
+/// The event letting plugins cancel a health change, if it is damage.
+pub fn damage_event(entity: Uid, change: &comp::HealthChange) -> Option<EntityDamageEvent> {
+    (change.amount < 0.0).then(|| EntityDamageEvent {
+        entity,
+        attacker: change.by.map(|by| by.uid()),
+        amount: -change.amount,
+        source: change.cause.map(Into::into),
+    })
+}
+
+/// Executes a cancellable event, returns `true` if any plugin cancelled it.
+pub fn is_cancelled<T: Event<Response = CancelResult>>(state: &State, event: &T) -> bool {
+    execute_event(state, event)
+        .into_iter()
+        .any(|r| r == CancelResult::Cancel)
+}
+
+fn entity(server: &Server, uid: Uid) -> Result<EcsEntity, String> {
+    server
+        .state
+        .ecs()
+        .entity_from_uid(uid.0)
+        .ok_or_else(|| format!("No entity with uid {}", uid))
+}
+
+/// Gives `amount` of the item to the inventory. If they don't all fit, none
+/// are given and false is returned.
+fn give_items(
+    inventory: &mut comp::Inventory,
+    mut item: Item,
+    amount: u32,
+    ability_map: &AbilityMap,
+    msm: &MaterialStatManifest,
+) -> bool {
+    // Pushing a single stack either adds all of it or nothing
+    if item.set_amount(amount).is_ok() {
+        return inventory.push(item).is_ok();
+    }
+    // This item can't stack that much, push each item separately to a copy of
+    // the inventory which is only kept if all of them fit
+    let mut new_inventory = inventory.clone();
+    let fits = (0..amount).all(|_| new_inventory.push(item.duplicate(ability_map, msm)).is_ok());
+    if fits {
+        *inventory = new_inventory;
+    }
+    fits
+}
+
+fn remove_items(inventory: &mut comp::Inventory, item: &str, amount: u32) -> bool {
+    let slots = inventory
+        .slots_with_id()
+        .filter(|(_, slot)| {
+            slot.as_ref()
+                .map_or(false, |i| i.item_definition_id() == item)
+        })
+        .map(|(slot, _)| slot)
+        .collect::<Vec<_>>();
+    // Only remove anything once it's known that all of them can be removed
+    let available = slots
+        .iter()
+        .map(|slot| inventory.get(*slot).map_or(0, |i| i.amount()))
+        .sum::<u32>();
+    if available < amount {
+        return false;
+    }
+    let mut remaining = amount;
+    for slot in slots {
+        if remaining == 0 {
+            break;
+        }
+        let stack_amount = inventory.get(slot).map_or(0, |i| i.amount());
+        if stack_amount > remaining {
+            if let Some(Some(stack)) = inventory.slot_mut(slot) {
+                let _ = stack.decrease_amount(remaining);
+            }
+            remaining = 0;
+        } else {
+            inventory.remove(slot);
+            remaining -= stack_amount;
+        }
+    }
+    true
+}
+
+fn handle_plugin_action(server: &mut Server, plugin: &str, action: Action) -> Result<(), String> {
+    match action {
+        // Handled as soon as they are emitted by the plugin
+        Action::ServerClose | Action::Print(_) => {},
+        Action::PlayerSendMessage(uid, msg) => {
+            let target = entity(server, uid)?;
+            server.notify_client(target, ServerGeneral::server_msg(ChatType::Meta, msg));
+        },
+        Action::KillEntity(uid) => {
+            let target = entity(server, uid)?;
+            server
+                .state
+                .ecs()
+                .write_storage::<comp::Health>()
+                .get_mut(target)
+                .map(|mut health| health.kill())
+                .ok_or_else(|| format!("Entity {} has no health", uid))?;
+        },
+        Action::ChangeHealth {
+            entity: uid,
+            amount,
+        } => {
+            let target = entity(server, uid)?;
+            let time = *server.state.ecs().read_resource::<Time>();
+            server
+                .state
+                .ecs()
+                .read_resource::<EventBus<ServerEvent>>()
+                .emit_now(ServerEvent::HealthChange {
+                    entity: target,
+                    change: comp::HealthChange {
+                        amount,
+                        by: None,
+                        cause: None,
+                        time,
+                    },
+                });
+        },
+        Action::Teleport { entity: uid, pos } => {
+            let target = entity(server, uid)?;
+            server
+                .state
+                .ecs()
+                .write_storage::<comp::Pos>()
+                .get_mut(target)
+                .map(|current_pos| current_pos.0 = pos)
+                .ok_or_else(|| format!("Entity {} has no position", uid))?;
+            let _ = server
+                .state
+                .ecs()
+                .write_storage()
+                .insert(target, comp::ForceUpdate);
+        },
+        Action::GiveItem {
+            entity: uid,
+            item,
+            amount,
+        } => {
+            let target = entity(server, uid)?;
+            let item =
+                Item::new_from_asset(&item).map_err(|_| format!("Invalid item: {}", item))?;
+            let ecs = server.state.ecs();
+            let mut inventories = ecs.write_storage::<comp::Inventory>();
+            let inventory = inventories
+                .get_mut(target)
+                .ok_or_else(|| format!("Entity {} has no inventory", uid))?;
+            if !give_items(
+                inventory,
+                item,
+                amount,
+                &ecs.read_resource::<AbilityMap>(),
+                &ecs.read_resource::<MaterialStatManifest>(),
+            ) {
+                return Err(format!("Inventory of entity {} is full", uid));
+            }
+            let _ = ecs.write_storage().insert(
+                target,
+                comp::InventoryUpdate::new(comp::InventoryUpdateEvent::Given),
+            );
+        },
+        Action::RemoveItem {
+            entity: uid,
+            item,
+            amount,
+        } => {
+            let target = entity(server, uid)?;
+            let ecs = server.state.ecs();
+            let mut inventories = ecs.write_storage::<comp::Inventory>();
+            let inventory = inventories
+                .get_mut(target)
+                .ok_or_else(|| format!("Entity {} has no inventory", uid))?;
+            if !remove_items(inventory, &item, amount) {
+                return Err(format!("Entity {} has less than {} {}", uid, amount, item));
+            }
+            let _ = ecs.write_storage().insert(
+                target,
+                comp::InventoryUpdate::new(comp::InventoryUpdateEvent::Gave),
+            );
+        },
+        Action::ApplyBuff {
+            entity: uid,
+            kind,
+            strength,
+            duration,
+        } => {
+            let target = entity(server, uid)?;
+            let data = BuffData::new(strength, duration.map(Duration::from_secs_f64));
+            server
+                .state
+                .ecs()
+                .read_resource::<EventBus<ServerEvent>>()
+                .emit_now(ServerEvent::Buff {
+                    entity: target,
+                    buff_change: BuffChange::Add(Buff::new(
+                        kind,
+                        data,
+                        vec![],
+                        BuffSource::Unknown,
+                    )),
+                });
+        },
+        Action::RemoveBuff { entity: uid, kind } => {
+            let target = entity(server, uid)?;
+            server
+                .state
+                .ecs()
+                .read_resource::<EventBus<ServerEvent>>()
+                .emit_now(ServerEvent::Buff {
+                    entity: target,
+                    buff_change: BuffChange::RemoveByKind(kind),
+                });
+        },
+        Action::SetBlock { pos, block } => {
+            let kind = BlockKind::from_str(&block.kind)
+                .map_err(|_| format!("Invalid block kind: {}", block.kind))?;
+            let new_block = Block::new(kind, Rgb::from(block.color.unwrap_or([255; 3])));
+            server.state.set_block(pos, new_block);
+            #[cfg(feature = "persistent_world")]
+            if let Some(terrain_persistence) = server
+                .state
+                .ecs()
+                .try_fetch_mut::<crate::TerrainPersistence>()
+                .as_mut()
+            {
+                terrain_persistence.set_block(pos, new_block);
+            }
+        },
+        Action::SpawnNpc(spawn) => spawn_npc(server, spawn)?,
+        Action::SetStorageValue { key, value } => {
+            let quota = server.settings().plugin_storage_quota;
+            let ecs = server.state.ecs();
+            let mut storage = ecs.write_resource::<PluginStorage>();
+            let (keys, bytes) = storage.usage(plugin);
+            let (keys, bytes) = match storage.get(plugin, &key) {
+                Some(old) => (keys, bytes - old.len() + value.len()),
+                None => (keys + 1, bytes + key.len() + value.len()),
+            };
+            if keys > quota.max_keys || bytes > quota.max_bytes {
+                return Err(format!(
+                    "Storage quota exceeded ({} keys, {} bytes), can't set {}",
+                    keys, bytes, key
+                ));
+            }
+            storage.set(plugin, key.clone(), value.clone());
+            ecs.read_resource::<PluginStorageUpdater>()
+                .update(PluginStorageUpdate::Set {
+                    plugin: plugin.to_owned(),
+                    key,
+                    value,
+                });
+        },
+        Action::RemoveStorageValue(key) => {
+            let ecs = server.state.ecs();
+            if ecs
+                .write_resource::<PluginStorage>()
+                .remove(plugin, &key)
+                .is_some()
+            {
+                ecs.read_resource::<PluginStorageUpdater>()
+                    .update(PluginStorageUpdate::Remove {
+                        plugin: plugin.to_owned(),
+                        key,
+                    });
+            }
+        },
+    }
+    Ok(())
+}
+
+fn spawn_npc(server: &mut Server, spawn: NpcSpawn) -> Result<(), String> {
+    let npc::NpcBody(id, mut body) = npc::NpcBody::from_str(&spawn.body)
+        .map_err(|_| format!("Invalid npc body: {}", spawn.body))?;
+    let body = body();
+    let alignment = match spawn.alignment {
+        NpcAlignment::Wild => comp::Alignment::Wild,
+        NpcAlignment::Enemy => comp::Alignment::Enemy,
+        NpcAlignment::Npc => comp::Alignment::Npc,
+        NpcAlignment::Tame => comp::Alignment::Tame,
+        NpcAlignment::Passive => comp::Alignment::Passive,
+    };
+    let name = spawn
+        .name
+        .unwrap_or_else(|| get_npc_name(id, npc::BodyType::from_body(body)));
+    let loadout = LoadoutBuilder::from_default(&body).build();
+
+    let mut entity_base = server
+        .state
+        .create_npc(
+            comp::Pos(spawn.pos),
+            comp::Stats::new(name),
+            comp::SkillSet::default(),
+            Some(comp::Health::new(body, 1)),
+            comp::Poise::new(body),
+            comp::Inventory::new_with_loadout(loadout),
+            body,
+        )
+        .with(alignment);
+
+    if spawn.agent {
+        entity_base = entity_base.with(comp::Agent::from_body(&body).with_patrol_origin(spawn.pos));
+    }
+
+    if let Some(group) = match alignment {
+        comp::Alignment::Enemy => Some(comp::group::ENEMY),
+        comp::Alignment::Npc | comp::Alignment::Tame => Some(comp::group::NPC),
+        _ => None,
+    } {
+        entity_base = entity_base.with(group);
+    }
+
+    entity_base.build();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::combat::DamageSource;
+
+    fn give(inventory: &mut comp::Inventory, item: &str, amount: u32) -> bool {
+        give_items(
+            inventory,
+            Item::new_from_asset_expect(item),
+            amount,
+            &AbilityMap::default(),
+            &MaterialStatManifest::default(),
+        )
+    }
+
+    #[test]
+    fn remove_items_removes_all_or_leaves_inventory_unchanged() {
+        let mut inventory = comp::Inventory::new_empty();
+        assert!(give(&mut inventory, "common.items.food.cheese", 3));
+        assert!(give(
+            &mut inventory,
+            "common.items.weapons.sword.starter",
+            2
+        ));
+        let count = |inventory: &comp::Inventory, item: &str| {
+            inventory
+                .slots()
+                .flatten()
+                .filter(|i| i.item_definition_id() == item)
+                .map(|i| i.amount())
+                .sum::<u32>()
+        };
+
+        // Only two swords
+        assert!(!remove_items(
+            &mut inventory,
+            "common.items.weapons.sword.starter",
+            3
+        ));
+        assert_eq!(count(&inventory, "common.items.weapons.sword.starter"), 2);
+
+        assert!(remove_items(&mut inventory, "common.items.food.cheese", 2));
+        assert_eq!(count(&inventory, "common.items.food.cheese"), 1);
+        assert!(remove_items(
+            &mut inventory,
+            "common.items.weapons.sword.starter",
+            2
+        ));
+        assert_eq!(count(&inventory, "common.items.weapons.sword.starter"), 0);
+        assert!(!remove_items(&mut inventory, "common.items.food.cheese", 2));
+        assert_eq!(inventory.populated_slots(), 1);
+    }
+
+    #[test]
+    fn give_items_fits_or_leaves_inventory_unchanged() {
+        let mut inventory = comp::Inventory::new_empty();
+        let free_slots = inventory.free_slots();
+        assert!(give(
+            &mut inventory,
+            "common.items.weapons.sword.starter",
+            free_slots as u32 - 1
+        ));
+        assert_eq!(inventory.free_slots(), 1);
+
+        // Two swords need two slots
+        assert!(!give(
+            &mut inventory,
+            "common.items.weapons.sword.starter",
+            2
+        ));
+        assert_eq!(inventory.free_slots(), 1);
+
+        // A stack only needs one
+        assert!(give(&mut inventory, "common.items.food.cheese", 2));
+        assert_eq!(inventory.free_slots(), 0);
+        assert!(!give(
+            &mut inventory,
+            "common.items.weapons.sword.starter",
+            1
+        ));
+        assert_eq!(inventory.populated_slots(), free_slots);
+    }
+
+    #[test]
+    fn damage_events_have_a_source() {
+        let uid = Uid(1);
+        let change = |amount, cause| comp::HealthChange {
+            amount,
+            by: None,
+            cause,
+            time: Time(0.0),
+        };
+
+        let hit = damage_event(uid, &change(-10.0, Some(DamageSource::Melee))).unwrap();
+        assert_eq!(hit.amount, 10.0);
+        assert_eq!(hit.source, Some(plugin_api::event::DamageSource::Melee));
+
+        let burning = damage_event(
+            uid,
+            &change(-1.0, Some(DamageSource::Buff(comp::BuffKind::Burning))),
+        )
+        .unwrap();
+        assert_eq!(
+            burning.source,
+            Some(plugin_api::event::DamageSource::Buff(
+                comp::BuffKind::Burning
+            ))
+        );
+
+        assert!(damage_event(uid, &change(5.0, None)).is_none());
+    }
+}
@@ -0,0 +1,102 @@
+use crate::{
+    reports::{ChatHistory, Reports},
+    Server, Settings,
+};
+use common::{cmd::ChatCommand, comp, comp::ChatType, resources::Time};
+use common_net::msg::{validate_chat_msg, ServerGeneral};
+use specs::{Entity as EcsEntity, Join, WorldExt};
+use tracing::info;
+
+pub fn handle_report(server: &Server, reporter: EcsEntity, player: String, reason: String) {
+    let (chat_type, msg) = match file_report(server, reporter, &player, reason) {
+        Ok(id) => (
+            ChatType::CommandInfo,
+            format!("Thank you, your report #{} was sent to the moderators.", id),
+        ),
+        Err(err) => (ChatType::CommandError, err),
+    };
+    server.notify_client(reporter, ServerGeneral::server_msg(chat_type, msg));
+}
+
+/// Files the report, returning its id, and tells the moderators online about
+/// it.
+fn file_report(
+    server: &Server,
+    reporter: EcsEntity,
+    player: &str,
+    reason: String,
+) -> Result<u64, String> {
+    let reason = reason.trim().to_owned();
+    if reason.is_empty() {
+        return Err("Please give a reason for your report.".to_owned());
+    }
+    if validate_chat_msg(&reason).is_err() {
+        return Err("The reason of your report is too long.".to_owned());
+    }
+
+    let ecs = server.state.ecs();
+    let (reporter_uuid, reporter_username) = ecs
+        .read_storage::<comp::Player>()
+        .get(reporter)
+        .map(|player| (player.uuid(), player.alias.clone()))
+        .ok_or_else(|| "Only players can file reports.".to_owned())?;
+    let settings = ecs.read_resource::<Settings>();
+    let time = ecs.read_resource::<Time>().0;
+    ecs.read_resource::<Reports>()
+        .can_file(&settings.reports, time, reporter_uuid)
+        .map_err(|refused| refused.info())?;
+
+    // Only players who are online or chatted recently can be reported, so
+    // made-up names are refused without asking the auth server.
+    let online = (&ecs.read_storage::<comp::Player>())
+        .join()
+        .find(|online| online.alias == player)
+        .map(|online| online.uuid());
+    let reported_uuid = online
+        .or_else(|| ecs.read_resource::<ChatHistory>().find_sender(player))
+        .ok_or_else(|| format!("{} is neither online nor chatted recently.", player))?;
+    if reported_uuid == reporter_uuid {
+        return Err("You can't report yourself.".to_owned());
+    }
+
+    let chat = ecs.read_resource::<ChatHistory>().lines_of(&reported_uuid);
+    let id = ecs
+        .write_resource::<Reports>()
+        .file(
+            &settings.reports,
+            time,
+            reporter_uuid,
+            reporter_username.clone(),
+            reported_uuid,
+            player.to_owned(),
+            reason,
+            chat,
+        )
+        .map_err(|refused| refused.info())?;
+    info!(
+        "{} ({}) filed the report #{} against {} ({})",
+        reporter_username, reporter_uuid, id, player, reported_uuid
+    );
+
+    let moderators = (&ecs.entities(), &ecs.read_storage::<comp::Player>())
+        .join()
+        .filter(|(entity, _)| {
+            let cmd = ChatCommand::Reports;
+            server.entity_can_use_command(*entity, cmd.keyword(), cmd.needs_role())
+        })
+        .map(|(entity, _)| entity)
+        .collect::<Vec<_>>();
+    for moderator in moderators {
+        server.notify_client(
+            moderator,
+            ServerGeneral::server_msg(
+                ChatType::CommandInfo,
+                format!(
+                    "{} reported {}, use /reports to review report #{}.",
+                    reporter_username, player, id
+                ),
+            ),
+        );
+    }
+    Ok(id)
+}
@@ -1,4 +1,9 @@
-use crate::Server;
+use crate::{rtsim::SiteEconomies, Server};
+#[cfg(feature = "worldgen")]
+use common::{
+    comp::inventory::trade_pricing::TradePricing,
+    trade::{Good, SiteId},
+};
 use common::{
     comp::{
         agent::{Agent, AgentEvent},
@@ -32,12 +37,13 @@ fn notify_agent_simple(
 fn notify_agent_prices(
     mut agents: specs::WriteStorage<Agent>,
     index: &IndexOwned,
+    economies: &SiteEconomies,
     entity: EcsEntity,
     event: AgentEvent,
 ) {
     if let Some((Some(site_id), agent)) = agents.get_mut(entity).map(|a| (a.behavior.trade_site, a))
     {
-        let prices = index.get_site_prices(site_id);
+        let prices = economies.get_site_prices(index, site_id);
         if let AgentEvent::UpdatePendingTrade(boxval) = event {
             // Box<(tid, pend, _, inventories)>) = event {
             agent
@@ -91,6 +97,8 @@ pub fn handle_process_trade_action(
             if let Entry::Occupied(entry) = trades.trades.entry(trade_id) {
                 let parties = entry.get().parties;
                 if entry.get().should_commit() {
+                    #[cfg(feature = "worldgen")]
+                    let site_goods = site_traded_goods(server.state.ecs(), entry.get());
                     let result = commit_trade(server.state.ecs(), entry.get());
                     entry.remove();
                     for party in parties.iter() {
@@ -104,6 +112,27 @@ pub fn handle_process_trade_action(
                         }
                         trades.entity_trades.remove_entry(party);
                     }
+                    // Goods sold to or bought from merchants change the stock of their site
+                    #[cfg(feature = "worldgen")]
+                    if let (TradeResult::Completed, Some((site_id, goods))) = (&result, site_goods)
+                    {
+                        if let Some(site) = server.index.sites.recreate_id(site_id) {
+                            server
+                                .state
+                                .ecs()
+                                .write_resource::<SiteEconomies>()
+                                .record_trade(site, &goods);
+                        }
+                    }
+                    #[cfg(feature = "plugins")]
+                    if result == TradeResult::Completed {
+                        super::plugin::execute_event(
+                            &server.state,
+                            &plugin_api::event::TradeCompleteEvent {
+                                parties: parties.map(|id| plugin_api::event::Player { id }),
+                            },
+                        );
+                    }
                 } else {
                     let mut entities: [Option<specs::Entity>; 2] = [None, None];
                     let mut inventories: [Option<ReducedInventory>; 2] = [None, None];
@@ -125,10 +154,15 @@ pub fn handle_process_trade_action(
                             #[cfg(feature = "worldgen")]
                             {
                                 prices = prices.or_else(|| {
-                                    agents
-                                        .get(e)
-                                        .and_then(|a| a.behavior.trade_site)
-                                        .and_then(|id| server.index.get_site_prices(id))
+                                    agents.get(e).and_then(|a| a.behavior.trade_site).and_then(
+                                        |id| {
+                                            server
+                                                .state
+                                                .ecs()
+                                                .read_resource::<SiteEconomies>()
+                                                .get_site_prices(&server.index, id)
+                                        },
+                                    )
                                 });
                             }
                         }
@@ -148,6 +182,7 @@ pub fn handle_process_trade_action(
                             notify_agent_prices(
                                 server.state.ecs().write_storage::<Agent>(),
                                 &server.index,
+                                &server.state.ecs().read_resource::<SiteEconomies>(),
                                 e,
                                 AgentEvent::UpdatePendingTrade(Box::new((
                                     trade_id,
@@ -190,6 +225,37 @@ pub fn cancel_trade_for(server: &mut Server, entity: EcsEntity) {
     }
 }
 
+/// The goods a merchant trading on behalf of a site gains (+) and loses (-) in
+/// a trade, along with that site
+#[cfg(feature = "worldgen")]
+fn site_traded_goods(
+    ecs: &specs::World,
+    trade: &PendingTrade,
+) -> Option<(SiteId, Vec<(Good, f32)>)> {
+    let agents = ecs.read_storage::<Agent>();
+    let inventories = ecs.read_storage::<Inventory>();
+    let entities = trade.parties.map(|party| ecs.entity_from_uid(party.0));
+    let (merchant, site_id) = entities.iter().enumerate().find_map(|(who, entity)| {
+        agents
+            .get((*entity)?)
+            .and_then(|agent| agent.behavior.trade_site)
+            .map(|site_id| (who, site_id))
+    })?;
+
+    let mut goods = Vec::new();
+    for (who, entity) in entities.iter().enumerate() {
+        let inventory = inventories.get((*entity)?)?;
+        let sign = if who == merchant { -1.0 } else { 1.0 };
+        for (slot, quantity) in trade.offers[who].iter() {
+            if let Some(item) = inventory.get(*slot) {
+                let (good, factor) = TradePricing::get_material(item.item_definition_id());
+                goods.push((good, sign * factor * *quantity as f32));
+            }
+        }
+    }
+    Some((site_id, goods))
+}
+
 /// Commit a trade that both parties have agreed to, modifying their respective
 /// inventories
 fn commit_trade(ecs: &specs::World, trade: &PendingTrade) -> TradeResult {
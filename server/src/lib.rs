@@ -14,7 +14,9 @@
 #![cfg_attr(not(feature = "worldgen"), feature(const_panic))]
 
 pub mod alias_validator;
+pub mod audit_log;
 mod character_creator;
+pub mod chat_limiter;
 pub mod chunk_generator;
 pub mod client;
 pub mod cmd;
@@ -28,7 +30,10 @@ pub mod metrics;
 pub mod persistence;
 mod pet;
 pub mod presence;
+pub mod query_server;
+pub mod reports;
 pub mod rtsim;
+pub mod session;
 pub mod settings;
 pub mod state_ext;
 pub mod sys;
@@ -50,15 +55,19 @@ pub use crate::{
 use crate::terrain_persistence::TerrainPersistence;
 use crate::{
     alias_validator::AliasValidator,
+    chat_limiter::ChatLimiter,
     chunk_generator::ChunkGenerator,
     client::Client,
-    cmd::ChatCommandExt,
+    cmd::{ChatCommandExt, ConsoleOutput},
     connection_handler::ConnectionHandler,
     data_dir::DataDir,
     login_provider::LoginProvider,
     persistence::PersistedComponents,
     presence::{Presence, RegionSubscription, RepositionOnChunkLoad},
+    query_server::QueryServer,
+    reports::{ChatHistory, Reports},
     rtsim::RtSim,
+    session::Session,
     state_ext::StateExt,
     sys::sentinel::{DeletedEntities, TrackedStorages},
 };
@@ -90,6 +99,7 @@ use common_systems::add_local_systems;
 use metrics::{EcsSystemMetrics, PhysicsMetrics, TickMetrics};
 use network::{ListenAddr, Network, Pid};
 use persistence::{
+    backup::DatabaseBackups,
     character_loader::{CharacterLoader, CharacterLoaderResponseKind},
     character_updater::CharacterUpdater,
 };
@@ -117,8 +127,10 @@ use std::sync::RwLock;
 
 #[cfg(feature = "plugins")]
 use {
-    common::uid::UidAllocator,
-    common_state::plugin::{memory_manager::EcsWorld, PluginMgr},
+    common::{terrain::TerrainGrid, uid::UidAllocator},
+    common_state::plugin::{
+        memory_manager::EcsWorld, storage::PluginStorage, watcher::PluginWatcher, PluginMgr,
+    },
 };
 
 use common::comp::Anchor;
@@ -194,12 +206,17 @@ pub struct Server {
     map: WorldMapMsg,
 
     connection_handler: ConnectionHandler,
+    /// Answers status queries of server browsers
+    query_server: Option<QueryServer>,
 
     runtime: Arc<Runtime>,
 
     metrics_shutdown: Arc<Notify>,
     database_settings: Arc<RwLock<DatabaseSettings>>,
     disconnect_all_clients_requested: bool,
+    /// Watches the plugin directory to hot-reload plugins
+    #[cfg(feature = "plugins")]
+    plugin_watcher: Option<PluginWatcher>,
 }
 
 impl Server {
@@ -230,8 +247,24 @@ impl Server {
         let ecs_system_metrics = EcsSystemMetrics::new(&registry).unwrap();
         let tick_metrics = TickMetrics::new(&registry).unwrap();
         let physics_metrics = PhysicsMetrics::new(&registry).unwrap();
+        #[cfg(feature = "plugins")]
+        let plugin_metrics = metrics::PluginMetrics::new(&registry).unwrap();
         let battlemode_buffer = BattleModeBuffer::default();
 
+        #[cfg(feature = "plugins")]
+        let mut state = State::server_with_plugins(
+            persistence::plugin_storage::load(&database_settings.read().unwrap()).unwrap_or_else(
+                |e| {
+                    error!(
+                        ?e,
+                        "Failed to load the plugin storage, plugins will start empty"
+                    );
+                    Default::default()
+                },
+            ),
+            settings.plugin_limits,
+        );
+        #[cfg(not(feature = "plugins"))]
         let mut state = State::server();
         state.ecs_mut().insert(battlemode_buffer);
         state.ecs_mut().insert(settings.clone());
@@ -257,6 +290,8 @@ impl Server {
         state.ecs_mut().insert(ecs_system_metrics);
         state.ecs_mut().insert(tick_metrics);
         state.ecs_mut().insert(physics_metrics);
+        #[cfg(feature = "plugins")]
+        state.ecs_mut().insert(plugin_metrics);
         if settings.experimental_terrain_persistence {
             #[cfg(feature = "persistent_world")]
             {
@@ -280,6 +315,14 @@ impl Server {
             .ecs_mut()
             .write_resource::<SlowJobPool>()
             .configure("CHUNK_GENERATOR", |n| n / 2 + n / 4);
+        state
+            .ecs_mut()
+            .write_resource::<SlowJobPool>()
+            .configure("RTSIM_SAVE", |_| 1);
+        state
+            .ecs_mut()
+            .write_resource::<SlowJobPool>()
+            .configure("DB_BACKUP", |_| 1);
         state
             .ecs_mut()
             .insert(ChunkGenerator::new(chunk_gen_metrics));
@@ -287,6 +330,12 @@ impl Server {
         state.ecs_mut().insert(CharacterUpdater::new(
             Arc::<RwLock<DatabaseSettings>>::clone(&database_settings),
         )?);
+        #[cfg(feature = "plugins")]
+        state
+            .ecs_mut()
+            .insert(persistence::plugin_storage::PluginStorageUpdater::new(
+                Arc::<RwLock<DatabaseSettings>>::clone(&database_settings),
+            ));
 
         let ability_map = comp::item::tool::AbilityMap::<comp::AbilityItem>::load_expect_cloned(
             "common.abilities.ability_set_manifest",
@@ -299,6 +348,10 @@ impl Server {
         state.ecs_mut().insert(CharacterLoader::new(
             Arc::<RwLock<DatabaseSettings>>::clone(&database_settings),
         )?);
+        state.ecs_mut().insert(DatabaseBackups::new(
+            Arc::<RwLock<DatabaseSettings>>::clone(&database_settings),
+            settings.database_backups,
+        ));
 
         // System schedulers to control execution of systems
         state
@@ -309,43 +362,22 @@ impl Server {
         state.ecs_mut().register::<RegionSubscription>();
         state.ecs_mut().register::<Client>();
         state.ecs_mut().register::<Presence>();
+        state.ecs_mut().register::<Session>();
         state.ecs_mut().register::<wiring::WiringElement>();
         state.ecs_mut().register::<wiring::Circuit>();
         state.ecs_mut().register::<comp::Anchor>();
         state.ecs_mut().register::<comp::Pet>();
         state.ecs_mut().register::<login_provider::PendingLogin>();
         state.ecs_mut().register::<RepositionOnChunkLoad>();
+        state.ecs_mut().register::<ConsoleOutput>();
 
         //Alias validator
-        let banned_words_paths = &settings.banned_words_files;
-        let mut banned_words = Vec::new();
-        for path in banned_words_paths {
-            let mut list = match std::fs::File::open(&path) {
-                Ok(file) => match ron::de::from_reader(&file) {
-                    Ok(vec) => vec,
-                    Err(error) => {
-                        tracing::warn!(?error, ?file, "Couldn't deserialize banned words file");
-                        return Err(Error::Other(format!(
-                            "Couldn't read banned words file \"{}\"",
-                            path.to_string_lossy()
-                        )));
-                    },
-                },
-                Err(error) => {
-                    tracing::warn!(?error, ?path, "Couldn't open banned words file");
-                    return Err(Error::Other(format!(
-                        "Couldn't open banned words file \"{}\". Error: {}",
-                        path.to_string_lossy(),
-                        error
-                    )));
-                },
-            };
-            banned_words.append(&mut list);
-        }
-        let banned_words_count = banned_words.len();
-        tracing::debug!(?banned_words_count);
-        tracing::trace!(?banned_words);
-        state.ecs_mut().insert(AliasValidator::new(banned_words));
+        let alias_validator =
+            AliasValidator::from_files(&settings.banned_words_files).map_err(Error::Other)?;
+        state.ecs_mut().insert(alias_validator);
+        state.ecs_mut().insert(ChatLimiter::default());
+        state.ecs_mut().insert(ChatHistory::default());
+        state.ecs_mut().insert(Reports::load(data_dir));
 
         #[cfg(feature = "worldgen")]
         let (world, index) = World::generate(
@@ -463,14 +495,13 @@ impl Server {
             )
             .await
         });
-        runtime.block_on(network.listen(ListenAddr::Tcp(settings.gameserver_address)))?;
-        runtime.block_on(network.listen(ListenAddr::Mpsc(14004)))?;
-        if let Some(quic) = &settings.quic_files {
+        // the certificate is used for QUIC and to optionally encrypt TCP with TLS
+        let certificate = settings.quic_files.as_ref().and_then(|files| {
             use rustls_pemfile::Item;
             use std::fs;
             match || -> Result<_, Box<dyn std::error::Error>> {
-                let key = fs::read(&quic.key)?;
-                let key = if quic.key.extension().map_or(false, |x| x == "der") {
+                let key = fs::read(&files.key)?;
+                let key = if files.key.extension().map_or(false, |x| x == "der") {
                     rustls::PrivateKey(key)
                 } else {
                     debug!("convert pem key to der");
@@ -483,17 +514,45 @@ impl Server {
                         .ok_or("No valid pem key in file")?;
                     rustls::PrivateKey(key)
                 };
-                let cert_chain = fs::read(&quic.cert)?;
-                let cert_chain = if quic.cert.extension().map_or(false, |x| x == "der") {
+                let cert_chain = fs::read(&files.cert)?;
+                let cert_chain = if files.cert.extension().map_or(false, |x| x == "der") {
                     vec![rustls::Certificate(cert_chain)]
                 } else {
                     debug!("convert pem cert to der");
                     let certs = rustls_pemfile::certs(&mut cert_chain.as_slice())?;
                     certs.into_iter().map(rustls::Certificate).collect()
                 };
-                let server_config = quinn::ServerConfig::with_single_cert(cert_chain, key)?;
-                Ok(server_config)
+                Ok((cert_chain, key))
             }() {
+                Ok(certificate) => Some(certificate),
+                Err(e) => {
+                    error!(?e, ?settings.quic_files, "Failed to load Certificate, run without Quic and Tls");
+                    None
+                },
+            }
+        });
+        runtime.block_on(network.listen(ListenAddr::Tcp(settings.gameserver_address)))?;
+        if let Some(tls_address) = settings.tls_address {
+            match &certificate {
+                Some((cert_chain, key)) => match rustls::ServerConfig::builder()
+                    .with_safe_defaults()
+                    .with_no_client_auth()
+                    .with_single_cert(cert_chain.clone(), key.clone())
+                {
+                    Ok(tls_config) => {
+                        info!(?tls_address, "Tls is enabled");
+                        runtime.block_on(
+                            network.listen(ListenAddr::Tls(tls_address, Arc::new(tls_config))),
+                        )?;
+                    },
+                    Err(e) => error!(?e, "Failed to create Tls config, run without Tls"),
+                },
+                None => error!("Tls needs a certificate in quic_files, run without Tls"),
+            }
+        }
+        runtime.block_on(network.listen(ListenAddr::Mpsc(14004)))?;
+        if let Some((cert_chain, key)) = certificate {
+            match quinn::ServerConfig::with_single_cert(cert_chain, key) {
                 Ok(server_config) => {
                     warn!(
                         "QUIC is enabled. This is experimental and not recommended in production"
@@ -503,12 +562,16 @@ impl Server {
                             .listen(ListenAddr::Quic(settings.gameserver_address, server_config)),
                     )?;
                 },
-                Err(e) => {
-                    error!(?e, ?settings.quic_files, "Failed to load Quic Certificate, run without Quic")
-                },
+                Err(e) => error!(?e, "Failed to create Quic config, run without Quic"),
             }
         }
         let connection_handler = ConnectionHandler::new(network, &runtime);
+        let query_server = QueryServer::start(
+            &runtime,
+            settings.query,
+            map.dimensions_lg.map(|lg| 1 << lg),
+            state.ecs(),
+        );
 
         // Initiate real-time world simulation
         #[cfg(feature = "worldgen")]
@@ -516,6 +579,16 @@ impl Server {
         #[cfg(not(feature = "worldgen"))]
         rtsim::init(&mut state);
 
+        #[cfg(feature = "plugins")]
+        let plugin_watcher = PluginWatcher::new(&PluginMgr::assets_dir())
+            .map_err(|e| {
+                warn!(
+                    ?e,
+                    "Failed to watch plugin directory, hot-reloading disabled"
+                )
+            })
+            .ok();
+
         let this = Self {
             state,
             world,
@@ -523,11 +596,14 @@ impl Server {
             map,
 
             connection_handler,
+            query_server,
             runtime,
 
             metrics_shutdown,
             database_settings,
             disconnect_all_clients_requested: false,
+            #[cfg(feature = "plugins")]
+            plugin_watcher,
         };
 
         debug!(?settings, "created veloren server with");
@@ -542,17 +618,7 @@ impl Server {
         Ok(this)
     }
 
-    pub fn get_server_info(&self) -> ServerInfo {
-        let settings = self.state.ecs().fetch::<Settings>();
-        let editable_settings = self.state.ecs().fetch::<EditableSettings>();
-        ServerInfo {
-            name: settings.server_name.clone(),
-            description: (&*editable_settings.server_description).clone(),
-            git_hash: common::util::GIT_HASH.to_string(),
-            git_date: common::util::GIT_DATE.to_string(),
-            auth_provider: settings.auth_server_address.clone(),
-        }
-    }
+    pub fn get_server_info(&self) -> ServerInfo { server_info(self.state.ecs()) }
 
     /// Get a reference to the server's settings
     pub fn settings(&self) -> impl Deref<Target = Settings> + '_ {
@@ -634,7 +700,15 @@ impl Server {
         // 1) Build up a list of events for this frame, to be passed to the frontend.
         let mut frontend_events = Vec::new();
 
-        // 2)
+        // 2) Reload the plugins that changed on disk, apply the actions emitted by
+        // plugins during the last tick and let them know that a new one started
+        #[cfg(feature = "plugins")]
+        {
+            self.handle_plugin_file_changes();
+            self.handle_plugin_actions();
+            self.plugins_tick(dt);
+            self.update_plugin_metrics();
+        }
 
         let before_new_connections = Instant::now();
 
@@ -670,6 +744,9 @@ impl Server {
         // Handle game events
         frontend_events.append(&mut self.handle_events());
 
+        #[cfg(feature = "plugins")]
+        self.plugins_new_chunks();
+
         let before_update_terrain_and_regions = Instant::now();
 
         // Apply terrain changes and update the region map after processing server
@@ -986,6 +1063,30 @@ impl Server {
             .ecs()
             .try_fetch_mut::<TerrainPersistence>()
             .map(|mut t| t.maintain());
+
+        // Periodically save the rtsim state
+        let ecs = self.state.ecs();
+        ecs.write_resource::<rtsim::RtSimPersistence>().maintain(
+            &ecs.read_resource::<RtSim>(),
+            &ecs.read_resource::<rtsim::SiteEconomies>(),
+            ecs.read_resource::<Time>().0,
+            &ecs.read_resource::<SlowJobPool>(),
+        );
+
+        // Periodically back up the database
+        ecs.write_resource::<DatabaseBackups>().maintain(
+            ecs.read_resource::<Time>().0,
+            &ecs.read_resource::<SlowJobPool>(),
+        );
+
+        // Forget the chat of players who stopped chatting
+        ecs.write_resource::<ChatHistory>()
+            .maintain(ecs.read_resource::<Time>().0);
+
+        // Keep the status answered to server browsers up to date
+        if let Some(query_server) = &mut self.query_server {
+            query_server.maintain(ecs, ecs.read_resource::<Time>().0);
+        }
     }
 
     fn initialize_client(
@@ -1127,6 +1228,16 @@ impl Server {
     where
         S: Into<ServerMsg>,
     {
+        let msg = msg.into();
+        if let (Some(output), ServerMsg::General(ServerGeneral::ChatMsg(chat_msg))) = (
+            self.state
+                .ecs()
+                .write_storage::<ConsoleOutput>()
+                .get_mut(entity),
+            &msg,
+        ) {
+            output.0.push(chat_msg.message.clone());
+        }
         self.state
             .ecs()
             .read_storage::<Client>()
@@ -1160,12 +1271,35 @@ impl Server {
             #[cfg(feature = "plugins")]
             {
                 let plugin_manager = self.state.ecs().read_resource::<PluginMgr>();
+                if let Some(command) = plugin_manager.command(&name) {
+                    let checked = if !self.entity_can_use_command(
+                        entity,
+                        &command.keyword,
+                        command.needs_role,
+                    ) {
+                        Err(format!("You don't have permission to use '/{}'.", name))
+                    } else {
+                        command.check_args(&args)
+                    };
+                    if let Err(e) = checked {
+                        self.notify_client(
+                            entity,
+                            ServerGeneral::server_msg(comp::ChatType::CommandError, e),
+                        );
+                        return;
+                    }
+                }
                 let ecs_world = EcsWorld {
                     entities: &self.state.ecs().entities(),
                     health: self.state.ecs().read_component().into(),
                     uid: self.state.ecs().read_component().into(),
                     uid_allocator: &self.state.ecs().read_resource::<UidAllocator>().into(),
                     player: self.state.ecs().read_component().into(),
+                    pos: self.state.ecs().read_component().into(),
+                    inventory: self.state.ecs().read_component().into(),
+                    buffs: self.state.ecs().read_component().into(),
+                    terrain: &self.state.ecs().read_resource::<TerrainGrid>().into(),
+                    storage: &self.state.ecs().read_resource::<PluginStorage>().into(),
                 };
                 let uid = if let Some(uid) = ecs_world.uid.get(entity).copied() {
                     uid
@@ -1254,6 +1388,25 @@ impl Server {
             .map(|admin| admin.0)
     }
 
+    /// Whether the role of the entity, or its permission groups, allow it to
+    /// use the command.
+    fn entity_can_use_command(
+        &self,
+        entity: EcsEntity,
+        keyword: &str,
+        needs_role: Option<comp::AdminRole>,
+    ) -> bool {
+        can_use_command(
+            self.entity_admin_role(entity),
+            self.state
+                .ecs()
+                .read_storage::<comp::Permissions>()
+                .get(entity),
+            keyword,
+            needs_role,
+        )
+    }
+
     pub fn number_of_players(&self) -> i64 {
         self.state.ecs().read_storage::<Client>().join().count() as i64
     }
@@ -1319,6 +1472,71 @@ impl Server {
         };
     }
 
+    /// NOTE: Do *not* allow this to be called from any command that doesn't go
+    /// through the CLI!
+    pub fn assign_permission_group(&self, username: &str, group: &str) {
+        let mut editable_settings = self.editable_settings_mut();
+        let login_provider = self.state.ecs().fetch::<LoginProvider>();
+        let data_dir = self.data_dir();
+        if let Some(uuid) = assign_permission_group(
+            username,
+            group,
+            &login_provider,
+            &mut editable_settings,
+            &data_dir.path,
+        ) {
+            drop((data_dir, login_provider, editable_settings));
+            self.refresh_permissions(uuid);
+        }
+    }
+
+    /// NOTE: Do *not* allow this to be called from any command that doesn't go
+    /// through the CLI!
+    pub fn unassign_permission_group(&self, username: &str, group: &str) {
+        let mut editable_settings = self.editable_settings_mut();
+        let login_provider = self.state.ecs().fetch::<LoginProvider>();
+        let data_dir = self.data_dir();
+        if let Some(uuid) = unassign_permission_group(
+            username,
+            group,
+            &login_provider,
+            &mut editable_settings,
+            &data_dir.path,
+        ) {
+            drop((data_dir, login_provider, editable_settings));
+            self.refresh_permissions(uuid);
+        }
+    }
+
+    /// Lists the permission groups, along with what they allow and their
+    /// members.
+    pub fn permission_groups_summary(&self) -> String {
+        self.editable_settings().permission_groups.summary()
+    }
+
+    /// Updates the permissions of the player if they are online, after their
+    /// permission groups changed.
+    fn refresh_permissions(&self, uuid: common::uuid::Uuid) {
+        let permissions = self
+            .editable_settings()
+            .permission_groups
+            .permissions_of(&uuid);
+        let ecs = self.state.ecs();
+        let entity = (&ecs.entities(), &ecs.read_storage::<comp::Player>())
+            .join()
+            .find(|(_, player)| player.uuid() == uuid)
+            .map(|(e, _)| e);
+        if let Some(entity) = entity {
+            let mut storage = ecs.write_storage::<comp::Permissions>();
+            if permissions.is_empty() {
+                storage.remove(entity);
+            } else {
+                // If the entity was just deleted, we can ignore the write failure.
+                let _ = storage.insert(entity, permissions);
+            }
+        }
+    }
+
     /// Useful for testing without a client
     /// view_distance: distance in chunks that are persisted, this acts like the
     /// player view distance so it is actually a bit farther due to a buffer
@@ -1355,10 +1573,67 @@ impl Server {
         info!("SQL log mode changed to {:?}", sql_log_mode);
     }
 
+    /// Starts a backup of the database in the background
+    pub fn backup_database(&self) {
+        info!("Backing up the database due to local console command");
+        let ecs = self.state.ecs();
+        ecs.read_resource::<DatabaseBackups>()
+            .backup_now(&ecs.read_resource::<SlowJobPool>());
+    }
+
     pub fn disconnect_all_clients(&mut self) {
         info!("Disconnecting all clients due to local console command");
         self.disconnect_all_clients_requested = true;
     }
+
+    /// Executes a chat command from the server console with administrator
+    /// permissions, returning the messages it sent back.  Commands performed on
+    /// behalf of a player, which need to know who issued them, will fail.
+    pub fn execute_console_command(&mut self, cmd: &ChatCommand, args: Vec<String>) -> Vec<String> {
+        info!(?cmd, ?args, "Executing chat command from local console");
+        let entity = self
+            .state
+            .ecs_mut()
+            .create_entity()
+            .with(comp::Admin(comp::AdminRole::Admin))
+            .with(ConsoleOutput::default())
+            .build();
+        cmd.execute(self, entity, args);
+        let output = self
+            .state
+            .ecs()
+            .write_storage::<ConsoleOutput>()
+            .remove(entity)
+            .map_or_else(Vec::new, |output| output.0);
+        if let Err(e) = self.state.ecs_mut().delete_entity(entity) {
+            error!(?e, "Failed to delete the console entity");
+        }
+        output
+    }
+}
+
+/// Whether a player with the role and the permissions granted by their groups
+/// can use the command.
+fn can_use_command(
+    role: Option<comp::AdminRole>,
+    permissions: Option<&comp::Permissions>,
+    keyword: &str,
+    needs_role: Option<comp::AdminRole>,
+) -> bool {
+    needs_role <= role
+        || permissions.map_or(false, |permissions| permissions.commands.contains(keyword))
+}
+
+fn server_info(ecs: &specs::World) -> ServerInfo {
+    let settings = ecs.fetch::<Settings>();
+    let editable_settings = ecs.fetch::<EditableSettings>();
+    ServerInfo {
+        name: settings.server_name.clone(),
+        description: (&*editable_settings.server_description).clone(),
+        git_hash: common::util::GIT_HASH.to_string(),
+        git_date: common::util::GIT_DATE.to_string(),
+        auth_provider: settings.auth_server_address.clone(),
+    }
 }
 
 impl Drop for Server {
@@ -1376,6 +1651,13 @@ impl Drop for Server {
                 info!("Unloading terrain persistence...");
                 terrain_persistence.unload_all()
             });
+
+        let ecs = self.state.ecs();
+        ecs.write_resource::<rtsim::RtSimPersistence>().save(
+            &ecs.read_resource::<RtSim>(),
+            &ecs.read_resource::<rtsim::SiteEconomies>(),
+            ecs.read_resource::<Time>().0,
+        );
     }
 }
 
@@ -1493,3 +1775,139 @@ pub fn remove_admin(
         },
     }
 }
+
+/// If successful returns the Some(uuid) of the user added to the group
+///
+/// NOTE: Do *not* allow this to be called from any command that doesn't go
+/// through the CLI!
+#[must_use]
+pub fn assign_permission_group(
+    username: &str,
+    group: &str,
+    login_provider: &LoginProvider,
+    editable_settings: &mut EditableSettings,
+    data_dir: &std::path::Path,
+) -> Option<common::uuid::Uuid> {
+    use crate::settings::EditableSetting;
+    if !editable_settings
+        .permission_groups
+        .groups
+        .contains_key(group)
+    {
+        error!("There is no permission group named {}", group);
+        return None;
+    }
+    match login_provider.username_to_uuid(username) {
+        Ok(uuid) => handle_edit(
+            uuid,
+            editable_settings
+                .permission_groups
+                .edit(data_dir, |permission_groups| {
+                    let membership = permission_groups.members.entry(uuid).or_insert_with(|| {
+                        settings::GroupMembership {
+                            username_when_assigned: username.into(),
+                            date: chrono::Utc::now(),
+                            groups: Default::default(),
+                        }
+                    });
+                    if membership.groups.insert(group.to_owned()) {
+                        membership.username_when_assigned = username.into();
+                        membership.date = chrono::Utc::now();
+                        Some(format!(
+                            "Successfully added {} ({}) to the permission group {}!",
+                            username, uuid, group
+                        ))
+                    } else {
+                        info!("{} ({}) is already in the group {}!", username, uuid, group);
+                        None
+                    }
+                }),
+        ),
+        Err(err) => {
+            error!(
+                ?err,
+                "Could not find uuid for this name; either the user does not exist or there was \
+                 an error communicating with the auth server."
+            );
+            None
+        },
+    }
+}
+
+/// If successful returns the Some(uuid) of the user removed from the group
+///
+/// NOTE: Do *not* allow this to be called from any command that doesn't go
+/// through the CLI!
+#[must_use]
+pub fn unassign_permission_group(
+    username: &str,
+    group: &str,
+    login_provider: &LoginProvider,
+    editable_settings: &mut EditableSettings,
+    data_dir: &std::path::Path,
+) -> Option<common::uuid::Uuid> {
+    use crate::settings::EditableSetting;
+    match login_provider.username_to_uuid(username) {
+        Ok(uuid) => handle_edit(
+            uuid,
+            editable_settings
+                .permission_groups
+                .edit(data_dir, |permission_groups| {
+                    let membership = permission_groups.members.get_mut(&uuid);
+                    if membership.map_or(false, |membership| membership.groups.remove(group)) {
+                        // Don't keep users around once they are in no group.
+                        permission_groups
+                            .members
+                            .retain(|_, membership| !membership.groups.is_empty());
+                        Some(format!(
+                            "Successfully removed {} ({}) from the permission group {}",
+                            username, uuid, group
+                        ))
+                    } else {
+                        info!("{} ({}) is not in the group {}!", username, uuid, group);
+                        None
+                    }
+                }),
+        ),
+        Err(err) => {
+            error!(
+                ?err,
+                "Could not find uuid for this name; either the user does not exist or there was \
+                 an error communicating with the auth server."
+            );
+            None
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::can_use_command;
+    use common::comp::{AdminRole, Permissions};
+
+    #[test]
+    fn command_permissions() {
+        let permissions = Permissions {
+            commands: std::iter::once("tp".to_owned()).collect(),
+            ..Default::default()
+        };
+        let mod_cmd = Some(AdminRole::Moderator);
+        let admin_cmd = Some(AdminRole::Admin);
+
+        assert!(can_use_command(None, None, "help", None));
+        assert!(!can_use_command(None, None, "tp", mod_cmd));
+        assert!(can_use_command(None, Some(&permissions), "tp", mod_cmd));
+        assert!(can_use_command(None, Some(&permissions), "tp", admin_cmd));
+        assert!(!can_use_command(None, Some(&permissions), "kick", mod_cmd));
+
+        assert!(can_use_command(mod_cmd, None, "kick", mod_cmd));
+        assert!(!can_use_command(mod_cmd, None, "ban", admin_cmd));
+        assert!(can_use_command(
+            mod_cmd,
+            Some(&permissions),
+            "tp",
+            admin_cmd
+        ));
+        assert!(can_use_command(admin_cmd, None, "ban", admin_cmd));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::settings::{AdminRecord, BanEntry, WhitelistRecord};
+use crate::settings::{AdminRecord, Banlist, WhitelistRecord};
 use authc::{AuthClient, AuthClientError, AuthToken, Uuid};
 use chrono::Utc;
 use common::comp::AdminRole;
@@ -6,7 +6,7 @@ use common_net::msg::RegisterError;
 use hashbrown::HashMap;
 use specs::Component;
 use specs_idvs::IdvStorage;
-use std::{str::FromStr, sync::Arc};
+use std::{net::IpAddr, str::FromStr, sync::Arc};
 use tokio::{runtime::Runtime, sync::oneshot};
 use tracing::{error, info};
 
@@ -105,38 +105,16 @@ impl LoginProvider {
         #[cfg(feature = "plugins")] plugin_manager: &PluginMgr,
         admins: &HashMap<Uuid, AdminRecord>,
         whitelist: &HashMap<Uuid, WhitelistRecord>,
-        banlist: &HashMap<Uuid, BanEntry>,
+        banlist: &Banlist,
+        ip: Option<IpAddr>,
     ) -> Option<Result<(String, Uuid), RegisterError>> {
         match pending.pending_r.try_recv() {
             Ok(Err(e)) => Some(Err(e)),
             Ok(Ok((username, uuid))) => {
-                let now = Utc::now();
-                // Hardcoded admins can always log in.
-                let admin = admins.get(&uuid);
-                if let Some(ban) = banlist
-                    .get(&uuid)
-                    .and_then(|ban_record| ban_record.current.action.ban())
-                {
-                    // Make sure the ban is active, and that we can't override it.
-                    //
-                    // If we are an admin and our role is at least as high as the role of the
-                    // person who banned us, we can override the ban; we negate this to find
-                    // people who cannot override it.
-                    let exceeds_ban_role = |admin: &AdminRecord| {
-                        Into::<AdminRole>::into(admin.role)
-                            >= Into::<AdminRole>::into(ban.performed_by_role())
-                    };
-                    if !ban.is_expired(now) && !admin.map_or(false, exceeds_ban_role) {
-                        // Pull reason string out of ban record and send a copy of it
-                        return Some(Err(RegisterError::Banned(ban.reason.clone())));
-                    }
-                }
-
-                // non-admins can only join if the whitelist is empty (everyone can join)
-                // or their name is in the whitelist.
-                if admin.is_none() && !whitelist.is_empty() && !whitelist.contains_key(&uuid) {
-                    return Some(Err(RegisterError::NotOnWhitelist));
+                if let Err(e) = Self::check_uuid(uuid, ip, admins, whitelist, banlist) {
+                    return Some(Err(e));
                 }
+                let admin = admins.get(&uuid);
 
                 #[cfg(feature = "plugins")]
                 {
@@ -174,6 +152,49 @@ impl LoginProvider {
         }
     }
 
+    /// Checks whether the user may play here, according to their bans, the
+    /// bans of the address they connect from and the whitelist.
+    pub fn check_uuid(
+        uuid: Uuid,
+        ip: Option<IpAddr>,
+        admins: &HashMap<Uuid, AdminRecord>,
+        whitelist: &HashMap<Uuid, WhitelistRecord>,
+        banlist: &Banlist,
+    ) -> Result<(), RegisterError> {
+        let now = Utc::now();
+        // Hardcoded admins can always log in.
+        let admin = admins.get(&uuid);
+        // Bans of the user, then of the address they connect from
+        let bans = banlist
+            .uuid_bans()
+            .get(&uuid)
+            .and_then(|ban_record| ban_record.current.action.ban())
+            .into_iter()
+            .chain(ip.into_iter().flat_map(|ip| banlist.bans_of_ip(ip)));
+        for ban in bans {
+            // Make sure the ban is active, and that we can't override it.
+            //
+            // If we are an admin and our role is at least as high as the role of the
+            // person who banned us, we can override the ban; we negate this to find
+            // people who cannot override it.
+            let exceeds_ban_role = |admin: &AdminRecord| {
+                Into::<AdminRole>::into(admin.role)
+                    >= Into::<AdminRole>::into(ban.performed_by_role())
+            };
+            if !ban.is_expired(now) && !admin.map_or(false, exceeds_ban_role) {
+                // Pull reason string out of ban record and send a copy of it
+                return Err(RegisterError::Banned(ban.reason.clone()));
+            }
+        }
+
+        // non-admins can only join if the whitelist is empty (everyone can join)
+        // or their name is in the whitelist.
+        if admin.is_none() && !whitelist.is_empty() && !whitelist.contains_key(&uuid) {
+            return Err(RegisterError::NotOnWhitelist);
+        }
+        Ok(())
+    }
+
     async fn query(
         srv: Arc<AuthClient>,
         username_or_token: &str,
@@ -28,6 +28,12 @@ pub struct PlayerMetrics {
     pub clients_connected: IntCounter,
     pub players_connected: IntCounter,
     pub clients_disconnected: IntCounterVec, // timeout, network_error, gracefully
+    pub chat_messages_dropped: IntCounterVec, // too_fast, repeated, muted
+}
+
+pub struct PluginMetrics {
+    pub limits_exceeded: IntCounterVec, // plugin, limit
+    pub plugins_disabled: IntGauge,
 }
 
 pub struct NetworkRequestMetrics {
@@ -167,15 +173,48 @@ impl PlayerMetrics {
             ),
             &["reason"],
         )?;
+        let chat_messages_dropped = IntCounterVec::new(
+            Opts::new(
+                "chat_messages_dropped",
+                "shows the number of chat messages dropped by the spam protection and the reason",
+            ),
+            &["reason"],
+        )?;
 
         registry.register(Box::new(clients_connected.clone()))?;
         registry.register(Box::new(players_connected.clone()))?;
         registry.register(Box::new(clients_disconnected.clone()))?;
+        registry.register(Box::new(chat_messages_dropped.clone()))?;
 
         Ok(Self {
             clients_connected,
             players_connected,
             clients_disconnected,
+            chat_messages_dropped,
+        })
+    }
+}
+
+impl PluginMetrics {
+    pub fn new(registry: &Registry) -> Result<Self, prometheus::Error> {
+        let limits_exceeded = IntCounterVec::new(
+            Opts::new(
+                "plugin_limits_exceeded",
+                "shows the number of times each plugin exceeded its fuel or memory budget",
+            ),
+            &["plugin", "limit"],
+        )?;
+        let plugins_disabled = IntGauge::with_opts(Opts::new(
+            "plugins_disabled",
+            "shows the number of plugins disabled for exceeding their budget too many times",
+        ))?;
+
+        registry.register(Box::new(limits_exceeded.clone()))?;
+        registry.register(Box::new(plugins_disabled.clone()))?;
+
+        Ok(Self {
+            limits_exceeded,
+            plugins_disabled,
         })
     }
 }
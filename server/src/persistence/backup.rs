@@ -0,0 +1,427 @@
+//! Online backups of the character database, using the SQLite backup API so
+//! that the server can keep running while they are made.
+
+use super::{error::PersistenceError, ConnectionMode, DatabaseSettings};
+use crate::settings::DatabaseBackupSettings;
+use chrono::Local;
+use common::slowjob::SlowJobPool;
+use rusqlite::{ffi, Connection, DatabaseName, ErrorCode, OpenFlags, NO_PARAMS};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tracing::{error, info, warn};
+
+const BACKUP_DIR: &str = "backups";
+/// Prefix of the backups subject to the retention settings
+const BACKUP_PREFIX: &str = "db_";
+/// Prefix of the copies of the database made before restoring a backup, which
+/// are never deleted automatically
+const PRE_RESTORE_PREFIX: &str = "pre_restore_";
+const BACKUP_EXTENSION: &str = "sqlite";
+
+pub fn backup_dir(settings: &DatabaseSettings) -> PathBuf { settings.db_dir.join(BACKUP_DIR) }
+
+/// Makes the database backups at the interval from the settings.
+pub struct DatabaseBackups {
+    database_settings: Arc<RwLock<DatabaseSettings>>,
+    settings: DatabaseBackupSettings,
+    last_backup: f64,
+}
+
+impl DatabaseBackups {
+    pub fn new(
+        database_settings: Arc<RwLock<DatabaseSettings>>,
+        settings: DatabaseBackupSettings,
+    ) -> Self {
+        Self {
+            database_settings,
+            settings,
+            last_backup: 0.0,
+        }
+    }
+
+    /// Makes a backup in the background once the backup interval elapsed.
+    pub fn maintain(&mut self, time: f64, slow_jobs: &SlowJobPool) {
+        let interval = match self.settings.interval {
+            Some(interval) => interval.as_secs_f64(),
+            None => return,
+        };
+        if time - self.last_backup < interval {
+            return;
+        }
+        self.last_backup = time;
+        self.backup_now(slow_jobs);
+    }
+
+    /// Makes a backup in the background right away.
+    pub fn backup_now(&self, slow_jobs: &SlowJobPool) {
+        let database_settings = self
+            .database_settings
+            .read()
+            .expect("DatabaseSettings RwLock was poisoned")
+            .clone();
+        let retention = self.settings.retention;
+        slow_jobs.spawn("DB_BACKUP", move || {
+            if let Err(e) = backup(&database_settings, retention) {
+                error!(?e, "Failed to back up the database");
+            }
+        });
+    }
+}
+
+/// Backs the database up in the backup directory, then deletes the oldest
+/// backups so that at most `retention` of them are kept, if it isn't 0.
+pub fn backup(settings: &DatabaseSettings, retention: usize) -> Result<PathBuf, PersistenceError> {
+    let path = backup_to(settings, BACKUP_PREFIX)?;
+    if retention > 0 {
+        prune_backups(settings, retention)?;
+    }
+    Ok(path)
+}
+
+fn backup_to(settings: &DatabaseSettings, prefix: &str) -> Result<PathBuf, PersistenceError> {
+    if !settings.db_dir.join("db.sqlite").exists() {
+        return Err(PersistenceError::OtherError(
+            "There is no database to back up".to_owned(),
+        ));
+    }
+    let connection = super::establish_connection(settings, ConnectionMode::ReadOnly);
+    backup_with(&connection, settings, prefix)
+}
+
+fn backup_with(
+    connection: &Connection,
+    settings: &DatabaseSettings,
+    prefix: &str,
+) -> Result<PathBuf, PersistenceError> {
+    let dir = backup_dir(settings);
+    fs::create_dir_all(&dir).map_err(|e| {
+        PersistenceError::OtherError(format!("Failed to create {}: {}", dir.display(), e))
+    })?;
+    let path = new_backup_path(&dir, prefix);
+    // Back up to a temporary file first, so that an interrupted backup is never
+    // mistaken for a complete one.
+    let partial_path = path.with_extension("partial");
+
+    connection.backup(DatabaseName::Main, &partial_path, None)?;
+    fs::rename(&partial_path, &path).map_err(|e| {
+        PersistenceError::OtherError(format!(
+            "Failed to move the backup to {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+
+    info!("Backed up the database to {}", path.display());
+    Ok(path)
+}
+
+/// Path for a new backup named after the current time, which sorts after the
+/// existing backups and doesn't replace any of them.
+fn new_backup_path(dir: &Path, prefix: &str) -> PathBuf {
+    let name = format!("{}{}", prefix, Local::now().format("%Y%m%d_%H%M%S_%3f"));
+    let mut path = dir.join(format!("{}.{}", name, BACKUP_EXTENSION));
+    let mut suffix = 1;
+    while path.exists() || path.with_extension("partial").exists() {
+        path = dir.join(format!("{}_{}.{}", name, suffix, BACKUP_EXTENSION));
+        suffix += 1;
+    }
+    path
+}
+
+fn prune_backups(settings: &DatabaseSettings, retention: usize) -> Result<(), PersistenceError> {
+    let mut backups = list_backups(settings)?
+        .into_iter()
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| name.starts_with(BACKUP_PREFIX))
+        })
+        .collect::<Vec<_>>();
+    // The names contain the date, so the oldest backups come first.
+    backups.sort();
+    let excess = backups.len().saturating_sub(retention);
+    for path in &backups[..excess] {
+        match fs::remove_file(path) {
+            Ok(()) => info!("Deleted the old database backup {}", path.display()),
+            Err(e) => warn!(
+                ?e,
+                "Failed to delete the old database backup {}",
+                path.display()
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Lists the backups in the backup directory.
+pub fn list_backups(settings: &DatabaseSettings) -> Result<Vec<PathBuf>, PersistenceError> {
+    let dir = backup_dir(settings);
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(PersistenceError::OtherError(format!(
+                "Failed to read {}: {}",
+                dir.display(),
+                e
+            )));
+        },
+    };
+    Ok(entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .map_or(false, |extension| extension == BACKUP_EXTENSION)
+        })
+        .collect())
+}
+
+/// Replaces the database with the backup, after checking that it is intact
+/// and that its schema is not newer than this server knows of.  Older backups
+/// are migrated when the server starts.  The current database, if any, is
+/// backed up first, and the path of that copy is returned.
+///
+/// Fails if the database is in use, e.g. by a running server.
+pub fn restore(
+    settings: &DatabaseSettings,
+    backup: &Path,
+) -> Result<Option<PathBuf>, PersistenceError> {
+    let backup_version = validate_backup(backup)?;
+    let current_version = super::latest_migration_version();
+    if backup_version > current_version {
+        return Err(PersistenceError::OtherError(format!(
+            "The backup is at migration version {}, but this server only knows up to version {}",
+            backup_version, current_version
+        )));
+    }
+
+    let db_path = settings.db_dir.join("db.sqlite");
+    // Held until the backup is swapped in, so that the database can't be opened
+    // meanwhile.
+    let lock = if db_path.exists() {
+        Some(lock_database(&db_path)?)
+    } else {
+        None
+    };
+    let pre_restore = match &lock {
+        Some(connection) => Some(backup_with(connection, settings, PRE_RESTORE_PREFIX)?),
+        None => None,
+    };
+
+    // Copy next to the database first, so that the database is swapped in a
+    // single rename.
+    let restoring_path = db_path.with_extension("restoring");
+    fs::copy(backup, &restoring_path).map_err(|e| {
+        PersistenceError::OtherError(format!("Failed to copy {}: {}", backup.display(), e))
+    })?;
+    // The write-ahead log of the replaced database must not be applied to the
+    // restored one. Leaving WAL mode moves it into the replaced database and
+    // deletes it, leftovers of a database which is gone are deleted here.
+    if let Some(connection) = &lock {
+        connection.pragma_update(None, "journal_mode", &"DELETE")?;
+    }
+    for suffix in &["-wal", "-shm"] {
+        let path = settings.db_dir.join(format!("db.sqlite{}", suffix));
+        if let Err(e) = fs::remove_file(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(PersistenceError::OtherError(format!(
+                    "Failed to remove {}: {}",
+                    path.display(),
+                    e
+                )));
+            }
+        }
+    }
+    fs::rename(&restoring_path, &db_path).map_err(|e| {
+        PersistenceError::OtherError(format!("Failed to replace {}: {}", db_path.display(), e))
+    })?;
+    drop(lock);
+
+    info!(
+        "Restored the database from {} at migration version {}",
+        backup.display(),
+        backup_version
+    );
+    Ok(pre_restore)
+}
+
+/// Opens the database with an exclusive lock, which fails while it is open
+/// anywhere else and keeps it from being opened until the connection is closed.
+fn lock_database(db_path: &Path) -> Result<Connection, PersistenceError> {
+    let connection = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_WRITE)
+        .map_err(PersistenceError::DatabaseConnectionError)?;
+    connection.busy_timeout(Duration::from_millis(250))?;
+    connection.pragma_update(None, "locking_mode", &"EXCLUSIVE")?;
+    // The lock is only taken on the first access and then kept.
+    connection
+        .execute_batch("BEGIN EXCLUSIVE; COMMIT;")
+        .map_err(|e| match e {
+            rusqlite::Error::SqliteFailure(
+                ffi::Error {
+                    code: ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked,
+                    ..
+                },
+                _,
+            ) => PersistenceError::OtherError(
+                "The database is in use, stop the server before restoring a backup".to_owned(),
+            ),
+            e => PersistenceError::DatabaseError(e),
+        })?;
+    Ok(connection)
+}
+
+/// Checks the integrity of the backup, returning its migration version.
+fn validate_backup(backup: &Path) -> Result<i64, PersistenceError> {
+    let connection = Connection::open_with_flags(backup, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(PersistenceError::DatabaseConnectionError)?;
+
+    let integrity: String =
+        connection.query_row("PRAGMA integrity_check", NO_PARAMS, |row| row.get(0))?;
+    if integrity != "ok" {
+        return Err(PersistenceError::OtherError(format!(
+            "The backup is corrupted: {}",
+            integrity
+        )));
+    }
+
+    super::applied_migration_version(&connection)
+        .ok()
+        .flatten()
+        .ok_or_else(|| {
+            PersistenceError::OtherError("The backup has no migration history".to_owned())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::{run_migrations, SqlLogMode};
+
+    fn settings(name: &str) -> DatabaseSettings {
+        let db_dir =
+            std::env::temp_dir().join(format!("veloren-backup-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&db_dir);
+        let settings = DatabaseSettings {
+            db_dir,
+            sql_log_mode: SqlLogMode::Disabled,
+        };
+        run_migrations(&settings);
+        settings
+    }
+
+    fn set_marker(path: &Path, marker: i64) {
+        let connection = Connection::open(path).unwrap();
+        connection
+            .execute_batch(&format!(
+                "CREATE TABLE IF NOT EXISTS marker (value INTEGER);
+                 DELETE FROM marker;
+                 INSERT INTO marker VALUES ({});",
+                marker
+            ))
+            .unwrap();
+    }
+
+    fn marker(path: &Path) -> i64 {
+        let connection = Connection::open(path).unwrap();
+        connection
+            .query_row("SELECT value FROM marker", NO_PARAMS, |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn validate_backups() {
+        let settings = settings("validate");
+        let backup = backup(&settings, 0).unwrap();
+        assert_eq!(
+            validate_backup(&backup).unwrap(),
+            super::super::latest_migration_version()
+        );
+
+        let unmigrated = settings.db_dir.join("unmigrated.sqlite");
+        set_marker(&unmigrated, 1);
+        assert!(validate_backup(&unmigrated).is_err());
+
+        let garbage = settings.db_dir.join("garbage.sqlite");
+        fs::write(&garbage, b"not a database").unwrap();
+        assert!(validate_backup(&garbage).is_err());
+    }
+
+    #[test]
+    fn backups_made_at_once_are_kept() {
+        let settings = settings("names");
+        let first = backup(&settings, 0).unwrap();
+        let second = backup(&settings, 0).unwrap();
+        assert_ne!(first, second);
+        assert!(first.exists() && second.exists());
+        assert_eq!(list_backups(&settings).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn restore_refuses_newer_backups() {
+        let settings = settings("newer");
+        let db_path = settings.db_dir.join("db.sqlite");
+        let backup = backup(&settings, 0).unwrap();
+        Connection::open(&backup)
+            .unwrap()
+            .execute(
+                "INSERT INTO refinery_schema_history (version, name, applied_on, checksum) VALUES \
+                 (?1, 'future', '', '')",
+                &[super::super::latest_migration_version() + 1],
+            )
+            .unwrap();
+        set_marker(&db_path, 1);
+
+        assert!(restore(&settings, &backup).is_err());
+        assert_eq!(marker(&db_path), 1);
+    }
+
+    #[test]
+    fn restore_refuses_open_databases() {
+        let settings = settings("open");
+        let db_path = settings.db_dir.join("db.sqlite");
+        set_marker(&db_path, 1);
+        let backup = backup(&settings, 0).unwrap();
+        set_marker(&db_path, 2);
+
+        let connection = super::super::establish_connection(&settings, ConnectionMode::ReadOnly);
+        assert!(restore(&settings, &backup).is_err());
+        assert_eq!(marker(&db_path), 2);
+        drop(connection);
+
+        assert!(restore(&settings, &backup).is_ok());
+        assert_eq!(marker(&db_path), 1);
+    }
+
+    #[test]
+    fn restore_swaps_the_write_ahead_log() {
+        let settings = settings("wal");
+        let db_path = settings.db_dir.join("db.sqlite");
+        let wal_path = settings.db_dir.join("db.sqlite-wal");
+        set_marker(&db_path, 1);
+        let backup = backup(&settings, 0).unwrap();
+
+        // Leave a write-ahead log with a change behind, like a crashed server
+        let connection = super::super::establish_connection(&settings, ConnectionMode::ReadWrite);
+        connection
+            .pragma_update(None, "wal_autocheckpoint", &0)
+            .unwrap();
+        connection
+            .execute("UPDATE marker SET value = 2", NO_PARAMS)
+            .unwrap();
+        let stale_wal = settings.db_dir.join("stale-wal");
+        fs::copy(&wal_path, &stale_wal).unwrap();
+        drop(connection);
+        fs::copy(&backup, &db_path).unwrap();
+        fs::copy(&stale_wal, &wal_path).unwrap();
+
+        let pre_restore = restore(&settings, &backup).unwrap().unwrap();
+        assert!(!wal_path.exists());
+        assert!(!settings.db_dir.join("db.sqlite-shm").exists());
+        assert_eq!(marker(&pre_restore), 2);
+        assert_eq!(marker(&db_path), 1);
+    }
+}
@@ -35,6 +35,8 @@ use tracing::{debug, error, trace, warn};
 /// general, these have many invariants that need to be maintained when they're
 /// called--do not assume it's safe to make these public!
 mod conversions;
+/// Export and import of characters between servers
+pub mod export;
 
 pub(crate) type EntityId = i64;
 
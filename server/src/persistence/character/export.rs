@@ -0,0 +1,460 @@
+//! Export of characters to self-describing JSON files, so that they can be
+//! moved to the database of another server.
+//!
+//! The exported data is in the format used by the database, which the
+//! conversions to and from components already know how to handle; importing
+//! a character converts it back to components, so that it is validated
+//! against the assets of the importing server before being stored.
+
+use super::{
+    conversions::{
+        convert_active_abilities_from_database, convert_active_abilities_to_database,
+        convert_body_from_database, convert_body_to_database_json,
+        convert_inventory_from_database_items, convert_skill_groups_to_database,
+        convert_skill_set_from_database, convert_stats_from_database,
+        convert_waypoint_from_database_json, convert_waypoint_to_database_json,
+    },
+    create_character, get_pseudo_containers, load_character_data, load_character_list, load_items,
+    update_pets,
+};
+use crate::{
+    alias_validator::AliasValidator,
+    persistence::{
+        applied_migration_version, character_updater::PetPersistenceData, error::PersistenceError,
+        establish_connection, models, ConnectionMode, DatabaseSettings, PersistedComponents,
+    },
+};
+use chrono::{DateTime, Utc};
+use common::{character::CharacterId, comp};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{fs, path::Path};
+use tracing::info;
+
+/// Versioned export files, one per version.
+#[derive(Deserialize, Serialize)]
+pub enum CharacterExportRaw {
+    V1(CharacterExport),
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct CharacterExport {
+    /// Version of the game which exported the character
+    pub game_version: String,
+    /// Latest migration applied to the database the character was exported
+    /// from, the character can't be imported in an older database
+    pub migration_version: i64,
+    pub exported_at: DateTime<Utc>,
+    pub alias: String,
+    pub body: ExportedBody,
+    pub waypoint: Option<Value>,
+    pub skill_groups: Vec<ExportedSkillGroup>,
+    pub ability_sets: Value,
+    /// Items are linked to their parent by id, the items directly in the
+    /// inventory or the loadout having the id of the inventory or loadout as
+    /// parent
+    pub inventory_id: i64,
+    pub inventory: Vec<ExportedItem>,
+    pub loadout_id: i64,
+    pub loadout: Vec<ExportedItem>,
+    pub pets: Vec<ExportedPet>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ExportedBody {
+    pub variant: String,
+    pub data: Value,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ExportedSkillGroup {
+    pub kind: String,
+    pub earned_exp: i64,
+    pub spent_exp: i64,
+    pub skills: Value,
+    /// Hash of the skill group definition, the skills are refunded if it
+    /// changed
+    pub hash: Vec<u8>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ExportedItem {
+    pub id: i64,
+    pub parent_id: i64,
+    pub definition_id: String,
+    pub stack_size: i32,
+    pub position: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ExportedPet {
+    pub name: String,
+    pub body: ExportedBody,
+}
+
+impl CharacterExport {
+    pub fn save(self, path: &Path) -> Result<(), PersistenceError> {
+        let json = serde_json::to_string_pretty(&CharacterExportRaw::V1(self))?;
+        fs::write(path, json).map_err(|e| {
+            PersistenceError::OtherError(format!("Failed to write {}: {}", path.display(), e))
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, PersistenceError> {
+        let json = fs::read_to_string(path).map_err(|e| {
+            PersistenceError::OtherError(format!("Failed to read {}: {}", path.display(), e))
+        })?;
+        Ok(match serde_json::from_str(&json)? {
+            CharacterExportRaw::V1(export) => export,
+        })
+    }
+}
+
+impl From<models::Item> for ExportedItem {
+    fn from(item: models::Item) -> Self {
+        Self {
+            id: item.item_id,
+            parent_id: item.parent_container_item_id,
+            definition_id: item.item_definition_id,
+            stack_size: item.stack_size,
+            position: item.position,
+        }
+    }
+}
+
+impl From<&ExportedItem> for models::Item {
+    fn from(item: &ExportedItem) -> Self {
+        Self {
+            item_id: item.id,
+            parent_container_item_id: item.parent_id,
+            item_definition_id: item.definition_id.clone(),
+            stack_size: item.stack_size,
+            position: item.position.clone(),
+        }
+    }
+}
+
+fn export_body(body: &comp::Body) -> Result<ExportedBody, PersistenceError> {
+    let (variant, data) = convert_body_to_database_json(body)?;
+    Ok(ExportedBody {
+        variant: variant.to_owned(),
+        data: serde_json::from_str(&data)?,
+    })
+}
+
+fn import_body(body: &ExportedBody) -> Result<comp::Body, PersistenceError> {
+    convert_body_from_database(&body.variant, &body.data.to_string())
+}
+
+/// Exports the character of the player with the given alias or id.
+pub fn export_character(
+    settings: &DatabaseSettings,
+    player_uuid: &str,
+    character: &str,
+) -> Result<CharacterExport, PersistenceError> {
+    let connection = establish_connection(settings, ConnectionMode::ReadOnly);
+    let character_id = load_character_list(player_uuid, &connection)?
+        .into_iter()
+        .filter_map(|item| item.character.id.map(|id| (id, item.character.alias)))
+        .find(|(id, alias)| id.to_string() == character || alias.eq_ignore_ascii_case(character))
+        .map(|(id, _)| id)
+        .ok_or_else(|| {
+            PersistenceError::OtherError(format!("The player has no character {}", character))
+        })?;
+
+    let PersistedComponents {
+        body,
+        stats,
+        skill_set,
+        inventory: _,
+        waypoint,
+        pets,
+        active_abilities,
+    } = load_character_data(player_uuid.to_owned(), character_id, &connection)?;
+    // The items are exported as stored, since the conversion to the database
+    // items is meant for updating the stored ones.
+    let containers = get_pseudo_containers(&connection, character_id)?;
+    let inventory = load_items(&connection, containers.inventory_container_id)?;
+    let loadout = load_items(&connection, containers.loadout_container_id)?;
+
+    let export = CharacterExport {
+        game_version: common::util::DISPLAY_VERSION_LONG.clone(),
+        migration_version: applied_migration_version(&connection)?.unwrap_or(0),
+        exported_at: Utc::now(),
+        alias: stats.name,
+        body: export_body(&body)?,
+        waypoint: convert_waypoint_to_database_json(waypoint)
+            .map(|waypoint| serde_json::from_str(&waypoint))
+            .transpose()?,
+        skill_groups: convert_skill_groups_to_database(character_id, skill_set.skill_groups())
+            .into_iter()
+            .map(|skill_group| ExportedSkillGroup {
+                kind: skill_group.skill_group_kind,
+                earned_exp: skill_group.earned_exp,
+                spent_exp: skill_group.spent_exp,
+                // An invalid value refunds the skills on import, as it would on load.
+                skills: serde_json::from_str(&skill_group.skills).unwrap_or(Value::Null),
+                hash: skill_group.hash_val,
+            })
+            .collect(),
+        ability_sets: serde_json::from_str(
+            &convert_active_abilities_to_database(character_id, &active_abilities).ability_sets,
+        )?,
+        inventory_id: containers.inventory_container_id,
+        inventory: inventory.into_iter().map(ExportedItem::from).collect(),
+        loadout_id: containers.loadout_container_id,
+        loadout: loadout.into_iter().map(ExportedItem::from).collect(),
+        pets: pets
+            .iter()
+            .map(|(_, body, stats)| {
+                Ok(ExportedPet {
+                    name: stats.name.clone(),
+                    body: export_body(body)?,
+                })
+            })
+            .collect::<Result<_, PersistenceError>>()?,
+    };
+    info!(
+        "Exported the character {} ({}) of {}",
+        export.alias, character_id, player_uuid
+    );
+    Ok(export)
+}
+
+/// Imports the character for the player, under a new alias if one is given.
+/// The alias is validated like the one of a newly created character. Returns
+/// the id of the new character.
+pub fn import_character(
+    settings: &DatabaseSettings,
+    alias_validator: &AliasValidator,
+    player_uuid: &str,
+    alias: Option<&str>,
+    export: CharacterExport,
+) -> Result<CharacterId, PersistenceError> {
+    let alias = alias.unwrap_or(&export.alias);
+    alias_validator
+        .validate(alias)
+        .map_err(|e| PersistenceError::OtherError(e.to_string()))?;
+
+    let mut connection = establish_connection(settings, ConnectionMode::ReadWrite);
+    let migration_version = applied_migration_version(&connection)?.unwrap_or(0);
+    if export.migration_version > migration_version {
+        return Err(PersistenceError::OtherError(format!(
+            "The character was exported from a database at migration version {}, but this \
+             database is at version {}",
+            export.migration_version, migration_version
+        )));
+    }
+
+    let skill_groups = export
+        .skill_groups
+        .iter()
+        .map(|skill_group| models::SkillGroup {
+            entity_id: 0,
+            skill_group_kind: skill_group.kind.clone(),
+            earned_exp: skill_group.earned_exp,
+            spent_exp: skill_group.spent_exp,
+            skills: skill_group.skills.to_string(),
+            hash_val: skill_group.hash.clone(),
+        })
+        .collect::<Vec<_>>();
+    let inventory = convert_inventory_from_database_items(
+        export.inventory_id,
+        &export
+            .inventory
+            .iter()
+            .map(models::Item::from)
+            .collect::<Vec<_>>(),
+        export.loadout_id,
+        &export
+            .loadout
+            .iter()
+            .map(models::Item::from)
+            .collect::<Vec<_>>(),
+    )?;
+    // The ids are those of the exporting database, new ones are assigned when
+    // creating the character.
+    inventory
+        .slots()
+        .filter_map(Option::as_ref)
+        .chain(
+            inventory
+                .loadout_items_with_persistence_key()
+                .filter_map(|(_, item)| item),
+        )
+        .for_each(forget_item_id);
+    let pets = export
+        .pets
+        .iter()
+        .map(|pet| {
+            Ok((
+                comp::Pet::default(),
+                import_body(&pet.body)?,
+                comp::Stats::new(pet.name.clone()),
+            ))
+        })
+        .collect::<Result<Vec<PetPersistenceData>, PersistenceError>>()?;
+
+    let persisted_components = PersistedComponents {
+        body: import_body(&export.body)?,
+        stats: convert_stats_from_database(alias.to_owned()),
+        skill_set: convert_skill_set_from_database(&skill_groups),
+        inventory,
+        waypoint: export
+            .waypoint
+            .map(|waypoint| convert_waypoint_from_database_json(&waypoint.to_string()))
+            .transpose()?,
+        pets: Vec::new(),
+        active_abilities: convert_active_abilities_from_database(&models::AbilitySets {
+            entity_id: 0,
+            ability_sets: export.ability_sets.to_string(),
+        }),
+    };
+
+    let mut transaction = connection.connection.transaction()?;
+    let (character_id, _) =
+        create_character(player_uuid, alias, persisted_components, &mut transaction)?;
+    update_pets(character_id, pets, &mut transaction)?;
+    transaction.commit()?;
+
+    info!(
+        "Imported the character {} ({}) for {}",
+        alias, character_id, player_uuid
+    );
+    Ok(character_id)
+}
+
+fn forget_item_id(item: &comp::Item) {
+    item.get_item_id_for_database().store(None);
+    item.components().iter().for_each(forget_item_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::{run_migrations, SqlLogMode};
+    use common::comp::{
+        humanoid, inventory::loadout_builder::LoadoutBuilder, Inventory, Item, SkillSet, Stats,
+    };
+
+    fn settings(name: &str) -> DatabaseSettings {
+        let db_dir =
+            std::env::temp_dir().join(format!("veloren-export-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&db_dir);
+        let settings = DatabaseSettings {
+            db_dir,
+            sql_log_mode: SqlLogMode::Disabled,
+        };
+        run_migrations(&settings);
+        settings
+    }
+
+    fn create(settings: &DatabaseSettings, player_uuid: &str, alias: &str) -> CharacterId {
+        let loadout = LoadoutBuilder::empty()
+            .defaults()
+            .active_mainhand(Some(Item::new_from_asset_expect(
+                "common.items.weapons.sword.starter",
+            )))
+            .build();
+        let mut inventory = Inventory::new_with_loadout(loadout);
+        inventory
+            .push(Item::new_from_asset_expect("common.items.food.cheese"))
+            .unwrap();
+        let mut connection = establish_connection(settings, ConnectionMode::ReadWrite);
+        let mut transaction = connection.connection.transaction().unwrap();
+        let (character_id, _) = create_character(
+            player_uuid,
+            alias,
+            PersistedComponents {
+                body: comp::Body::Humanoid(humanoid::Body::random()),
+                stats: Stats::new(alias.to_owned()),
+                skill_set: SkillSet::default(),
+                inventory,
+                waypoint: None,
+                pets: Vec::new(),
+                active_abilities: Default::default(),
+            },
+            &mut transaction,
+        )
+        .unwrap();
+        transaction.commit().unwrap();
+        character_id
+    }
+
+    fn item_definitions(inventory: &Inventory) -> Vec<String> {
+        let mut items = inventory
+            .slots()
+            .filter_map(Option::as_ref)
+            .chain(
+                inventory
+                    .loadout_items_with_persistence_key()
+                    .filter_map(|(_, item)| item),
+            )
+            .map(|item| item.item_definition_id().to_owned())
+            .collect::<Vec<_>>();
+        items.sort();
+        items
+    }
+
+    #[test]
+    fn export_and_import() {
+        let settings = settings("round-trip");
+        let character_id = create(&settings, "exporter", "Exported");
+        let path = settings.db_dir.join("character.json");
+        export_character(&settings, "exporter", "exported")
+            .unwrap()
+            .save(&path)
+            .unwrap();
+
+        let export = CharacterExport::load(&path).unwrap();
+        let imported_id = import_character(
+            &settings,
+            &AliasValidator::default(),
+            "importer",
+            None,
+            export,
+        )
+        .unwrap();
+        assert_ne!(imported_id, character_id);
+
+        let connection = establish_connection(&settings, ConnectionMode::ReadOnly);
+        let original =
+            load_character_data("exporter".to_owned(), character_id, &connection).unwrap();
+        let imported =
+            load_character_data("importer".to_owned(), imported_id, &connection).unwrap();
+        assert_eq!(imported.body, original.body);
+        assert_eq!(imported.stats.name, "Exported");
+        assert_eq!(
+            item_definitions(&imported.inventory),
+            item_definitions(&original.inventory)
+        );
+        assert!(
+            item_definitions(&imported.inventory).contains(&"common.items.food.cheese".to_owned())
+        );
+    }
+
+    #[test]
+    fn import_validates_the_alias() {
+        let settings = settings("alias");
+        create(&settings, "exporter", "Exported");
+        let path = settings.db_dir.join("character.json");
+        export_character(&settings, "exporter", "Exported")
+            .unwrap()
+            .save(&path)
+            .unwrap();
+        let validator = AliasValidator::new(vec!["banned".to_owned()]);
+
+        for alias in &[Some("BannedName"), Some("Thisnameismuchtoolongtoimport")] {
+            let export = CharacterExport::load(&path).unwrap();
+            assert!(import_character(&settings, &validator, "importer", *alias, export).is_err());
+        }
+        let export = CharacterExport::load(&path).unwrap();
+        assert!(import_character(&settings, &validator, "importer", None, export).is_ok());
+
+        let connection = establish_connection(&settings, ConnectionMode::ReadOnly);
+        assert_eq!(
+            load_character_list("importer", &connection).unwrap().len(),
+            1
+        );
+    }
+}
@@ -1,12 +1,16 @@
 //! DB operations and schema migrations
 
+pub mod backup;
 pub(in crate::persistence) mod character;
+pub use character::export as character_export;
 pub mod character_loader;
 pub mod character_updater;
 mod diesel_to_rusqlite;
 pub mod error;
 mod json_models;
 mod models;
+#[cfg(feature = "plugins")]
+pub mod plugin_storage;
 
 use crate::persistence::character_updater::PetPersistenceData;
 use common::comp;
@@ -171,6 +175,27 @@ pub fn run_migrations(settings: &DatabaseSettings) {
     info!("Applied {} database migrations", applied_migrations);
 }
 
+/// The latest migration known to this server
+pub(crate) fn latest_migration_version() -> i64 {
+    embedded::migrations::runner()
+        .get_migrations()
+        .iter()
+        .map(|migration| i64::from(migration.version()))
+        .max()
+        .unwrap_or(0)
+}
+
+/// The latest migration applied to the database, if it was ever migrated
+pub(crate) fn applied_migration_version(
+    connection: &Connection,
+) -> Result<Option<i64>, rusqlite::Error> {
+    connection.query_row(
+        "SELECT MAX(version) FROM refinery_schema_history",
+        rusqlite::NO_PARAMS,
+        |row| row.get(0),
+    )
+}
+
 // These callbacks use info logging because they are never enabled by default,
 // only when explicitly turned on via CLI arguments or interactive CLI commands.
 // Setting them to anything other than info would remove the ability to get SQL
@@ -0,0 +1,184 @@
+//! Persistence of the key-value storage of plugins, see [`PluginStorage`]
+
+use crate::persistence::{
+    error::PersistenceError, establish_connection, ConnectionMode, DatabaseSettings,
+    VelorenConnection,
+};
+use common_state::plugin::storage::PluginStorage;
+use rusqlite::{params, DropBehavior, NO_PARAMS};
+use std::sync::{Arc, RwLock};
+use tracing::{error, trace};
+
+pub enum PluginStorageUpdate {
+    Set {
+        plugin: String,
+        key: String,
+        value: Vec<u8>,
+    },
+    Remove {
+        plugin: String,
+        key: String,
+    },
+}
+
+/// Loads the data stored by every plugin, this is executed during server
+/// startup.
+pub fn load(settings: &DatabaseSettings) -> Result<PluginStorage, PersistenceError> {
+    let conn = establish_connection(settings, ConnectionMode::ReadOnly);
+    let mut stmt = conn.prepare_cached(
+        "
+        SELECT  plugin,
+                key,
+                value
+        FROM    plugin_storage",
+    )?;
+
+    let mut storage = PluginStorage::default();
+    for row in stmt.query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))? {
+        let (plugin, key, value): (String, String, Vec<u8>) = row?;
+        storage.set(&plugin, key, value);
+    }
+
+    Ok(storage)
+}
+
+/// A unidirectional messaging resource for saving the changes of the plugin
+/// storage in a background thread.
+pub struct PluginStorageUpdater {
+    update_tx: Option<crossbeam_channel::Sender<PluginStorageUpdate>>,
+    handle: Option<std::thread::JoinHandle<()>>,
+}
+
+impl PluginStorageUpdater {
+    pub fn new(settings: Arc<RwLock<DatabaseSettings>>) -> Self {
+        let (update_tx, update_rx) = crossbeam_channel::unbounded::<PluginStorageUpdate>();
+
+        let builder = std::thread::Builder::new().name("plugin_storage_updater".into());
+        let handle = builder
+            .spawn(move || {
+                // Unwrap here is safe as there is no code that can panic when the write lock is
+                // taken that could cause the RwLock to become poisoned.
+                let mut conn =
+                    establish_connection(&*settings.read().unwrap(), ConnectionMode::ReadWrite);
+                while let Ok(update) = update_rx.recv() {
+                    // Write every update that is already waiting in the same transaction
+                    let updates = std::iter::once(update)
+                        .chain(update_rx.try_iter())
+                        .collect::<Vec<_>>();
+                    conn.update_log_mode(&settings);
+                    if let Err(e) = execute_updates(updates, &mut conn) {
+                        error!(?e, "Error during plugin storage update");
+                    }
+                }
+            })
+            .unwrap();
+
+        Self {
+            update_tx: Some(update_tx),
+            handle: Some(handle),
+        }
+    }
+
+    pub fn update(&self, update: PluginStorageUpdate) {
+        if let Err(e) = self.update_tx.as_ref().unwrap().send(update) {
+            error!(?e, "Could not send plugin storage update");
+        }
+    }
+}
+
+impl Drop for PluginStorageUpdater {
+    fn drop(&mut self) {
+        drop(self.update_tx.take());
+        if let Err(e) = self.handle.take().unwrap().join() {
+            error!(?e, "Error from joining plugin storage update thread");
+        }
+    }
+}
+
+fn execute_updates(
+    updates: Vec<PluginStorageUpdate>,
+    connection: &mut VelorenConnection,
+) -> Result<(), PersistenceError> {
+    let mut transaction = connection.connection.transaction()?;
+    transaction.set_drop_behavior(DropBehavior::Rollback);
+    trace!("Transaction started for plugin storage update");
+
+    for update in updates {
+        match update {
+            PluginStorageUpdate::Set { plugin, key, value } => {
+                let mut stmt = transaction.prepare_cached(
+                    "
+                    REPLACE
+                    INTO    plugin_storage (plugin,
+                                            key,
+                                            value)
+                    VALUES  (?1, ?2, ?3)",
+                )?;
+                stmt.execute(params![plugin, key, value])?;
+            },
+            PluginStorageUpdate::Remove { plugin, key } => {
+                let mut stmt = transaction.prepare_cached(
+                    "
+                    DELETE
+                    FROM    plugin_storage
+                    WHERE   plugin = ?1
+                    AND     key = ?2",
+                )?;
+                stmt.execute(params![plugin, key])?;
+            },
+        }
+    }
+
+    transaction.commit()?;
+    trace!("Commit for plugin storage update completed");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::{run_migrations, SqlLogMode};
+
+    fn set(plugin: &str, key: &str, value: &[u8]) -> PluginStorageUpdate {
+        PluginStorageUpdate::Set {
+            plugin: plugin.to_owned(),
+            key: key.to_owned(),
+            value: value.to_vec(),
+        }
+    }
+
+    #[test]
+    fn updates_are_loaded_back() {
+        let db_dir =
+            std::env::temp_dir().join(format!("veloren-plugin-storage-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&db_dir);
+        let settings = DatabaseSettings {
+            db_dir,
+            sql_log_mode: SqlLogMode::Disabled,
+        };
+        run_migrations(&settings);
+
+        let mut connection = establish_connection(&settings, ConnectionMode::ReadWrite);
+        execute_updates(
+            vec![
+                set("a", "score", b"1"),
+                set("a", "name", b"old"),
+                set("b", "score", b"2"),
+                PluginStorageUpdate::Remove {
+                    plugin: "a".to_owned(),
+                    key: "name".to_owned(),
+                },
+                set("a", "score", b"10"),
+            ],
+            &mut connection,
+        )
+        .unwrap();
+        drop(connection);
+
+        let storage = load(&settings).unwrap();
+        assert_eq!(storage.get("a", "score"), Some(&b"10"[..]));
+        assert_eq!(storage.get("a", "name"), None);
+        assert_eq!(storage.get("b", "score"), Some(&b"2"[..]));
+        assert_eq!(storage.usage("a"), (1, "score".len() + 2));
+    }
+}
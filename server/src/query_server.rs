@@ -0,0 +1,325 @@
+//! Answers status queries of server browsers over UDP, see
+//! [`common_net::msg::query`].
+
+use crate::{settings::QuerySettings, Settings};
+use common::comp::Player;
+use common_net::msg::query::{
+    decode_query_packet, encode_query_packet, QueryRequest, QueryResponse, ServerStatus,
+    MAX_QUERY_PACKET_SIZE, QUERY_VERSION,
+};
+use hashbrown::HashMap;
+use specs::{Join, World, WorldExt};
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash, Hasher},
+    net::IpAddr,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+use tokio::{net::UdpSocket, runtime::Runtime, task::JoinHandle};
+use tracing::{debug, error, info};
+use vek::*;
+
+/// Time between two updates of the answered status, in seconds
+const STATUS_UPDATE_INTERVAL: f64 = 1.0;
+/// Addresses the rate limiter keeps track of at once, queries from others are
+/// ignored until some are back within the limits
+const MAX_TRACKED_ADDRESSES: usize = 4096;
+/// Time a challenge is accepted for at least, it is valid for up to twice as
+/// long
+const CHALLENGE_LIFETIME: Duration = Duration::from_secs(30);
+
+/// Answers status queries in the background with the status it is kept
+/// updated with.
+pub struct QueryServer {
+    status: Arc<RwLock<ServerStatus>>,
+    world_size: Vec2<u32>,
+    last_update: f64,
+    task: JoinHandle<()>,
+}
+
+impl QueryServer {
+    /// Starts answering queries on the address from the settings, if any.
+    pub fn start(
+        runtime: &Runtime,
+        settings: QuerySettings,
+        world_size: Vec2<u32>,
+        ecs: &World,
+    ) -> Option<Self> {
+        let address = settings.address?;
+        let socket = match runtime.block_on(UdpSocket::bind(address)) {
+            Ok(socket) => socket,
+            Err(e) => {
+                error!(?e, ?address, "Failed to bind the status query socket");
+                return None;
+            },
+        };
+        info!(?address, "Answering status queries");
+        let status = Arc::new(RwLock::new(server_status(ecs, world_size)));
+        let task = runtime.spawn(answer_queries(socket, settings, Arc::clone(&status)));
+        Some(Self {
+            status,
+            world_size,
+            last_update: 0.0,
+            task,
+        })
+    }
+
+    /// Updates the answered status once the update interval elapsed.
+    pub fn maintain(&mut self, ecs: &World, time: f64) {
+        if time - self.last_update < STATUS_UPDATE_INTERVAL {
+            return;
+        }
+        self.last_update = time;
+        let status = server_status(ecs, self.world_size);
+        *self
+            .status
+            .write()
+            .expect("Server status RwLock was poisoned") = status;
+    }
+}
+
+impl Drop for QueryServer {
+    fn drop(&mut self) { self.task.abort(); }
+}
+
+fn server_status(ecs: &World, world_size: Vec2<u32>) -> ServerStatus {
+    let settings = ecs.read_resource::<Settings>();
+    let players = (&ecs.read_storage::<Player>())
+        .join()
+        .map(|player| player.alias.clone())
+        .collect::<Vec<_>>();
+    ServerStatus {
+        info: crate::server_info(ecs),
+        players_online: players.len() as u32,
+        max_players: settings.max_players as u32,
+        world_size,
+        battle_mode: settings.battle_mode.default_mode(),
+        battle_mode_choosable: settings.battle_mode.allow_choosing(),
+        players: Some(players),
+    }
+}
+
+async fn answer_queries(
+    socket: UdpSocket,
+    settings: QuerySettings,
+    status: Arc<RwLock<ServerStatus>>,
+) {
+    let mut limiter = RateLimiter::default();
+    let challenges = Challenges::new();
+    let mut buf = vec![0; MAX_QUERY_PACKET_SIZE];
+    loop {
+        let (len, addr) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => {
+                debug!(?e, "Failed to receive status query");
+                continue;
+            },
+        };
+        let request = match decode_query_packet::<QueryRequest>(&buf[..len]) {
+            Some(request) => request,
+            None => continue,
+        };
+        let now = Instant::now();
+        if !limiter.check(&settings, addr.ip(), now) {
+            debug!(?addr, "Ignoring status query over the rate limit");
+            continue;
+        }
+        let challenge = challenges.verify(addr.ip(), request.challenge, now);
+        if let Some(packet) = answer(&request, challenge, &settings, &status) {
+            if let Err(e) = socket.send_to(&packet, addr).await {
+                debug!(?e, ?addr, "Failed to answer status query");
+            }
+        }
+    }
+}
+
+/// The answer to `request`, `challenge` being the one to send back if the
+/// request's one wasn't valid.
+fn answer(
+    request: &QueryRequest,
+    challenge: Result<(), u64>,
+    settings: &QuerySettings,
+    status: &RwLock<ServerStatus>,
+) -> Option<Vec<u8>> {
+    if request.version != QUERY_VERSION {
+        return encode_query_packet(&QueryResponse::UnsupportedVersion(QUERY_VERSION)).ok();
+    }
+    if let Err(challenge) = challenge {
+        return encode_query_packet(&QueryResponse::Challenge(challenge)).ok();
+    }
+    let mut status = status
+        .read()
+        .expect("Server status RwLock was poisoned")
+        .clone();
+    if !(request.include_players && settings.list_players) {
+        status.players = None;
+    }
+    let packet = encode_query_packet(&QueryResponse::Status(Box::new(status.clone()))).ok()?;
+    if packet.len() <= MAX_QUERY_PACKET_SIZE {
+        Some(packet)
+    } else {
+        // Too many players to list them all
+        status.players = None;
+        encode_query_packet(&QueryResponse::Status(Box::new(status))).ok()
+    }
+}
+
+/// Challenges proving that a query comes from the address it claims to, they
+/// are derived from the address with a secret key so they don't need to be
+/// stored.
+struct Challenges {
+    key: RandomState,
+    start: Instant,
+}
+
+impl Challenges {
+    fn new() -> Self {
+        Self {
+            key: RandomState::new(),
+            start: Instant::now(),
+        }
+    }
+
+    /// Checks the challenge sent by `ip`, returning the current one to send
+    /// back if it isn't valid.
+    fn verify(&self, ip: IpAddr, challenge: Option<u64>, now: Instant) -> Result<(), u64> {
+        let period = now.duration_since(self.start).as_secs() / CHALLENGE_LIFETIME.as_secs();
+        let current = self.challenge(ip, period);
+        let valid = challenge.map_or(false, |challenge| {
+            challenge == current || (period > 0 && challenge == self.challenge(ip, period - 1))
+        });
+        if valid { Ok(()) } else { Err(current) }
+    }
+
+    fn challenge(&self, ip: IpAddr, period: u64) -> u64 {
+        let mut hasher = self.key.build_hasher();
+        (ip, period).hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// Limits the queries of each ip address. Answers are larger than queries, so
+/// without a limit the server could be used to flood spoofed addresses.
+#[derive(Default)]
+struct RateLimiter {
+    /// Queries each address can send right away, refilled over time up to the
+    /// burst allowance
+    tokens: HashMap<IpAddr, (f32, Instant)>,
+}
+
+impl RateLimiter {
+    fn check(&mut self, settings: &QuerySettings, ip: IpAddr, now: Instant) -> bool {
+        let burst = settings.burst as f32;
+        let refill_secs = burst / settings.requests_per_second.max(0.001);
+        if self.tokens.len() >= MAX_TRACKED_ADDRESSES {
+            // Forget about the addresses which are back within the limits
+            self.tokens
+                .retain(|_, (_, last)| now.duration_since(*last).as_secs_f32() < refill_secs);
+            if self.tokens.len() >= MAX_TRACKED_ADDRESSES && !self.tokens.contains_key(&ip) {
+                return false;
+            }
+        }
+        let (tokens, last) = self.tokens.entry(ip).or_insert((burst, now));
+        *tokens = (*tokens
+            + now.duration_since(*last).as_secs_f32() * settings.requests_per_second)
+            .min(burst);
+        *last = now;
+        if *tokens < 1.0 {
+            false
+        } else {
+            *tokens -= 1.0;
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::resources::BattleMode;
+    use common_net::msg::ServerInfo;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn rate_limit_per_address() {
+        let settings = QuerySettings {
+            address: None,
+            list_players: true,
+            requests_per_second: 1.0,
+            burst: 2,
+        };
+        let mut limiter = RateLimiter::default();
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let other = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+        let now = Instant::now();
+
+        assert!(limiter.check(&settings, ip, now));
+        assert!(limiter.check(&settings, ip, now));
+        assert!(!limiter.check(&settings, ip, now));
+        assert!(limiter.check(&settings, other, now));
+        assert!(limiter.check(&settings, ip, now + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn answers_verified_queries_only() {
+        let settings = QuerySettings::default();
+        let status = RwLock::new(ServerStatus {
+            info: ServerInfo {
+                name: "Server".to_owned(),
+                description: String::new(),
+                git_hash: String::new(),
+                git_date: String::new(),
+                auth_provider: None,
+            },
+            players_online: 1,
+            max_players: 10,
+            world_size: Vec2::new(1024, 1024),
+            battle_mode: BattleMode::PvE,
+            battle_mode_choosable: false,
+            players: Some(vec!["player".to_owned()]),
+        });
+        let request = QueryRequest {
+            version: QUERY_VERSION,
+            include_players: true,
+            challenge: None,
+        };
+        let decode = |packet: Option<Vec<u8>>| {
+            decode_query_packet::<QueryResponse>(&packet.expect("no answer")).expect("invalid")
+        };
+
+        assert!(matches!(
+            decode(answer(&request, Err(42), &settings, &status)),
+            QueryResponse::Challenge(42)
+        ));
+        // Players aren't listed unless the settings allow it
+        match decode(answer(&request, Ok(()), &settings, &status)) {
+            QueryResponse::Status(status) => {
+                assert_eq!(status.players_online, 1);
+                assert!(status.players.is_none());
+            },
+            response => panic!("unexpected response: {:?}", response),
+        }
+    }
+
+    #[test]
+    fn challenge_per_address() {
+        let challenges = Challenges::new();
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let other = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+        let now = challenges.start;
+
+        let challenge = challenges.verify(ip, None, now).unwrap_err();
+        assert_eq!(challenges.verify(ip, Some(challenge), now), Ok(()));
+        assert!(challenges.verify(ip, Some(challenge + 1), now).is_err());
+        assert!(challenges.verify(other, Some(challenge), now).is_err());
+        // Still accepted right after a new challenge is handed out
+        let later = now + CHALLENGE_LIFETIME;
+        assert_eq!(challenges.verify(ip, Some(challenge), later), Ok(()));
+        assert!(
+            challenges
+                .verify(ip, Some(challenge), later + CHALLENGE_LIFETIME)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,487 @@
+//! Reports of players breaking the rules, filed by other players and reviewed
+//! by moderators, along with the recent chat of the reported players which is
+//! attached to them.
+
+use crate::settings::ReportSettings;
+use authc::Uuid;
+use chrono::{DateTime, Utc};
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+use tracing::{error, warn};
+
+const REPORTS_FILENAME: &str = "reports.json";
+/// Seconds after which the chat history of a player who stopped chatting is
+/// forgotten
+const CHAT_HISTORY_RETENTION: f64 = 3600.0;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ChatLine {
+    pub date: DateTime<Utc>,
+    pub sender: Uuid,
+    pub sender_username: String,
+    /// The player the message was sent to, for tells
+    pub recipient_username: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for ChatLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {}",
+            self.date.format("%H:%M:%S"),
+            self.sender_username
+        )?;
+        if let Some(recipient) = &self.recipient_username {
+            write!(f, " -> {}", recipient)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+struct PlayerChatHistory {
+    lines: VecDeque<ChatLine>,
+    last_line: f64,
+}
+
+/// The last chat lines sent by or to each player, by UUID so that they can
+/// still be reported after leaving.
+#[derive(Default)]
+pub struct ChatHistory {
+    players: HashMap<Uuid, PlayerChatHistory>,
+}
+
+impl ChatHistory {
+    /// Records the line in the history of its sender, and of its recipient if
+    /// any, keeping at most `max_lines` lines per player.
+    pub fn record(&mut self, max_lines: usize, time: f64, line: ChatLine, recipient: Option<Uuid>) {
+        if max_lines == 0 {
+            return;
+        }
+        for uuid in std::iter::once(line.sender).chain(recipient) {
+            let history = self
+                .players
+                .entry(uuid)
+                .or_insert_with(|| PlayerChatHistory {
+                    lines: VecDeque::with_capacity(max_lines),
+                    last_line: time,
+                });
+            while history.lines.len() >= max_lines {
+                history.lines.pop_front();
+            }
+            history.lines.push_back(line.clone());
+            history.last_line = time;
+        }
+    }
+
+    /// The UUID of the player who sent recorded lines under this username.
+    pub fn find_sender(&self, username: &str) -> Option<Uuid> {
+        self.players
+            .iter()
+            .filter(|(uuid, history)| {
+                history
+                    .lines
+                    .iter()
+                    .any(|line| line.sender == **uuid && line.sender_username == username)
+            })
+            .max_by(|(_, a), (_, b)| {
+                a.last_line
+                    .partial_cmp(&b.last_line)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .map(|(uuid, _)| *uuid)
+    }
+
+    /// The recorded lines involving the player, oldest first.
+    pub fn lines_of(&self, uuid: &Uuid) -> Vec<ChatLine> {
+        self.players
+            .get(uuid)
+            .map_or_else(Vec::new, |history| history.lines.iter().cloned().collect())
+    }
+
+    /// Forgets the players who haven't chatted for a while.
+    pub fn maintain(&mut self, time: f64) {
+        self.players
+            .retain(|_, history| time - history.last_line < CHAT_HISTORY_RETENTION);
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Report {
+    pub id: u64,
+    pub date: DateTime<Utc>,
+    pub reporter: Uuid,
+    pub reporter_username: String,
+    pub reported: Uuid,
+    pub reported_username: String,
+    pub reason: String,
+    /// The last chat lines sent by or to the reported player when the report
+    /// was filed
+    pub chat: Vec<ChatLine>,
+    pub resolution: Option<Resolution>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Resolution {
+    pub date: DateTime<Utc>,
+    /// None when resolved from the server console
+    pub moderator: Option<Uuid>,
+    pub moderator_username: String,
+    /// What was done about the report
+    pub note: String,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#{} [{}] {} reported {}: {}",
+            self.id,
+            self.date.format("%Y-%m-%d %H:%M:%S"),
+            self.reporter_username,
+            self.reported_username,
+            self.reason,
+        )?;
+        if let Some(resolution) = &self.resolution {
+            write!(
+                f,
+                " (resolved by {}: {})",
+                resolution.moderator_username, resolution.note
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Why a report was refused
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ReportRefused {
+    /// The player filed a report too recently, and can file another one in
+    /// the given number of seconds
+    TooSoon(f64),
+    /// The player has as many open reports as allowed
+    TooManyOpen,
+    /// The player already has an open report against the same player
+    AlreadyReported,
+}
+
+impl ReportRefused {
+    /// The explanation sent to the player
+    pub fn info(&self) -> String {
+        match self {
+            ReportRefused::TooSoon(remaining) => format!(
+                "You can file another report in {} seconds.",
+                remaining.ceil()
+            ),
+            ReportRefused::TooManyOpen => {
+                "You have too many reports waiting for a moderator already.".to_owned()
+            },
+            ReportRefused::AlreadyReported => {
+                "You already reported this player, a moderator will look into it.".to_owned()
+            },
+        }
+    }
+}
+
+/// Versioned reports files, one per version.
+#[derive(Deserialize, Serialize)]
+enum ReportsRaw {
+    V1(Vec<Report>),
+}
+
+/// The reports filed on this server, saved in the data directory whenever
+/// they change.
+pub struct Reports {
+    path: PathBuf,
+    reports: Vec<Report>,
+    /// When each player last filed a report, by UUID
+    last_report: HashMap<Uuid, f64>,
+}
+
+impl Reports {
+    pub fn load(data_dir: &Path) -> Self {
+        let path = data_dir.join(REPORTS_FILENAME);
+        let reports = match fs::read_to_string(&path) {
+            Ok(json) => match serde_json::from_str(&json) {
+                Ok(ReportsRaw::V1(reports)) => reports,
+                Err(err) => {
+                    // Keep the file around rather than overwriting it with the next report.
+                    let invalid_path = path.with_extension("invalid.json");
+                    error!(
+                        ?err,
+                        "Failed to parse the reports, moving them to {}",
+                        invalid_path.display()
+                    );
+                    if let Err(err) = fs::rename(&path, &invalid_path) {
+                        error!(?err, "Failed to move the reports");
+                    }
+                    Vec::new()
+                },
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => {
+                error!(?err, "Failed to read {}", path.display());
+                Vec::new()
+            },
+        };
+        Self {
+            path,
+            reports,
+            last_report: HashMap::new(),
+        }
+    }
+
+    fn save(&self) {
+        // Write to a temporary file first, so that the reports aren't lost if
+        // the server stops while writing.
+        let tmp_path = self.path.with_extension("json.tmp");
+        let result = serde_json::to_string_pretty(&ReportsRaw::V1(self.reports.clone()))
+            .map_err(io::Error::from)
+            .and_then(|json| fs::write(&tmp_path, json))
+            .and_then(|()| fs::rename(&tmp_path, &self.path));
+        if let Err(err) = result {
+            warn!(
+                ?err,
+                "Failed to write the reports to disk, but succeeded in memory"
+            );
+        }
+    }
+
+    fn open_reports_of(&self, reporter: Uuid) -> impl Iterator<Item = &Report> + Clone {
+        self.reports
+            .iter()
+            .filter(move |report| report.reporter == reporter && report.resolution.is_none())
+    }
+
+    /// Checks that the reporter isn't reporting too often, before looking up
+    /// who they are reporting.
+    pub fn can_file(
+        &self,
+        settings: &ReportSettings,
+        time: f64,
+        reporter: Uuid,
+    ) -> Result<(), ReportRefused> {
+        if settings.max_open > 0 && self.open_reports_of(reporter).count() >= settings.max_open {
+            return Err(ReportRefused::TooManyOpen);
+        }
+        if let Some(last_report) = self.last_report.get(&reporter) {
+            let remaining = settings.cooldown.as_secs_f64() - (time - last_report);
+            if remaining > 0.0 {
+                return Err(ReportRefused::TooSoon(remaining));
+            }
+        }
+        Ok(())
+    }
+
+    /// Files the report if the reporter isn't reporting too often, returning
+    /// its id.
+    #[allow(clippy::too_many_arguments)]
+    pub fn file(
+        &mut self,
+        settings: &ReportSettings,
+        time: f64,
+        reporter: Uuid,
+        reporter_username: String,
+        reported: Uuid,
+        reported_username: String,
+        reason: String,
+        chat: Vec<ChatLine>,
+    ) -> Result<u64, ReportRefused> {
+        if self
+            .open_reports_of(reporter)
+            .any(|report| report.reported == reported)
+        {
+            return Err(ReportRefused::AlreadyReported);
+        }
+        self.can_file(settings, time, reporter)?;
+
+        let id = self.reports.last().map_or(1, |report| report.id + 1);
+        self.reports.push(Report {
+            id,
+            date: Utc::now(),
+            reporter,
+            reporter_username,
+            reported,
+            reported_username,
+            reason,
+            chat,
+            resolution: None,
+        });
+        self.last_report.insert(reporter, time);
+        self.save();
+        Ok(id)
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Report> {
+        self.reports.iter().find(|report| report.id == id)
+    }
+
+    /// The reports which weren't resolved yet, oldest first.
+    pub fn open(&self) -> impl Iterator<Item = &Report> {
+        self.reports
+            .iter()
+            .filter(|report| report.resolution.is_none())
+    }
+
+    /// Marks the report as resolved, returning it, or an error message if
+    /// there is no such open report.
+    pub fn resolve(&mut self, id: u64, resolution: Resolution) -> Result<&Report, String> {
+        let index = match self.reports.iter().position(|report| report.id == id) {
+            Some(index) => index,
+            None => return Err(format!("There is no report #{}", id)),
+        };
+        if let Some(previous) = &self.reports[index].resolution {
+            return Err(format!(
+                "Report #{} was already resolved by {}",
+                id, previous.moderator_username
+            ));
+        }
+        self.reports[index].resolution = Some(resolution);
+        self.save();
+        Ok(&self.reports[index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn reports(name: &str) -> Reports {
+        let dir =
+            std::env::temp_dir().join(format!("veloren-reports-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Reports::load(&dir)
+    }
+
+    fn file(
+        reports: &mut Reports,
+        time: f64,
+        reporter: u128,
+        reported: u128,
+    ) -> Result<u64, ReportRefused> {
+        let settings = ReportSettings {
+            chat_lines: 20,
+            cooldown: Duration::from_secs(60),
+            max_open: 2,
+        };
+        reports.file(
+            &settings,
+            time,
+            Uuid::from_u128(reporter),
+            format!("player{}", reporter),
+            Uuid::from_u128(reported),
+            format!("player{}", reported),
+            "griefing".to_owned(),
+            Vec::new(),
+        )
+    }
+
+    fn line(sender: u128, message: &str) -> ChatLine {
+        ChatLine {
+            date: Utc::now(),
+            sender: Uuid::from_u128(sender),
+            sender_username: format!("player{}", sender),
+            recipient_username: None,
+            message: message.to_owned(),
+        }
+    }
+
+    fn resolution() -> Resolution {
+        Resolution {
+            date: Utc::now(),
+            moderator: None,
+            moderator_username: "Server".to_owned(),
+            note: "warned".to_owned(),
+        }
+    }
+
+    #[test]
+    fn file_limits() {
+        let mut reports = reports("limits");
+        assert_eq!(file(&mut reports, 0.0, 1, 2), Ok(1));
+        assert_eq!(
+            file(&mut reports, 100.0, 1, 2),
+            Err(ReportRefused::AlreadyReported)
+        );
+        assert_eq!(
+            file(&mut reports, 30.0, 1, 3),
+            Err(ReportRefused::TooSoon(30.0))
+        );
+        assert_eq!(file(&mut reports, 60.0, 1, 3), Ok(2));
+        assert_eq!(
+            file(&mut reports, 200.0, 1, 4),
+            Err(ReportRefused::TooManyOpen)
+        );
+        // Other players have their own limits.
+        assert_eq!(file(&mut reports, 200.0, 5, 4), Ok(3));
+
+        // Resolved reports don't count towards the open ones.
+        assert!(reports.resolve(1, resolution()).is_ok());
+        assert_eq!(file(&mut reports, 200.0, 1, 4), Ok(4));
+        assert_eq!(
+            file(&mut reports, 300.0, 1, 2).err(),
+            Some(ReportRefused::TooManyOpen)
+        );
+        assert_eq!(
+            reports.open().map(|report| report.id).collect::<Vec<_>>(),
+            vec![2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn resolve() {
+        let mut reports = reports("resolve");
+        assert_eq!(file(&mut reports, 0.0, 1, 2), Ok(1));
+        assert!(reports.resolve(2, resolution()).is_err());
+        let resolved = reports.resolve(1, resolution()).unwrap();
+        assert_eq!(resolved.resolution.as_ref().unwrap().note, "warned");
+        assert!(reports.resolve(1, resolution()).is_err());
+        assert!(reports.get(1).unwrap().resolution.is_some());
+
+        // Resolutions are saved.
+        let reloaded = Reports::load(reports.path.parent().unwrap());
+        assert!(reloaded.get(1).unwrap().resolution.is_some());
+        assert_eq!(reloaded.open().count(), 0);
+    }
+
+    #[test]
+    fn chat_history_keeps_last_lines() {
+        let mut history = ChatHistory::default();
+        for i in 0..5 {
+            history.record(
+                3,
+                i as f64,
+                line(1, &i.to_string()),
+                Some(Uuid::from_u128(2)),
+            );
+        }
+        history.record(3, 5.0, line(2, "reply"), None);
+        let messages = |uuid| {
+            history
+                .lines_of(&Uuid::from_u128(uuid))
+                .into_iter()
+                .map(|line| line.message)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(messages(1), vec!["2", "3", "4"]);
+        assert_eq!(messages(2), vec!["3", "4", "reply"]);
+
+        // Nothing is recorded without lines to keep.
+        history.record(0, 6.0, line(3, "hidden"), None);
+        assert!(messages(3).is_empty());
+
+        assert_eq!(history.find_sender("player2"), Some(Uuid::from_u128(2)));
+        assert_eq!(history.find_sender("player3"), None);
+
+        history.maintain(5.0 + CHAT_HISTORY_RETENTION);
+        assert!(messages(1).is_empty());
+        assert!(messages(2).is_empty());
+    }
+}
@@ -0,0 +1,468 @@
+//! The economies of the sites while the server runs.
+
+use super::persistence::{EconomiesSave, SiteEconomySave};
+use common::{
+    store::Id,
+    trade::{Good, SiteId, SiteInformation, SitePrices},
+};
+use hashbrown::HashMap;
+use std::convert::TryFrom;
+use world::{
+    index::Index,
+    sim2,
+    site::{
+        economy::{direct_use_goods, good_list, Economy, GoodIndex, GoodMap},
+        Site,
+    },
+};
+
+/// Amount of goods a merchant can carry between two sites
+const CARGO_CAPACITY: f32 = 50.0;
+
+/// In-game time in an economic day, the economies tick once per day.
+const DAY_LENGTH: f64 = 24.0 * 3600.0;
+
+/// Most days the economies advance by at once, so that skipping time ahead
+/// doesn't make them take a single huge step
+const MAX_DAYS_PER_TICK: f32 = 1.0;
+
+/// Goods carried by a merchant, along with their amount
+pub type Cargo = Vec<(Good, f32)>;
+
+/// The economy of each site as it evolves while the server runs, the
+/// economies stored in the world index are the ones the world was generated
+/// with.
+#[derive(Default)]
+pub struct SiteEconomies {
+    economies: HashMap<Id<Site>, Economy>,
+    /// Time of day of the last tick of the economies
+    last_tick: Option<f64>,
+    /// Days elapsed in the economic simulation, continuing from the world
+    /// generation
+    days: f32,
+}
+
+impl SiteEconomies {
+    pub fn new(index: &Index) -> Self {
+        Self {
+            economies: index
+                .sites
+                .iter()
+                .map(|(id, site)| (id, site.economy.clone()))
+                .collect(),
+            last_tick: None,
+            days: index.time,
+        }
+    }
+
+    pub fn get(&self, site: Id<Site>) -> Option<&Economy> { self.economies.get(&site) }
+
+    pub fn get_mut(&mut self, site: Id<Site>) -> Option<&mut Economy> {
+        self.economies.get_mut(&site)
+    }
+
+    /// Keeps the economies of the sites the world simulates running, as
+    /// in-game days pass.
+    pub fn tick(&mut self, index: &Index, time_of_day: f64) {
+        let dt = match self.elapsed_days(time_of_day) {
+            Some(dt) => dt,
+            None => return,
+        };
+        self.days += dt;
+
+        for (&site, economy) in self.economies.iter_mut() {
+            if index.sites[site].do_economic_simulation() {
+                sim2::tick_runtime_economy(economy, site, dt, self.days);
+            }
+        }
+    }
+
+    /// The days to advance the economies by, once a day passed since the last
+    /// tick.
+    fn elapsed_days(&mut self, time_of_day: f64) -> Option<f32> {
+        let last_tick = *self.last_tick.get_or_insert(time_of_day);
+        if time_of_day < last_tick {
+            // The time was set back, count the days from there.
+            self.last_tick = Some(time_of_day);
+            return None;
+        }
+        if time_of_day - last_tick < DAY_LENGTH {
+            return None;
+        }
+        self.last_tick = Some(time_of_day);
+        Some((((time_of_day - last_tick) / DAY_LENGTH) as f32).min(MAX_DAYS_PER_TICK))
+    }
+
+    /// What changed in the economies, to be saved with the rtsim state.
+    pub fn snapshot(&self) -> EconomiesSave {
+        let goods = |map: &GoodMap<f32>| {
+            good_list()
+                .map(|good| (Good::from(good), map[good]))
+                .collect::<Vec<_>>()
+        };
+        EconomiesSave {
+            days: self.days,
+            sites: self
+                .economies
+                .iter()
+                .map(|(site, economy)| SiteEconomySave {
+                    site: site.id(),
+                    pop: economy.pop,
+                    stocks: goods(&economy.stocks),
+                    unconsumed_stock: goods(&economy.unconsumed_stock),
+                    values: good_list()
+                        .filter_map(|good| {
+                            economy.values[good].map(|value| (Good::from(good), value))
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Restores the economies saved with the rtsim state, on top of the ones
+    /// the world was generated with.
+    pub fn restore(&mut self, index: &Index, save: EconomiesSave) {
+        self.days = save.days;
+        for site in save.sites {
+            let economy = match index
+                .sites
+                .recreate_id(site.site)
+                .and_then(|id| self.economies.get_mut(&id))
+            {
+                Some(economy) => economy,
+                None => continue,
+            };
+            economy.pop = site.pop;
+            set_goods(&mut economy.stocks, site.stocks);
+            set_goods(&mut economy.unconsumed_stock, site.unconsumed_stock);
+            economy.values = GoodMap::from_default(None);
+            for (good, value) in site.values {
+                if let Ok(good) = GoodIndex::try_from(good) {
+                    economy.values[good] = Some(value);
+                }
+            }
+        }
+    }
+
+    /// The current prices at the site, as used by its merchants.
+    pub fn get_site_prices(&self, index: &Index, site_id: SiteId) -> Option<SitePrices> {
+        index
+            .sites
+            .recreate_id(site_id)
+            .and_then(|site| self.get(site))
+            .map(Economy::get_site_prices)
+    }
+
+    /// The trading information of the site, with its current stock.
+    pub fn trade_information(&self, index: &Index, site: Id<Site>) -> Option<SiteInformation> {
+        let economy = self.get(site)?;
+        index.sites[site]
+            .trade_information(site.id())
+            .map(|info| SiteInformation {
+                unconsumed_stock: economy
+                    .unconsumed_stock
+                    .iter()
+                    .map(|(good, amount)| (good.into(), *amount))
+                    .collect(),
+                ..info
+            })
+    }
+
+    /// Accounts for an inhabitant of the site dying, merchants lose the goods
+    /// they were carrying along with them.
+    pub fn record_death(&mut self, site: Id<Site>, merchant: bool) {
+        if let Some(economy) = self.economies.get_mut(&site) {
+            if merchant {
+                // A merchant carries their share of the goods the site can spare
+                let share = 1.0 / economy.pop.max(1.0);
+                for good in good_list() {
+                    economy.stocks[good] =
+                        (economy.stocks[good] - economy.unconsumed_stock[good] * share).max(0.0);
+                }
+            }
+            economy.pop = (economy.pop - 1.0).max(1.0);
+        }
+    }
+
+    /// Accounts for a new inhabitant settling in the site.
+    pub fn record_arrival(&mut self, site: Id<Site>) {
+        if let Some(economy) = self.economies.get_mut(&site) {
+            economy.pop += 1.0;
+        }
+    }
+
+    /// Accounts for goods changing hands with the site, positive amounts are
+    /// gained by the site.
+    pub fn record_trade(&mut self, site: Id<Site>, goods: &[(Good, f32)]) {
+        if let Some(economy) = self.economies.get_mut(&site) {
+            for &(good, amount) in goods {
+                add_stock(economy, good, amount);
+            }
+        }
+    }
+
+    /// Loads a merchant leaving the site with the goods it can spare.
+    pub fn load_cargo(&mut self, site: Id<Site>) -> Cargo {
+        match self.economies.get_mut(&site) {
+            Some(economy) => take_surplus(economy, CARGO_CAPACITY),
+            None => Vec::new(),
+        }
+    }
+
+    /// Sells the cargo of a merchant at the site, and buys back what the site
+    /// can spare with the coins earned.
+    pub fn exchange_cargo(&mut self, site: Id<Site>, cargo: Cargo) -> Cargo {
+        let economy = match self.economies.get_mut(&site) {
+            Some(economy) => economy,
+            None => return cargo,
+        };
+        let prices = economy.get_site_prices();
+        let coin_value = |goods: &[(Good, f32)]| {
+            let coin_price = prices
+                .values
+                .get(&Good::Coin)
+                .copied()
+                .unwrap_or(1.0)
+                .max(Economy::MINIMUM_PRICE);
+            goods
+                .iter()
+                .map(|(good, amount)| match good {
+                    Good::Coin => *amount,
+                    _ => amount * prices.values.get(good).copied().unwrap_or_default() / coin_price,
+                })
+                .sum::<f32>()
+        };
+
+        // Sell the cargo, for as many coins as the site has
+        let earned = coin_value(&cargo).min(stock(economy, Good::Coin));
+        for (good, amount) in cargo {
+            add_stock(economy, good, amount);
+        }
+        add_stock(economy, Good::Coin, -earned);
+
+        // And spend them on the goods the site does not need
+        let mut cargo = take_surplus(economy, CARGO_CAPACITY);
+        let cost = coin_value(&cargo);
+        if cost > earned {
+            // Give back what the merchant cannot afford
+            let kept = earned / cost;
+            for (good, amount) in cargo.iter_mut() {
+                add_stock(economy, *good, *amount * (1.0 - kept));
+                *amount *= kept;
+            }
+        }
+        let spent = cost.min(earned);
+        add_stock(economy, Good::Coin, spent);
+        cargo.push((Good::Coin, earned - spent));
+        cargo
+    }
+
+    /// Unloads the cargo of a merchant coming back to the site.
+    pub fn unload_cargo(&mut self, site: Id<Site>, cargo: Cargo) {
+        self.record_trade(site, &cargo);
+    }
+}
+
+fn stock(economy: &Economy, good: Good) -> f32 {
+    GoodIndex::try_from(good).map_or(0.0, |good| economy.stocks[good])
+}
+
+fn set_goods(map: &mut GoodMap<f32>, goods: Vec<(Good, f32)>) {
+    for (good, amount) in goods {
+        if let Ok(good) = GoodIndex::try_from(good) {
+            map[good] = amount;
+        }
+    }
+}
+
+fn add_stock(economy: &mut Economy, good: Good, amount: f32) {
+    if let Ok(good) = GoodIndex::try_from(good) {
+        let new_stock = (economy.stocks[good] + amount).max(0.0);
+        economy.unconsumed_stock[good] += new_stock - economy.stocks[good];
+        economy.stocks[good] = new_stock;
+    }
+}
+
+/// Takes up to `capacity` of the goods the site does not need.
+fn take_surplus(economy: &mut Economy, capacity: f32) -> Cargo {
+    let direct_use = direct_use_goods();
+    let surplus = good_list()
+        .filter(|good| !direct_use.contains(good) && Good::from(*good) != Good::Coin)
+        .map(|good| {
+            (
+                good,
+                economy.unconsumed_stock[good].min(economy.stocks[good]),
+            )
+        })
+        .filter(|(_, amount)| *amount > 0.0)
+        .collect::<Vec<_>>();
+    let total = surplus.iter().map(|(_, amount)| amount).sum::<f32>();
+    let share = (capacity / total.max(0.001)).min(1.0);
+
+    surplus
+        .into_iter()
+        .map(|(good, amount)| {
+            let good = Good::from(good);
+            add_stock(economy, good, -amount * share);
+            (good, amount * share)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn site(id: u64) -> Id<Site> {
+        // Ids can only be made by a store of sites otherwise.
+        serde_json::from_str(&id.to_string()).unwrap()
+    }
+
+    fn economies() -> SiteEconomies {
+        let mut economy = Economy::default();
+        set_goods(&mut economy.unconsumed_stock, vec![
+            (Good::Wood, 30.0),
+            (Good::Stone, 30.0),
+            (Good::Coin, 500.0),
+            (Good::Transportation, 10.0),
+        ]);
+        SiteEconomies {
+            economies: std::iter::once((site(0), economy)).collect(),
+            last_tick: None,
+            days: 0.0,
+        }
+    }
+
+    fn amount(cargo: &[(Good, f32)], good: Good) -> f32 {
+        cargo
+            .iter()
+            .filter(|(g, _)| *g == good)
+            .map(|(_, amount)| amount)
+            .sum()
+    }
+
+    #[test]
+    fn surplus_is_shared_up_to_capacity() {
+        let mut economies = economies();
+        let economy = economies.get_mut(site(0)).unwrap();
+        let cargo = take_surplus(economy, 30.0);
+        // Coins and goods the site uses directly are not for sale.
+        assert_eq!(cargo.len(), 2);
+        assert!((amount(&cargo, Good::Wood) - 15.0).abs() < 0.001);
+        assert!((amount(&cargo, Good::Stone) - 15.0).abs() < 0.001);
+        assert!((stock(economy, Good::Wood) - 85.0).abs() < 0.001);
+        assert!(
+            (economy.unconsumed_stock[GoodIndex::try_from(Good::Wood).unwrap()] - 15.0).abs()
+                < 0.001
+        );
+
+        // What is left fits in the next cargo.
+        let cargo = take_surplus(economy, 100.0);
+        assert!((amount(&cargo, Good::Wood) - 15.0).abs() < 0.001);
+        assert!(take_surplus(economy, 100.0).is_empty());
+    }
+
+    #[test]
+    fn deaths_and_arrivals() {
+        let mut economies = economies();
+        economies.get_mut(site(0)).unwrap().pop = 2.0;
+        let wood = stock(economies.get(site(0)).unwrap(), Good::Wood);
+
+        economies.record_death(site(0), false);
+        let economy = economies.get(site(0)).unwrap();
+        assert!((economy.pop - 1.0).abs() < 0.001);
+        assert!((stock(economy, Good::Wood) - wood).abs() < 0.001);
+
+        economies.record_arrival(site(0));
+        // A merchant takes their share of the goods the site can spare along.
+        economies.record_death(site(0), true);
+        let economy = economies.get(site(0)).unwrap();
+        assert!((economy.pop - 1.0).abs() < 0.001);
+        assert!((stock(economy, Good::Wood) - (wood - 15.0)).abs() < 0.001);
+
+        // Sites keep at least one inhabitant.
+        economies.record_death(site(0), false);
+        assert!((economies.get(site(0)).unwrap().pop - 1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn load_cargo() {
+        let mut economies = economies();
+        let cargo = economies.load_cargo(site(0));
+        assert!(
+            (amount(&cargo, Good::Wood) + amount(&cargo, Good::Stone) - CARGO_CAPACITY).abs()
+                < 0.001
+        );
+        assert!(economies.load_cargo(site(1)).is_empty());
+    }
+
+    #[test]
+    fn record_trade() {
+        let mut economies = economies();
+        economies.record_trade(site(0), &[(Good::Wood, 20.0), (Good::Stone, -150.0)]);
+        let economy = economies.get(site(0)).unwrap();
+        assert!((stock(economy, Good::Wood) - 120.0).abs() < 0.001);
+        // Stocks can't run negative.
+        assert!(stock(economy, Good::Stone).abs() < 0.001);
+        // Trades with unknown sites are ignored.
+        economies.record_trade(site(1), &[(Good::Wood, 20.0)]);
+    }
+
+    #[test]
+    fn exchange_cargo_keeps_goods_and_coins() {
+        let mut economies = economies();
+        let before = economies.get(site(0)).unwrap().clone();
+        let sold = vec![(Good::Food, 10.0), (Good::Coin, 5.0)];
+        let bought = economies.exchange_cargo(site(0), sold.clone());
+        let after = economies.get(site(0)).unwrap();
+
+        // Nothing was made or lost in the exchange.
+        for good in [Good::Food, Good::Coin, Good::Wood, Good::Stone] {
+            let total_before = stock(&before, good) + amount(&sold, good);
+            let total_after = stock(after, good) + amount(&bought, good);
+            assert!(
+                (total_before - total_after).abs() < 0.01,
+                "{:?}: {} != {}",
+                good,
+                total_before,
+                total_after
+            );
+        }
+        // The merchant bought surplus goods with what it earned.
+        assert!(amount(&bought, Good::Wood) > 0.0);
+
+        // Cargo brought to unknown sites is kept.
+        let kept = economies.exchange_cargo(site(1), sold.clone());
+        assert_eq!(kept, sold);
+    }
+
+    #[test]
+    fn tick_clamps_elapsed_days() {
+        let mut economies = economies();
+        assert_eq!(economies.elapsed_days(1000.0), None);
+        assert_eq!(economies.elapsed_days(1000.0 + DAY_LENGTH / 2.0), None);
+        assert_eq!(economies.elapsed_days(1000.0 + DAY_LENGTH), Some(1.0));
+        // Skipping ahead advances a single day at most.
+        assert_eq!(
+            economies.elapsed_days(1000.0 + 30.0 * DAY_LENGTH),
+            Some(MAX_DAYS_PER_TICK)
+        );
+        // Setting the time back restarts the day from there, rather than
+        // stalling until the previous time comes again.
+        assert_eq!(economies.elapsed_days(0.0), None);
+        assert_eq!(economies.elapsed_days(DAY_LENGTH), Some(1.0));
+    }
+
+    #[test]
+    fn snapshot_records_changes() {
+        let mut economies = economies();
+        economies.days = 12.5;
+        economies.record_trade(site(0), &[(Good::Wood, 20.0)]);
+        let save = economies.snapshot();
+        assert_eq!(save.sites.len(), 1);
+        assert!((amount(&save.sites[0].stocks, Good::Wood) - 120.0).abs() < 0.001);
+        assert!((amount(&save.sites[0].unconsumed_stock, Good::Wood) - 50.0).abs() < 0.001);
+        assert_eq!(save.sites[0].values, vec![(Good::Coin, 2.0)]);
+    }
+}
@@ -1,40 +1,102 @@
-use super::*;
+use super::{economy::Cargo, *};
 use common::{
     comp::inventory::{
         loadout_builder::{make_food_bag, make_potion_bag},
         slot::ArmorSlot,
     },
-    resources::Time,
+    resources::{Time, TimeOfDay},
     rtsim::{Memory, MemoryItem},
     store::Id,
     terrain::TerrainGrid,
     trade, LoadoutBuilder,
 };
 use rand_distr::{Distribution, Normal};
+use serde::{Deserialize, Serialize};
 use std::f32::consts::PI;
 use tracing::warn;
 use world::{
     civ::{Site, Track},
+    site::SiteKind,
+    site2::{self, PlotKind},
     util::RandomPerm,
     IndexRef, World,
 };
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Entity {
+    #[serde(skip)]
     pub is_loaded: bool,
     pub pos: Vec3<f32>,
     pub seed: u32,
     pub last_time_ticked: f64,
+    #[serde(skip)]
     pub controller: RtSimController,
     pub kind: RtSimEntityKind,
     pub brain: Brain,
 }
 
-#[derive(Clone, Copy, strum::EnumIter)]
+#[derive(Clone, Copy, strum::EnumIter, Serialize, Deserialize)]
 pub enum RtSimEntityKind {
     Random,
     Cultist,
     Villager,
     Merchant,
+    Guard,
+    Farmer,
+    Blacksmith,
+    Hunter,
+}
+
+impl RtSimEntityKind {
+    /// The daily schedule of town dwellers, as the hours at which they switch
+    /// activity. The last activity of the day carries on past midnight.
+    fn schedule(&self, seed: u32) -> &'static [(f64, Activity)] {
+        use Activity::*;
+        match self {
+            // Half of the guards take the night shift
+            RtSimEntityKind::Guard if seed % 2 == 0 => {
+                &[(0.0, Sleep), (6.0, Work), (18.0, Eat), (19.0, Sleep)]
+            },
+            RtSimEntityKind::Guard => &[(0.0, Work), (6.0, Eat), (7.0, Sleep), (18.0, Work)],
+            RtSimEntityKind::Farmer => &[
+                (0.0, Sleep),
+                (5.0, Work),
+                (12.0, Eat),
+                (13.0, Work),
+                (19.0, Eat),
+                (21.0, Sleep),
+            ],
+            RtSimEntityKind::Hunter => &[(0.0, Sleep), (4.0, Work), (16.0, Eat), (20.0, Sleep)],
+            _ => &[
+                (0.0, Sleep),
+                (7.0, Work),
+                (12.0, Eat),
+                (13.0, Work),
+                (18.0, Eat),
+                (21.0, Sleep),
+            ],
+        }
+    }
+
+    /// The activity planned by the schedule at the given time of day.
+    fn activity(&self, seed: u32, time_of_day: &TimeOfDay) -> Activity {
+        let hour = time_of_day.0.rem_euclid(24.0 * 3600.0) / 3600.0;
+        let schedule = self.schedule(seed);
+        schedule
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= hour)
+            .or_else(|| schedule.last())
+            .map_or(Activity::Sleep, |(_, activity)| *activity)
+    }
+}
+
+/// What a town dweller is busy with, depending on the time of day
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Activity {
+    Work,
+    Eat,
+    Sleep,
 }
 
 const BIRD_MEDIUM_ROSTER: &[comp::bird_medium::Species] = &[
@@ -64,6 +126,14 @@ impl Entity {
 
     pub fn loadout_rng(&self) -> impl Rng { self.rng(PERM_LOADOUT) }
 
+    /// Shifts the absolute times remembered by the entity by `offset`, used
+    /// when restoring an entity saved by a server whose clock started at a
+    /// different time.
+    pub fn rebase_time(&mut self, offset: f64) {
+        self.last_time_ticked += offset;
+        self.brain.rebase_time(offset);
+    }
+
     pub fn get_body(&self) -> comp::Body {
         match self.kind {
             RtSimEntityKind::Random => {
@@ -92,7 +162,13 @@ impl Entity {
                     },
                 }
             },
-            RtSimEntityKind::Cultist | RtSimEntityKind::Villager | RtSimEntityKind::Merchant => {
+            RtSimEntityKind::Cultist
+            | RtSimEntityKind::Villager
+            | RtSimEntityKind::Merchant
+            | RtSimEntityKind::Guard
+            | RtSimEntityKind::Farmer
+            | RtSimEntityKind::Blacksmith
+            | RtSimEntityKind::Hunter => {
                 let species = *(&comp::humanoid::ALL_SPECIES)
                     .choose(&mut self.rng(PERM_SPECIES))
                     .unwrap();
@@ -105,6 +181,7 @@ impl Entity {
         &self,
         world: &World,
         index: &world::IndexOwned,
+        economies: &SiteEconomies,
     ) -> Option<trade::SiteInformation> {
         let site = match self.kind {
             /*
@@ -121,7 +198,7 @@ impl Entity {
         }?;
 
         let site = world.civs().sites[site].site_tmp?;
-        index.sites[site].trade_information(site.id())
+        economies.trade_information(index, site)
     }
 
     pub fn get_entity_config(&self) -> &str {
@@ -156,7 +233,188 @@ impl Entity {
         }
     }
 
-    pub fn tick(&mut self, time: &Time, terrain: &TerrainGrid, world: &World, index: &IndexRef) {
+    /// Chance per second for the entity to die while travelling through
+    /// unloaded terrain, to bandits, wildlife and the like.
+    pub fn danger(&self, world: &World) -> f32 {
+        // An unprotected traveller in the wilds dies in about an hour
+        const WILDERNESS_DANGER: f32 = 1.0 / 3600.0;
+
+        let body = self.get_body();
+        if matches!(
+            body,
+            comp::Body::Ship(_) | comp::Body::BirdMedium(_) | comp::Body::BirdLarge(_)
+        ) {
+            return 0.0;
+        }
+        let chunk = match world.sim().get_wpos(self.pos.xy().map(|e| e as i32)) {
+            Some(chunk) if chunk.sites.is_empty() => chunk,
+            // Sites are safe
+            _ => return 0.0,
+        };
+        // Roads are patrolled
+        let road = if chunk.path.0.is_way() { 0.25 } else { 1.0 };
+        let toughness = match self.kind {
+            RtSimEntityKind::Guard | RtSimEntityKind::Hunter | RtSimEntityKind::Cultist => 4.0,
+            _ => 1.0,
+        };
+        WILDERNESS_DANGER * chunk.spawn_rate * (1.0 + chunk.chaos) * road / toughness
+    }
+
+    /// The site the entity is replaced at when it dies.
+    pub fn home_site(&self, world: &World) -> Option<Id<Site>> {
+        self.brain.home_site().or_else(|| {
+            // Entities without a home are replaced at the closest town
+            world
+                .civs()
+                .sites
+                .iter()
+                .filter(|(_, site)| site.is_settlement())
+                .min_by_key(|(_, site)| {
+                    let wpos = site.center.map2(TerrainChunk::RECT_SIZE, |e, sz| {
+                        e * sz as i32 + sz as i32 / 2
+                    });
+                    wpos.map(|e| e as f32).distance_squared(self.pos.xy()) as u32
+                })
+                .map(|(id, _)| id)
+        })
+    }
+
+    /// A newcomer taking the place of the entity at the given site, sharing
+    /// its occupation but none of its memories.
+    pub fn replacement(&self, home: Id<Site>, world: &World, time: &Time) -> Self {
+        let wpos = world.civs().sites[home]
+            .center
+            .map2(TerrainChunk::RECT_SIZE, |e, sz| {
+                e * sz as i32 + sz as i32 / 2
+            });
+        Self {
+            is_loaded: false,
+            pos: wpos.map(|e| e as f32).with_z(0.0),
+            seed: thread_rng().gen(),
+            last_time_ticked: time.0,
+            controller: RtSimController::default(),
+            kind: self.kind,
+            brain: self.brain.reborn(),
+        }
+    }
+
+    /// The activity the daily schedule of the entity plans at the given time
+    /// of day.
+    pub fn activity(&self, time_of_day: &TimeOfDay) -> Activity {
+        self.kind.activity(self.seed, time_of_day)
+    }
+
+    /// Where in its town the entity goes for the given activity.
+    fn routine_wpos(
+        &self,
+        activity: Activity,
+        site: &site2::Site,
+        time: &Time,
+        time_of_day: &TimeOfDay,
+    ) -> Vec2<i32> {
+        let nth_plot = |n: u32, filter: fn(&PlotKind) -> bool| {
+            let plots = site
+                .plots()
+                .filter(|plot| filter(plot.kind()))
+                .collect::<Vec<_>>();
+            (!plots.is_empty())
+                .then(|| site.tile_center_wpos(plots[n as usize % plots.len()].root_tile()))
+        };
+        let home = || nth_plot(self.seed, |kind| matches!(kind, PlotKind::House(_)));
+        // Sites have no taverns yet, people gather on the main square to eat
+        let main_square = || {
+            site.plazas()
+                .next()
+                .map(|plaza| site.tile_center_wpos(site.plot(plaza).root_tile()))
+        };
+        let center = site.bounds().center();
+        // A point around the town at the given distance from its edge
+        let around_town = |turn: f32, dist: f32| {
+            let theta = (self.seed % 628) as f32 / 100.0 + turn;
+            center
+                + (Vec2::new(theta.cos(), theta.sin()) * (site.radius() + dist)).map(|e| e as i32)
+        };
+        let day = (time_of_day.0 / (24.0 * 3600.0)) as u32;
+
+        let wpos = match (activity, self.kind) {
+            (Activity::Sleep, _) => home(),
+            (Activity::Eat, _) => main_square(),
+            (Activity::Work, RtSimEntityKind::Blacksmith) => {
+                nth_plot(self.seed, |kind| matches!(kind, PlotKind::Workshop(_)))
+            },
+            (Activity::Work, RtSimEntityKind::Merchant) => {
+                nth_plot(self.seed, |kind| matches!(kind, PlotKind::Plaza))
+            },
+            (Activity::Work, RtSimEntityKind::Farmer) => Some(around_town(0.0, 32.0)),
+            // Hunters head somewhere else in the wild every 10 minutes
+            (Activity::Work, RtSimEntityKind::Hunter) => {
+                Some(around_town((time.0 / 600.0).floor() as f32, 150.0))
+            },
+            // Guards patrol along the edge of the town
+            (Activity::Work, RtSimEntityKind::Guard) => {
+                Some(around_town((time.0 / 60.0).floor() as f32 * 0.5, -8.0))
+            },
+            // Other villagers go about their business in a different place each day
+            (Activity::Work, _) => nth_plot(self.seed.wrapping_add(day), |kind| {
+                matches!(
+                    kind,
+                    PlotKind::House(_) | PlotKind::Workshop(_) | PlotKind::Plaza
+                )
+            }),
+        };
+
+        // Spread out people sharing the same place
+        wpos.or_else(main_square).unwrap_or(center)
+            + Vec2::new(self.seed % 9, (self.seed / 9) % 9).map(|e| e as i32 - 4)
+    }
+
+    /// Heads directly towards a site, returns whether it was reached.
+    fn head_to_site(
+        &mut self,
+        site_id: Id<Site>,
+        terrain: &TerrainGrid,
+        world: &World,
+        index: &IndexRef,
+    ) -> bool {
+        let site = &world.civs().sites[site_id];
+        let wpos = site.center.map2(TerrainChunk::RECT_SIZE, |e, sz| {
+            e * sz as i32 + sz as i32 / 2
+        });
+        if wpos.map(|e| e as f32).distance_squared(self.pos.xy()) < 64.0f32.powi(2) {
+            return true;
+        }
+
+        let destination_name = site
+            .site_tmp
+            .map_or("".to_string(), |id| index.sites[id].name().to_string());
+        let travel_to = self.pos.xy()
+            + (wpos.map(|e| e as f32 + 0.5) - self.pos.xy())
+                .try_normalized()
+                .unwrap_or_else(Vec2::zero)
+                * 64.0;
+        let travel_to_alt = world
+            .sim()
+            .get_alt_approx(travel_to.map(|e| e as i32))
+            .unwrap_or(0.0) as i32;
+        let travel_to = terrain
+            .find_space(travel_to.map(|e| e as i32).with_z(travel_to_alt))
+            .map(|e| e as f32)
+            + Vec3::new(0.5, 0.5, 0.0);
+
+        self.controller.travel_to = Some((travel_to, destination_name));
+        self.controller.speed_factor = 0.70;
+        false
+    }
+
+    pub fn tick(
+        &mut self,
+        time: &Time,
+        time_of_day: &TimeOfDay,
+        terrain: &TerrainGrid,
+        world: &World,
+        index: &IndexRef,
+        economies: &mut SiteEconomies,
+    ) {
         self.brain.route = match self.brain.route.clone() {
             Travel::Lost => {
                 match self.get_body() {
@@ -628,6 +886,85 @@ impl Entity {
                     }
                 }
             },
+            Travel::Routine { site_id } => {
+                let site = &world.civs().sites[site_id];
+                if let Some((SiteKind::Refactor(site2), destination_name)) = site
+                    .site_tmp
+                    .map(|id| (&index.sites[id].kind, index.sites[id].name().to_string()))
+                {
+                    let activity = self.activity(time_of_day);
+                    let wpos = self.routine_wpos(activity, site2, time, time_of_day);
+                    let dist = wpos.map(|e| e as f32).distance_squared(self.pos.xy()) as u32;
+
+                    if dist < 8_u32.pow(2) {
+                        self.controller.travel_to = None;
+                    } else {
+                        let travel_to_alt = world.sim().get_alt_approx(wpos).unwrap_or(0.0) as i32;
+                        let travel_to = terrain
+                            .find_space(wpos.with_z(travel_to_alt))
+                            .map(|e| e as f32)
+                            + Vec3::new(0.5, 0.5, 0.0);
+
+                        self.controller.travel_to = Some((travel_to, destination_name));
+                        self.controller.speed_factor = match activity {
+                            Activity::Sleep => 0.5,
+                            Activity::Eat | Activity::Work => 0.7,
+                        };
+                    }
+                    Travel::Routine { site_id }
+                } else {
+                    // Only site2 towns have a layout to follow a schedule in
+                    Travel::InSite { site_id }
+                }
+            },
+            Travel::Caravan {
+                home_id,
+                target_id,
+                returning,
+                cargo,
+            } => {
+                let destination = if returning { home_id } else { target_id };
+                if !self.head_to_site(destination, terrain, world, index) {
+                    Travel::Caravan {
+                        home_id,
+                        target_id,
+                        returning,
+                        cargo,
+                    }
+                } else if let (false, Some(site)) =
+                    (returning, world.civs().sites[target_id].site_tmp)
+                {
+                    // Trade the goods from home for those of the target site
+                    Travel::Caravan {
+                        home_id,
+                        target_id,
+                        returning: true,
+                        cargo: economies.exchange_cargo(site, cargo),
+                    }
+                } else {
+                    let home = world.civs().sites[home_id].site_tmp;
+                    if let Some(site) = home {
+                        economies.unload_cargo(site, cargo);
+                    }
+                    // Set off again towards one of the neighbouring towns
+                    let target = world
+                        .civs()
+                        .neighbors(home_id)
+                        .filter(|id| world.civs().sites[*id].is_settlement())
+                        .collect::<Vec<_>>()
+                        .choose(&mut thread_rng())
+                        .copied();
+                    match (target, home) {
+                        (Some(target_id), Some(site)) => Travel::Caravan {
+                            home_id,
+                            target_id,
+                            returning: false,
+                            cargo: economies.load_cargo(site),
+                        },
+                        _ => Travel::Routine { site_id: home_id },
+                    }
+                }
+            },
             Travel::Idle => Travel::Idle,
         };
 
@@ -638,7 +975,7 @@ impl Entity {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 enum Travel {
     // The initial state all entities start in, and a fallback for when a state has stopped making
     // sense. Non humanoids will always revert to this state after reaching their goal since the
@@ -677,6 +1014,17 @@ enum Travel {
         raid_complete: bool,
         time_to_move: Option<f64>,
     },
+    // Follow the daily schedule of the entity in its home town
+    Routine {
+        site_id: Id<Site>,
+    },
+    // Carry goods from a home town to a neighbouring one and trade them there, then head back
+    Caravan {
+        home_id: Id<Site>,
+        target_id: Id<Site>,
+        returning: bool,
+        cargo: Cargo,
+    },
     // For testing purposes
     Idle,
 }
@@ -685,7 +1033,7 @@ impl Default for Travel {
     fn default() -> Self { Self::Lost }
 }
 
-#[derive(Default)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct Brain {
     begin: Option<Id<Site>>,
     tgt: Option<Id<Site>>,
@@ -724,7 +1072,7 @@ impl Brain {
         Self {
             begin: Some(home_id),
             tgt: None,
-            route: Travel::Idle,
+            route: Travel::Routine { site_id: home_id },
             last_visited: None,
             memories: Vec::new(),
         }
@@ -734,7 +1082,23 @@ impl Brain {
         Self {
             begin: Some(home_id),
             tgt: None,
-            route: Travel::Idle,
+            route: Travel::Routine { site_id: home_id },
+            last_visited: None,
+            memories: Vec::new(),
+        }
+    }
+
+    /// A merchant travelling between its home town and the neighbouring ones.
+    pub fn caravan(home_id: Id<Site>) -> Self {
+        Self {
+            begin: Some(home_id),
+            tgt: None,
+            route: Travel::Caravan {
+                home_id,
+                target_id: home_id,
+                returning: true,
+                cargo: Vec::new(),
+            },
             last_visited: None,
             memories: Vec::new(),
         }
@@ -742,6 +1106,46 @@ impl Brain {
 
     pub fn begin_site(&self) -> Option<Id<Site>> { self.begin }
 
+    pub fn is_caravan(&self) -> bool { matches!(self.route, Travel::Caravan { .. }) }
+
+    fn home_site(&self) -> Option<Id<Site>> {
+        match self.route {
+            Travel::DirectRaid { home_id, .. } | Travel::Caravan { home_id, .. } => Some(home_id),
+            _ => self.begin,
+        }
+    }
+
+    /// The brain of an entity replacing this one, with the same home and
+    /// goals.
+    fn reborn(&self) -> Self {
+        let route = match &self.route {
+            Travel::DirectRaid {
+                target_id, home_id, ..
+            } => Travel::DirectRaid {
+                target_id: *target_id,
+                home_id: *home_id,
+                raid_complete: false,
+                time_to_move: None,
+            },
+            Travel::Routine { site_id } => Travel::Routine { site_id: *site_id },
+            Travel::Caravan { home_id, .. } => Travel::Caravan {
+                home_id: *home_id,
+                target_id: *home_id,
+                returning: true,
+                cargo: Vec::new(),
+            },
+            Travel::Idle => Travel::Idle,
+            _ => Travel::Lost,
+        };
+        Self {
+            begin: self.begin,
+            tgt: None,
+            route,
+            last_visited: None,
+            memories: Vec::new(),
+        }
+    }
+
     pub fn add_memory(&mut self, memory: Memory) { self.memories.push(memory); }
 
     pub fn forget_enemy(&mut self, to_forget: &str) {
@@ -794,6 +1198,19 @@ impl Brain {
                 MemoryItem::CharacterFight { name, .. } if name == name_to_remember)
         })
     }
+
+    fn rebase_time(&mut self, offset: f64) {
+        if let Travel::DirectRaid {
+            time_to_move: Some(time_to_move),
+            ..
+        } = &mut self.route
+        {
+            *time_to_move += offset;
+        }
+        for memory in &mut self.memories {
+            memory.time_to_forget += offset;
+        }
+    }
 }
 
 fn humanoid_config(kind: RtSimEntityKind) -> &'static str {
@@ -802,6 +1219,10 @@ fn humanoid_config(kind: RtSimEntityKind) -> &'static str {
         RtSimEntityKind::Random => "common.entity.world.traveler",
         RtSimEntityKind::Villager => "common.entity.village.villager",
         RtSimEntityKind::Merchant => "common.entity.village.merchant",
+        RtSimEntityKind::Guard => "common.entity.village.guard",
+        RtSimEntityKind::Farmer => "common.entity.village.farmer",
+        RtSimEntityKind::Blacksmith => "common.entity.village.blacksmith",
+        RtSimEntityKind::Hunter => "common.entity.village.hunter",
     }
 }
 
@@ -880,4 +1301,31 @@ mod tests {
             std::mem::drop(EntityInfo::at(dummy_pos).with_asset_expect(config));
         }
     }
+
+    #[test]
+    fn daily_activities() {
+        let at = |hour: f64| TimeOfDay(hour * 3600.0);
+        let farmer = RtSimEntityKind::Farmer;
+        assert_eq!(farmer.activity(0, &at(4.0)), Activity::Sleep);
+        assert_eq!(farmer.activity(0, &at(5.0)), Activity::Work);
+        assert_eq!(farmer.activity(0, &at(12.5)), Activity::Eat);
+        assert_eq!(farmer.activity(0, &at(22.0)), Activity::Sleep);
+        // The schedule repeats every day
+        assert_eq!(farmer.activity(0, &at(24.0 * 3.0 + 12.5)), Activity::Eat);
+
+        // Half of the guards work at night
+        let guard = RtSimEntityKind::Guard;
+        assert_eq!(guard.activity(0, &at(3.0)), Activity::Sleep);
+        assert_eq!(guard.activity(1, &at(3.0)), Activity::Work);
+        assert_eq!(guard.activity(0, &at(12.0)), Activity::Work);
+        assert_eq!(guard.activity(1, &at(12.0)), Activity::Sleep);
+
+        for kind in RtSimEntityKind::iter() {
+            for seed in 0..2 {
+                let schedule = kind.schedule(seed);
+                assert_eq!(schedule[0].0, 0.0);
+                assert!(schedule.windows(2).all(|w| w[0].0 < w[1].0));
+            }
+        }
+    }
 }
@@ -1,15 +1,20 @@
 #![allow(dead_code)] // TODO: Remove this when rtsim is fleshed out
 
 mod chunks;
+mod economy;
 mod entity;
 mod load_chunks;
+mod persistence;
 mod tick;
 mod unload_chunks;
 
 use self::chunks::Chunks;
+use crate::{data_dir::DataDir, Settings};
 use common::{
     comp,
+    resources::Time,
     rtsim::{Memory, RtSimController, RtSimEntity, RtSimId},
+    store::Id,
     terrain::TerrainChunk,
     vol::RectRasterableVol,
 };
@@ -19,13 +24,44 @@ use rand::prelude::*;
 use slab::Slab;
 use specs::{DispatcherBuilder, WorldExt};
 use vek::*;
+use world::civ::Site;
 
-pub use self::entity::{Brain, Entity, RtSimEntityKind};
+pub use self::{
+    economy::SiteEconomies,
+    entity::{Activity, Brain, Entity, RtSimEntityKind},
+    persistence::RtSimPersistence,
+};
+
+/// How common each profession is among the inhabitants of towns
+const TOWN_PROFESSIONS: &[(RtSimEntityKind, f32)] = &[
+    (RtSimEntityKind::Villager, 4.0),
+    (RtSimEntityKind::Farmer, 2.0),
+    (RtSimEntityKind::Guard, 1.0),
+    (RtSimEntityKind::Blacksmith, 1.0),
+    (RtSimEntityKind::Hunter, 1.0),
+];
+
+/// Chance for a merchant to travel between towns rather than staying home
+const CARAVAN_CHANCE: f64 = 0.25;
+
+/// Time it takes for a dead entity to be replaced at its home site
+const RESPAWN_DELAY: f64 = 20.0 * 60.0;
 
 pub struct RtSim {
     tick: u64,
     chunks: Chunks,
     entities: Slab<Entity>,
+    /// Entities that died since the last tick
+    deaths: Vec<Entity>,
+    /// Entities waiting to take the place of dead ones
+    respawns: Vec<Respawn>,
+}
+
+/// An entity that will be added to the simulation once the time comes
+pub struct Respawn {
+    pub time: f64,
+    pub home: Id<Site>,
+    pub entity: Entity,
 }
 
 impl RtSim {
@@ -34,6 +70,8 @@ impl RtSim {
             tick: 0,
             chunks: Chunks::new(world_chunk_size),
             entities: Slab::new(),
+            deaths: Vec::new(),
+            respawns: Vec::new(),
         }
     }
 
@@ -69,9 +107,13 @@ impl RtSim {
         self.entities.get_mut(entity).map(|e| e.pos = pos);
     }
 
+    /// Removes a dead entity from the simulation, it is replaced at its home
+    /// site after a while.
     pub fn destroy_entity(&mut self, entity: RtSimId) {
         // tracing::info!("Destroyed rtsim entity {}", entity);
-        self.entities.remove(entity);
+        if self.entities.contains(entity) {
+            self.deaths.push(self.entities.remove(entity));
+        }
     }
 
     pub fn get_entity(&self, entity: RtSimId) -> Option<&Entity> { self.entities.get(entity) }
@@ -115,6 +157,65 @@ pub fn init(
     #[cfg(not(feature = "worldgen"))]
     let mut rtsim = RtSim::new(Vec2::new(40, 40));
 
+    #[cfg(feature = "worldgen")]
+    let (world_seed, site_count) = (world.sim().seed, world.civs().sites.len());
+    #[cfg(not(feature = "worldgen"))]
+    let (world_seed, site_count) = (0, 0);
+    let persistence = RtSimPersistence::new(
+        &state.ecs().fetch::<DataDir>().path,
+        world_seed,
+        site_count,
+        state.ecs().fetch::<Settings>().rtsim_autosave_interval,
+    );
+
+    let economies_save = if let Some((tick, entities, economies)) =
+        persistence.load(state.ecs().fetch::<Time>().0)
+    {
+        rtsim.tick = tick;
+        for entity in entities {
+            rtsim.entities.insert(entity);
+        }
+        economies
+    } else {
+        generate(
+            &mut rtsim,
+            #[cfg(feature = "worldgen")]
+            world,
+            #[cfg(feature = "worldgen")]
+            index,
+            #[cfg(feature = "worldgen")]
+            spawn_point,
+        );
+        None
+    };
+
+    state.ecs_mut().insert(rtsim);
+    state.ecs_mut().insert(persistence);
+    #[cfg(feature = "worldgen")]
+    {
+        let mut economies = SiteEconomies::new(index.index);
+        if let Some(save) = economies_save {
+            economies.restore(index.index, save);
+        }
+        state.ecs_mut().insert(economies);
+    }
+    #[cfg(not(feature = "worldgen"))]
+    {
+        // There are no sites to restore the economies of without a world.
+        drop(economies_save);
+        state.ecs_mut().insert(SiteEconomies::default());
+    }
+    state.ecs_mut().register::<RtSimEntity>();
+    tracing::info!("Initiated real-time world simulation");
+}
+
+/// Populates a new rtsim state from the world.
+fn generate(
+    #[cfg_attr(not(feature = "worldgen"), allow(unused_variables))] rtsim: &mut RtSim,
+    #[cfg(feature = "worldgen")] world: &world::World,
+    #[cfg(feature = "worldgen")] index: world::IndexRef,
+    #[cfg(feature = "worldgen")] spawn_point: crate::SpawnPoint,
+) {
     // TODO: Determine number of rtsim entities based on things like initial site
     // populations rather than world size
     #[cfg(feature = "worldgen")]
@@ -212,7 +313,9 @@ pub fn init(
                             seed: thread_rng().gen(),
                             controller: RtSimController::default(),
                             last_time_ticked: 0.0,
-                            kind: RtSimEntityKind::Villager,
+                            kind: TOWN_PROFESSIONS
+                                .choose_weighted(&mut thread_rng(), |(_, weight)| *weight)
+                                .map_or(RtSimEntityKind::Villager, |(kind, _)| *kind),
                             brain: Brain::villager(site_id),
                         });
                     }
@@ -236,7 +339,11 @@ pub fn init(
                             controller: RtSimController::default(),
                             last_time_ticked: 0.0,
                             kind: RtSimEntityKind::Merchant,
-                            brain: Brain::merchant(site_id),
+                            brain: if thread_rng().gen_bool(CARAVAN_CHANCE) {
+                                Brain::caravan(site_id)
+                            } else {
+                                Brain::merchant(site_id)
+                            },
                         });
                     }
                 },
@@ -244,8 +351,4 @@ pub fn init(
             }
         }
     }
-
-    state.ecs_mut().insert(rtsim);
-    state.ecs_mut().register::<RtSimEntity>();
-    tracing::info!("Initiated real-time world simulation");
 }
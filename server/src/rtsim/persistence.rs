@@ -0,0 +1,316 @@
+//! Saving and loading of the rtsim state, so that the simulated world does
+//! not start over each time the server restarts.
+
+use super::{Entity, RtSim, SiteEconomies};
+use atomicwrites::{AtomicFile, OverwriteBehavior};
+use common::slowjob::SlowJobPool;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tracing::{debug, error, info, warn};
+
+const FILENAME: &str = "rtsim.ron";
+
+/// NOTE: Always replace this with the latest save version, then update
+/// `RtSimSaveRaw` and its conversions.
+pub use self::v1::*;
+
+/// Versioned save files, one per version.
+#[derive(Deserialize, Serialize)]
+pub enum RtSimSaveRaw {
+    V0(v0::RtSimSave),
+    V1(v1::RtSimSave),
+}
+
+impl From<RtSimSave> for RtSimSaveRaw {
+    fn from(value: RtSimSave) -> Self {
+        // Replace variant with that of current latest version.
+        Self::V1(value)
+    }
+}
+
+impl From<RtSimSaveRaw> for RtSimSave {
+    fn from(value: RtSimSaveRaw) -> Self {
+        match value {
+            RtSimSaveRaw::V0(value) => value.into(),
+            RtSimSaveRaw::V1(value) => value,
+        }
+    }
+}
+
+mod v0 {
+    use super::Entity;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Deserialize, Serialize)]
+    pub struct RtSimSave {
+        /// Seed of the world the state was simulated in, the state of another
+        /// world refers to sites that do not exist.
+        pub world_seed: u32,
+        pub site_count: usize,
+        pub tick: u64,
+        /// Server time at which the state was saved, the times stored by the
+        /// entities are relative to it.
+        pub time: f64,
+        pub entities: Vec<Entity>,
+    }
+}
+
+mod v1 {
+    use super::{v0 as prev, Entity};
+    use common::trade::Good;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Deserialize, Serialize)]
+    pub struct RtSimSave {
+        /// Seed of the world the state was simulated in, the state of another
+        /// world refers to sites that do not exist.
+        pub world_seed: u32,
+        pub site_count: usize,
+        pub tick: u64,
+        /// Server time at which the state was saved, the times stored by the
+        /// entities are relative to it.
+        pub time: f64,
+        pub entities: Vec<Entity>,
+        /// None for saves made before the economies were saved, whose sites
+        /// start over from the economies the world was generated with
+        pub economies: Option<EconomiesSave>,
+    }
+
+    /// What changed in the economies of the sites since the world was
+    /// generated.
+    #[derive(Deserialize, Serialize)]
+    pub struct EconomiesSave {
+        /// Days elapsed in the economic simulation
+        pub days: f32,
+        pub sites: Vec<SiteEconomySave>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    pub struct SiteEconomySave {
+        /// Id of the site in the world index
+        pub site: u64,
+        pub pop: f32,
+        pub stocks: Vec<(Good, f32)>,
+        pub unconsumed_stock: Vec<(Good, f32)>,
+        /// Goods without a value are left out
+        pub values: Vec<(Good, f32)>,
+    }
+
+    impl From<prev::RtSimSave> for RtSimSave {
+        fn from(value: prev::RtSimSave) -> Self {
+            Self {
+                world_seed: value.world_seed,
+                site_count: value.site_count,
+                tick: value.tick,
+                time: value.time,
+                entities: value.entities,
+                economies: None,
+            }
+        }
+    }
+}
+
+/// Periodically saves the rtsim state to the data directory.
+pub struct RtSimPersistence {
+    path: PathBuf,
+    world_seed: u32,
+    site_count: usize,
+    autosave_interval: f64,
+    last_save: f64,
+}
+
+impl RtSimPersistence {
+    pub fn new(
+        data_dir: &Path,
+        world_seed: u32,
+        site_count: usize,
+        autosave_interval: Duration,
+    ) -> Self {
+        Self {
+            path: data_dir.join(FILENAME),
+            world_seed,
+            site_count,
+            autosave_interval: autosave_interval.as_secs_f64(),
+            last_save: 0.0,
+        }
+    }
+
+    /// Loads the saved state, if there is one matching the current world.
+    /// Times are rebased on `time`, the current server time.
+    pub fn load(&self, time: f64) -> Option<(u64, Vec<Entity>, Option<EconomiesSave>)> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                error!(?e, "Failed to open rtsim save {:?}", self.path);
+                return None;
+            },
+        };
+        let save = match ron::de::from_reader::<_, RtSimSaveRaw>(file) {
+            Ok(save) => RtSimSave::from(save),
+            Err(e) => {
+                error!(?e, "Failed to parse rtsim save {:?}", self.path);
+                return None;
+            },
+        };
+
+        if save.world_seed != self.world_seed || save.site_count != self.site_count {
+            warn!(
+                "The rtsim save {:?} was made for another world, generating a new rtsim state",
+                self.path
+            );
+            return None;
+        }
+
+        let offset = time - save.time;
+        let mut entities = save.entities;
+        entities
+            .iter_mut()
+            .for_each(|entity| entity.rebase_time(offset));
+        info!(
+            "Loaded {} rtsim entities from {:?}",
+            entities.len(),
+            self.path
+        );
+        Some((save.tick, entities, save.economies))
+    }
+
+    fn snapshot(&self, rtsim: &RtSim, economies: &SiteEconomies, time: f64) -> RtSimSave {
+        RtSimSave {
+            world_seed: self.world_seed,
+            site_count: self.site_count,
+            tick: rtsim.tick,
+            time,
+            // Entities waiting to replace dead ones are saved as having arrived already
+            entities: rtsim
+                .entities
+                .iter()
+                .map(|(_, entity)| entity)
+                .chain(rtsim.respawns.iter().map(|respawn| &respawn.entity))
+                .cloned()
+                .collect(),
+            economies: Some(economies.snapshot()),
+        }
+    }
+
+    /// Saves the state in the background once the autosave interval elapsed.
+    pub fn maintain(
+        &mut self,
+        rtsim: &RtSim,
+        economies: &SiteEconomies,
+        time: f64,
+        slow_jobs: &SlowJobPool,
+    ) {
+        if time - self.last_save < self.autosave_interval {
+            return;
+        }
+        self.last_save = time;
+
+        let save = self.snapshot(rtsim, economies, time);
+        let path = self.path.clone();
+        slow_jobs.spawn("RTSIM_SAVE", move || {
+            if let Err(e) = write_save(&path, save) {
+                error!(?e, "Failed to save rtsim state to {:?}", path);
+            }
+        });
+    }
+
+    /// Saves the state immediately, used when the server shuts down.
+    pub fn save(&mut self, rtsim: &RtSim, economies: &SiteEconomies, time: f64) {
+        self.last_save = time;
+        let save = self.snapshot(rtsim, economies, time);
+        match write_save(&self.path, save) {
+            Ok(()) => info!("Saved rtsim state to {:?}", self.path),
+            Err(e) => error!(?e, "Failed to save rtsim state to {:?}", self.path),
+        }
+    }
+}
+
+fn write_save(path: &Path, save: RtSimSave) -> io::Result<()> {
+    let ron = ron::ser::to_string(&RtSimSaveRaw::from(save))
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    AtomicFile::new(path, OverwriteBehavior::AllowOverwrite)
+        .write(|file| file.write_all(ron.as_bytes()))
+        .map_err(|e| match e {
+            atomicwrites::Error::Internal(e) | atomicwrites::Error::User(e) => e,
+        })?;
+    debug!("Wrote rtsim state to {:?}", path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::trade::Good;
+
+    fn data_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("veloren-rtsim-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn save(world_seed: u32, tick: u64, time: f64) -> RtSimSave {
+        RtSimSave {
+            world_seed,
+            site_count: 3,
+            tick,
+            time,
+            entities: Vec::new(),
+            economies: Some(EconomiesSave {
+                days: 2.0,
+                sites: vec![SiteEconomySave {
+                    site: 1,
+                    pop: 10.0,
+                    stocks: vec![(Good::Food, 5.0)],
+                    unconsumed_stock: Vec::new(),
+                    values: vec![(Good::Food, 0.5)],
+                }],
+            }),
+        }
+    }
+
+    #[test]
+    fn load_saves_of_the_same_world() {
+        let dir = data_dir("world");
+        let persistence = RtSimPersistence::new(&dir, 42, 3, Duration::from_secs(60));
+        assert!(persistence.load(0.0).is_none());
+
+        write_save(&dir.join(FILENAME), save(42, 7, 10.0)).unwrap();
+        let (tick, entities, economies) = persistence.load(100.0).unwrap();
+        assert_eq!(tick, 7);
+        assert!(entities.is_empty());
+        let economies = economies.unwrap();
+        assert_eq!(economies.days, 2.0);
+        assert_eq!(economies.sites[0].stocks, vec![(Good::Food, 5.0)]);
+
+        let other_seed = RtSimPersistence::new(&dir, 43, 3, Duration::from_secs(60));
+        assert!(other_seed.load(100.0).is_none());
+        let other_sites = RtSimPersistence::new(&dir, 42, 4, Duration::from_secs(60));
+        assert!(other_sites.load(100.0).is_none());
+    }
+
+    #[test]
+    fn v0_saves_are_migrated() {
+        let dir = data_dir("v0");
+        let save = RtSimSaveRaw::V0(v0::RtSimSave {
+            world_seed: 42,
+            site_count: 3,
+            tick: 7,
+            time: 10.0,
+            entities: Vec::new(),
+        });
+        std::fs::write(dir.join(FILENAME), ron::ser::to_string(&save).unwrap()).unwrap();
+
+        let persistence = RtSimPersistence::new(&dir, 42, 3, Duration::from_secs(60));
+        let (tick, _, economies) = persistence.load(100.0).unwrap();
+        assert_eq!(tick, 7);
+        assert!(economies.is_none());
+    }
+}
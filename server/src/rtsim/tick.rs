@@ -6,7 +6,7 @@ use common::{
     comp,
     event::{EventBus, ServerEvent},
     generation::{BodyBuilder, EntityConfig, EntityInfo},
-    resources::{DeltaTime, Time},
+    resources::{DeltaTime, Time, TimeOfDay},
     terrain::TerrainGrid,
 };
 use common_ecs::{Job, Origin, Phase, System};
@@ -19,9 +19,11 @@ impl<'a> System<'a> for Sys {
     #[allow(clippy::type_complexity)]
     type SystemData = (
         Read<'a, Time>,
+        Read<'a, TimeOfDay>,
         Read<'a, DeltaTime>,
         Read<'a, EventBus<ServerEvent>>,
         WriteExpect<'a, RtSim>,
+        WriteExpect<'a, SiteEconomies>,
         ReadExpect<'a, TerrainGrid>,
         ReadExpect<'a, Arc<world::World>>,
         ReadExpect<'a, world::IndexOwned>,
@@ -38,9 +40,11 @@ impl<'a> System<'a> for Sys {
         _job: &mut Job<Self>,
         (
             time,
+            time_of_day,
             _dt,
             server_event_bus,
             mut rtsim,
+            mut economies,
             terrain,
             world,
             index,
@@ -52,10 +56,13 @@ impl<'a> System<'a> for Sys {
         let rtsim = &mut *rtsim;
         rtsim.tick += 1;
 
+        economies.tick(&index, time_of_day.0);
+
         // Update unloaded rtsim entities, in groups at a time
         const TICK_STAGGER: usize = 30;
         let entities_per_iteration = rtsim.entities.len() / TICK_STAGGER;
         let mut to_reify = Vec::new();
+        let mut to_kill = Vec::new();
         for (id, entity) in rtsim
             .entities
             .iter_mut()
@@ -94,14 +101,66 @@ impl<'a> System<'a> for Sys {
                 {
                     entity.pos.z = alt;
                 }
+
+                // Travelling through dangerous lands might be the last thing the entity does
+                if thread_rng().gen_bool((entity.danger(&world) * dt).clamp(0.0, 1.0) as f64) {
+                    to_kill.push(id);
+                    continue;
+                }
             }
-            entity.tick(&time, &terrain, &world, &index.as_index_ref());
+            entity.tick(
+                &time,
+                &time_of_day,
+                &terrain,
+                &world,
+                &index.as_index_ref(),
+                &mut economies,
+            );
         }
 
         // Tick entity AI each time if it's loaded
         for (_, entity) in rtsim.entities.iter_mut().filter(|(_, e)| e.is_loaded) {
             entity.last_time_ticked = time.0;
-            entity.tick(&time, &terrain, &world, &index.as_index_ref());
+            entity.tick(
+                &time,
+                &time_of_day,
+                &terrain,
+                &world,
+                &index.as_index_ref(),
+                &mut economies,
+            );
+        }
+
+        for id in to_kill {
+            rtsim.destroy_entity(id);
+        }
+
+        // Dead entities are replaced by newcomers at their home site after a while,
+        // leaving a gap in the population of the site in the meantime
+        for dead in std::mem::take(&mut rtsim.deaths) {
+            if let Some(home) = dead.home_site(&world) {
+                if let Some(site) = world.civs().sites[home].site_tmp {
+                    // The cargo of caravans was already taken out of the stock of their home
+                    let merchant =
+                        matches!(dead.kind, RtSimEntityKind::Merchant) && !dead.brain.is_caravan();
+                    economies.record_death(site, merchant);
+                }
+                rtsim.respawns.push(Respawn {
+                    time: time.0 + RESPAWN_DELAY,
+                    home,
+                    entity: dead.replacement(home, &world, &time),
+                });
+            }
+        }
+        let (arrived, waiting) = std::mem::take(&mut rtsim.respawns)
+            .into_iter()
+            .partition::<Vec<_>, _>(|respawn| respawn.time <= time.0);
+        rtsim.respawns = waiting;
+        for respawn in arrived {
+            if let Some(site) = world.civs().sites[respawn.home].site_tmp {
+                economies.record_arrival(site);
+            }
+            rtsim.entities.insert(respawn.entity);
         }
 
         let mut server_emitter = server_event_bus.emitter();
@@ -139,7 +198,7 @@ impl<'a> System<'a> for Sys {
                     .with_entity_config(entity_config, Some(entity_config_path))
                     .with_lazy_loadout(ad_hoc_loadout);
                 // Merchants can be traded with
-                if let Some(economy) = entity.get_trade_info(&world, &index) {
+                if let Some(economy) = entity.get_trade_info(&world, &index, &economies) {
                     entity_info = entity_info
                         .with_agent_mark(comp::agent::Mark::Merchant)
                         .with_economy(&economy);
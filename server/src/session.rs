@@ -0,0 +1,52 @@
+use common_net::msg::SessionToken;
+use specs::Component;
+use specs_idvs::IdvStorage;
+
+/// Session of an ingame character, lets its client resume it after losing the
+/// connection. While the client is gone the entity has no `Client` component.
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub token: SessionToken,
+    /// Time the connection was lost at, the character is removed once the
+    /// reconnect grace period has passed
+    pub disconnected_at: Option<f64>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self {
+            token: rand::random(),
+            disconnected_at: None,
+        }
+    }
+
+    pub fn is_expired(&self, now: f64, grace_period: std::time::Duration) -> bool {
+        self.disconnected_at
+            .map_or(false, |at| now - at > grace_period.as_secs_f64())
+    }
+}
+
+impl Default for Session {
+    fn default() -> Self { Self::new() }
+}
+
+impl Component for Session {
+    type Storage = IdvStorage<Self>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn expires_after_grace_period() {
+        let grace_period = Duration::from_secs(60);
+        let mut session = Session::new();
+        assert!(!session.is_expired(1000.0, grace_period));
+
+        session.disconnected_at = Some(100.0);
+        assert!(!session.is_expired(130.0, grace_period));
+        assert!(session.is_expired(161.0, grace_period));
+    }
+}
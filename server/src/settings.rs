@@ -1,6 +1,8 @@
 pub mod admin;
 pub mod banlist;
 mod editable;
+pub mod mutelist;
+pub mod permission_groups;
 pub mod server_description;
 pub mod whitelist;
 
@@ -8,8 +10,10 @@ pub use editable::{EditableSetting, Error as SettingError};
 
 pub use admin::{AdminRecord, Admins};
 pub use banlist::{
-    Ban, BanAction, BanEntry, BanError, BanErrorKind, BanInfo, BanKind, BanRecord, Banlist,
+    Ban, BanAction, BanEntry, BanError, BanErrorKind, BanInfo, BanKind, BanRecord, Banlist, IpRange,
 };
+pub use mutelist::{MuteInfo, MuteRecord, Mutelist};
+pub use permission_groups::{GroupMembership, PermissionGroup, PermissionGroups};
 pub use server_description::ServerDescription;
 pub use whitelist::{Whitelist, WhitelistInfo, WhitelistRecord};
 
@@ -18,6 +22,8 @@ use common::{
     calendar::{Calendar, CalendarEvent},
     resources::BattleMode,
 };
+#[cfg(feature = "plugins")]
+use common_state::plugin::limits::PluginLimits;
 use core::time::Duration;
 use portpicker::pick_unused_port;
 use serde::{Deserialize, Serialize};
@@ -36,6 +42,8 @@ const WHITELIST_FILENAME: &str = "whitelist.ron";
 const BANLIST_FILENAME: &str = "banlist.ron";
 const SERVER_DESCRIPTION_FILENAME: &str = "description.ron";
 const ADMINS_FILENAME: &str = "admins.ron";
+const MUTELIST_FILENAME: &str = "mutelist.ron";
+const PERMISSION_GROUPS_FILENAME: &str = "permission_groups.ron";
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct X509FilePair {
@@ -88,12 +96,167 @@ impl CalendarMode {
     }
 }
 
+/// Limits on the persistent key-value storage of each plugin
+#[cfg(feature = "plugins")]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PluginStorageQuota {
+    pub max_keys: usize,
+    /// Total size of the keys and values
+    pub max_bytes: usize,
+}
+
+#[cfg(feature = "plugins")]
+impl Default for PluginStorageQuota {
+    fn default() -> Self {
+        Self {
+            max_keys: 4096,
+            max_bytes: 4 * 1024 * 1024,
+        }
+    }
+}
+
+/// Limits on how much players can chat, to protect against spam
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChatLimits {
+    /// Messages a player can send per second over time
+    pub messages_per_second: f32,
+    /// Messages a player can send in quick succession
+    pub burst: u32,
+    /// Times a player can repeat the same message in a row
+    pub max_repeats: u32,
+    /// Dropped messages after which a player is temporarily muted, 0 to never
+    /// mute them
+    pub violations_before_mute: u32,
+    pub mute_duration: Duration,
+}
+
+impl Default for ChatLimits {
+    fn default() -> Self {
+        Self {
+            messages_per_second: 0.5,
+            burst: 5,
+            max_repeats: 2,
+            violations_before_mute: 5,
+            mute_duration: Duration::from_secs(60),
+        }
+    }
+}
+
+/// How often the character database is backed up while the server runs
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DatabaseBackupSettings {
+    /// Time between two backups, None to disable the periodic backups
+    pub interval: Option<Duration>,
+    /// Number of periodic backups to keep, the oldest ones being deleted, or 0
+    /// to keep all of them
+    pub retention: usize,
+}
+
+impl Default for DatabaseBackupSettings {
+    fn default() -> Self {
+        Self {
+            interval: Some(Duration::from_secs(6 * 3600)),
+            retention: 8,
+        }
+    }
+}
+
+/// Limits on the reports players can file against each other
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReportSettings {
+    /// Last chat lines of the reported player attached to a report
+    pub chat_lines: usize,
+    /// Time a player has to wait between two reports
+    pub cooldown: Duration,
+    /// Reports of a player which can wait for a moderator at once, or 0 for no
+    /// limit
+    pub max_open: usize,
+}
+
+impl Default for ReportSettings {
+    fn default() -> Self {
+        Self {
+            chat_lines: 20,
+            cooldown: Duration::from_secs(60),
+            max_open: 3,
+        }
+    }
+}
+
+/// Widest address ranges moderators can ban, only admins can ban wider ones
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IpBanSettings {
+    /// Shortest prefix length of IPv4 ranges, e.g. 24 for `1.2.3.0/24`
+    pub min_ipv4_prefix_len: u8,
+    /// Shortest prefix length of IPv6 ranges
+    pub min_ipv6_prefix_len: u8,
+}
+
+impl Default for IpBanSettings {
+    fn default() -> Self {
+        Self {
+            min_ipv4_prefix_len: 24,
+            min_ipv6_prefix_len: 48,
+        }
+    }
+}
+
+impl IpBanSettings {
+    /// Whether moderators can ban the range
+    pub fn allows_moderators(&self, range: &IpRange) -> bool {
+        range.prefix_len()
+            >= if range.is_ipv4() {
+                self.min_ipv4_prefix_len
+            } else {
+                self.min_ipv6_prefix_len
+            }
+    }
+}
+
+/// Status queries of server browsers, answered without them connecting
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuerySettings {
+    /// UDP address status queries are answered on, None to not answer them,
+    /// e.g. `0.0.0.0:14006`
+    pub address: Option<SocketAddr>,
+    /// Whether the aliases of the online players are listed, they become
+    /// public to anyone able to query the server
+    pub list_players: bool,
+    /// Queries an ip address can send per second over time, more are ignored
+    pub requests_per_second: f32,
+    /// Queries an ip address can send in quick succession
+    pub burst: u32,
+}
+
+impl Default for QuerySettings {
+    fn default() -> Self {
+        Self {
+            address: None,
+            list_players: false,
+            requests_per_second: 1.0,
+            burst: 5,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(default)]
 pub struct Settings {
     pub gameserver_address: SocketAddr,
     pub metrics_address: SocketAddr,
+    pub query: QuerySettings,
     pub auth_server_address: Option<String>,
+    /// TCP address TLS encrypted connections are accepted on, using the
+    /// certificate of `quic_files`, None to not accept them, e.g.
+    /// `0.0.0.0:14007`
+    pub tls_address: Option<SocketAddr>,
+    /// certificate for QUIC and TLS
     pub quic_files: Option<X509FilePair>,
     pub max_players: usize,
     pub world_seed: u32,
@@ -107,10 +270,23 @@ pub struct Settings {
     pub banned_words_files: Vec<PathBuf>,
     pub max_player_group_size: u32,
     pub client_timeout: Duration,
+    /// How long the character of a client that lost its connection stays in
+    /// the world for it to reconnect, zero disables reconnecting
+    pub reconnect_grace_period: Duration,
     pub spawn_town: Option<String>,
     pub safe_spawn: bool,
     pub max_player_for_kill_broadcast: Option<usize>,
     pub calendar_mode: CalendarMode,
+    /// Time between two saves of the real-time simulation state
+    pub rtsim_autosave_interval: Duration,
+    #[cfg(feature = "plugins")]
+    pub plugin_storage_quota: PluginStorageQuota,
+    pub chat_limits: ChatLimits,
+    pub database_backups: DatabaseBackupSettings,
+    pub reports: ReportSettings,
+    pub ip_bans: IpBanSettings,
+    #[cfg(feature = "plugins")]
+    pub plugin_limits: PluginLimits,
 
     /// Experimental feature. No guaranteed forwards-compatibility, may be
     /// removed at *any time* with no migration.
@@ -123,7 +299,9 @@ impl Default for Settings {
         Self {
             gameserver_address: SocketAddr::from(([0; 4], 14004)),
             metrics_address: SocketAddr::from(([0; 4], 14005)),
+            query: QuerySettings::default(),
             auth_server_address: Some("https://auth.veloren.net".into()),
+            tls_address: None,
             quic_files: None,
             world_seed: DEFAULT_WORLD_SEED,
             server_name: "Veloren Alpha".into(),
@@ -136,9 +314,19 @@ impl Default for Settings {
             max_player_group_size: 6,
             calendar_mode: CalendarMode::Auto,
             client_timeout: Duration::from_secs(40),
+            reconnect_grace_period: Duration::from_secs(60),
             spawn_town: None,
             safe_spawn: true,
             max_player_for_kill_broadcast: None,
+            rtsim_autosave_interval: Duration::from_secs(600),
+            #[cfg(feature = "plugins")]
+            plugin_storage_quota: PluginStorageQuota::default(),
+            chat_limits: ChatLimits::default(),
+            database_backups: DatabaseBackupSettings::default(),
+            reports: ReportSettings::default(),
+            ip_bans: IpBanSettings::default(),
+            #[cfg(feature = "plugins")]
+            plugin_limits: PluginLimits::default(),
             experimental_terrain_persistence: false,
         }
     }
@@ -205,7 +393,12 @@ impl Settings {
                 [127, 0, 0, 1],
                 pick_unused_port().expect("Failed to find unused port!"),
             )),
+            query: QuerySettings {
+                address: None,
+                ..load.query
+            },
             auth_server_address: None,
+            tls_address: None,
             quic_files: None,
             // If loading the default map file, make sure the seed is also default.
             world_seed: if load.map_file.is_some() {
@@ -254,6 +447,8 @@ pub struct EditableSettings {
     pub banlist: Banlist,
     pub server_description: ServerDescription,
     pub admins: Admins,
+    pub mutelist: Mutelist,
+    pub permission_groups: PermissionGroups,
 }
 
 impl EditableSettings {
@@ -263,6 +458,8 @@ impl EditableSettings {
             banlist: Banlist::load(data_dir),
             server_description: ServerDescription::load(data_dir),
             admins: Admins::load(data_dir),
+            mutelist: Mutelist::load(data_dir),
+            permission_groups: PermissionGroups::load(data_dir),
         }
     }
 
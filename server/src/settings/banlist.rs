@@ -12,7 +12,7 @@ use serde::{Deserialize, Serialize};
 /// BanlistRaw, the TryFrom<BanlistRaw> for Banlist, the previously most recent
 /// module, and add a new module for the latest version!  Please respect the
 /// migration upgrade guarantee found in the parent module with any upgrade.
-pub use self::v1::*;
+pub use self::v2::*;
 
 /// Versioned settings files, one per version (v0 is only here as an example; we
 /// do not expect to see any actual v0 settings files).
@@ -20,12 +20,13 @@ pub use self::v1::*;
 pub enum BanlistRaw {
     V0(v0::Banlist),
     V1(v1::Banlist),
+    V2(v2::Banlist),
 }
 
 impl From<Banlist> for BanlistRaw {
     fn from(value: Banlist) -> Self {
         // Replace variant with that of current latest version.
-        Self::V1(value)
+        Self::V2(value)
     }
 }
 
@@ -37,9 +38,10 @@ impl TryFrom<BanlistRaw> for (Version, Banlist) {
         Ok(match value {
             // Old versions
             V0(value) => (Version::Old, value.try_into()?),
+            V1(value) => (Version::Old, value.try_into()?),
             // Latest version (move to old section using the pattern of other old version when it
             // is no longer latest).
-            V1(mut value) => (value.validate()?, value),
+            V2(mut value) => (value.validate()?, value),
         })
     }
 }
@@ -77,12 +79,19 @@ pub enum BanErrorKind {
 #[allow(dead_code)]
 pub struct BanError {
     kind: BanErrorKind,
-    /// Uuid of affected user
-    uuid: Uuid,
+    /// Affected user or addresses
+    target: BanTarget,
     /// Username of affected user (as of ban/unban time).
     username: String,
 }
 
+/// What a ban applies to.
+#[derive(Clone, Copy, Debug)]
+enum BanTarget {
+    Uuid(Uuid),
+    Ip(IpRange),
+}
+
 mod legacy {
     use super::{v0 as next, Final, MIGRATION_UPGRADE_GUARANTEE};
     use authc::Uuid;
@@ -190,16 +199,371 @@ mod v0 {
 }
 
 mod v1 {
-    use super::{v0 as prev, BanError, BanErrorKind, BanKind, Final};
+    use super::{
+        v0 as prev, v2 as next, BanError, BanErrorKind, BanKind, BanTarget, Final,
+        MIGRATION_UPGRADE_GUARANTEE,
+    };
+    use crate::settings::editable::{EditableSetting, Version};
+    use authc::Uuid;
+    use chrono::{prelude::*, Utc};
+    use common::comp::AdminRole;
+    use core::{
+        convert::{TryFrom, TryInto},
+        ops::Deref,
+    };
+    use hashbrown::HashMap;
+    use serde::{Deserialize, Serialize};
+    use tracing::warn;
+
+    /// Important: even if the role we are storing here appears to be identical
+    /// to one used in another versioned store (like admin::Role), we *must*
+    /// have our own versioned copy!  This ensures that if there's an update
+    /// to the role somewhere else, the conversion function between them
+    /// will break, letting people make an intelligent decision.
+    ///
+    /// In particular, *never remove variants from this enum* (or any other enum
+    /// in a versioned settings file) without bumping the version and
+    /// writing a migration that understands how to properly deal with
+    /// existing instances of the old variant (you can delete From instances
+    /// for the old variants at this point).  Otherwise, we will lose
+    /// compatibility with old settings files, since we won't be able to
+    /// deserialize them!
+    #[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+    pub enum Role {
+        Moderator = 0,
+        Admin = 1,
+    }
+
+    impl From<AdminRole> for Role {
+        fn from(value: AdminRole) -> Self {
+            match value {
+                AdminRole::Moderator => Self::Moderator,
+                AdminRole::Admin => Self::Admin,
+            }
+        }
+    }
+
+    impl From<Role> for AdminRole {
+        fn from(value: Role) -> Self {
+            match value {
+                Role::Moderator => Self::Moderator,
+                Role::Admin => Self::Admin,
+            }
+        }
+    }
+
+    #[derive(Clone, Deserialize, Serialize)]
+    /// NOTE: May not be present if performed from the command line or from a
+    /// legacy file.
+    pub struct BanInfo {
+        pub performed_by: Uuid,
+        /// NOTE: May not be up to date, if we allow username changes.
+        pub performed_by_username: String,
+        /// NOTE: Role of the banning user at the time of the ban.
+        pub performed_by_role: Role,
+    }
+
+    #[derive(Clone, Deserialize, Serialize)]
+    pub struct Ban {
+        pub reason: String,
+        /// NOTE: Should only be None for migrations from legacy data.
+        pub info: Option<BanInfo>,
+        /// NOTE: Should always be higher than start_date, if both are
+        /// present!
+        pub end_date: Option<DateTime<Utc>>,
+    }
+
+    impl Ban {
+        /// Returns true if the ban is expired, false otherwise.
+        pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+            self.end_date.map_or(false, |end_date| end_date <= now)
+        }
+
+        pub fn performed_by_role(&self) -> Role {
+            self.info.as_ref().map(|info| info.performed_by_role)
+                // We know all legacy bans were performed by an admin, since we had no other roles
+                // at the time.
+                .unwrap_or(Role::Admin)
+        }
+    }
+
+    type Unban = BanInfo;
+
+    #[derive(Clone, Deserialize, Serialize)]
+    pub enum BanAction {
+        Unban(Unban),
+        Ban(Ban),
+    }
+
+    #[derive(Clone, Deserialize, Serialize)]
+    pub struct BanRecord {
+        /// Username of the user upon whom the action was performed, when it was
+        /// performed.
+        pub username_when_performed: String,
+        pub action: BanAction,
+        /// NOTE: When migrating from legacy versions, this will just be the
+        /// time of the first migration (only applies to BanRecord).
+        pub date: DateTime<Utc>,
+    }
+
+    impl BanRecord {
+        /// Returns true if this record represents an expired ban, false
+        /// otherwise.
+        fn is_expired(&self, now: DateTime<Utc>) -> bool {
+            match &self.action {
+                BanAction::Ban(ban) => ban.is_expired(now),
+                BanAction::Unban(_) => true,
+            }
+        }
+
+        /// The history vector in a BanEntry is stored forwards (from oldest
+        /// entry to newest), so `prev_record` is the previous entry in
+        /// this vector when iterating forwards (by array index).
+        ///
+        /// Errors are:
+        ///
+        /// AlreadyUnbanned if an unban comes after anything but a ban.
+        ///
+        /// Permission(Unban) if an unban attempt is by a user with a lower role
+        /// level than the original banning party.
+        ///
+        /// PermissionDenied(Ban) if a ban length is made shorter by a user with
+        /// a role level than the original banning party.
+        ///
+        /// InvalidDateRange if the end date of the ban exceeds the start date.
+        fn validate(&self, prev_record: Option<&BanRecord>) -> Result<(), BanErrorKind> {
+            // Check to make sure the actions temporally line up--if they don't, we will
+            // prevent warn an administrator (since this may indicate a system
+            // clock issue and could require manual editing to resolve).
+            // However, we will not actually invalidate the ban list for this, in case
+            // this would otherwise prevent people from adding a new ban.
+            //
+            // We also deliberately leave the bad order intact, in case this reflects
+            // history more accurately than the system clock does.
+            if let Some(prev_record) = prev_record {
+                if prev_record.date > self.date {
+                    warn!(
+                        "Ban list history is inconsistent, or a just-added ban was behind a \
+                         historical entry in the ban
+                          record; please investigate the contents of the file (might indicate a \
+                         system clock change?)."
+                    );
+                }
+            }
+            let ban = match (&self.action, prev_record.map(|record| &record.action)) {
+                // A ban is always valid if it follows an unban.
+                (BanAction::Ban(ban), None) | (BanAction::Ban(ban), Some(BanAction::Unban(_))) => {
+                    ban
+                },
+                // A ban record following a ban is valid if either the role of the person doing the
+                // banning is at least the privilege level of the person who did the ban, or the
+                // ban's new end time is at least the previous end time.
+                (BanAction::Ban(new_ban), Some(BanAction::Ban(old_ban))) => {
+                    match (new_ban.end_date, old_ban.end_date) {
+                        // New role ≥ old role
+                        _ if new_ban.performed_by_role() >= old_ban.performed_by_role() => new_ban,
+                        // Permanent ban retracted to temp ban.
+                        (Some(_), None) => {
+                            return Err(BanErrorKind::PermissionDenied(BanKind::Ban));
+                        },
+                        // Temp ban retracted to shorter temp ban.
+                        (Some(new_date), Some(old_date)) if new_date < old_date => {
+                            return Err(BanErrorKind::PermissionDenied(BanKind::Ban));
+                        },
+                        // Anything else (extension to permanent ban, or temp ban extension to
+                        // longer temp ban).
+                        _ => new_ban,
+                    }
+                },
+                // An unban record is invalid if it does not follow a ban.
+                (BanAction::Unban(_), None) | (BanAction::Unban(_), Some(BanAction::Unban(_))) => {
+                    return Err(BanErrorKind::AlreadyUnbanned);
+                },
+                // An unban record following a ban is valid if the role of the person doing the
+                // unbanning is at least the privilege level of the person who did the ban.
+                (BanAction::Unban(unban), Some(BanAction::Ban(ban))) => {
+                    if unban.performed_by_role >= ban.performed_by_role() {
+                        return Ok(());
+                    } else {
+                        return Err(BanErrorKind::PermissionDenied(BanKind::Unban));
+                    }
+                },
+            };
+
+            // End date of a ban must be at least as big as the start date.
+            if let Some(end_date) = ban.end_date {
+                if self.date > end_date {
+                    return Err(BanErrorKind::InvalidDateRange {
+                        start_date: self.date,
+                        end_date,
+                    });
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Deserialize, Serialize)]
+    pub struct BanEntry {
+        /// The latest ban record for this user.
+        pub current: BanRecord,
+        /// Historical ban records for this user, stored in order from oldest to
+        /// newest.
+        pub history: Vec<BanRecord>,
+        /// A *hint* about whether the system thinks this entry is expired,
+        /// mostly to make it easier for someone manually going through
+        /// a file to see whether an entry is currently in effect or
+        /// not.  This is based off the contents of `current`.
+        pub expired: bool,
+    }
+
+    impl Deref for BanEntry {
+        type Target = BanRecord;
+
+        fn deref(&self) -> &Self::Target { &self.current }
+    }
+
+    impl BanEntry {
+        /// Both validates, and updates the hint bit if it's inconsistent with
+        /// reality.
+        ///
+        /// If we were invalid, returns an error.  Otherwise, returns Ok(v),
+        /// where v is Latest if the hint bit was modified, Old
+        /// otherwise.
+        fn validate(
+            &mut self,
+            now: DateTime<Utc>,
+            uuid: Uuid,
+        ) -> Result<Version, <Final as EditableSetting>::Error> {
+            let make_error = |current_entry: &BanRecord| {
+                let username = current_entry.username_when_performed.clone();
+                move |kind| BanError {
+                    kind,
+                    target: BanTarget::Uuid(uuid),
+                    username,
+                }
+            };
+            // First, go forwards through history (also forwards in terms of the iterator
+            // direction), validating each entry in turn.
+            let mut prev_entry = None;
+            for current_entry in &self.history {
+                current_entry
+                    .validate(prev_entry)
+                    .map_err(make_error(current_entry))?;
+                prev_entry = Some(current_entry);
+            }
+
+            // History has now been validated, so validate the current entry.
+            self.current
+                .validate(prev_entry)
+                .map_err(make_error(&self.current))?;
+
+            // Make sure the expired hint is correct, and if not indicate that we should
+            // resave the file.
+            let is_expired = self.current.is_expired(now);
+            if self.expired != is_expired {
+                self.expired = is_expired;
+                Ok(Version::Old)
+            } else {
+                Ok(Version::Latest)
+            }
+        }
+    }
+
+    #[derive(Clone, Deserialize, Serialize, Default)]
+    #[serde(transparent)]
+    pub struct Banlist(pub(super) HashMap<Uuid, BanEntry>);
+
+    impl Banlist {
+        /// One-off migration from the previous version.  This must be
+        /// guaranteed to produce a valid settings file as long as it is
+        /// called with a valid settings file from the previous version.
+        pub(super) fn migrate(prev: prev::Banlist) -> Self {
+            // The ban start date for migrations from legacy is the current one; we could
+            // record that they actually have an unknown start date, but this
+            // would just complicate the format.
+            let date = Utc::now();
+            Banlist(
+                prev.0
+                    .into_iter()
+                    .map(
+                        |(
+                            uid,
+                            prev::BanRecord {
+                                username_when_banned,
+                                reason,
+                            },
+                        )| {
+                            (uid, BanEntry {
+                                current: BanRecord {
+                                    username_when_performed: username_when_banned,
+                                    // We only recorded unbans pre-migration.
+                                    action: BanAction::Ban(Ban {
+                                        reason,
+                                        // We don't know who banned this user pre-migration.
+                                        info: None,
+                                        // All bans pre-migration are of unlimited duration.
+                                        end_date: None,
+                                    }),
+                                    date,
+                                },
+                                // Old bans never expire, so set the expiration hint to false.
+                                expired: false,
+                                // There is no known ban history yet.
+                                history: Vec::new(),
+                            })
+                        },
+                    )
+                    .collect(),
+            )
+        }
+
+        /// Perform any needed validation on this banlist that can't be done
+        /// using parsing.
+        ///
+        /// The returned version being "Old" indicates the loaded setting has
+        /// been modified during validation (this is why validate takes
+        /// `&mut self`).
+        pub(super) fn validate(&mut self) -> Result<Version, <Final as EditableSetting>::Error> {
+            let mut version = Version::Latest;
+            let now = Utc::now();
+            for (&uuid, value) in self.0.iter_mut() {
+                if matches!(value.validate(now, uuid)?, Version::Old) {
+                    // Update detected.
+                    version = Version::Old;
+                }
+            }
+            Ok(version)
+        }
+    }
+
+    /// Pretty much every TryFrom implementation except that of the very last
+    /// version should look exactly like this.
+    impl TryFrom<Banlist> for Final {
+        type Error = <Final as EditableSetting>::Error;
+
+        #[allow(clippy::useless_conversion)]
+        fn try_from(mut value: Banlist) -> Result<Final, Self::Error> {
+            value.validate()?;
+            Ok(next::Banlist::migrate(value)
+                .try_into()
+                .expect(MIGRATION_UPGRADE_GUARANTEE))
+        }
+    }
+}
+
+mod v2 {
+    use super::{v1 as prev, BanError, BanErrorKind, BanKind, BanTarget, Final};
     use crate::settings::editable::{EditableSetting, Error, Version};
     use authc::Uuid;
     use chrono::{prelude::*, Utc};
     use common::comp::AdminRole;
-    use core::{mem, ops::Deref};
+    use core::{convert::TryFrom, fmt, hash::Hash, mem, ops::Deref, str::FromStr};
     use hashbrown::{hash_map, HashMap};
     use serde::{Deserialize, Serialize};
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
     use tracing::warn;
-    /* use super::v2 as next; */
+    /* use super::v3 as next; */
 
     /// Important: even if the role we are storing here appears to be identical
     /// to one used in another versioned store (like admin::Role), we *must*
@@ -238,6 +602,15 @@ mod v1 {
         }
     }
 
+    impl From<prev::Role> for Role {
+        fn from(value: prev::Role) -> Self {
+            match value {
+                prev::Role::Moderator => Self::Moderator,
+                prev::Role::Admin => Self::Admin,
+            }
+        }
+    }
+
     #[derive(Clone, Deserialize, Serialize)]
     /// NOTE: May not be present if performed from the command line or from a
     /// legacy file.
@@ -419,6 +792,33 @@ mod v1 {
     }
 
     impl BanEntry {
+        /// One-off migration of an entry of the previous version, which only
+        /// changes the version of the types.
+        fn migrate(prev: prev::BanEntry) -> Self {
+            let migrate_info = |info: prev::BanInfo| BanInfo {
+                performed_by: info.performed_by,
+                performed_by_username: info.performed_by_username,
+                performed_by_role: info.performed_by_role.into(),
+            };
+            let migrate_record = |record: prev::BanRecord| BanRecord {
+                username_when_performed: record.username_when_performed,
+                action: match record.action {
+                    prev::BanAction::Unban(unban) => BanAction::Unban(migrate_info(unban)),
+                    prev::BanAction::Ban(ban) => BanAction::Ban(Ban {
+                        reason: ban.reason,
+                        info: ban.info.map(migrate_info),
+                        end_date: ban.end_date,
+                    }),
+                },
+                date: record.date,
+            };
+            BanEntry {
+                current: migrate_record(prev.current),
+                history: prev.history.into_iter().map(migrate_record).collect(),
+                expired: prev.expired,
+            }
+        }
+
         /// Both validates, and updates the hint bit if it's inconsistent with
         /// reality.
         ///
@@ -428,13 +828,13 @@ mod v1 {
         fn validate(
             &mut self,
             now: DateTime<Utc>,
-            uuid: Uuid,
+            target: BanTarget,
         ) -> Result<Version, <Final as EditableSetting>::Error> {
             let make_error = |current_entry: &BanRecord| {
                 let username = current_entry.username_when_performed.clone();
                 move |kind| BanError {
                     kind,
-                    uuid,
+                    target,
                     username,
                 }
             };
@@ -465,17 +865,156 @@ mod v1 {
         }
     }
 
-    #[derive(Clone, Deserialize, Serialize, Default)]
-    #[serde(transparent)]
-    pub struct Banlist(pub(super) HashMap<Uuid, BanEntry>);
+    /// An IP address, or a range of addresses in CIDR notation (like
+    /// `192.168.0.0/16`).
+    #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+    #[serde(try_from = "String", into = "String")]
+    pub struct IpRange {
+        /// First address of the range.
+        addr: IpAddr,
+        /// Number of leading bits shared by the addresses of the range.
+        prefix_len: u8,
+    }
 
-    impl Deref for Banlist {
-        type Target = HashMap<Uuid, BanEntry>;
+    impl IpRange {
+        /// The range of addresses sharing the first `prefix_len` bits of
+        /// `addr`, or `None` if the prefix is longer than the address.
+        pub fn new(addr: IpAddr, prefix_len: u8) -> Option<Self> {
+            // IPv4-mapped IPv6 ranges are stored as the IPv4 range they cover
+            let (addr, prefix_len) = match (addr, to_canonical(addr)) {
+                (IpAddr::V6(_), IpAddr::V4(addr)) if prefix_len >= 96 => {
+                    (IpAddr::V4(addr), prefix_len - 96)
+                },
+                _ => (addr, prefix_len),
+            };
+            let addr = match addr {
+                IpAddr::V4(addr) if prefix_len <= 32 => {
+                    IpAddr::V4(Ipv4Addr::from(u32::from(addr) & v4_mask(prefix_len)))
+                },
+                IpAddr::V6(addr) if prefix_len <= 128 => {
+                    IpAddr::V6(Ipv6Addr::from(u128::from(addr) & v6_mask(prefix_len)))
+                },
+                _ => return None,
+            };
+            Some(Self { addr, prefix_len })
+        }
+
+        /// The range made of `addr` only.
+        pub fn single(addr: IpAddr) -> Self {
+            let prefix_len = if addr.is_ipv4() { 32 } else { 128 };
+            Self::new(addr, prefix_len).expect("The full prefix length of an address is valid")
+        }
 
-        fn deref(&self) -> &Self::Target { &self.0 }
+        fn is_single(&self) -> bool {
+            self.prefix_len == if self.addr.is_ipv4() { 32 } else { 128 }
+        }
+
+        pub fn is_ipv4(&self) -> bool { self.addr.is_ipv4() }
+
+        pub fn prefix_len(&self) -> u8 { self.prefix_len }
+
+        pub fn contains(&self, addr: IpAddr) -> bool {
+            match (self.addr, to_canonical(addr)) {
+                (IpAddr::V4(range), IpAddr::V4(addr)) => {
+                    u32::from(addr) & v4_mask(self.prefix_len) == u32::from(range)
+                },
+                (IpAddr::V6(range), IpAddr::V6(addr)) => {
+                    u128::from(addr) & v6_mask(self.prefix_len) == u128::from(range)
+                },
+                _ => false,
+            }
+        }
+    }
+
+    /// Clients connecting to an IPv6 socket over IPv4 have IPv4-mapped
+    /// addresses (`::ffff:a.b.c.d`), which should match the IPv4 bans.
+    fn to_canonical(addr: IpAddr) -> IpAddr {
+        match addr {
+            IpAddr::V6(v6) => match v6.segments() {
+                [0, 0, 0, 0, 0, 0xffff, high, low] => {
+                    IpAddr::V4(Ipv4Addr::from((high as u32) << 16 | low as u32))
+                },
+                _ => addr,
+            },
+            IpAddr::V4(_) => addr,
+        }
+    }
+
+    fn v4_mask(prefix_len: u8) -> u32 { u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0) }
+
+    fn v6_mask(prefix_len: u8) -> u128 {
+        u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0)
+    }
+
+    impl FromStr for IpRange {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let (addr, prefix_len) = match s.split_once('/') {
+                Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+                None => (s, None),
+            };
+            let addr = addr
+                .trim()
+                .parse::<IpAddr>()
+                .map_err(|_| format!("{:?} is not a valid IP address", addr))?;
+            match prefix_len {
+                Some(prefix_len) => {
+                    let prefix_len = prefix_len
+                        .trim()
+                        .parse::<u8>()
+                        .map_err(|_| format!("{:?} is not a valid prefix length", prefix_len))?;
+                    Self::new(addr, prefix_len).ok_or_else(|| {
+                        format!("Prefix length {} is too long for {}", prefix_len, addr)
+                    })
+                },
+                None => Ok(Self::single(addr)),
+            }
+        }
+    }
+
+    impl fmt::Display for IpRange {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            if self.is_single() {
+                write!(f, "{}", self.addr)
+            } else {
+                write!(f, "{}/{}", self.addr, self.prefix_len)
+            }
+        }
+    }
+
+    impl TryFrom<String> for IpRange {
+        type Error = String;
+
+        fn try_from(value: String) -> Result<Self, Self::Error> { value.parse() }
+    }
+
+    impl From<IpRange> for String {
+        fn from(value: IpRange) -> Self { value.to_string() }
+    }
+
+    #[derive(Clone, Deserialize, Serialize, Default)]
+    pub struct Banlist {
+        pub(super) uuid_bans: HashMap<Uuid, BanEntry>,
+        /// Bans of addresses, for servers where players can change their UUID
+        /// at will (like those without an auth server).
+        pub(super) ip_bans: HashMap<IpRange, BanEntry>,
     }
 
     impl Banlist {
+        pub fn uuid_bans(&self) -> &HashMap<Uuid, BanEntry> { &self.uuid_bans }
+
+        pub fn ip_bans(&self) -> &HashMap<IpRange, BanEntry> { &self.ip_bans }
+
+        /// The latest ban of each range containing `addr`, whether or not it
+        /// expired.
+        pub fn bans_of_ip(&self, addr: IpAddr) -> impl Iterator<Item = &Ban> + '_ {
+            self.ip_bans
+                .iter()
+                .filter(move |(range, _)| range.contains(addr))
+                .filter_map(|(_, entry)| entry.current.action.ban())
+        }
+
         /// Attempt to perform the ban action `action` for the user with UUID
         /// `uuid` and username `username`, starting from itme `now`
         /// (the information about the banning party will
@@ -521,6 +1060,52 @@ mod v1 {
             username_when_performed: String,
             action: BanAction,
             overwrite: bool,
+        ) -> Option<Result<(), Error<Final>>> {
+            self.edit_entry(
+                data_dir,
+                now,
+                |banlist| &mut banlist.uuid_bans,
+                uuid,
+                username_when_performed,
+                action,
+                overwrite,
+            )
+        }
+
+        /// Same as [`Banlist::ban_action`], for the addresses of `ip_range`.
+        /// `username_when_performed` is the user whose address was banned, if
+        /// any.
+        #[must_use]
+        pub fn ip_ban_action(
+            &mut self,
+            data_dir: &std::path::Path,
+            now: DateTime<Utc>,
+            ip_range: IpRange,
+            username_when_performed: String,
+            action: BanAction,
+            overwrite: bool,
+        ) -> Option<Result<(), Error<Final>>> {
+            self.edit_entry(
+                data_dir,
+                now,
+                |banlist| &mut banlist.ip_bans,
+                ip_range,
+                username_when_performed,
+                action,
+                overwrite,
+            )
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        fn edit_entry<K: Eq + Hash>(
+            &mut self,
+            data_dir: &std::path::Path,
+            now: DateTime<Utc>,
+            entries: impl FnOnce(&mut Banlist) -> &mut HashMap<K, BanEntry>,
+            key: K,
+            username_when_performed: String,
+            action: BanAction,
+            overwrite: bool,
         ) -> Option<Result<(), Error<Final>>> {
             assert!(
                 matches!(
@@ -538,8 +1123,8 @@ mod v1 {
 
             // Perform an atomic edit.
             Some(
-                self.edit(data_dir.as_ref(), |banlist| {
-                    match banlist.0.entry(uuid) {
+                self.edit(data_dir, |banlist| {
+                    match entries(banlist).entry(key) {
                         hash_map::Entry::Vacant(v) => {
                             // If this is an unban, it will have no effect, so return early.
                             if matches!(ban_record.action, BanAction::Unban(_)) {
@@ -591,43 +1176,15 @@ mod v1 {
         /// guaranteed to produce a valid settings file as long as it is
         /// called with a valid settings file from the previous version.
         pub(super) fn migrate(prev: prev::Banlist) -> Self {
-            // The ban start date for migrations from legacy is the current one; we could
-            // record that they actually have an unknown start date, but this
-            // would just complicate the format.
-            let date = Utc::now();
-            Banlist(
-                prev.0
+            Banlist {
+                uuid_bans: prev
+                    .0
                     .into_iter()
-                    .map(
-                        |(
-                            uid,
-                            prev::BanRecord {
-                                username_when_banned,
-                                reason,
-                            },
-                        )| {
-                            (uid, BanEntry {
-                                current: BanRecord {
-                                    username_when_performed: username_when_banned,
-                                    // We only recorded unbans pre-migration.
-                                    action: BanAction::Ban(Ban {
-                                        reason,
-                                        // We don't know who banned this user pre-migration.
-                                        info: None,
-                                        // All bans pre-migration are of unlimited duration.
-                                        end_date: None,
-                                    }),
-                                    date,
-                                },
-                                // Old bans never expire, so set the expiration hint to false.
-                                expired: false,
-                                // There is no known ban history yet.
-                                history: Vec::new(),
-                            })
-                        },
-                    )
+                    .map(|(uuid, entry)| (uuid, BanEntry::migrate(entry)))
                     .collect(),
-            )
+                // There were no address bans before this version.
+                ip_bans: HashMap::new(),
+            }
         }
 
         /// Perform any needed validation on this banlist that can't be done
@@ -639,8 +1196,14 @@ mod v1 {
         pub(super) fn validate(&mut self) -> Result<Version, <Final as EditableSetting>::Error> {
             let mut version = Version::Latest;
             let now = Utc::now();
-            for (&uuid, value) in self.0.iter_mut() {
-                if matches!(value.validate(now, uuid)?, Version::Old) {
+            for (&uuid, value) in self.uuid_bans.iter_mut() {
+                if matches!(value.validate(now, BanTarget::Uuid(uuid))?, Version::Old) {
+                    // Update detected.
+                    version = Version::Old;
+                }
+            }
+            for (&ip_range, value) in self.ip_bans.iter_mut() {
+                if matches!(value.validate(now, BanTarget::Ip(ip_range))?, Version::Old) {
                     // Update detected.
                     version = Version::Old;
                 }
@@ -661,3 +1224,38 @@ mod v1 {
         }
     } */
 }
+
+#[cfg(test)]
+mod tests {
+    use super::IpRange;
+    use std::net::IpAddr;
+
+    fn ip(addr: &str) -> IpAddr { addr.parse().unwrap() }
+
+    #[test]
+    fn ip_ranges() {
+        let range = "192.168.12.34/16".parse::<IpRange>().unwrap();
+        assert_eq!(range.to_string(), "192.168.0.0/16");
+        assert!(range.contains(ip("192.168.255.1")));
+        assert!(range.contains(ip("::ffff:192.168.0.1")));
+        assert!(!range.contains(ip("192.169.0.1")));
+
+        let single = "2001:db8::1".parse::<IpRange>().unwrap();
+        assert_eq!(single.to_string(), "2001:db8::1");
+        assert!(single.contains(ip("2001:db8::1")));
+        assert!(!single.contains(ip("2001:db8::2")));
+
+        assert!(
+            "0.0.0.0/0"
+                .parse::<IpRange>()
+                .unwrap()
+                .contains(ip("1.2.3.4"))
+        );
+        // Mapped ranges are as wide as the IPv4 range they cover
+        let mapped = "::ffff:10.0.0.0/104".parse::<IpRange>().unwrap();
+        assert!(mapped.is_ipv4());
+        assert_eq!(mapped.prefix_len(), 8);
+        assert!("10.0.0.0/33".parse::<IpRange>().is_err());
+        assert!("not an address".parse::<IpRange>().is_err());
+    }
+}
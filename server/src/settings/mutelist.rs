@@ -0,0 +1,315 @@
+//! Versioned mutelist settings files.
+
+use super::MUTELIST_FILENAME as FILENAME;
+use crate::settings::editable::{EditableSetting, Version};
+use core::convert::{Infallible, TryFrom};
+use serde::{Deserialize, Serialize};
+
+/// NOTE: Always replace this with the latest mutelist version. Then update the
+/// MutelistRaw, the TryFrom<MutelistRaw> for Mutelist, the previously most
+/// recent module, and add a new module for the latest version!  Please respect
+/// the migration upgrade guarantee found in the parent module with any upgrade.
+pub use self::v0::*;
+
+/// Versioned settings files, one per version.
+#[derive(Deserialize, Serialize)]
+pub enum MutelistRaw {
+    V0(v0::Mutelist),
+}
+
+impl From<Mutelist> for MutelistRaw {
+    fn from(value: Mutelist) -> Self {
+        // Replace variant with that of current latest version.
+        Self::V0(value)
+    }
+}
+
+impl TryFrom<MutelistRaw> for (Version, Mutelist) {
+    type Error = <Mutelist as EditableSetting>::Error;
+
+    fn try_from(value: MutelistRaw) -> Result<Self, <Mutelist as EditableSetting>::Error> {
+        use MutelistRaw::*;
+        Ok(match value {
+            // Latest version (move to old section using the pattern of other old version when it
+            // is no longer latest).
+            V0(mut value) => (value.validate()?, value),
+        })
+    }
+}
+
+type Final = Mutelist;
+
+impl EditableSetting for Mutelist {
+    type Error = Infallible;
+    /// The mutelist was versioned from the start, so there are no legacy files
+    /// to migrate; an unversioned file is read as the first version.
+    type Legacy = Mutelist;
+    type Setting = MutelistRaw;
+
+    const FILENAME: &'static str = FILENAME;
+}
+
+mod v0 {
+    use super::Final;
+    use crate::settings::editable::{EditableSetting, Error, Version};
+    use authc::Uuid;
+    use chrono::{prelude::*, Utc};
+    use common::comp::AdminRole;
+    use core::ops::Deref;
+    use hashbrown::HashMap;
+    use serde::{Deserialize, Serialize};
+    /* use super::v1 as next; */
+
+    /// Important: even if the role we are storing here appears to be identical
+    /// to one used in another versioned store (like admin::Role), we *must*
+    /// have our own versioned copy!  This ensures that if there's an update
+    /// to the role somewhere else, the conversion function between them
+    /// will break, letting people make an intelligent decision.
+    ///
+    /// In particular, *never remove variants from this enum* (or any other enum
+    /// in a versioned settings file) without bumping the version and
+    /// writing a migration that understands how to properly deal with
+    /// existing instances of the old variant (you can delete From instances
+    /// for the old variants at this point).  Otherwise, we will lose
+    /// compatibility with old settings files, since we won't be able to
+    /// deserialize them!
+    #[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+    pub enum Role {
+        Moderator = 0,
+        Admin = 1,
+    }
+
+    impl From<AdminRole> for Role {
+        fn from(value: AdminRole) -> Self {
+            match value {
+                AdminRole::Moderator => Self::Moderator,
+                AdminRole::Admin => Self::Admin,
+            }
+        }
+    }
+
+    impl From<Role> for AdminRole {
+        fn from(value: Role) -> Self {
+            match value {
+                Role::Moderator => Self::Moderator,
+                Role::Admin => Self::Admin,
+            }
+        }
+    }
+
+    #[derive(Clone, Deserialize, Serialize)]
+    pub struct MuteInfo {
+        pub performed_by: Uuid,
+        /// NOTE: May not be up to date, if we allow username changes.
+        pub performed_by_username: String,
+        /// NOTE: Role of the muting user at the time of the mute.
+        pub performed_by_role: Role,
+    }
+
+    #[derive(Clone, Deserialize, Serialize)]
+    pub struct MuteRecord {
+        /// NOTE: May not be up to date, if we allow username changes.
+        pub username_when_muted: String,
+        /// Date when the user was muted.
+        pub date: DateTime<Utc>,
+        pub reason: String,
+        /// NOTE: Should always be higher than the `date` of the record.
+        pub end_date: Option<DateTime<Utc>>,
+        pub info: MuteInfo,
+    }
+
+    impl MuteRecord {
+        /// Returns true if the mute is expired as of `now`, false otherwise.
+        pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+            self.end_date.map_or(false, |end_date| end_date <= now)
+        }
+
+        pub fn performed_by_role(&self) -> Role { self.info.performed_by_role }
+
+        /// The explanation sent to a muted player trying to talk.
+        pub fn info(&self) -> String {
+            let mut info = "You are muted".to_owned();
+            if let Some(end_date) = self.end_date {
+                info.push_str(&format!(" until {}", end_date.format("%Y-%m-%d %H:%M UTC")));
+            }
+            if !self.reason.is_empty() {
+                info.push_str(&format!(" for: {}", self.reason));
+            }
+            info
+        }
+    }
+
+    #[derive(Clone, Deserialize, Serialize, Default)]
+    #[serde(transparent)]
+    pub struct Mutelist(pub(super) HashMap<Uuid, MuteRecord>);
+
+    impl Deref for Mutelist {
+        type Target = HashMap<Uuid, MuteRecord>;
+
+        fn deref(&self) -> &Self::Target { &self.0 }
+    }
+
+    impl Mutelist {
+        /// The mute of the user, if they are muted as of `now`.
+        pub fn mute_of(&self, uuid: &Uuid, now: DateTime<Utc>) -> Option<&MuteRecord> {
+            self.0.get(uuid).filter(|record| !record.is_expired(now))
+        }
+
+        /// Mutes the user, returning None if they were already muted and
+        /// `overwrite` was not set.  Checking that the muting user has at
+        /// least the role of whoever performed an existing mute is up to the
+        /// caller.
+        #[must_use]
+        pub fn mute(
+            &mut self,
+            data_dir: &std::path::Path,
+            uuid: Uuid,
+            record: MuteRecord,
+            overwrite: bool,
+        ) -> Option<Result<(), Error<Final>>> {
+            let now = record.date;
+            self.edit(data_dir, |mutelist| {
+                if !overwrite && mutelist.mute_of(&uuid, now).is_some() {
+                    return None;
+                }
+                mutelist.0.insert(uuid, record);
+                Some(())
+            })
+            .map(|(_, result)| result)
+        }
+
+        /// Unmutes the user, returning None if they were not muted.
+        #[must_use]
+        pub fn unmute(
+            &mut self,
+            data_dir: &std::path::Path,
+            now: DateTime<Utc>,
+            uuid: &Uuid,
+        ) -> Option<Result<(), Error<Final>>> {
+            self.edit(data_dir, |mutelist| {
+                mutelist
+                    .0
+                    .remove(uuid)
+                    .filter(|record| !record.is_expired(now))
+                    .map(|_| ())
+            })
+            .map(|(_, result)| result)
+        }
+
+        /// Perform any needed validation on this mutelist that can't be done
+        /// using parsing.
+        ///
+        /// The returned version being "Old" indicates the loaded setting has
+        /// been modified during validation (this is why validate takes
+        /// `&mut self`).
+        pub(super) fn validate(&mut self) -> Result<Version, <Final as EditableSetting>::Error> {
+            // Expired mutes are of no further use, so drop them.
+            let now = Utc::now();
+            let len = self.0.len();
+            self.0.retain(|_, record| !record.is_expired(now));
+            Ok(if self.0.len() == len {
+                Version::Latest
+            } else {
+                Version::Old
+            })
+        }
+    }
+
+    // NOTE: Whenever there is a version upgrade, copy this note as well as the
+    // commented-out code below to the next version, then uncomment the code
+    // for this version.
+    /* impl TryFrom<Mutelist> for Final {
+        type Error = <Final as EditableSetting>::Error;
+
+        fn try_from(mut value: Mutelist) -> Result<Final, Self::Error> {
+            value.validate()?;
+            Ok(next::Mutelist::migrate(value).try_into().expect(MIGRATION_UPGRADE_GUARANTEE))
+        }
+    } */
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use authc::Uuid;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+
+    fn record(reason: &str, end_date: Option<DateTime<Utc>>) -> MuteRecord {
+        MuteRecord {
+            username_when_muted: "player".to_owned(),
+            date: Utc::now(),
+            reason: reason.to_owned(),
+            end_date,
+            info: MuteInfo {
+                performed_by: Uuid::from_u128(0),
+                performed_by_username: "moderator".to_owned(),
+                performed_by_role: Role::Moderator,
+            },
+        }
+    }
+
+    #[test]
+    fn mute_and_unmute() {
+        let data_dir =
+            std::env::temp_dir().join(format!("veloren-mutelist-{}", std::process::id()));
+        std::fs::create_dir_all(&data_dir).unwrap();
+        let mut mutelist = Mutelist::default();
+        let now = Utc::now();
+        let muted = Uuid::from_u128(1);
+
+        let mute = record("spam", Some(now + Duration::hours(1)));
+        assert!(
+            mutelist
+                .mute(&data_dir, muted, mute, false)
+                .unwrap()
+                .is_ok()
+        );
+        assert!(mutelist.mute_of(&muted, now).is_some());
+        assert!(mutelist.mute_of(&muted, now + Duration::hours(2)).is_none());
+
+        // An existing mute is only replaced when asked to
+        assert!(
+            mutelist
+                .mute(&data_dir, muted, record("again", None), false)
+                .is_none()
+        );
+        assert!(
+            mutelist
+                .mute(&data_dir, muted, record("again", None), true)
+                .is_some()
+        );
+        assert!(
+            mutelist
+                .mute_of(&muted, now + Duration::days(365))
+                .is_some()
+        );
+
+        assert!(mutelist.unmute(&data_dir, now, &muted).is_some());
+        assert!(mutelist.unmute(&data_dir, now, &muted).is_none());
+        assert!(mutelist.mute_of(&muted, now).is_none());
+    }
+
+    #[test]
+    fn expired_mutes_are_dropped() {
+        let now = Utc::now();
+        let mut mutelist = Mutelist::default();
+        mutelist.0.insert(
+            Uuid::from_u128(1),
+            record("", Some(now - Duration::hours(1))),
+        );
+        mutelist.0.insert(Uuid::from_u128(2), record("", None));
+
+        assert!(matches!(mutelist.validate(), Ok(Version::Old)));
+        assert_eq!(mutelist.len(), 1);
+        assert!(matches!(mutelist.validate(), Ok(Version::Latest)));
+    }
+
+    #[test]
+    fn mute_info() {
+        assert_eq!(record("", None).info(), "You are muted");
+        assert_eq!(
+            record("spam", Some(Utc.ymd(2021, 12, 15).and_hms(12, 30, 0))).info(),
+            "You are muted until 2021-12-15 12:30 UTC for: spam"
+        );
+    }
+}
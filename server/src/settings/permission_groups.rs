@@ -0,0 +1,373 @@
+//! Versioned permission groups settings files.
+
+use super::PERMISSION_GROUPS_FILENAME as FILENAME;
+use crate::settings::editable::{EditableSetting, Version};
+use core::convert::{Infallible, TryFrom};
+use serde::{Deserialize, Serialize};
+
+/// NOTE: Always replace this with the latest permission groups version. Then
+/// update the PermissionGroupsRaw, the TryFrom<PermissionGroupsRaw> for
+/// PermissionGroups, the previously most recent module, and add a new module
+/// for the latest version!  Please respect the migration upgrade guarantee
+/// found in the parent module with any upgrade.
+pub use self::v0::*;
+
+/// Versioned settings files, one per version.
+#[derive(Deserialize, Serialize)]
+pub enum PermissionGroupsRaw {
+    V0(v0::PermissionGroups),
+}
+
+impl From<PermissionGroups> for PermissionGroupsRaw {
+    fn from(value: PermissionGroups) -> Self {
+        // Replace variant with that of current latest version.
+        Self::V0(value)
+    }
+}
+
+impl TryFrom<PermissionGroupsRaw> for (Version, PermissionGroups) {
+    type Error = <PermissionGroups as EditableSetting>::Error;
+
+    fn try_from(
+        value: PermissionGroupsRaw,
+    ) -> Result<Self, <PermissionGroups as EditableSetting>::Error> {
+        use PermissionGroupsRaw::*;
+        Ok(match value {
+            // Latest version (move to old section using the pattern of other old version when it
+            // is no longer latest).
+            V0(mut value) => (value.validate()?, value),
+        })
+    }
+}
+
+type Final = PermissionGroups;
+
+impl EditableSetting for PermissionGroups {
+    type Error = Infallible;
+    /// The permission groups were versioned from the start, so there are no
+    /// legacy files to migrate; an unversioned file is read as the first
+    /// version.
+    type Legacy = PermissionGroups;
+    type Setting = PermissionGroupsRaw;
+
+    const FILENAME: &'static str = FILENAME;
+}
+
+mod v0 {
+    use super::Final;
+    use crate::settings::editable::{EditableSetting, Version};
+    use authc::Uuid;
+    use chrono::{prelude::*, Utc};
+    use common::{
+        cmd::ChatCommand,
+        comp::{AdminRole, Permissions},
+    };
+    use hashbrown::{HashMap, HashSet};
+    use serde::{Deserialize, Serialize};
+    use std::{collections::BTreeSet, str::FromStr};
+    use tracing::warn;
+    /* use super::v1 as next; */
+
+    /// Something a group allows its members to do, besides using commands.
+    ///
+    /// NOTE: *Never remove variants from this enum* without bumping the
+    /// version and writing a migration, or old settings files won't
+    /// deserialize anymore.
+    #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+    pub enum Ability {
+        /// Build in every build area, without being permitted to
+        Build,
+        /// Not being made invulnerable by safezones, e.g. to take part in
+        /// fights held in a town
+        BypassSafezone,
+    }
+
+    /// Important: even if the role we are storing here appears to be identical
+    /// to one used in another versioned store (like admin::Role), we *must*
+    /// have our own versioned copy!  This ensures that if there's an update
+    /// to the role somewhere else, the conversion function between them
+    /// will break, letting people make an intelligent decision.
+    ///
+    /// NOTE: *Never remove variants from this enum* without bumping the
+    /// version and writing a migration, or old settings files won't
+    /// deserialize anymore.
+    #[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+    pub enum Role {
+        Moderator = 0,
+        Admin = 1,
+    }
+
+    impl From<AdminRole> for Role {
+        fn from(value: AdminRole) -> Self {
+            match value {
+                AdminRole::Moderator => Self::Moderator,
+                AdminRole::Admin => Self::Admin,
+            }
+        }
+    }
+
+    impl From<Role> for AdminRole {
+        fn from(value: Role) -> Self {
+            match value {
+                Role::Moderator => Self::Moderator,
+                Role::Admin => Self::Admin,
+            }
+        }
+    }
+
+    #[derive(Clone, Default, Deserialize, Serialize)]
+    #[serde(default)]
+    pub struct PermissionGroup {
+        /// Keywords of the commands members can use, whichever role they
+        /// have
+        pub commands: BTreeSet<String>,
+        pub abilities: HashSet<Ability>,
+        /// Role members act with when using commands which record who
+        /// performed them, like bans. Members of groups without one can't
+        /// use these commands.
+        pub role: Option<Role>,
+    }
+
+    #[derive(Clone, Deserialize, Serialize)]
+    pub struct GroupMembership {
+        /// NOTE: May not be up to date, if we allow username changes.
+        pub username_when_assigned: String,
+        /// Date when the user was last assigned a group.
+        pub date: DateTime<Utc>,
+        pub groups: BTreeSet<String>,
+    }
+
+    #[derive(Clone, Deserialize, Serialize)]
+    #[serde(default)]
+    pub struct PermissionGroups {
+        pub groups: HashMap<String, PermissionGroup>,
+        pub members: HashMap<Uuid, GroupMembership>,
+    }
+
+    impl Default for PermissionGroups {
+        fn default() -> Self {
+            // An example of what groups are for, which has no members yet.
+            let event_host = PermissionGroup {
+                commands: [ChatCommand::Spawn, ChatCommand::Tp, ChatCommand::Goto]
+                    .iter()
+                    .map(|cmd| cmd.keyword().to_owned())
+                    .collect(),
+                abilities: HashSet::new(),
+                role: None,
+            };
+            Self {
+                groups: std::iter::once(("event_host".to_owned(), event_host)).collect(),
+                members: HashMap::new(),
+            }
+        }
+    }
+
+    impl PermissionGroups {
+        /// What the groups of the user allow them to do.
+        pub fn permissions_of(&self, uuid: &Uuid) -> Permissions {
+            let mut permissions = Permissions::default();
+            let groups = self
+                .members
+                .get(uuid)
+                .into_iter()
+                .flat_map(|membership| membership.groups.iter())
+                .filter_map(|name| self.groups.get(name));
+            for group in groups {
+                permissions.commands.extend(group.commands.iter().cloned());
+                for ability in &group.abilities {
+                    match ability {
+                        Ability::Build => permissions.build = true,
+                        Ability::BypassSafezone => permissions.bypass_safezone = true,
+                    }
+                }
+            }
+            permissions
+        }
+
+        /// The highest role of the groups of the user granting the command,
+        /// if any of them has one.
+        pub fn role_of(&self, uuid: &Uuid, command: &str) -> Option<AdminRole> {
+            self.members
+                .get(uuid)
+                .into_iter()
+                .flat_map(|membership| membership.groups.iter())
+                .filter_map(|name| self.groups.get(name))
+                .filter(|group| group.commands.contains(command))
+                .filter_map(|group| group.role)
+                .max()
+                .map(AdminRole::from)
+        }
+
+        /// Lists the groups, along with what they allow and their members.
+        pub fn summary(&self) -> String {
+            if self.groups.is_empty() {
+                return "No permission groups".to_owned();
+            }
+            let mut names = self.groups.keys().collect::<Vec<_>>();
+            names.sort();
+            names
+                .into_iter()
+                .map(|name| {
+                    let group = &self.groups[name];
+                    let mut members = self
+                        .members
+                        .values()
+                        .filter(|membership| membership.groups.contains(name))
+                        .map(|membership| membership.username_when_assigned.as_str())
+                        .collect::<Vec<_>>();
+                    members.sort_unstable();
+                    format!(
+                        "{}: commands {:?}, abilities {:?}, role {:?}, members {:?}",
+                        name, group.commands, group.abilities, group.role, members
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+
+        /// Perform any needed validation on these permission groups that
+        /// can't be done using parsing.
+        ///
+        /// The returned version being "Old" indicates the loaded setting has
+        /// been modified during validation (this is why validate takes
+        /// `&mut self`).
+        pub(super) fn validate(&mut self) -> Result<Version, <Final as EditableSetting>::Error> {
+            // Unknown commands may be plugin commands, and unknown groups may be
+            // added back later, so they are only reported.
+            for (name, group) in &self.groups {
+                for command in &group.commands {
+                    if ChatCommand::from_str(command).is_err() {
+                        warn!(
+                            "The permission group {} grants /{}, which is not a built-in command",
+                            name, command
+                        );
+                    }
+                }
+            }
+            for (uuid, membership) in &self.members {
+                for group in &membership.groups {
+                    if !self.groups.contains_key(group) {
+                        warn!(
+                            "{} ({}) is in the permission group {}, which doesn't exist",
+                            membership.username_when_assigned, uuid, group
+                        );
+                    }
+                }
+            }
+            Ok(Version::Latest)
+        }
+    }
+
+    // NOTE: Whenever there is a version upgrade, copy this note as well as the
+    // commented-out code below to the next version, then uncomment the code
+    // for this version.
+    /* impl TryFrom<PermissionGroups> for Final {
+        type Error = <Final as EditableSetting>::Error;
+
+        fn try_from(mut value: PermissionGroups) -> Result<Final, Self::Error> {
+            value.validate()?;
+            Ok(next::PermissionGroups::migrate(value).try_into().expect(MIGRATION_UPGRADE_GUARANTEE))
+        }
+    } */
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use authc::Uuid;
+    use chrono::Utc;
+    use common::comp::AdminRole;
+
+    fn group(commands: &[&str], abilities: &[Ability]) -> PermissionGroup {
+        PermissionGroup {
+            commands: commands.iter().map(|cmd| (*cmd).to_owned()).collect(),
+            abilities: abilities.iter().copied().collect(),
+            role: None,
+        }
+    }
+
+    fn member(groups: &[&str]) -> GroupMembership {
+        GroupMembership {
+            username_when_assigned: "player".to_owned(),
+            date: Utc::now(),
+            groups: groups.iter().map(|group| (*group).to_owned()).collect(),
+        }
+    }
+
+    #[test]
+    fn permissions_of_members() {
+        let builder = Uuid::from_u128(1);
+        let host = Uuid::from_u128(2);
+        let lost = Uuid::from_u128(3);
+        let groups = PermissionGroups {
+            groups: vec![
+                ("builders".to_owned(), group(&["build"], &[Ability::Build])),
+                (
+                    "hosts".to_owned(),
+                    group(&["tp", "spawn"], &[Ability::BypassSafezone]),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+            members: vec![
+                (builder, member(&["builders"])),
+                (host, member(&["builders", "hosts"])),
+                (lost, member(&["removed"])),
+            ]
+            .into_iter()
+            .collect(),
+        };
+
+        let permissions = groups.permissions_of(&builder);
+        assert_eq!(permissions.commands.iter().collect::<Vec<_>>(), vec![
+            "build"
+        ]);
+        assert!(permissions.build);
+        assert!(!permissions.bypass_safezone);
+
+        let permissions = groups.permissions_of(&host);
+        assert_eq!(permissions.commands.len(), 3);
+        assert!(permissions.commands.contains("spawn"));
+        assert!(permissions.build && permissions.bypass_safezone);
+
+        // Groups which don't exist anymore, and users in no group, allow nothing.
+        assert!(groups.permissions_of(&lost).is_empty());
+        assert!(groups.permissions_of(&Uuid::from_u128(4)).is_empty());
+    }
+
+    #[test]
+    fn roles_of_members() {
+        let helper = Uuid::from_u128(1);
+        let senior = Uuid::from_u128(2);
+        let groups = PermissionGroups {
+            groups: vec![
+                ("helpers".to_owned(), group(&["kick", "ban"], &[])),
+                ("moderators".to_owned(), PermissionGroup {
+                    role: Some(Role::Moderator),
+                    ..group(&["kick"], &[])
+                }),
+                ("seniors".to_owned(), PermissionGroup {
+                    role: Some(Role::Admin),
+                    ..group(&["ban"], &[])
+                }),
+            ]
+            .into_iter()
+            .collect(),
+            members: vec![
+                (helper, member(&["helpers"])),
+                (senior, member(&["helpers", "moderators", "seniors"])),
+            ]
+            .into_iter()
+            .collect(),
+        };
+
+        // Groups without a role don't give one, even if they grant the command
+        assert_eq!(groups.role_of(&helper, "kick"), None);
+        assert_eq!(groups.role_of(&helper, "ban"), None);
+        // Only the roles of the groups granting the command count
+        assert_eq!(groups.role_of(&senior, "kick"), Some(AdminRole::Moderator));
+        assert_eq!(groups.role_of(&senior, "ban"), Some(AdminRole::Admin));
+        assert_eq!(groups.role_of(&senior, "tp"), None);
+        assert_eq!(groups.role_of(&Uuid::from_u128(3), "kick"), None);
+    }
+}
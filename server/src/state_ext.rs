@@ -3,6 +3,7 @@ use crate::{
     persistence::PersistedComponents,
     pet::restore_pet,
     presence::{Presence, RepositionOnChunkLoad},
+    reports::{ChatHistory, ChatLine},
     settings::Settings,
     sys::sentinel::DeletedEntities,
     wiring, BattleModeBuffer, SpawnPoint,
@@ -625,6 +626,7 @@ impl StateExt for State {
     /// by location. Faction and group are limited by component.
     fn send_chat(&self, msg: comp::UnresolvedChatMsg) {
         let ecs = self.ecs();
+        record_chat_history(ecs, &msg);
         let is_within =
             |target, a: &comp::Pos, b: &comp::Pos| a.0.distance_squared(b.0) < target * target;
 
@@ -923,6 +925,37 @@ impl StateExt for State {
     }
 }
 
+/// Records the messages sent by players, so that they can be attached to
+/// reports.
+fn record_chat_history(ecs: &specs::World, msg: &comp::UnresolvedChatMsg) {
+    let players = ecs.read_storage::<comp::Player>();
+    let player_of = |uid: Uid| {
+        ecs.entity_from_uid(uid.0)
+            .and_then(|entity| players.get(entity))
+    };
+    let sender = match msg.uid().and_then(player_of) {
+        Some(sender) => sender,
+        None => return,
+    };
+    let recipient = match msg.chat_type {
+        comp::ChatType::Tell(_, to) => player_of(to),
+        _ => None,
+    };
+    let line = ChatLine {
+        date: chrono::Utc::now(),
+        sender: sender.uuid(),
+        sender_username: sender.alias.clone(),
+        recipient_username: recipient.map(|recipient| recipient.alias.clone()),
+        message: msg.message.clone(),
+    };
+    ecs.write_resource::<ChatHistory>().record(
+        ecs.read_resource::<Settings>().reports.chat_lines,
+        ecs.read_resource::<Time>().0,
+        line,
+        recipient.map(|recipient| recipient.uuid()),
+    );
+}
+
 fn send_to_group(g: &comp::Group, ecs: &specs::World, msg: &comp::ChatMsg) {
     for (client, group) in (
         &ecs.read_storage::<Client>(),
@@ -1,30 +1,62 @@
-use crate::client::Client;
+use crate::{
+    chat_limiter::ChatLimiter, client::Client, metrics::PlayerMetrics, EditableSettings, Settings,
+};
+use chrono::Utc;
 use common::{
-    comp::{ChatMode, Player},
+    comp::{ChatMode, ChatType, Player},
     event::{EventBus, ServerEvent},
     resources::Time,
     uid::Uid,
 };
 use common_ecs::{Job, Origin, Phase, System};
 use common_net::msg::{
-    validate_chat_msg, ChatMsgValidationError, ClientGeneral, MAX_BYTES_CHAT_MSG,
+    validate_chat_msg, ChatMsgValidationError, ClientGeneral, ServerGeneral, MAX_BYTES_CHAT_MSG,
 };
-use specs::{Entities, Join, Read, ReadStorage};
+use specs::{Entities, Join, Read, ReadExpect, ReadStorage, Write};
 use tracing::{debug, error, warn};
 
 impl Sys {
+    #[allow(clippy::too_many_arguments)]
     fn handle_general_msg(
         server_emitter: &mut common::event::Emitter<'_, ServerEvent>,
         entity: specs::Entity,
-        _client: &Client,
+        client: &Client,
         player: Option<&Player>,
         uids: &ReadStorage<'_, Uid>,
         chat_modes: &ReadStorage<'_, ChatMode>,
+        editable_settings: &EditableSettings,
+        settings: &Settings,
+        chat_limiter: &mut ChatLimiter,
+        player_metrics: &PlayerMetrics,
+        time: &Time,
         msg: ClientGeneral,
     ) -> Result<(), crate::error::Error> {
         match msg {
             ClientGeneral::ChatMsg(message) => {
-                if player.is_some() {
+                if let Some(player) = player {
+                    if let Some(mute) = editable_settings
+                        .mutelist
+                        .mute_of(&player.uuid(), Utc::now())
+                    {
+                        client.send(ServerGeneral::server_msg(
+                            ChatType::CommandError,
+                            mute.info(),
+                        ))?;
+                        return Ok(());
+                    }
+                    if let Err(limited) =
+                        chat_limiter.check(&settings.chat_limits, player.uuid(), &message, time.0)
+                    {
+                        player_metrics
+                            .chat_messages_dropped
+                            .with_label_values(&[limited.reason()])
+                            .inc();
+                        client.send(ServerGeneral::server_msg(
+                            ChatType::CommandError,
+                            limited.info(),
+                        ))?;
+                        return Ok(());
+                    }
                     match validate_chat_msg(&message) {
                         Ok(()) => {
                             if let Some(from) = uids.get(entity) {
@@ -50,6 +82,18 @@ impl Sys {
                     server_emitter.emit(ServerEvent::Command(entity, name, args));
                 }
             },
+            ClientGeneral::ReportPlayer {
+                player: reported,
+                reason,
+            } => {
+                if player.is_some() {
+                    server_emitter.emit(ServerEvent::Report {
+                        reporter: entity,
+                        player: reported,
+                        reason,
+                    });
+                }
+            },
             ClientGeneral::Terminate => {
                 debug!(?entity, "Client send message to terminate session");
                 server_emitter.emit(ServerEvent::ClientDisconnect(
@@ -76,6 +120,10 @@ impl<'a> System<'a> for Sys {
         ReadStorage<'a, ChatMode>,
         ReadStorage<'a, Player>,
         ReadStorage<'a, Client>,
+        ReadExpect<'a, EditableSettings>,
+        ReadExpect<'a, Settings>,
+        Write<'a, ChatLimiter>,
+        ReadExpect<'a, PlayerMetrics>,
     );
 
     const NAME: &'static str = "msg::general";
@@ -84,9 +132,22 @@ impl<'a> System<'a> for Sys {
 
     fn run(
         _job: &mut Job<Self>,
-        (entities, server_event_bus, time, uids, chat_modes, players, clients): Self::SystemData,
+        (
+            entities,
+            server_event_bus,
+            time,
+            uids,
+            chat_modes,
+            players,
+            clients,
+            editable_settings,
+            settings,
+            mut chat_limiter,
+            player_metrics,
+        ): Self::SystemData,
     ) {
         let mut server_emitter = server_event_bus.emitter();
+        chat_limiter.maintain(&settings.chat_limits, time.0);
 
         for (entity, client, player) in (&entities, &clients, (&players).maybe()).join() {
             let res = super::try_recv_all(client, 3, |client, msg| {
@@ -97,6 +158,11 @@ impl<'a> System<'a> for Sys {
                     player,
                     &uids,
                     &chat_modes,
+                    &editable_settings,
+                    &settings,
+                    &mut chat_limiter,
+                    &player_metrics,
+                    &time,
                     msg,
                 )
             });
@@ -3,9 +3,10 @@ use crate::TerrainPersistence;
 use crate::{client::Client, presence::Presence, Settings};
 use common::{
     comp::{
-        Admin, CanBuild, ControlEvent, Controller, ForceUpdate, Health, Ori, Player, Pos, SkillSet,
-        Vel,
+        Admin, CanBuild, ControlEvent, Controller, ForceUpdate, Health, Ori, Permissions, Player,
+        Pos, SkillSet, Vel,
     },
+    depot::Id,
     event::{EventBus, ServerEvent},
     link::Is,
     mounting::Rider,
@@ -19,12 +20,70 @@ use common_state::{BlockChange, BuildAreas};
 use specs::{Entities, Join, Read, ReadExpect, ReadStorage, Write, WriteStorage};
 use tracing::{debug, trace, warn};
 use vek::*;
+#[cfg(feature = "plugins")]
+use {
+    common::{
+        comp::{Buffs, Inventory},
+        uid::{Uid, UidAllocator},
+    },
+    common_state::plugin::{memory_manager::EcsWorld, storage::PluginStorage, PluginMgr},
+    plugin_api::event::{BlockBreakEvent, BlockPlaceEvent, CancelResult},
+    specs::SystemData,
+};
 
 #[cfg(feature = "persistent_world")]
 pub type TerrainPersistenceData<'a> = Option<Write<'a, TerrainPersistence>>;
 #[cfg(not(feature = "persistent_world"))]
 pub type TerrainPersistenceData<'a> = ();
 
+/// Everything needed to let plugins cancel building
+#[cfg(feature = "plugins")]
+#[derive(SystemData)]
+pub struct PluginData<'a> {
+    entities: Entities<'a>,
+    plugin_mgr: Read<'a, PluginMgr>,
+    uids: ReadStorage<'a, Uid>,
+    healths: ReadStorage<'a, Health>,
+    players: ReadStorage<'a, Player>,
+    inventories: ReadStorage<'a, Inventory>,
+    buffs: ReadStorage<'a, Buffs>,
+    uid_allocator: Read<'a, UidAllocator>,
+    terrain: ReadExpect<'a, TerrainGrid>,
+    storage: Read<'a, PluginStorage>,
+}
+#[cfg(not(feature = "plugins"))]
+pub type PluginData<'a> = ();
+
+#[cfg(feature = "plugins")]
+impl<'a> PluginData<'a> {
+    /// Returns `true` if any plugin cancelled the event
+    fn is_cancelled<T: plugin_api::Event<Response = CancelResult>>(
+        &self,
+        positions: &WriteStorage<'_, Pos>,
+        event: &T,
+    ) -> bool {
+        let ecs_world = EcsWorld {
+            entities: &self.entities,
+            health: (&self.healths).into(),
+            uid: (&self.uids).into(),
+            player: (&self.players).into(),
+            pos: positions.into(),
+            inventory: (&self.inventories).into(),
+            buffs: (&self.buffs).into(),
+            uid_allocator: &self.uid_allocator,
+            terrain: &self.terrain,
+            storage: &self.storage,
+        };
+        match self.plugin_mgr.execute_event(&ecs_world, event) {
+            Ok(results) => results.contains(&CancelResult::Cancel),
+            Err(e) => {
+                tracing::error!(?e, "Failed to execute plugin event");
+                false
+            },
+        }
+    }
+}
+
 impl Sys {
     #[allow(clippy::too_many_arguments)]
     fn handle_client_in_game_msg(
@@ -34,6 +93,7 @@ impl Sys {
         maybe_presence: &mut Option<&mut Presence>,
         terrain: &ReadExpect<'_, TerrainGrid>,
         can_build: &ReadStorage<'_, CanBuild>,
+        permissions: &ReadStorage<'_, Permissions>,
         is_rider: &ReadStorage<'_, Is<Rider>>,
         force_updates: &ReadStorage<'_, ForceUpdate>,
         skill_sets: &mut WriteStorage<'_, SkillSet>,
@@ -47,6 +107,7 @@ impl Sys {
         build_areas: &Read<'_, BuildAreas>,
         player_physics_settings: &mut Write<'_, PlayerPhysicsSettings>,
         _terrain_persistence: &mut TerrainPersistenceData<'_>,
+        _plugin_data: &PluginData<'_>,
         maybe_player: &Option<&Player>,
         maybe_admin: &Option<&Admin>,
         msg: ClientGeneral,
@@ -201,7 +262,13 @@ impl Sys {
             ClientGeneral::BreakBlock(pos) => {
                 if let Some(comp_can_build) = can_build.get(entity) {
                     if comp_can_build.enabled {
-                        for area in comp_can_build.build_areas.iter() {
+                        for area in allowed_build_areas(
+                            comp_can_build,
+                            permissions.get(entity),
+                            build_areas,
+                        )
+                        .iter()
+                        {
                             if let Some(old_block) = build_areas
                                 .areas()
                                 .get(*area)
@@ -210,6 +277,17 @@ impl Sys {
                                 .filter(|aabb| aabb.contains_point(pos))
                                 .and_then(|_| terrain.get(pos).ok())
                             {
+                                #[cfg(feature = "plugins")]
+                                if let Some(uid) = _plugin_data.uids.get(entity) {
+                                    let event = BlockBreakEvent {
+                                        player: plugin_api::event::Player { id: *uid },
+                                        pos,
+                                        block: (*old_block).into(),
+                                    };
+                                    if _plugin_data.is_cancelled(positions, &event) {
+                                        continue;
+                                    }
+                                }
                                 let new_block = old_block.into_vacant();
                                 let _was_set = block_changes.try_set(pos, new_block).is_some();
                                 #[cfg(feature = "persistent_world")]
@@ -227,7 +305,13 @@ impl Sys {
             ClientGeneral::PlaceBlock(pos, new_block) => {
                 if let Some(comp_can_build) = can_build.get(entity) {
                     if comp_can_build.enabled {
-                        for area in comp_can_build.build_areas.iter() {
+                        for area in allowed_build_areas(
+                            comp_can_build,
+                            permissions.get(entity),
+                            build_areas,
+                        )
+                        .iter()
+                        {
                             if build_areas
                                 .areas()
                                 .get(*area)
@@ -236,6 +320,17 @@ impl Sys {
                                 .filter(|aabb| aabb.contains_point(pos))
                                 .is_some()
                             {
+                                #[cfg(feature = "plugins")]
+                                if let Some(uid) = _plugin_data.uids.get(entity) {
+                                    let event = BlockPlaceEvent {
+                                        player: plugin_api::event::Player { id: *uid },
+                                        pos,
+                                        block: new_block.into(),
+                                    };
+                                    if _plugin_data.is_cancelled(positions, &event) {
+                                        continue;
+                                    }
+                                }
                                 let _was_set = block_changes.try_set(pos, new_block).is_some();
                                 #[cfg(feature = "persistent_world")]
                                 if _was_set {
@@ -294,12 +389,27 @@ impl Sys {
             | ClientGeneral::TerrainChunkRequest { .. }
             | ClientGeneral::ChatMsg(_)
             | ClientGeneral::Command(..)
+            | ClientGeneral::ReportPlayer { .. }
             | ClientGeneral::Terminate => tracing::error!("not a client_in_game msg"),
         }
         Ok(())
     }
 }
 
+/// The build areas the player can build in, which are all of them if their
+/// permission groups allow them to build anywhere.
+fn allowed_build_areas(
+    can_build: &CanBuild,
+    permissions: Option<&Permissions>,
+    build_areas: &BuildAreas,
+) -> Vec<Id<Aabb<i32>>> {
+    if permissions.map_or(false, |permissions| permissions.build) {
+        build_areas.areas().ids().collect()
+    } else {
+        can_build.build_areas.iter().copied().collect()
+    }
+}
+
 /// This system will handle new messages from clients
 #[derive(Default)]
 pub struct Sys;
@@ -310,6 +420,7 @@ impl<'a> System<'a> for Sys {
         Read<'a, EventBus<ServerEvent>>,
         ReadExpect<'a, TerrainGrid>,
         ReadStorage<'a, CanBuild>,
+        ReadStorage<'a, Permissions>,
         ReadStorage<'a, ForceUpdate>,
         ReadStorage<'a, Is<Rider>>,
         WriteStorage<'a, SkillSet>,
@@ -325,6 +436,7 @@ impl<'a> System<'a> for Sys {
         Read<'a, BuildAreas>,
         Write<'a, PlayerPhysicsSettings>,
         TerrainPersistenceData<'a>,
+        PluginData<'a>,
         ReadStorage<'a, Player>,
         ReadStorage<'a, Admin>,
     );
@@ -340,6 +452,7 @@ impl<'a> System<'a> for Sys {
             server_event_bus,
             terrain,
             can_build,
+            permissions,
             force_updates,
             is_rider,
             mut skill_sets,
@@ -355,6 +468,7 @@ impl<'a> System<'a> for Sys {
             build_areas,
             mut player_physics_settings,
             mut terrain_persistence,
+            plugin_data,
             players,
             admins,
         ): Self::SystemData,
@@ -378,6 +492,7 @@ impl<'a> System<'a> for Sys {
                     &mut maybe_presence.as_deref_mut(),
                     &terrain,
                     &can_build,
+                    &permissions,
                     &is_rider,
                     &force_updates,
                     &mut skill_sets,
@@ -391,6 +506,7 @@ impl<'a> System<'a> for Sys {
                     &build_areas,
                     &mut player_physics_settings,
                     &mut terrain_persistence,
+                    &plugin_data,
                     &player,
                     &maybe_admin,
                     msg,
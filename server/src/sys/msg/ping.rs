@@ -1,4 +1,4 @@
-use crate::{client::Client, Settings};
+use crate::{client::Client, session::Session, Settings};
 use common::{
     event::{EventBus, ServerEvent},
     resources::Time,
@@ -28,6 +28,7 @@ impl<'a> System<'a> for Sys {
         Read<'a, EventBus<ServerEvent>>,
         Read<'a, Time>,
         ReadStorage<'a, Client>,
+        ReadStorage<'a, Session>,
         Read<'a, Settings>,
     );
 
@@ -37,7 +38,7 @@ impl<'a> System<'a> for Sys {
 
     fn run(
         _job: &mut Job<Self>,
-        (entities, server_event_bus, time, clients, settings): Self::SystemData,
+        (entities, server_event_bus, time, clients, sessions, settings): Self::SystemData,
     ) {
         let mut server_emitter = server_event_bus.emitter();
 
@@ -73,5 +74,19 @@ impl<'a> System<'a> for Sys {
                 },
             }
         }
+
+        // Remove the characters of clients that didn't reconnect in time
+        for (entity, session, _) in (&entities, &sessions, !&clients).join() {
+            if session.is_expired(time.0, settings.reconnect_grace_period) {
+                info!(
+                    ?entity,
+                    "client didn't reconnect in time, removing its character"
+                );
+                server_emitter.emit(ServerEvent::ClientDisconnect(
+                    entity,
+                    common::comp::DisconnectReason::Timeout,
+                ));
+            }
+        }
     }
 }
@@ -2,11 +2,13 @@ use crate::{
     client::Client,
     login_provider::{LoginProvider, PendingLogin},
     metrics::PlayerMetrics,
+    session::Session,
     EditableSettings, Settings,
 };
 use common::{
-    comp::{Admin, Player, Stats},
+    comp::{Admin, Buffs, Inventory, Permissions, Player, Pos, Stats},
     event::{EventBus, ServerEvent},
+    terrain::TerrainGrid,
     uid::{Uid, UidAllocator},
 };
 use common_ecs::{Job, Origin, Phase, System};
@@ -23,12 +25,19 @@ use specs::{
 use tracing::trace;
 
 #[cfg(feature = "plugins")]
-use {common_state::plugin::memory_manager::EcsWorld, common_state::plugin::PluginMgr};
+use {
+    common_state::plugin::memory_manager::EcsWorld, common_state::plugin::storage::PluginStorage,
+    common_state::plugin::PluginMgr,
+};
 
 #[cfg(feature = "plugins")]
 type ReadPlugin<'a> = Read<'a, PluginMgr>;
 #[cfg(not(feature = "plugins"))]
 type ReadPlugin<'a> = Option<Read<'a, ()>>;
+#[cfg(feature = "plugins")]
+type ReadPluginStorage<'a> = Read<'a, PluginStorage>;
+#[cfg(not(feature = "plugins"))]
+type ReadPluginStorage<'a> = Option<Read<'a, ()>>;
 
 #[derive(SystemData)]
 pub struct ReadData<'a> {
@@ -36,6 +45,7 @@ pub struct ReadData<'a> {
     stats: ReadStorage<'a, Stats>,
     uids: ReadStorage<'a, Uid>,
     clients: ReadStorage<'a, Client>,
+    sessions: ReadStorage<'a, Session>,
     server_event_bus: Read<'a, EventBus<ServerEvent>>,
     player_metrics: ReadExpect<'a, PlayerMetrics>,
     settings: ReadExpect<'a, Settings>,
@@ -43,6 +53,11 @@ pub struct ReadData<'a> {
     _healths: ReadStorage<'a, Health>, // used by plugin feature
     _plugin_mgr: ReadPlugin<'a>,       // used by plugin feature
     _uid_allocator: Read<'a, UidAllocator>, // used by plugin feature
+    _positions: ReadStorage<'a, Pos>,  // used by plugin feature
+    _inventories: ReadStorage<'a, Inventory>, // used by plugin feature
+    _buffs: ReadStorage<'a, Buffs>,    // used by plugin feature
+    _terrain: ReadExpect<'a, TerrainGrid>, // used by plugin feature
+    _plugin_storage: ReadPluginStorage<'a>, // used by plugin feature
 }
 
 /// This system will handle new messages from clients
@@ -53,6 +68,7 @@ impl<'a> System<'a> for Sys {
         ReadData<'a>,
         WriteStorage<'a, Player>,
         WriteStorage<'a, Admin>,
+        WriteStorage<'a, Permissions>,
         WriteStorage<'a, PendingLogin>,
         WriteExpect<'a, LoginProvider>,
     );
@@ -67,6 +83,7 @@ impl<'a> System<'a> for Sys {
             read_data,
             mut players,
             mut admins,
+            mut permissions,
             mut pending_logins,
             mut login_provider,
         ): Self::SystemData,
@@ -97,6 +114,55 @@ impl<'a> System<'a> for Sys {
         // defer auth lockup
         for (entity, client) in (&read_data.entities, &read_data.clients).join() {
             let _ = super::try_recv_all(client, 0, |_, msg: ClientRegister| {
+                if let Some(token) = msg.session {
+                    // Resume the session of a character that lost its connection, if its
+                    // player may still play here
+                    let session = (&read_data.entities, &read_data.sessions, &players)
+                        .join()
+                        .find(|(_, session, _)| session.token == token);
+                    let resumed = match session {
+                        None => Err(RegisterError::SessionExpired),
+                        // The server didn't notice yet that the connection was lost, or the
+                        // token is used while the original client is still connected
+                        Some((character, session, _))
+                            if session.disconnected_at.is_none()
+                                || read_data.clients.contains(character) =>
+                        {
+                            Err(RegisterError::Kicked(String::from(
+                                "The session is still in use.",
+                            )))
+                        },
+                        Some((character, _, player)) => LoginProvider::check_uuid(
+                            player.uuid(),
+                            client.remote_ip(),
+                            &*read_data.editable_settings.admins,
+                            &*read_data.editable_settings.whitelist,
+                            &*read_data.editable_settings.banlist,
+                        )
+                        .and_then(|()| {
+                            // The connecting client is already counted
+                            if read_data.clients.join().count() > read_data.settings.max_players {
+                                Err(RegisterError::TooManyPlayers)
+                            } else {
+                                Ok(character)
+                            }
+                        }),
+                    };
+                    match resumed {
+                        Ok(character) => {
+                            trace!(?entity, ?character, "resuming session");
+                            server_emitter.emit(ServerEvent::ResumeSession { entity, character });
+                        },
+                        Err(e) => {
+                            server_emitter.emit(ServerEvent::ClientDisconnect(
+                                entity,
+                                common::comp::DisconnectReason::Kicked,
+                            ));
+                            client.send(ServerRegisterAnswer::Err(e))?;
+                        },
+                    }
+                    return Ok(());
+                }
                 trace!(?msg.token_or_username, "defer auth lockup");
                 let pending = login_provider.verify(&msg.token_or_username);
                 let _ = pending_logins.insert(entity, pending);
@@ -116,7 +182,12 @@ impl<'a> System<'a> for Sys {
                     health: (&read_data._healths).into(),
                     uid: (&read_data.uids).into(),
                     player: (&players).into(),
+                    pos: (&read_data._positions).into(),
+                    inventory: (&read_data._inventories).into(),
+                    buffs: (&read_data._buffs).into(),
                     uid_allocator: &read_data._uid_allocator,
+                    terrain: &read_data._terrain,
+                    storage: &read_data._plugin_storage,
                 };
 
                 let (username, uuid) = match login_provider.login(
@@ -128,6 +199,7 @@ impl<'a> System<'a> for Sys {
                     &*read_data.editable_settings.admins,
                     &*read_data.editable_settings.whitelist,
                     &*read_data.editable_settings.banlist,
+                    client.remote_ip(),
                 ) {
                     None => return Ok(()),
                     Some(r) => {
@@ -148,8 +220,9 @@ impl<'a> System<'a> for Sys {
                 };
 
                 // Check if user is already logged-in
+                // (or waits for its client to reconnect)
                 if let Some((old_entity, old_client, _)) =
-                    (&read_data.entities, &read_data.clients, &players)
+                    (&read_data.entities, read_data.clients.maybe(), &players)
                         .join()
                         .find(|(_, _, old_player)| old_player.uuid() == uuid)
                 {
@@ -158,9 +231,12 @@ impl<'a> System<'a> for Sys {
                         old_entity,
                         common::comp::DisconnectReason::NewerLogin,
                     ));
-                    let _ = old_client.send(ServerGeneral::Disconnect(DisconnectReason::Kicked(
-                        String::from("You have logged in from another location."),
-                    )));
+                    if let Some(old_client) = old_client {
+                        let _ =
+                            old_client.send(ServerGeneral::Disconnect(DisconnectReason::Kicked(
+                                String::from("You have logged in from another location."),
+                            )));
+                    }
                     // We can't login the new client right now as the
                     // removal of the old client and player occurs later in
                     // the tick, so we instead setup the new login to be
@@ -199,6 +275,17 @@ impl<'a> System<'a> for Sys {
                             .expect("Inserting into players proves the entity exists.");
                     }
 
+                    // Same for what their permission groups allow them to do
+                    let player_permissions = read_data
+                        .editable_settings
+                        .permission_groups
+                        .permissions_of(&uuid);
+                    if !player_permissions.is_empty() {
+                        permissions
+                            .insert(entity, player_permissions)
+                            .expect("Inserting into players proves the entity exists.");
+                    }
+
                     // Tell the client its request was successful.
                     client.send(ServerRegisterAnswer::Ok(()))?;
 
@@ -207,6 +294,12 @@ impl<'a> System<'a> for Sys {
                         player_list.clone(),
                     )))?;
 
+                    // Send the commands declared by plugins
+                    #[cfg(feature = "plugins")]
+                    client.send(ServerGeneral::ServerCommands(
+                        read_data._plugin_mgr.commands().cloned().collect(),
+                    ))?;
+
                     // Add to list to notify all clients of the new player
                     new_players.push(entity);
                 }
@@ -302,6 +302,10 @@ widget_ids! {
         group_window,
         item_info,
 
+        // Reconnecting indicator
+        reconnecting_txt,
+        reconnecting_bg,
+
         // Free look indicator
         free_look_txt,
         free_look_bg,
@@ -664,6 +668,7 @@ pub struct Show {
     social_search_key: Option<String>,
     want_grab: bool,
     stats: bool,
+    reconnecting: bool,
     free_look: bool,
     auto_walk: bool,
     camera_clamp: bool,
@@ -1060,6 +1065,7 @@ impl Hud {
                 want_grab: true,
                 ingame: true,
                 stats: false,
+                reconnecting: false,
                 free_look: false,
                 auto_walk: false,
                 camera_clamp: false,
@@ -3305,6 +3311,23 @@ impl Hud {
 
         let mut indicator_offset = 40.0;
 
+        // Reconnecting indicator
+        if self.show.reconnecting {
+            Text::new(i18n.get("hud.reconnecting_indicator"))
+                .color(TEXT_BG)
+                .mid_top_with_margin_on(ui_widgets.window, indicator_offset)
+                .font_id(self.fonts.cyri.conrod_id)
+                .font_size(self.fonts.cyri.scale(20))
+                .set(self.ids.reconnecting_bg, ui_widgets);
+            indicator_offset += 30.0;
+            Text::new(i18n.get("hud.reconnecting_indicator"))
+                .color(KILL_COLOR)
+                .top_left_with_margins_on(self.ids.reconnecting_bg, -1.0, -1.0)
+                .font_id(self.fonts.cyri.conrod_id)
+                .font_size(self.fonts.cyri.scale(20))
+                .set(self.ids.reconnecting_txt, ui_widgets);
+        }
+
         // Free look indicator
         if let Some(freelook_key) = global_state
             .settings
@@ -4131,6 +4154,8 @@ impl Hud {
         }
     }
 
+    pub fn reconnecting(&mut self, reconnecting: bool) { self.show.reconnecting = reconnecting; }
+
     pub fn free_look(&mut self, free_look: bool) { self.show.free_look = free_look; }
 
     pub fn auto_walk(&mut self, auto_walk: bool) { self.show.auto_walk = auto_walk; }
@@ -14,6 +14,7 @@ use crate::{
 use client::{
     addr::ConnectionArgs,
     error::{InitProtocolError, NetworkConnectError, NetworkError},
+    tls::PinnedCerts,
     Client, ServerInfo,
 };
 use client_init::{ClientInit, Error as InitError, Msg as InitMsg};
@@ -21,7 +22,7 @@ use common::comp;
 use common_base::span;
 use i18n::LocalizationHandle;
 use scene::Scene;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use tokio::runtime;
 use tracing::error;
 use ui::{Event as MainMenuEvent, MainMenuUi};
@@ -50,6 +51,8 @@ pub struct MainMenuState {
     main_menu_ui: MainMenuUi,
     init: InitState,
     scene: Scene,
+    /// Certificates pinned while connecting over TLS, saved once connected
+    pinned_certs: Option<PinnedCerts>,
 }
 
 impl MainMenuState {
@@ -59,6 +62,7 @@ impl MainMenuState {
             main_menu_ui: MainMenuUi::new(global_state),
             init: InitState::None,
             scene: Scene::new(global_state.window.renderer_mut()),
+            pinned_certs: None,
         }
     }
 }
@@ -166,6 +170,14 @@ impl PlayState for MainMenuState {
         // Poll client creation.
         match self.init.client().and_then(|init| init.poll()) {
             Some(InitMsg::Done(Ok(mut client))) => {
+                if let Some(pinned_certs) = self.pinned_certs.take() {
+                    if let Ok(pinned_certs) = pinned_certs.lock() {
+                        global_state.settings.networking.pinned_certs = pinned_certs.clone();
+                        global_state
+                            .settings
+                            .save_to_file_warn(&global_state.config_dir);
+                    }
+                }
                 // Register voxygen components / resources
                 crate::ecs::init(client.state_mut().ecs_mut());
                 self.init = InitState::Pipeline(Box::new(client));
@@ -273,6 +285,7 @@ impl PlayState for MainMenuState {
                 } => {
                     let mut net_settings = &mut global_state.settings.networking;
                     let use_quic = net_settings.use_quic;
+                    let use_tls = net_settings.use_tls;
                     net_settings.username = username.clone();
                     net_settings.default_server = server_address.clone();
                     if !net_settings.servers.contains(&server_address) {
@@ -287,6 +300,14 @@ impl PlayState for MainMenuState {
                             hostname: server_address,
                             prefer_ipv6: false,
                         }
+                    } else if use_tls {
+                        let pinned_certs = Arc::new(Mutex::new(net_settings.pinned_certs.clone()));
+                        self.pinned_certs = Some(Arc::clone(&pinned_certs));
+                        ConnectionArgs::Tls {
+                            hostname: server_address,
+                            prefer_ipv6: false,
+                            pinned_certs: Some(pinned_certs),
+                        }
                     } else {
                         ConnectionArgs::Tcp {
                             hostname: server_address,
@@ -436,6 +457,7 @@ fn get_client_msg_error(
                 format!("{}: {}", localization.get("main.login.banned"), reason)
             },
             Error::InvalidCharacter => localization.get("main.login.invalid_character").into(),
+            Error::SessionExpired => localization.get("main.login.session_expired").into(),
             Error::NetworkErr(NetworkError::ConnectFailed(NetworkConnectError::Handshake(
                 InitProtocolError::WrongVersion(_),
             ))) => net_error(
@@ -14,6 +14,7 @@ use vek::*;
 use client::{self, Client};
 use common::{
     assets::AssetExt,
+    cmd::ChatCommand,
     comp,
     comp::{
         inventory::slot::{EquipSlot, Slot},
@@ -188,7 +189,9 @@ impl SessionState {
             self.mumble_link.update(player_pos, player_pos);
         }
 
-        for event in client.tick(self.inputs.clone(), dt, crate::ecs::sys::add_local_systems)? {
+        let events = client.tick(self.inputs.clone(), dt, crate::ecs::sys::add_local_systems)?;
+        self.hud.reconnecting(client.is_reconnecting());
+        for event in events {
             match event {
                 client::Event::Chat(m) => {
                     self.hud.new_message(m);
@@ -1128,7 +1131,14 @@ impl PlayState for SessionState {
                         self.client.borrow_mut().send_chat(msg);
                     },
                     HudEvent::SendCommand(name, args) => {
-                        self.client.borrow_mut().send_command(name, args);
+                        let mut client = self.client.borrow_mut();
+                        // Reports have a message of their own
+                        match args.split_first() {
+                            Some((player, reason)) if name == ChatCommand::Report.keyword() => {
+                                client.report_player(player.clone(), reason.join(" "))
+                            },
+                            _ => client.send_command(name, args),
+                        }
                     },
                     HudEvent::CharacterSelection => {
                         self.client.borrow_mut().request_remove_character()
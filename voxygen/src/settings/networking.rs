@@ -1,4 +1,4 @@
-use hashbrown::HashSet;
+use hashbrown::{HashMap, HashSet};
 use serde::{Deserialize, Serialize};
 
 /// `NetworkingSettings` stores server and networking settings.
@@ -10,6 +10,11 @@ pub struct NetworkingSettings {
     pub default_server: String,
     pub trusted_auth_servers: HashSet<String>,
     pub use_quic: bool,
+    /// Connect over TCP with TLS, ignored if `use_quic` is set
+    pub use_tls: bool,
+    /// SHA-256 fingerprints of server certificates trusted on first use, by
+    /// server address
+    pub pinned_certs: HashMap<String, String>,
 }
 
 impl Default for NetworkingSettings {
@@ -23,6 +28,8 @@ impl Default for NetworkingSettings {
                 .map(|s| s.to_string())
                 .collect(),
             use_quic: false,
+            use_tls: false,
+            pinned_certs: HashMap::new(),
         }
     }
 }
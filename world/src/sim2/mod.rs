@@ -3,7 +3,7 @@ use crate::{
     site::{
         economy::{
             decay_rate, direct_use_goods, good_list, transportation_effort, Economy, GoodIndex,
-            GoodMap, LaborIndex, LaborMap, TradeDelivery, TradeOrder,
+            GoodMap, LaborIndex, LaborMap, TradeDelivery, TradeInformation, TradeOrder,
         },
         Site, SiteKind,
     },
@@ -297,7 +297,7 @@ lazy_static! {
 // returns wares spent (-) and procured (+)
 // potential_trade: positive = buy, (negative = sell, unused)
 fn plan_trade_for_site(
-    site: &mut Site,
+    economy: &mut Economy,
     site_id: &Id<Site>,
     transportation_capacity: f32,
     external_orders: &mut DHashMap<Id<Site>, Vec<TradeOrder>>,
@@ -305,7 +305,7 @@ fn plan_trade_for_site(
 ) -> GoodMap<f32> {
     // TODO: Do we have some latency of information here (using last years
     // capacity?)
-    //let total_transport_capacity = site.economy.stocks[Transportation];
+    //let total_transport_capacity = economy.stocks[Transportation];
     // TODO: We don't count the capacity per site, but globally (so there might be
     // some imbalance in dispatch vs collection across sites (e.g. more dispatch
     // than collection at one while more collection than dispatch at another))
@@ -317,29 +317,25 @@ fn plan_trade_for_site(
     let mut result = GoodMap::default();
     const MIN_SELL_PRICE: f32 = 1.0;
     // value+amount per good
-    let mut missing_goods: Vec<(GoodIndex, (f32, f32))> = site
-        .economy
+    let mut missing_goods: Vec<(GoodIndex, (f32, f32))> = economy
         .surplus
         .iter()
         .filter(|(g, a)| (**a < 0.0 && *g != *TRANSPORTATION_INDEX))
         .map(|(g, a)| {
             (
                 g,
-                (
-                    site.economy.values[g].unwrap_or(Economy::MINIMUM_PRICE),
-                    -*a,
-                ),
+                (economy.values[g].unwrap_or(Economy::MINIMUM_PRICE), -*a),
             )
         })
         .collect();
     missing_goods.sort_by(|a, b| b.1.0.partial_cmp(&a.1.0).unwrap_or(Less));
     let mut extra_goods: GoodMap<f32> = GoodMap::from_iter(
-        site.economy
+        economy
             .surplus
             .iter()
             .chain(core::iter::once((
                 *COIN_INDEX,
-                &site.economy.stocks[*COIN_INDEX],
+                &economy.stocks[*COIN_INDEX],
             )))
             .filter(|(g, a)| (**a > 0.0 && *g != *TRANSPORTATION_INDEX))
             .map(|(g, a)| (g, *a)),
@@ -347,8 +343,7 @@ fn plan_trade_for_site(
     );
     // ratio+price per good and site
     type GoodRatioPrice = Vec<(GoodIndex, (f32, f32))>;
-    let good_payment: DHashMap<Id<Site>, GoodRatioPrice> = site
-        .economy
+    let good_payment: DHashMap<Id<Site>, GoodRatioPrice> = economy
         .neighbors
         .iter()
         .map(|n| {
@@ -361,7 +356,7 @@ fn plan_trade_for_site(
                         g,
                         (
                             last_val
-                                / site.economy.values[g]
+                                / economy.values[g]
                                     .unwrap_or(-1.0)
                                     .max(Economy::MINIMUM_PRICE),
                             last_val,
@@ -379,8 +374,7 @@ fn plan_trade_for_site(
         .iter()
         .map(|(g, _)| {
             (*g, {
-                let mut neighbor_prices: Vec<(Id<Site>, (f32, f32))> = site
-                    .economy
+                let mut neighbor_prices: Vec<(Id<Site>, (f32, f32))> = economy
                     .neighbors
                     .iter()
                     .filter(|n| n.last_supplies[*g] > 0.0)
@@ -393,8 +387,7 @@ fn plan_trade_for_site(
         .collect();
     // TODO: we need to introduce priority (according to available transportation
     // capacity)
-    let mut neighbor_orders: DHashMap<Id<Site>, GoodMap<f32>> = site
-        .economy
+    let mut neighbor_orders: DHashMap<Id<Site>, GoodMap<f32>> = economy
         .neighbors
         .iter()
         .map(|n| (n.id, GoodMap::default()))
@@ -404,7 +397,7 @@ fn plan_trade_for_site(
         debug!(
             "Site {} #neighbors {} Transport capacity {}",
             site_id.id(),
-            site.economy.neighbors.len(),
+            economy.neighbors.len(),
             transportation_capacity,
         );
         debug!("missing {:#?} extra {:#?}", missing_goods, extra_goods,);
@@ -496,7 +489,7 @@ fn plan_trade_for_site(
     //     info!("orders {:#?}", neighbor_orders,);
     // }
     // TODO: Use planned orders and calculate value, stock etc. accordingly
-    for n in &site.economy.neighbors {
+    for n in &economy.neighbors {
         if let Some(orders) = neighbor_orders.get(&n.id) {
             for (g, a) in orders.iter() {
                 result[g] += *a;
@@ -685,13 +678,13 @@ fn trade_at_site(
 
 /// 3rd step of trading
 fn collect_deliveries(
-    site: &mut Site,
+    economy: &mut Economy,
     deliveries: &mut Vec<TradeDelivery>,
     ctx: &mut vergleich::Context,
 ) {
     // collect all the goods we shipped
     let mut last_exports = GoodMap::from_iter(
-        site.economy
+        economy
             .active_exports
             .iter()
             .filter(|(_g, a)| **a > 0.0)
@@ -705,12 +698,7 @@ fn collect_deliveries(
             last_exports[i.0] -= ictx.value(&format!("{:?}", i.0), *i.1);
         }
         // remember price
-        if let Some(n) = site
-            .economy
-            .neighbors
-            .iter_mut()
-            .find(|n| n.id == d.supplier)
-        {
+        if let Some(n) = economy.neighbors.iter_mut().find(|n| n.id == d.supplier) {
             // remember (and consume) last values
             std::mem::swap(&mut n.last_values, &mut d.prices);
             std::mem::swap(&mut n.last_supplies, &mut d.supply);
@@ -720,7 +708,7 @@ fn collect_deliveries(
                     // likely rounding error, ignore
                     debug!("Unexpected delivery for {:?} {}", g, *a);
                 } else {
-                    site.economy.stocks[g] += *a;
+                    economy.stocks[g] += *a;
                 }
             }
         }
@@ -729,8 +717,8 @@ fn collect_deliveries(
         info!("non empty deliveries {:?}", deliveries);
         deliveries.clear();
     }
-    std::mem::swap(&mut last_exports, &mut site.economy.last_exports);
-    //site.economy.active_exports.clear();
+    std::mem::swap(&mut last_exports, &mut economy.last_exports);
+    //economy.active_exports.clear();
 }
 
 /// Simulate a site's economy. This simulation is roughly equivalent to the
@@ -750,27 +738,46 @@ fn collect_deliveries(
 /// dynamically react to environmental changes. If a product becomes available
 /// through a mechanism such as trade, an entire arm of the economy may
 /// materialise to take advantage of this.
-pub fn tick_site_economy(
-    index: &mut Index,
-    site_id: Id<Site>,
-    dt: f32,
-    mut vc: vergleich::Context,
-) {
+pub fn tick_site_economy(index: &mut Index, site_id: Id<Site>, dt: f32, vc: vergleich::Context) {
     let site = &mut index.sites[site_id];
     if !site.do_economic_simulation() {
         return;
     }
+    let trade = if INTER_SITE_TRADE {
+        Some(&mut index.trade)
+    } else {
+        None
+    };
+    tick_economy(&mut site.economy, site_id, dt, index.time, trade, vc);
+}
+
+/// Simulates an economy at runtime, goods only move between sites through
+/// the merchants of the game rather than through the abstract trade of the
+/// world generation.
+pub fn tick_runtime_economy(economy: &mut Economy, site_id: Id<Site>, dt: f32, time: f32) {
+    tick_economy(economy, site_id, dt, time, None, vergleich::Context {});
+}
 
+/// Advances an economy by `dt` days, trading with other sites through
+/// `trade_info` if given.
+fn tick_economy(
+    economy: &mut Economy,
+    site_id: Id<Site>,
+    dt: f32,
+    time: f32,
+    mut trade_info: Option<&mut TradeInformation>,
+    mut vc: vergleich::Context,
+) {
     // collect goods from trading
-    if INTER_SITE_TRADE {
-        let deliveries = index.trade.deliveries.get_mut(&site_id);
+    if let Some(trade_info) = trade_info.as_deref_mut() {
+        let deliveries = trade_info.deliveries.get_mut(&site_id);
         if let Some(deliveries) = deliveries {
-            collect_deliveries(site, deliveries, &mut vc);
+            collect_deliveries(economy, deliveries, &mut vc);
         }
     }
 
-    let orders = site.economy.get_orders();
-    let productivity = site.economy.get_productivity();
+    let orders = economy.get_orders();
+    let productivity = economy.get_productivity();
 
     for i in productivity.iter() {
         vc.context("productivity")
@@ -780,10 +787,10 @@ pub fn tick_site_economy(
     let mut demand = GoodMap::from_default(0.0);
     for (labor, orders) in &orders {
         let workers = if let Some(labor) = labor {
-            site.economy.labors[*labor]
+            economy.labors[*labor]
         } else {
             1.0
-        } * site.economy.pop;
+        } * economy.pop;
         for (good, amount) in orders {
             demand[*good] += *amount * workers;
         }
@@ -798,17 +805,16 @@ pub fn tick_site_economy(
         .find(|(_, v)| v.0 == *TRANSPORTATION_INDEX)
         .map(|(l, _)| l);
 
-    let mut supply = site.economy.stocks; //GoodMap::from_default(0.0);
+    let mut supply = economy.stocks; //GoodMap::from_default(0.0);
     for (labor, goodvec) in productivity.iter() {
         //for (output_good, _) in goodvec.iter() {
         //info!("{} supply{:?}+={}", site_id.id(), Good::from(goodvec.0),
-        // site.economy.yields[labor] * site.economy.labors[labor] * site.economy.pop);
-        supply[goodvec.0] +=
-            site.economy.yields[labor] * site.economy.labors[labor] * site.economy.pop;
+        // economy.yields[labor] * economy.labors[labor] * economy.pop);
+        supply[goodvec.0] += economy.yields[labor] * economy.labors[labor] * economy.pop;
         vc.context(&std::format!("{:?}-{:?}", Good::from(goodvec.0), labor))
-            .value("yields", site.economy.yields[labor]);
+            .value("yields", economy.yields[labor]);
         vc.context(&std::format!("{:?}-{:?}", Good::from(goodvec.0), labor))
-            .value("labors", site.economy.labors[labor]);
+            .value("labors", economy.labors[labor]);
         //}
     }
 
@@ -817,29 +823,29 @@ pub fn tick_site_economy(
             .value(&std::format!("{:?}", Good::from(i.0)), *i.1);
     }
 
-    let stocks = &site.economy.stocks;
+    let stocks = &economy.stocks;
     for i in stocks.iter() {
         vc.context("stocks")
             .value(&std::format!("{:?}", Good::from(i.0)), *i.1);
     }
-    site.economy.surplus = demand.map(|g, demand| supply[g] + stocks[g] - demand);
-    site.economy.marginal_surplus = demand.map(|g, demand| supply[g] - demand);
+    economy.surplus = demand.map(|g, demand| supply[g] + stocks[g] - demand);
+    economy.marginal_surplus = demand.map(|g, demand| supply[g] - demand);
 
     // plan trading with other sites
-    let external_orders = &mut index.trade.orders;
+    let mut external_orders = trade_info.map(|trade_info| &mut trade_info.orders);
     let mut potential_trade = GoodMap::from_default(0.0);
     // use last year's generated transportation for merchants (could we do better?
     // this is in line with the other professions)
-    let transportation_capacity = site.economy.stocks[*TRANSPORTATION_INDEX];
-    let trade = if INTER_SITE_TRADE {
+    let transportation_capacity = economy.stocks[*TRANSPORTATION_INDEX];
+    let trade = if let Some(external_orders) = external_orders.as_deref_mut() {
         let trade = plan_trade_for_site(
-            site,
+            economy,
             &site_id,
             transportation_capacity,
             external_orders,
             &mut potential_trade,
         );
-        site.economy.active_exports = GoodMap::from_iter(trade.iter().map(|(g, a)| (g, -*a)), 0.0); // TODO: check for availability?
+        economy.active_exports = GoodMap::from_iter(trade.iter().map(|(g, a)| (g, -*a)), 0.0); // TODO: check for availability?
 
         // add the wares to sell to demand and the goods to buy to supply
         for (g, a) in trade.iter() {
@@ -862,10 +868,10 @@ pub fn tick_site_economy(
     // Note that values are used for workforce allocation and are not the same thing
     // as price
     // fall back to old (less wrong than other goods) coin logic
-    let old_coin_surplus = site.economy.stocks[*COIN_INDEX] - demand[*COIN_INDEX];
-    let values = &mut site.economy.values;
+    let old_coin_surplus = economy.stocks[*COIN_INDEX] - demand[*COIN_INDEX];
+    let values = &mut economy.values;
 
-    site.economy.surplus.iter().for_each(|(good, surplus)| {
+    economy.surplus.iter().for_each(|(good, surplus)| {
         let old_surplus = if good == *COIN_INDEX {
             old_coin_surplus
         } else {
@@ -905,13 +911,13 @@ pub fn tick_site_economy(
                     all_trade_goods
                         .iter()
                         .chain(std::iter::once(&goodvec.0))
-                        .map(|&output_good| site.economy.values[output_good].unwrap_or(0.0))
+                        .map(|&output_good| economy.values[output_good].unwrap_or(0.0))
                         .max_by(|a, b| a.abs().partial_cmp(&b.abs()).unwrap_or(Less))
                 } else {
-                    site.economy.values[goodvec.0]
+                    economy.values[goodvec.0]
                 }
                 .unwrap_or(0.0)
-                    * site.economy.productivity[labor],
+                    * economy.productivity[labor],
             )
         }),
         0.0,
@@ -922,23 +928,23 @@ pub fn tick_site_economy(
     let mut labor_context = vc.context("labor");
     productivity.iter().for_each(|(labor, _)| {
         let smooth = 0.8;
-        site.economy.labors[labor] = labor_context.value(
+        economy.labors[labor] = labor_context.value(
             &format!("{:?}", labor),
-            smooth * site.economy.labors[labor]
+            smooth * economy.labors[labor]
                 + (1.0 - smooth)
                     * (labor_ratios[labor].max(labor_ratio_sum / 1000.0) / labor_ratio_sum),
         );
-        assert!(site.economy.labors[labor] >= 0.0);
+        assert!(economy.labors[labor] >= 0.0);
     });
 
     // Production
-    let stocks_before = site.economy.stocks;
+    let stocks_before = economy.stocks;
     // TODO: Should we recalculate demand after labor reassignment?
 
     let direct_use = direct_use_goods();
     // Handle the stocks you can't pile (decay)
     for g in direct_use {
-        site.economy.stocks[*g] = 0.0;
+        economy.stocks[*g] = 0.0;
     }
 
     let mut total_labor_values = GoodMap::<f32>::default();
@@ -946,10 +952,10 @@ pub fn tick_site_economy(
     let mut total_outputs = GoodMap::<f32>::default();
     for (labor, orders) in orders.iter() {
         let workers = if let Some(labor) = labor {
-            site.economy.labors[*labor]
+            economy.labors[*labor]
         } else {
             1.0
-        } * site.economy.pop;
+        } * economy.pop;
         assert!(workers >= 0.0);
         let is_merchant = merchant_labor == *labor;
 
@@ -981,26 +987,26 @@ pub fn tick_site_economy(
             let used = quantity * labor_productivity;
 
             // Material cost of each factor of production
-            total_materials_cost += used * site.economy.labor_values[*good].unwrap_or(0.0);
+            total_materials_cost += used * economy.labor_values[*good].unwrap_or(0.0);
 
             // Deplete stocks accordingly
             if !direct_use.contains(good) {
-                site.economy.stocks[*good] = (site.economy.stocks[*good] - used).max(0.0);
+                economy.stocks[*good] = (economy.stocks[*good] - used).max(0.0);
             }
         }
         let mut produced_goods: GoodMap<f32> = GoodMap::from_default(0.0);
-        if INTER_SITE_TRADE && is_merchant {
+        if let Some(external_orders) = external_orders.as_deref_mut().filter(|_| is_merchant) {
             // TODO: replan for missing merchant productivity???
             for (g, a) in trade.iter() {
                 if !direct_use.contains(&g) {
                     if *a < 0.0 {
                         // take these goods to the road
-                        if site.economy.stocks[g] + *a < 0.0 {
+                        if economy.stocks[g] + *a < 0.0 {
                             // we have a problem: Probably due to a shift in productivity we have
                             // less goods available than planned,
                             // so we would need to reduce the amount shipped
-                            debug!("NEG STOCK {:?} {} {}", g, site.economy.stocks[g], *a);
-                            let reduced_amount = site.economy.stocks[g];
+                            debug!("NEG STOCK {:?} {} {}", g, economy.stocks[g], *a);
+                            let reduced_amount = economy.stocks[g];
                             let planned_amount: f32 = external_orders
                                 .iter()
                                 .map(|i| {
@@ -1017,14 +1023,14 @@ pub fn tick_site_economy(
                                     l.amount[g] *= scale;
                                 }
                             }
-                            site.economy.stocks[g] = 0.0;
+                            economy.stocks[g] = 0.0;
                         }
-                        //                    assert!(site.economy.stocks[g] + *a >= 0.0);
+                        //                    assert!(economy.stocks[g] + *a >= 0.0);
                         else {
-                            site.economy.stocks[g] += *a;
+                            economy.stocks[g] += *a;
                         }
                     }
-                    total_materials_cost += (-*a) * site.economy.labor_values[g].unwrap_or(0.0);
+                    total_materials_cost += (-*a) * economy.labor_values[g].unwrap_or(0.0);
                 } else {
                     // count on receiving these
                     produced_goods[g] += *a;
@@ -1033,7 +1039,7 @@ pub fn tick_site_economy(
             debug!(
                 "merchant {} {}: {:?} {} {:?}",
                 site_id.id(),
-                site.economy.pop,
+                economy.pop,
                 produced_goods,
                 total_materials_cost,
                 trade
@@ -1043,16 +1049,16 @@ pub fn tick_site_economy(
         // Industries produce things
         if let Some(labor) = labor {
             let work_products = &productivity[*labor];
-            //let workers = site.economy.labors[*labor] * site.economy.pop;
+            //let workers = economy.labors[*labor] * economy.pop;
             //let final_rate = rate;
             //let yield_per_worker = labor_productivity;
-            site.economy.yields[*labor] = labor_productivity * work_products.1;
-            site.economy.productivity[*labor] = labor_productivity;
+            economy.yields[*labor] = labor_productivity * work_products.1;
+            economy.productivity[*labor] = labor_productivity;
             //let total_product_rate: f32 = work_products.iter().map(|(_, r)| *r).sum();
             let (stock, rate) = work_products;
             let total_output = labor_productivity * *rate * workers;
             assert!(total_output >= 0.0);
-            site.economy.stocks[*stock] += total_output;
+            economy.stocks[*stock] += total_output;
             produced_goods[*stock] += total_output;
 
             let produced_amount: f32 = produced_goods.iter().map(|(_, a)| *a).sum();
@@ -1061,7 +1067,7 @@ pub fn tick_site_economy(
                 // Materials cost per unit
                 // TODO: How to handle this reasonably for multiple producers (collect upper and
                 // lower term separately)
-                site.economy.material_costs[stock] =
+                economy.material_costs[stock] =
                     total_materials_cost / amount.max(0.001) * cost_weight;
                 // Labor costs
                 let wages = 1.0;
@@ -1075,7 +1081,7 @@ pub fn tick_site_economy(
     }
 
     // Update labour values per unit
-    site.economy.labor_values = total_labor_values.map(|stock, tlv| {
+    economy.labor_values = total_labor_values.map(|stock, tlv| {
         let total_output = total_outputs[stock];
         if total_output > 0.01 {
             Some(tlv / total_output)
@@ -1085,46 +1091,43 @@ pub fn tick_site_economy(
     });
 
     // Decay stocks (the ones which totally decay are handled later)
-    site.economy
+    economy
         .stocks
         .iter_mut()
         .map(|(c, v)| (v, 1.0 - decay_rate(c)))
         .for_each(|(v, factor)| *v *= factor);
 
     // Decay stocks
-    site.economy.replenish(index.time);
+    economy.replenish(time);
 
     // Births/deaths
     const NATURAL_BIRTH_RATE: f32 = 0.05;
     const DEATH_RATE: f32 = 0.005;
-    let birth_rate = if site.economy.surplus[*FOOD_INDEX] > 0.0 {
+    let birth_rate = if economy.surplus[*FOOD_INDEX] > 0.0 {
         NATURAL_BIRTH_RATE
     } else {
         0.0
     };
-    site.economy.pop += vc.value(
-        "pop",
-        dt / YEAR * site.economy.pop * (birth_rate - DEATH_RATE),
-    );
+    economy.pop += vc.value("pop", dt / YEAR * economy.pop * (birth_rate - DEATH_RATE));
 
     // calculate the new unclaimed stock
-    //let next_orders = site.economy.get_orders();
+    //let next_orders = economy.get_orders();
     // orders are static
     let mut next_demand = GoodMap::from_default(0.0);
     for (labor, orders) in orders.iter() {
         let workers = if let Some(labor) = labor {
-            site.economy.labors[*labor]
+            economy.labors[*labor]
         } else {
             1.0
-        } * site.economy.pop;
+        } * economy.pop;
         for (good, amount) in orders {
             next_demand[*good] += *amount * workers;
             assert!(next_demand[*good] >= 0.0);
         }
     }
     let mut us = vc.context("unconsumed");
-    site.economy.unconsumed_stock = GoodMap::from_iter(
-        site.economy.stocks.iter().map(|(g, a)| {
+    economy.unconsumed_stock = GoodMap::from_iter(
+        economy.stocks.iter().map(|(g, a)| {
             (
                 g,
                 us.value(&format!("{:?}", Good::from(g)), *a - next_demand[g]),
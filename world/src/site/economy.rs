@@ -268,14 +268,14 @@ impl<V: Copy + Default> LaborMap<V> {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct AreaResources {
     pub resource_sum: GoodMap<f32>,
     pub resource_chunks: GoodMap<f32>,
     pub chunks: u32,
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct NaturalResources {
     // resources per distance, we should increase labor cost for far resources
     pub per_area: Vec<AreaResources>,
@@ -363,7 +363,7 @@ pub struct TradeInformation {
     pub deliveries: DHashMap<Id<Site>, Vec<TradeDelivery>>, // per receiver
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct NeighborInformation {
     pub id: Id<Site>,
     pub travel_distance: usize,
@@ -373,7 +373,7 @@ pub struct NeighborInformation {
     pub last_supplies: GoodMap<f32>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Economy {
     // Population
     pub pop: f32,